@@ -0,0 +1,19 @@
+// Rubrail update throughput benchmark
+//
+// Measures how quickly items can be updated, and how long button presses
+// take to reach their callbacks.  See the `benchmark` module documentation.
+//
+// $ cargo bench --features benchmark
+//
+extern crate rubrail;
+
+use rubrail::prelude::*;
+use rubrail::benchmark;
+
+const ITERATIONS: u32 = 10000;
+
+fn main() {
+    let mut tb = Touchbar::alloc("bench");
+    let report = benchmark::benchmark_updates(&mut tb, ITERATIONS);
+    println!("{}", report);
+}
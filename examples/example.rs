@@ -17,11 +17,7 @@
 extern crate rubrail;
 extern crate fruitbasket;
 
-use rubrail::Touchbar;
-use rubrail::TTouchbar;
-use rubrail::TScrubberData;
-use rubrail::ItemId;
-use rubrail::SwipeState;
+use rubrail::prelude::*;
 
 #[macro_use]
 extern crate log;
@@ -44,8 +40,7 @@ impl TScrubberData for TouchbarHandler {
         // font.  no idea what it's like on other machines.  does the touchbar
         // font change? ¯\_(ツ)_/¯
         let len = self.devices.borrow()[idx as usize].len() as u32;
-        let width = len * 8 + 20;
-        width
+        len * 8 + 20
     }
     fn touch(&self, _item: ItemId, idx: u32) {
         info!("scrub touch: {}", idx);
@@ -57,25 +52,58 @@ fn populate(bar_rc: Rc<RefCell<Touchbar>>, count: u32, stopper: fruitbasket::Fru
     // it can be passed around in the button callbacks.
     let mut tb = (bar_rc).borrow_mut();
 
-    // Create the lowest level "root" touchbar
-    let mut barid = tb.create_bar();
+    // Create a data backend for scrolling text "scrubbers"
+    let scrubber = Rc::new(TouchbarHandler {
+        devices: RefCell::new(vec![
+            "one".to_string(), "two".to_string(),
+            "a little bit longer one".to_string(),
+            "three".to_string(),
+            "this one is really quite a bit longer than the others".to_string()]),
+    });
 
-    // Create a quit button for root bar
-    let quit_stopper = stopper.clone();
-    let quit_id = tb.create_button(None, Some("Quit"), Box::new(move |_| {quit_stopper.stop();}));
+    let button = |text: &str| ButtonOptions { text: Some(text.to_string()), ..Default::default() };
 
-    // Create an action button for the root bar.  When clicked, it will
-    // close the bar and re-create itself.
+    // The quit button stops the app, and the action button closes the bar and
+    // re-creates it.
+    let quit_stopper = stopper.clone();
     let bar_copy = bar_rc.clone();
     let text = format!("button{}", count);
-    let button1_id = tb.create_button(None, Some(&text), Box::new(move |_| {
-        populate(bar_copy.clone(), count+1, stopper.clone())
-    }));
 
-    // Create a text label for the root bar
-    let label1_id = tb.create_label("This is a label");
+    // Describe the lowest level "root" touchbar and everything under it.
+    // 'Popbars' are second level deep bars, and the first one contains
+    // another popbar to make a 2-level deep UI.
+    //
+    // Both scrubbers use the same data and callbacks, but they are different
+    // instances and can store a different active selection.
+    let (barid, items) = tb.build(vec![
+        BarItemSpec::Button(button("Quit"), Box::new(move |_| {quit_stopper.stop();})),
+        BarItemSpec::Button(button(&text), Box::new(move |_| {
+            populate(bar_copy.clone(), count+1, stopper.clone())
+        })),
+        BarItemSpec::Popover(button("Popbar1"), vec![
+            BarItemSpec::Button(button("1 level deep"), Box::new(move |_| {})),
+            BarItemSpec::Popover(button("Popbar2"), vec![
+                BarItemSpec::Button(button("2 levels deep"), Box::new(move |_| {})),
+            ]),
+            BarItemSpec::Slider(SliderOptions {
+                min: 0.0,
+                max: 50.0,
+                label: Some("Slide".to_string()),
+                ..Default::default()
+            }, Box::new(move |_s,v| {info!("Slid to: {}", v);})).named("slider1"),
+            BarItemSpec::Scrubber(scrubber.clone(), ScrubberOptions::default()).named("scrubber2"),
+        ]),
+        BarItemSpec::Label("This is a label".to_string()).named("label1"),
+        BarItemSpec::Scrubber(scrubber.clone(), ScrubberOptions::default()).named("scrubber1"),
+    ]);
+
+    // Finish setting up the items that were given names
+    let label1_id = items["label1"];
     tb.update_label(&label1_id, "This is a label\nWith two rows");
     tb.update_label_width(&label1_id, 100);
+    tb.select_scrubber_item(&items["scrubber1"], 1);
+    tb.select_scrubber_item(&items["scrubber2"], 3);
+    tb.update_slider(&items["slider1"], 15.0);
 
     // Support double-clicking the label with one finger
     tb.add_item_tap_gesture(&label1_id, 2, 1, Box::new(move |_item| {
@@ -89,7 +117,7 @@ fn populate(bar_rc: Rc<RefCell<Touchbar>>, count: u32, stopper: fruitbasket::Fru
         let color: f64 = match translation.abs().trunc() as u32 {
             t if t < 10 => 1.0,
             t if t > 100 => 0.0,
-            _ => (45. / translation.abs()),
+            _ => 45. / translation.abs(),
         };
         let rgba = match state {
             SwipeState::Ended => (1.0, 1.0, 1.0, 1.0),
@@ -103,51 +131,6 @@ fn populate(bar_rc: Rc<RefCell<Touchbar>>, count: u32, stopper: fruitbasket::Fru
         unsafe { rubrail::util::set_text_color(item, rgba.0, rgba.1, rgba.2, rgba.3); }
     }));
 
-    // Create a data backend for scrolling text "scrubbers"
-    let scrubber = Rc::new(TouchbarHandler {
-        devices: RefCell::new(vec![
-            "one".to_string(), "two".to_string(),
-            "a little bit longer one".to_string(),
-            "three".to_string(),
-            "this one is really quite a bit longer than the others".to_string()]),
-    });
-
-    // Create a scrubber for the root bar
-    let scrubber1_id = tb.create_text_scrubber(scrubber.clone());
-    tb.select_scrubber_item(&scrubber1_id, 1);
-
-    // Create a 'popbar', a second level deep bar
-    let mut popbar1_id = tb.create_bar();
-    let popbutton1_id = tb.create_popover_item(None, Some("Popbar1"), &popbar1_id);
-
-    // Create another scrubber with the same data, for the popbar.
-    // Note that the data and callbacks are shared, but this is a different
-    // instance and can store a different active selection.
-    let scrubber2_id = tb.create_text_scrubber(scrubber.clone());
-    tb.select_scrubber_item(&scrubber2_id, 3);
-
-    // Create a slider for the popbar.
-    let slider1_id = tb.create_slider(0.0, 50.0, Some("Slide"), true,
-                                      Box::new(move |_s,v| {info!("Slid to: {}", v);}));
-    tb.update_slider(&slider1_id, 15.0);
-
-    // Create a another popbar.  This will make a 2-level deep UI.
-    let mut popbar2_id = tb.create_bar();
-    let popbutton2_id = tb.create_popover_item(None, Some("Popbar2"), &popbar2_id);
-
-    // Create buttons to display on the popbars
-    let popbar_button_id = tb.create_button(None, Some("1 level deep"), Box::new(move |_| {}));
-    let deep_button_id = tb.create_button(None, Some("2 levels deep"), Box::new(move |_| {}));
-
-    // Layout the deepest (2-level) popbar
-    tb.add_items_to_bar(&mut popbar2_id, vec![deep_button_id]);
-
-    // Layout the middle (1-level) popbar
-    tb.add_items_to_bar(&mut popbar1_id, vec![popbar_button_id, popbutton2_id, slider1_id, scrubber2_id]);
-
-    // Layout the root bar
-    tb.add_items_to_bar(&mut barid, vec![quit_id, button1_id, popbutton1_id, label1_id, scrubber1_id]);
-
     // Register the root bar and display it.
     tb.set_bar_as_root(barid);
 }
@@ -0,0 +1,159 @@
+//! Micro-benchmarks of item update throughput
+//!
+//! Only built with the `benchmark` feature.  `benchmark_updates()` measures
+//! how quickly a controller can update labels, sliders, and scrubbers, and
+//! how long it takes for a button press to reach its callback.  Run it
+//! against the real backend, from inside an app bundle on the main thread, to
+//! get meaningful numbers; the 'dummy' backend only measures the overhead of
+//! the calls themselves.
+//!
+//! The same measurements are available as a `cargo bench` target:
+//!
+//! `$ cargo bench --features benchmark`
+//!
+//! The items created by a benchmark are never added to a bar, and are
+//! destroyed when it finishes.
+
+use std::cell::Cell;
+use std::fmt;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use interface::*;
+
+/// Results of `benchmark_updates()`
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct BenchmarkReport {
+    /// Number of updates of each kind that were timed
+    pub iterations: u32,
+    /// Label text changes per second
+    pub label_updates_per_sec: f64,
+    /// Slider value changes per second
+    pub slider_updates_per_sec: f64,
+    /// Scrubber refreshes per second
+    pub scrubber_refreshes_per_sec: f64,
+    /// Average time from a button press to its callback running
+    ///
+    /// `None` if presses can't be simulated, as with the 'dummy' backend.
+    pub callback_latency: Option<Duration>,
+}
+
+impl fmt::Display for BenchmarkReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "iterations:         {}", self.iterations)?;
+        writeln!(f, "label updates:      {:.0}/s", self.label_updates_per_sec)?;
+        writeln!(f, "slider updates:     {:.0}/s", self.slider_updates_per_sec)?;
+        writeln!(f, "scrubber refreshes: {:.0}/s", self.scrubber_refreshes_per_sec)?;
+        match self.callback_latency {
+            Some(latency) => write!(f, "callback latency:   {:?}", latency),
+            None => write!(f, "callback latency:   unavailable"),
+        }
+    }
+}
+
+struct BenchmarkScrubber;
+
+impl TScrubberData for BenchmarkScrubber {
+    fn count(&self, _item: ItemId) -> u32 { 20 }
+    fn text(&self, _item: ItemId, idx: u32) -> String { format!("item {}", idx) }
+    fn width(&self, _item: ItemId, _idx: u32) -> u32 { 50 }
+    fn touch(&self, _item: ItemId, _idx: u32) {}
+}
+
+fn per_sec(iterations: u32, elapsed: Duration) -> f64 {
+    let secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+    match secs > 0. {
+        true => iterations as f64 / secs,
+        false => f64::INFINITY,
+    }
+}
+
+fn time<F: FnMut(u32)>(iterations: u32, mut f: F) -> f64 {
+    let start = Instant::now();
+    for i in 0..iterations {
+        f(i);
+    }
+    per_sec(iterations, start.elapsed())
+}
+
+#[cfg(all(target_os = "macos", any(feature = "private_api", feature = "public_api")))]
+fn press(button: &ItemId) -> bool {
+    use objc::runtime::Object;
+    unsafe {
+        let view: *mut Object = msg_send![button.as_raw() as *mut Object, view];
+        let _:() = msg_send![view, performClick: 0u64];
+    }
+    true
+}
+
+#[cfg(not(all(target_os = "macos", any(feature = "private_api", feature = "public_api"))))]
+fn press(_button: &ItemId) -> bool {
+    false
+}
+
+/// Measure item update throughput and callback dispatch latency
+///
+/// Creates a label, slider, scrubber, and button, and times `iterations`
+/// updates of each.  Must be called on the main thread.
+///
+/// # Arguments
+///
+/// * `tb` - Touch Bar controller to benchmark
+/// * `iterations` - Number of updates of each kind to time
+///
+/// # Returns
+///
+/// The measured rates and latency
+pub fn benchmark_updates<T: TTouchbar>(tb: &mut T, iterations: u32) -> BenchmarkReport {
+    let label = tb.create_label("benchmark");
+    let label_updates_per_sec = time(iterations, |i| {
+        tb.update_label(&label, &i.to_string());
+    });
+
+    let options = SliderOptions { max: iterations as f64, ..SliderOptions::default() };
+    let slider = tb.new_slider(options, Box::new(|_, _| {}));
+    let slider_updates_per_sec = time(iterations, |i| {
+        tb.update_slider(&slider, i as f64);
+    });
+
+    let scrubber = tb.new_scrubber(Rc::new(BenchmarkScrubber), ScrubberOptions::default());
+    let scrubber_refreshes_per_sec = time(iterations, |_| {
+        tb.refresh_scrubber(&scrubber);
+    });
+
+    let pressed = Rc::new(Cell::new(None));
+    let cb_pressed = pressed.clone();
+    let options = ButtonOptions { text: Some("benchmark".to_string()), ..ButtonOptions::default() };
+    let button = tb.new_button(options, Box::new(move |_| {
+        cb_pressed.set(Some(Instant::now()));
+    }));
+    let mut total = Duration::new(0, 0);
+    let mut presses = 0;
+    for _ in 0..iterations {
+        pressed.set(None);
+        let start = Instant::now();
+        if !press(&button) {
+            break;
+        }
+        if let Some(end) = pressed.get() {
+            total += end.duration_since(start);
+            presses += 1;
+        }
+    }
+    let callback_latency = match presses {
+        0 => None,
+        n => Some(total / n),
+    };
+
+    for item in &[label, slider, scrubber, button] {
+        tb.destroy_item(*item);
+    }
+
+    BenchmarkReport {
+        iterations,
+        label_updates_per_sec,
+        slider_updates_per_sec,
+        scrubber_refreshes_per_sec,
+        callback_latency,
+    }
+}
@@ -1,7 +1,7 @@
 #[allow(dead_code)]
 use super::interface::*;
 
-///
+/// No-op Touch Bar controller for platforms without a Touch Bar
 pub struct DummyTouchbar {}
 
 #[allow(dead_code)]
@@ -10,17 +10,63 @@ impl TTouchbar for DummyTouchbar {
     fn alloc(_title: &str) -> DummyTouchbar { DummyTouchbar {} }
 }
 
+/// Stand-ins for the Mac utility functions
 ///
+/// Colors and resources are recorded per thread instead of displayed, so code
+/// using these helpers can be unit tested on any platform.
 pub mod util {
     use super::ItemId;
-    ///
+    use std::cell::RefCell;
+    use std::collections::BTreeMap;
+
+    type Color = (f64, f64, f64, f64);
+
+    thread_local! {
+        static BG_COLORS: RefCell<BTreeMap<ItemId, Color>> = const { RefCell::new(BTreeMap::new()) };
+        static TEXT_COLORS: RefCell<BTreeMap<ItemId, Color>> = const { RefCell::new(BTreeMap::new()) };
+        static RESOURCES: RefCell<BTreeMap<(String, String), String>> = const { RefCell::new(BTreeMap::new()) };
+    }
+
+    /// Does nothing
     pub fn print_nsstring(_str: *mut u64) {}
-    ///
+    /// Always returns an empty string
     pub fn nsstring_decode(_str: *mut u64) -> String { String::new() }
+    /// Returns the path registered with `register_bundled_resource()`, if any
+    pub fn bundled_resource_path(name: &str, extension: &str) -> Option<String> {
+        RESOURCES.with(|r| r.borrow().get(&(name.to_string(), extension.to_string())).cloned())
+    }
+    /// Register a path to be returned by `bundled_resource_path()`
+    pub fn register_bundled_resource(name: &str, extension: &str, path: &str) {
+        RESOURCES.with(|r| {
+            r.borrow_mut().insert((name.to_string(), extension.to_string()), path.to_string());
+        });
+    }
+    /// Records the color, retrievable with `last_bg_color()`
+    ///
+    /// # Safety
     ///
-    pub fn bundled_resource_path(_name: &str, _extension: &str) -> Option<String> { None }
+    /// Always safe.  Marked unsafe to match the Mac implementation.
+    pub unsafe fn set_bg_color(item: &ItemId, r: f64, g: f64, b: f64, alpha: f64) {
+        BG_COLORS.with(|c| { c.borrow_mut().insert(*item, (r, g, b, alpha)); });
+    }
+    /// Records the color, retrievable with `last_text_color()`
     ///
-    pub unsafe fn set_bg_color(_item: &ItemId, _r: f64, _g: f64, _b: f64, _alpha: f64) { }
+    /// # Safety
     ///
-    pub unsafe fn set_text_color(_item: &ItemId, _r: f64, _g: f64, _b: f64, _alpha: f64) { }
+    /// Always safe.  Marked unsafe to match the Mac implementation.
+    pub unsafe fn set_text_color(item: &ItemId, r: f64, g: f64, b: f64, alpha: f64) {
+        TEXT_COLORS.with(|c| { c.borrow_mut().insert(*item, (r, g, b, alpha)); });
+    }
+    /// Last color passed to `set_bg_color()` for an item, as (r, g, b, alpha)
+    pub fn last_bg_color(item: &ItemId) -> Option<(f64, f64, f64, f64)> {
+        BG_COLORS.with(|c| c.borrow().get(item).cloned())
+    }
+    /// Last color passed to `set_text_color()` for an item, as (r, g, b, alpha)
+    pub fn last_text_color(item: &ItemId) -> Option<(f64, f64, f64, f64)> {
+        TEXT_COLORS.with(|c| c.borrow().get(item).cloned())
+    }
+    /// Always returns `false`
+    pub fn focus_mode_active() -> bool { false }
+    /// Always returns `false`
+    pub fn dark_mode_active() -> bool { false }
 }
@@ -1,26 +1,75 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
 use std::rc::Rc;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
 
-/// Reference to a horizontal bar created by a `TTouchbar`
-///
-/// A `BarId` is returned any time a bar is created, where a bar is a horizontal
-/// view that can have smaller UI elements added to it, and can eventually be
-/// displayed on the Touch Bar hardware.
-///
-/// The two types of bars are the _root bar_, which appears when you first open
-/// the Touch Bar by clicking on its icon in the Control Strip, and _popover
-/// bars_, which behave identically but are presented by clicking on buttons
-/// on a bar or popover.  Popovers provide the mechanism for recursive menus.
-///
-/// # Memory Allocation
-///
-/// Memory is allocated for a bar when it is created, and is not released until
-/// the bar is made the root bar, or a popover of the root bar, _enabled_, _and
-/// subsequently replaced by another bar_.  That is, memory is deallocated
-/// recursively when an available Touch Bar menu is replaced.  If a menu is
-/// never registered as the active menu, then it will _never be deallocated_.
-/// `BarId` does _not_ implement the Drop trait, and does _not_ deallocate any
-/// memory when it falls out of scope.
-pub type BarId = u64;
+// Define a handle wrapping a raw Objective-C pointer, so different kinds of
+// handles can't be mixed up
+macro_rules! objc_handle {
+    ($(#[$attr:meta])* $name:ident) => {
+        $(#[$attr])*
+        #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy, Default)]
+        pub struct $name(u64);
+
+        impl $name {
+            /// Wrap a raw Objective-C pointer
+            ///
+            /// Only pointers previously returned by `as_raw()` should be
+            /// wrapped.
+            pub fn from_raw(raw: u64) -> $name {
+                $name(raw)
+            }
+
+            /// The underlying Objective-C pointer, for raw Objective-C calls
+            pub fn as_raw(&self) -> u64 {
+                self.0
+            }
+        }
+
+        impl From<u64> for $name {
+            fn from(raw: u64) -> $name {
+                $name(raw)
+            }
+        }
+
+        impl From<$name> for u64 {
+            fn from(handle: $name) -> u64 {
+                handle.0
+            }
+        }
+    }
+}
+
+objc_handle! {
+    /// Reference to a horizontal bar created by a `TTouchbar`
+    ///
+    /// A `BarId` is returned any time a bar is created, where a bar is a horizontal
+    /// view that can have smaller UI elements added to it, and can eventually be
+    /// displayed on the Touch Bar hardware.
+    ///
+    /// The two types of bars are the _root bar_, which appears when you first open
+    /// the Touch Bar by clicking on its icon in the Control Strip, and _popover
+    /// bars_, which behave identically but are presented by clicking on buttons
+    /// on a bar or popover.  Popovers provide the mechanism for recursive menus.
+    ///
+    /// # Memory Allocation
+    ///
+    /// Memory is allocated for a bar when it is created, and is not released until
+    /// the bar is made the root bar, or a popover of the root bar, _enabled_, _and
+    /// subsequently replaced by another bar_.  That is, memory is deallocated
+    /// recursively when an available Touch Bar menu is replaced.  If a menu is
+    /// never registered as the active menu, then it will _never be deallocated_,
+    /// unless it is freed explicitly with `destroy_bar()`.
+    /// `BarId` does _not_ implement the Drop trait, and does _not_ deallocate any
+    /// memory when it falls out of scope.  Wrap it with `own_bar()` for a handle
+    /// that does.
+    BarId
+}
 
 /// Reference to an item that can be added to a bar created by a `TTouchbar`
 ///
@@ -33,9 +82,229 @@ pub type BarId = u64;
 /// parent bar that owns it is released.  This means it follows the same memory
 /// management cycle as `BarId` -- items are not released unless they are
 /// assigned to a bar, that bar is registered as the root bar, and then that bar
-/// is replaced.  `ItemId` does not implement the Drop trait, and does _not_
-/// deallocate memory when it falls out of scope.
-pub type ItemId = u64;
+/// is replaced.  Items that will never be shown can be freed explicitly with
+/// `destroy_item()`.  `ItemId` does not implement the Drop trait, and does
+/// _not_ deallocate memory when it falls out of scope.  Wrap it with
+/// `own_item()` for a handle that does.
+///
+/// # Stale Items
+///
+/// Each item is stamped with a generation when it is created.  Using an
+/// item through an `ItemId` after the item was freed reports
+/// `TouchbarError::StaleItem` and does nothing, even if a newer item has
+/// since been allocated at the same address.  Two `ItemId`s compare equal if
+/// they refer to the same address, regardless of generation.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ItemId {
+    raw: u64,
+    generation: u32,
+}
+
+impl ItemId {
+    /// Wrap a raw Objective-C pointer
+    ///
+    /// Only pointers previously returned by `as_raw()` should be wrapped.  The
+    /// wrapped id has no generation, so it takes the generation of whichever
+    /// item is currently allocated at that address.
+    pub fn from_raw(raw: u64) -> ItemId {
+        ItemId { raw, generation: 0 }
+    }
+
+    /// Wrap a raw Objective-C pointer to an item created in `generation`
+    ///
+    /// Used by `TTouchbar` implementations when creating items.
+    pub fn with_generation(raw: u64, generation: u32) -> ItemId {
+        ItemId { raw, generation }
+    }
+
+    /// The underlying Objective-C pointer, for raw Objective-C calls
+    pub fn as_raw(&self) -> u64 {
+        self.raw
+    }
+
+    /// Generation the item was created in, or 0 if unknown
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+impl PartialEq for ItemId {
+    fn eq(&self, other: &ItemId) -> bool {
+        self.raw == other.raw
+    }
+}
+
+impl Eq for ItemId {}
+
+impl PartialOrd for ItemId {
+    fn partial_cmp(&self, other: &ItemId) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ItemId {
+    fn cmp(&self, other: &ItemId) -> Ordering {
+        self.raw.cmp(&other.raw)
+    }
+}
+
+impl Hash for ItemId {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.raw.hash(state);
+    }
+}
+
+impl From<u64> for ItemId {
+    fn from(raw: u64) -> ItemId {
+        ItemId::from_raw(raw)
+    }
+}
+
+impl From<ItemId> for u64 {
+    fn from(handle: ItemId) -> u64 {
+        handle.raw
+    }
+}
+
+/// An `ItemId` that frees its item when dropped
+///
+/// Returned by `TTouchbar::own_item()`.  Dropping the handle frees the item,
+/// unless it is in a bar at the time, in which case it is freed along with
+/// the bar as usual.  Items are freed on the next pass of the application's
+/// run loop, so a handle can safely be dropped from inside a callback.
+/// Dropping a handle after the `Touchbar` that created it does nothing.
+///
+/// The handle dereferences to its `ItemId`, so it can be passed to any
+/// function taking `&ItemId`.
+pub struct OwnedItem {
+    id: ItemId,
+    release: Option<Box<dyn FnOnce()>>,
+}
+
+impl OwnedItem {
+    /// Wrap an `ItemId`, calling `release` when the handle is dropped
+    ///
+    /// Used by `TTouchbar` implementations.  Applications should call
+    /// `TTouchbar::own_item()` instead.
+    pub fn new(id: ItemId, release: Box<dyn FnOnce()>) -> OwnedItem {
+        OwnedItem { id, release: Some(release) }
+    }
+
+    /// Give up ownership, returning the `ItemId` without freeing the item
+    pub fn into_id(mut self) -> ItemId {
+        self.release = None;
+        self.id
+    }
+}
+
+impl Deref for OwnedItem {
+    type Target = ItemId;
+    fn deref(&self) -> &ItemId {
+        &self.id
+    }
+}
+
+impl Drop for OwnedItem {
+    fn drop(&mut self) {
+        if let Some(release) = self.release.take() {
+            release();
+        }
+    }
+}
+
+/// A `BarId` that frees its bar when dropped
+///
+/// Returned by `TTouchbar::own_bar()`.  Dropping the handle frees the bar and
+/// its items, unless it is a root bar or is opened by a popover at the time,
+/// in which case it is freed along with them as usual.  Bars are freed on the
+/// next pass of the application's run loop, so a handle can safely be
+/// dropped from inside a callback.  Dropping a handle after the `Touchbar`
+/// that created it does nothing.
+///
+/// The handle dereferences to its `BarId`, so it can be passed to any
+/// function taking `&BarId`.
+pub struct OwnedBar {
+    id: BarId,
+    release: Option<Box<dyn FnOnce()>>,
+}
+
+impl OwnedBar {
+    /// Wrap a `BarId`, calling `release` when the handle is dropped
+    ///
+    /// Used by `TTouchbar` implementations.  Applications should call
+    /// `TTouchbar::own_bar()` instead.
+    pub fn new(id: BarId, release: Box<dyn FnOnce()>) -> OwnedBar {
+        OwnedBar { id, release: Some(release) }
+    }
+
+    /// Give up ownership, returning the `BarId` without freeing the bar
+    pub fn into_id(mut self) -> BarId {
+        self.release = None;
+        self.id
+    }
+}
+
+impl Deref for OwnedBar {
+    type Target = BarId;
+    fn deref(&self) -> &BarId {
+        &self.id
+    }
+}
+
+impl Drop for OwnedBar {
+    fn drop(&mut self) {
+        if let Some(release) = self.release.take() {
+            release();
+        }
+    }
+}
+
+/// Memory audit of a `TTouchbar`, returned by `allocation_report()`
+///
+/// Intended for integration tests, which can assert that an application
+/// doesn't leak Touch Bar objects with `assert!(tb.allocation_report().is_clean())`
+/// after tearing down its bars.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct AllocationStats {
+    /// Live Objective-C objects of each of Rubrail's `RR*` classes, by class
+    /// name
+    ///
+    /// Objects are only counted when compiled with the `objc_wrapper`
+    /// feature, and this is empty otherwise.
+    pub live_objects: BTreeMap<String, usize>,
+    /// Items not in the root bar or any popover reachable from it
+    ///
+    /// These are never freed unless they are added to a bar that becomes the
+    /// root bar, or are freed explicitly.  Items that are hidden from the
+    /// current root bar but kept for later are also listed.
+    pub unattached_items: Vec<ItemId>,
+    /// Images that were created but never given to an item
+    pub unused_images: Vec<TouchbarImage>,
+}
+
+impl AllocationStats {
+    /// Whether nothing is leaked: no live objects, unattached items or unused
+    /// images
+    ///
+    /// Live objects are only counted with the `objc_wrapper` feature.  Call
+    /// after the root bar has been hidden or freed, since its objects are
+    /// otherwise still alive.
+    pub fn is_clean(&self) -> bool {
+        self.live_objects.values().all(|count| *count == 0) &&
+            self.unattached_items.is_empty() &&
+            self.unused_images.is_empty()
+    }
+}
+
+objc_handle! {
+    /// Reference to an additional Control Strip item of a `TTouchbar`
+    ///
+    /// Every controller has one Control Strip item, whose root bar is set with
+    /// `set_bar_as_root()`.  A `TrayId` is returned when another item is added
+    /// with `create_control_strip_item()`, and refers to that item until it is
+    /// removed.
+    TrayId
+}
 
 /// A callback that is called when a button on a Touch Bar is pressed
 ///
@@ -47,6 +316,84 @@ pub type ItemId = u64;
 /// * first - `ItemId` of the button that was pressed
 pub type ButtonCb = Box<dyn Fn(&ItemId)>;
 
+objc_handle! {
+    /// Reference to the control inside of a Touch Bar item
+    ///
+    /// Items are containers around a single control, such as a button, text
+    /// field, or scrubber.  A `ControlId` refers to that inner control, which is
+    /// what raw Objective-C extensions typically need to talk to.
+    ControlId
+}
+
+/// Details of a touch interaction with an item
+///
+/// An `ItemEvent` is delivered to `EventCb` callbacks, and carries both the
+/// item that was touched and the control inside of it, so handlers do not need
+/// to look one up from the other.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct ItemEvent {
+    /// `ItemId` of the item that was touched
+    pub item: ItemId,
+    /// `ControlId` of the control inside the item that received the touch
+    pub control: ControlId,
+}
+
+/// A callback that is called when an item is touched, with full event details
+///
+/// `EventCb` is an alternative to `ButtonCb` for handlers that need more than
+/// the `ItemId`.  It is expected to be a Boxed closure.
+///
+/// # Arguments
+///
+/// * first - `ItemEvent` describing the item and control that were touched
+pub type EventCb = Box<dyn Fn(&ItemEvent)>;
+
+/// A callback that builds the bar of a lazy popover
+///
+/// Typically captures a shared reference to the `Touchbar`, creates a new bar
+/// with fresh contents, and returns it.
+///
+/// # Arguments
+///
+/// * first - `ItemId` of the popover being opened
+///
+/// # Returns
+///
+/// The newly created bar to show in the popover
+pub type PopoverBuildCb = Box<dyn Fn(&ItemId) -> BarId>;
+
+/// A callback that is called when an option is chosen from a dropdown button
+///
+/// # Arguments
+///
+/// * first - `ItemId` of the dropdown button
+/// * second - Index of the chosen option
+pub type DropdownCb = Box<dyn Fn(&ItemId, u32)>;
+
+/// A callback that is called when a segment of a picker is selected
+///
+/// # Arguments
+///
+/// * first - `ItemId` of the picker
+/// * second - Index of the selected segment
+pub type PickerCb = Box<dyn Fn(&ItemId, u32)>;
+
+/// A callback that is called when the rating of a rating item is changed
+///
+/// # Arguments
+///
+/// * first - `ItemId` of the rating item
+/// * second - New rating, from 0 to the number of stars
+pub type RatingCb = Box<dyn Fn(&ItemId, u32)>;
+
+/// A callback that is called when a media control button is pressed
+///
+/// # Arguments
+///
+/// * first - `ItemId` of the media controls item
+/// * second - Which button was pressed
+pub type MediaCb = Box<dyn Fn(&ItemId, MediaEvent)>;
+
 /// A callback that is called when the value of a slide on a Touch Bar changes
 ///
 /// 'SliderCb' is expected to be a Boxed closure, and it receives the `ItemId`
@@ -58,6 +405,52 @@ pub type ButtonCb = Box<dyn Fn(&ItemId)>;
 /// * second - Current value of the slider
 pub type SliderCb = Box<dyn Fn(&ItemId, f64)>;
 
+/// A callback that is called when the date of a date picker changes
+///
+/// `DateCb` is expected to be a Boxed closure, and it receives the `ItemId`
+/// of the date picker that changed, and the newly selected date and time.
+///
+/// # Arguments
+///
+/// * first - `ItemId` of the date picker that was changed
+/// * second - Newly selected date and time
+pub type DateCb = Box<dyn Fn(&ItemId, SystemTime)>;
+
+/// A callback that is called when the text of a text field is edited
+///
+/// `TextCb` is expected to be a Boxed closure, and it receives the `ItemId`
+/// of the text field that was edited, and its complete new text.
+///
+/// # Arguments
+///
+/// * first - `ItemId` of the text field that was edited
+/// * second - Current text of the text field
+pub type TextCb = Box<dyn Fn(&ItemId, &str)>;
+
+/// A callback that draws the contents of a canvas item
+///
+/// `CanvasCb` is expected to be a Boxed closure.  It receives the `ItemId` of
+/// the canvas being drawn and the dimensions of the image to draw, and returns
+/// the image as raw pixels.
+///
+/// The returned buffer must contain exactly `width * height` pixels, each as
+/// four bytes in RGBA order, row by row starting from the top-left corner.
+///
+/// # Arguments
+///
+/// * first - `ItemId` of the canvas being drawn
+/// * second - Width of the image, in pixels
+/// * third - Height of the image, in pixels
+pub type CanvasCb = Box<dyn Fn(&ItemId, u32, u32) -> Vec<u8>>;
+
+/// A callback that is called when an item is given a new width
+///
+/// # Arguments
+///
+/// * first - `ItemId` of the resized item
+/// * second - New width of the item on the bar, in pixels
+pub type ResizeCb = Box<dyn Fn(&ItemId, u32)>;
+
 /// A callback that is called when an item is swiped
 ///
 /// `SwipeCb` is expected to be a Boxed closure, and it receives the
@@ -72,15 +465,136 @@ pub type SliderCb = Box<dyn Fn(&ItemId, f64)>;
 ///   negative is left).
 pub type SwipeCb = Box<dyn Fn(&ItemId, SwipeState, f64)>;
 
-/// An allocated image that can be added to items
+/// A callback that is called when a gesture is detected on an item
 ///
-/// A `TouchbarImage` can be created from a path to a file or from a standard
-/// Apple template image, and then registered with Touch Bar items that support
-/// images, such as buttons and popovers.
-pub type TouchbarImage = u64;
+/// `GestureCb` is expected to be a Boxed closure, and it receives a
+/// `GestureEvent` describing the gesture each time its state changes.
+///
+/// # Arguments
+///
+/// * first - `GestureEvent` describing the item, gesture, and its progress
+pub type GestureCb = Box<dyn Fn(&GestureEvent)>;
+
+/// A callback that is called when macOS Focus (Do Not Disturb) changes
+///
+/// # Arguments
+///
+/// * first - Whether Focus is now active
+pub type FocusCb = Box<dyn Fn(bool)>;
+
+/// A callback that is called when the root bar is opened or closed
+///
+/// # Arguments
+///
+/// * first - Whether the root bar is now on screen
+pub type VisibilityCb = Box<dyn Fn(bool)>;
+
+/// A callback that performs one step of an incremental bar construction
+///
+/// Typically captures a shared reference to the `Touchbar` and creates a small
+/// batch of items per call.
+///
+/// # Arguments
+///
+/// * first - Index of this step, starting at 0
+///
+/// # Returns
+///
+/// `true` if there are more steps to run, `false` when construction is done
+pub type BuildStepCb = Box<dyn FnMut(usize) -> bool>;
+
+/// A callback that is called when the Touch Bar controller hits an error
+///
+/// Errors are detected asynchronously, often while the Touch Bar is being
+/// presented, so they are reported through this callback instead of as return
+/// values.
+///
+/// # Arguments
+///
+/// * first - The error that occurred
+pub type ErrorCb = Box<dyn Fn(&TouchbarError)>;
+
+/// Errors reported by the Touch Bar controller
+#[derive(PartialEq, Debug, Clone)]
+pub enum TouchbarError {
+    /// A Control Strip item with this identifier is already registered
+    ///
+    /// The system only shows one Control Strip item per identifier, so one of
+    /// the colliding icons silently disappears.
+    IdentifierCollision(String),
+    /// The Touch Bar asked for an item identifier that is not registered
+    ///
+    /// The slot is left empty.  This usually means an item was added to a bar
+    /// after being freed, or to a bar belonging to a different controller.
+    UnknownIdentifier(String),
+    /// An Objective-C class needed to create an item is missing
+    ///
+    /// The item is not created, but the rest of the bar still works.  This
+    /// happens when running on an OS version that lacks the class.
+    ClassUnavailable(String),
+    /// An image could not be loaded from the given location
+    ImageUnavailable(String),
+    /// An item can't be shown in the bar it was added to
+    ///
+    /// The item is left out of the bar instead of leaving an empty slot.
+    /// This happens when a popover is added to its own popover bar, directly
+    /// or through nested popovers, when an item is added to the same bar
+    /// twice, or when a popover bar starts or ends with spacers.
+    UnsupportedItem(String),
+    /// A layout description or item kind couldn't be parsed, at the given line
+    InvalidLayout(String),
+    /// An item was used after being freed
+    ///
+    /// The call is ignored instead of sending messages to the freed object.
+    /// This usually means an `ItemId` was kept after its bar was replaced.
+    StaleItem(ItemId),
+    /// An image was changed after being given to an item
+    ///
+    /// The call is ignored.  Items take ownership of the images they are
+    /// given, and free them along with the item.
+    StaleImage(TouchbarImage),
+}
+
+impl fmt::Display for TouchbarError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TouchbarError::IdentifierCollision(ref ident) =>
+                write!(f, "Control Strip identifier already registered: {}", ident),
+            TouchbarError::UnknownIdentifier(ref ident) =>
+                write!(f, "Touch Bar requested unknown item identifier: {}", ident),
+            TouchbarError::ClassUnavailable(ref name) =>
+                write!(f, "Objective-C class unavailable: {}", name),
+            TouchbarError::ImageUnavailable(ref location) =>
+                write!(f, "Failed to load image: {}", location),
+            TouchbarError::UnsupportedItem(ref reason) =>
+                write!(f, "Item not supported in bar: {}", reason),
+            TouchbarError::InvalidLayout(ref line) =>
+                write!(f, "Invalid layout description: {}", line),
+            TouchbarError::StaleItem(ref item) =>
+                write!(f, "Item used after being freed: <{:x}>", item.as_raw()),
+            TouchbarError::StaleImage(ref image) =>
+                write!(f, "Image used after being given to an item: <{:x}>", image.as_raw()),
+        }
+    }
+}
+
+impl error::Error for TouchbarError {}
+
+objc_handle! {
+    /// An allocated image that can be added to items
+    ///
+    /// A `TouchbarImage` can be created from a path to a file or from a standard
+    /// Apple template image, and then registered with Touch Bar items that support
+    /// images, such as buttons and popovers.
+    TouchbarImage
+}
 
 /// State of the current swipe gesture on an item
-#[derive(PartialEq, Debug)]
+///
+/// Also used to report the lifecycle of any gesture registered with
+/// `add_custom_gesture()`.  Discrete gestures, like clicks, are only reported
+/// once, as `Ended`.
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum SwipeState {
     /// Swipe gesture is newly detected (finger touched)
     Began,
@@ -99,6 +613,75 @@ pub enum SwipeState {
     Unknown,
 }
 
+/// Types of gestures that can be detected on an item
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum GestureKind {
+    /// One or more taps
+    Click,
+    /// A touch held in place for a minimum duration
+    Press,
+    /// A finger sliding across the item
+    ///
+    /// `GestureEvent::value` is the horizontal translation, in pixels.
+    Pan,
+    /// A pinch
+    ///
+    /// `GestureEvent::value` is the magnification, where 0.0 is unchanged.
+    Magnify,
+    /// Two fingers rotating around each other
+    ///
+    /// `GestureEvent::value` is the rotation, in radians.
+    Rotate,
+}
+
+/// Options for gestures registered with `add_custom_gesture()`
+///
+/// Options that don't apply to a particular `GestureKind` are ignored.  Start
+/// from `GestureConfig::default()` and change only the fields you need.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct GestureConfig {
+    /// Number of simultaneous fingers needed (`Click`, `Press`, `Pan`)
+    pub fingers: u32,
+    /// Number of discrete taps needed (`Click`)
+    pub clicks: u32,
+    /// How long a touch must be held (`Press`)
+    pub min_duration: Duration,
+}
+
+impl Default for GestureConfig {
+    fn default() -> GestureConfig {
+        GestureConfig {
+            fingers: 1,
+            clicks: 1,
+            min_duration: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Details of a gesture detected on an item
+///
+/// Delivered to `GestureCb` callbacks each time the gesture changes state.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct GestureEvent {
+    /// `ItemId` of the item the gesture was detected on
+    pub item: ItemId,
+    /// `ControlId` of the control inside the item that received the gesture
+    pub control: ControlId,
+    /// Type of gesture that was detected
+    pub kind: GestureKind,
+    /// Lifecycle state of the gesture
+    pub state: SwipeState,
+    /// Gesture-specific measurement.  See `GestureKind` for meanings.
+    ///
+    /// This is cumulative over the whole gesture.
+    pub value: f64,
+    /// Change in `value` since the previous event for this gesture
+    ///
+    /// Lets handlers apply incremental adjustments without keeping track of
+    /// the last value themselves.  Equal to `value` on the first event.
+    pub delta: f64,
+}
+
 /// Identifiers for Apple's standard button image templates
 #[allow(missing_docs)]
 pub enum ImageTemplate {
@@ -179,6 +762,7 @@ pub enum ImageTemplate {
 }
 
 /// Identifiers for the type of spacing available between items
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum SpacerType {
     /// "Small" space, defined by Apple
     Small,
@@ -188,6 +772,265 @@ pub enum SpacerType {
     Flexible
 }
 
+/// Which components of a date a date picker displays and edits
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum DatePickerElements {
+    /// Year, month, and day
+    Date,
+    /// Hours and minutes
+    Time,
+    /// Year, month, day, hours, and minutes
+    DateAndTime,
+}
+
+/// How an item is displayed while macOS Focus (Do Not Disturb) is active
+///
+/// Persistent bars are always one tap away, so items that exist to grab
+/// attention (notification counts, alert colors) can be toned down
+/// automatically while the user has asked not to be disturbed.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum FocusBehavior {
+    /// Item is unaffected by Focus
+    Normal,
+    /// Item is drawn partially transparent while Focus is active
+    Dim,
+    /// Item is hidden while Focus is active
+    Hide,
+}
+
+/// Vertical placement of a multi-line label's text within the bar
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum VerticalAlignment {
+    /// Lines start at the top of the bar
+    Top,
+    /// Lines are centered in the bar
+    Center,
+    /// Lines end at the bottom of the bar
+    Bottom,
+}
+
+/// Layout options for labels created with `create_multiline_label()`
+///
+/// Start from `LabelStyle::default()` and change only the fields you need.
+#[derive(PartialEq, Debug, Clone)]
+pub struct LabelStyle {
+    /// Font size of each line, in points
+    ///
+    /// Lines past the end of the list use the last size.  If empty, the
+    /// system font size is used for every line.
+    pub font_sizes: Vec<f64>,
+    /// Vertical placement of the lines
+    pub alignment: VerticalAlignment,
+}
+
+impl Default for LabelStyle {
+    fn default() -> LabelStyle {
+        LabelStyle {
+            font_sizes: vec![13., 10.],
+            alignment: VerticalAlignment::Center,
+        }
+    }
+}
+
+/// A run of styled text in a label
+///
+/// Used with `update_label_attributed()` to style parts of a label
+/// differently.  Start from `TextSpan::default()` and set the fields you need;
+/// unset options keep the label's normal style.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct TextSpan {
+    /// Text of this span
+    pub text: String,
+    /// Text color as red, green, blue, and alpha values (0.0 - 1.0)
+    pub color: Option<(f64, f64, f64, f64)>,
+    /// Whether the text is bold
+    pub bold: bool,
+    /// Font size, in points
+    pub font_size: Option<f64>,
+}
+
+/// What to return when an image file can't be loaded
+///
+/// Set with `set_missing_image_behavior()`.  Either way, the failure is logged
+/// with the resolved path and reported through `on_error()`.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum MissingImageBehavior {
+    /// Substitute a visible warning glyph, so the mistake shows on the bar
+    Placeholder,
+    /// Return an empty image handle, which displays nothing
+    Empty,
+}
+
+/// Which end of a slider an accessory button is attached to
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum SliderAccessory {
+    /// Accessory to the left of the slider, at its minimum value
+    Min,
+    /// Accessory to the right of the slider, at its maximum value
+    Max,
+}
+
+/// Buttons of a media controls item
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum MediaEvent {
+    /// Skip back to the previous track
+    Previous,
+    /// Toggle between playing and paused
+    PlayPause,
+    /// Skip ahead to the next track
+    Next,
+}
+
+/// Contents of one row of a stacked item
+///
+/// Each row is half the height of the bar, so controls are drawn at a small
+/// size.  Callbacks receive the `ItemId` of the stacked item.
+pub enum ItemSpec {
+    /// Static text
+    Label(String),
+    /// A button with an image, text, or both
+    Button {
+        /// Image shown on the button
+        image: Option<TouchbarImage>,
+        /// Text shown on the button
+        text: Option<String>,
+        /// Callback called when the button is pressed
+        cb: ButtonCb,
+    },
+    /// A horizontal slider
+    Slider {
+        /// Minimum value of the slider
+        min: f64,
+        /// Maximum value of the slider
+        max: f64,
+        /// Callback called when the slider changes
+        cb: SliderCb,
+    },
+}
+
+/// Kind of an item, as returned by `item_type()`
+///
+/// Items are identified by the function that created them.  Dropdowns are
+/// popovers, and menu rows are buttons.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ItemKind {
+    /// Created by `create_button()` or `create_menu_row()`
+    Button,
+    /// Created by `create_label()`
+    Label,
+    /// Created by `create_multiline_label()`
+    MultilineLabel,
+    /// Created by `create_countdown_label()`
+    Countdown,
+    /// Created by `create_slider()` and its variants
+    Slider,
+    /// Created by `create_knob()`
+    Knob,
+    /// Created by `create_text_scrubber()`
+    Scrubber,
+    /// Created by `create_popover_item()` or `create_dropdown_button()`
+    Popover,
+    /// Created by `create_spacer()`
+    Spacer,
+    /// Created by `create_date_picker()`
+    DatePicker,
+    /// Created by `create_text_field()`
+    TextField,
+    /// Created by `create_canvas()` or `create_full_width_canvas()`
+    Canvas,
+    /// Created by `create_graph()`
+    Graph,
+    /// Created by `create_passthrough_button()`
+    Passthrough,
+    /// Created by `create_picker()`
+    Picker,
+    /// Created by `create_rating()`
+    Rating,
+    /// Created by `create_media_controls()`
+    MediaControls,
+    /// Created by `create_stacked_item()`
+    Stacked,
+    /// Created by `create_now_playing_item()`
+    NowPlaying,
+}
+
+// Every kind, with its name as written in a `LayoutSpec`
+const ITEM_KIND_NAMES: &[(ItemKind, &str)] = &[
+    (ItemKind::Button, "Button"),
+    (ItemKind::Label, "Label"),
+    (ItemKind::MultilineLabel, "MultilineLabel"),
+    (ItemKind::Countdown, "Countdown"),
+    (ItemKind::Slider, "Slider"),
+    (ItemKind::Knob, "Knob"),
+    (ItemKind::Scrubber, "Scrubber"),
+    (ItemKind::Popover, "Popover"),
+    (ItemKind::Spacer, "Spacer"),
+    (ItemKind::DatePicker, "DatePicker"),
+    (ItemKind::TextField, "TextField"),
+    (ItemKind::Canvas, "Canvas"),
+    (ItemKind::Graph, "Graph"),
+    (ItemKind::Passthrough, "Passthrough"),
+    (ItemKind::Picker, "Picker"),
+    (ItemKind::Rating, "Rating"),
+    (ItemKind::MediaControls, "MediaControls"),
+    (ItemKind::Stacked, "Stacked"),
+    (ItemKind::NowPlaying, "NowPlaying"),
+];
+
+impl ItemKind {
+    /// Name of the kind, as written in a `LayoutSpec`
+    pub fn as_str(&self) -> &'static str {
+        ITEM_KIND_NAMES.iter().find(|&&(kind, _)| kind == *self).map(|&(_, name)| name).unwrap()
+    }
+}
+
+impl FromStr for ItemKind {
+    type Err = TouchbarError;
+    /// Parse a name returned by `as_str()`
+    fn from_str(s: &str) -> Result<ItemKind, TouchbarError> {
+        ITEM_KIND_NAMES.iter().find(|&&(_, name)| name == s).map(|&(kind, _)| kind)
+            .ok_or_else(|| TouchbarError::InvalidLayout(s.to_string()))
+    }
+}
+
+/// Scrolling behavior of a scrubber
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ScrubberMode {
+    /// Scrubber pages through its items, stopping on each one
+    ///
+    /// Good for precise pickers with a small number of options.
+    Fixed,
+    /// Scrubber scrolls freely with momentum
+    ///
+    /// Good for long lists that are flicked through quickly.
+    Free,
+}
+
+/// Scrolling mode assigned to newly created scrubbers
+///
+/// Change the mode of an individual scrubber with
+/// [`set_scrubber_mode`](trait.TTouchbar.html#method.set_scrubber_mode).
+pub const DEFAULT_SCRUBBER_MODE: ScrubberMode = ScrubberMode::Free;
+
+/// How a scrubber draws its selected item
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ScrubberStyle {
+    /// Outline drawn over the selected item
+    OutlineOverlay,
+    /// Rounded, filled background drawn behind the selected item
+    ///
+    /// Looks better than the outline on dark custom backgrounds.
+    RoundedBackground,
+    /// Selection is not drawn
+    None,
+}
+
+/// Selection style assigned to newly created scrubbers
+///
+/// Change the style of an individual scrubber with
+/// [`set_scrubber_style`](trait.TTouchbar.html#method.set_scrubber_style).
+pub const DEFAULT_SCRUBBER_STYLE: ScrubberStyle = ScrubberStyle::OutlineOverlay;
+
 /// The callback API for managing data in a Scrubber
 ///
 /// The Touch Bar supports a UI element called a 'scrubber', which is a
@@ -217,51 +1060,127 @@ pub trait TScrubberData {
     /// * `idx` - The index of the relevant item in the scrubber
     fn text(&self, item: ItemId, idx: u32) -> String;
 
-    /// Returns the width (in pixels) of the given index in the scrubber
+    /// Returns an image to display at the given index, instead of text
+    ///
+    /// Images are not freed by the scrubber, so return the same handle each
+    /// time an index is requested.  Images from `create_image_from_url()` are
+    /// shown once they finish downloading.  Defaults to showing text.
     ///
     /// # Arguments
     ///
     /// * `item` - The `ItemId` of the interacting scrubber
     /// * `idx` - The index of the relevant item in the scrubber
-    fn width(&self, item: ItemId, idx: u32) -> u32;
+    fn image(&self, item: ItemId, idx: u32) -> Option<TouchbarImage> { None }
 
-    /// Called when the given index in the scrubber is selected
+    /// Returns a small glyph to display before the text at the given index
+    ///
+    /// Useful for marking the active entry in a selector, such as with a
+    /// checkmark, without switching to an image scrubber.  Ignored for items
+    /// that return an image.  Defaults to no glyph.
     ///
     /// # Arguments
     ///
     /// * `item` - The `ItemId` of the interacting scrubber
     /// * `idx` - The index of the relevant item in the scrubber
-    fn touch(&self, item: ItemId, idx: u32);
-}
-
-/// API for creating, managing, and getting feedback from Touch Bar UIs
-///
-/// `TTouchbar` is the trait that defines the API for all interactions with the
-/// Touch Bar.
-///
-/// See the documentation of the [`Touchbar`](type.Touchbar.html) type for
-/// information on the default implementation.
-///
-/// An additional 'dummy' implementation is available by building Rubrail with
-/// the `--no-default-features` flags.  This allows you to build an application
-/// that assumes a Touch Bar exists, but to remove it without code changes on
-/// platforms that don't have a Touch Bar, or for distributing through official
-/// Apple channels which don't permit private API usage.
-///
-pub trait TTouchbar {
-    /// A concrete implementation of TTouchbar
-    type T: TTouchbar;
+    fn decoration(&self, item: ItemId, idx: u32) -> Option<ImageTemplate> { None }
 
-    /// Allocate a new Touch Bar interface
+    /// Returns an icon to display beside the text at the given index
     ///
-    /// This allocates a new Touch Bar interface, but does not cause anything
-    /// to be displayed yet.
+    /// When an icon is returned, the item shows the icon followed by its
+    /// text, as a device picker might.  Images are not freed by the scrubber,
+    /// so return the same handle each time.  Ignored for items that return an
+    /// `image()`.  Defaults to text only.
     ///
     /// # Arguments
     ///
-    /// * `title` - The text to display on the icon in the Control Strip area
-    ///   of the Touch Bar.  The title is only displayed if no icon is set.
-    ///   Note that any length is permitted, but the width of the icon is
+    /// * `item` - The `ItemId` of the interacting scrubber
+    /// * `idx` - The index of the relevant item in the scrubber
+    fn icon(&self, item: ItemId, idx: u32) -> Option<TouchbarImage> { None }
+
+    /// Returns the width (in pixels) of the given index in the scrubber
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - The `ItemId` of the interacting scrubber
+    /// * `idx` - The index of the relevant item in the scrubber
+    fn width(&self, item: ItemId, idx: u32) -> u32;
+
+    /// Called when the user selects the given index in the scrubber
+    ///
+    /// Selections made with `select_scrubber_item()` are reported to
+    /// `selected_programmatically()` instead, so applications that mirror
+    /// scrubber selection to other state don't receive their own changes
+    /// back.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - The `ItemId` of the interacting scrubber
+    /// * `idx` - The index of the relevant item in the scrubber
+    fn touch(&self, item: ItemId, idx: u32);
+
+    /// Called when `select_scrubber_item()` selects the given index
+    ///
+    /// Defaults to doing nothing, suppressing echoes of the application's
+    /// own selections.  Implement it to handle all selections, regardless of
+    /// where they came from.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - The `ItemId` of the interacting scrubber
+    /// * `idx` - The index of the relevant item in the scrubber
+    fn selected_programmatically(&self, item: ItemId, idx: u32) {}
+
+    /// Called when the given index is about to scroll into view
+    ///
+    /// A hint for data sources to prefetch expensive content, like
+    /// thumbnails, before it is requested.  Defaults to doing nothing.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - The `ItemId` of the interacting scrubber
+    /// * `idx` - The index of the relevant item in the scrubber
+    fn will_display(&self, item: ItemId, idx: u32) {}
+
+    /// Called when the given index has scrolled out of view
+    ///
+    /// A hint for data sources to release memory held for off-screen items.
+    /// Defaults to doing nothing.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - The `ItemId` of the interacting scrubber
+    /// * `idx` - The index of the relevant item in the scrubber
+    fn did_end_display(&self, item: ItemId, idx: u32) {}
+}
+
+/// API for creating, managing, and getting feedback from Touch Bar UIs
+///
+/// `TTouchbar` is the trait that defines the API for all interactions with the
+/// Touch Bar.
+///
+/// See the documentation of the [`Touchbar`](type.Touchbar.html) type for
+/// information on the default implementation.
+///
+/// An additional 'dummy' implementation is available by building Rubrail with
+/// the `--no-default-features` flags.  This allows you to build an application
+/// that assumes a Touch Bar exists, but to remove it without code changes on
+/// platforms that don't have a Touch Bar, or for distributing through official
+/// Apple channels which don't permit private API usage.
+///
+pub trait TTouchbar {
+    /// A concrete implementation of TTouchbar
+    type T: TTouchbar;
+
+    /// Allocate a new Touch Bar interface
+    ///
+    /// This allocates a new Touch Bar interface, but does not cause anything
+    /// to be displayed yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `title` - The text to display on the icon in the Control Strip area
+    ///   of the Touch Bar.  The title is only displayed if no icon is set.
+    ///   Note that any length is permitted, but the width of the icon is
     ///   controlled by the OS, and typically truncates anything over 5 chars.
     ///
     /// # Returns
@@ -270,6 +1189,31 @@ pub trait TTouchbar {
     ///
     fn alloc(title: &str) -> Self::T;
 
+    /// Allocate a new Touchbar with a custom identifier prefix
+    ///
+    /// Rubrail registers its bars and items with the system under string
+    /// identifiers, which are shared system-wide through the Control Strip.
+    /// By default they are prefixed with the application's bundle
+    /// identifier, falling back to a Rubrail default when running outside
+    /// of a bundle.  This uses `prefix` instead, which is useful for
+    /// unbundled applications that would otherwise share the fallback.
+    ///
+    /// The process ID and a random suffix are still appended, so multiple
+    /// instances of one application don't collide.
+    ///
+    /// # Arguments
+    ///
+    /// * `title` - The text to display on the icon in the Control Strip, as
+    ///   in `alloc()`
+    /// * `prefix` - Reverse-DNS namespace for identifiers, such as
+    ///   `com.example.myapp`
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated instance of the type.
+    ///
+    fn alloc_with_prefix(title: &str, prefix: &str) -> Self::T { Self::alloc(title) }
+
     /// Set an icon to display in the Control Strip
     ///
     /// It is preferrable to associate your Touch Bar menus with an icon instead
@@ -282,14 +1226,34 @@ pub trait TTouchbar {
     /// 80px @ 300dpi).
     ///
     /// Note that Rubrail must run from a Mac app bundle, which means the icon
-    /// will typically be in the bundle's Resources directory, and you must
-    /// take care to provide the path to it correctly.
+    /// will typically be in the bundle's Resources directory.  Rather than a
+    /// path, `image` can be the bare name of a bundled resource, such as
+    /// `"icon.png"` or `"icon"` (PNG assumed).  If an `@2x` version of the file
+    /// exists next to it, it is used on Retina displays.
     ///
     /// # Arguments
     ///
-    /// * `image` - Full path to an image following the Apple icon guidelines
+    /// * `image` - Path to, or resource name of, an image following the Apple
+    ///   icon guidelines
     ///
-    fn set_icon(&self, image: &str) {}
+    /// # Returns
+    ///
+    /// `TouchbarError::ImageUnavailable` if the image couldn't be found or
+    /// loaded, in which case the icon is unchanged
+    fn set_icon(&self, image: &str) -> Result<(), TouchbarError> { Ok(()) }
+
+    /// Set light and dark variants of the Control Strip icon
+    ///
+    /// Like `set_icon()`, but registers one icon for the light system
+    /// appearance and one for dark.  Rubrail displays the one matching the
+    /// current appearance, and switches automatically when it changes.
+    ///
+    /// # Arguments
+    ///
+    /// * `light` - Full path to the icon used in light mode
+    /// * `dark` - Full path to the icon used in dark mode
+    ///
+    fn set_icon_variants(&mut self, light: &str, dark: &str) {}
 
     /// Create a new horizontal bar UI
     ///
@@ -300,7 +1264,7 @@ pub trait TTouchbar {
     /// # Returns
     ///
     /// A newly allocated, empty bar.
-    fn create_bar(&mut self) -> BarId { 0 }
+    fn create_bar(&mut self) -> BarId { BarId(0) }
 
     /// Adds a group of ordered items to a bar
     ///
@@ -308,6 +1272,10 @@ pub trait TTouchbar {
     /// allocated bar.  This does not cause the bar to be displayed or
     /// registered.
     ///
+    /// Items are validated first, since popover bars are easy to wire up
+    /// wrong.  Items that can't be shown in the bar are skipped and reported
+    /// to the `on_error()` callback as `TouchbarError::UnsupportedItem`.
+    ///
     /// # Arguments
     ///
     /// * `bar_id` - Bar to add the items to
@@ -315,287 +1283,1824 @@ pub trait TTouchbar {
     ///
     fn add_items_to_bar(&mut self, bar_id: &BarId, items: Vec<ItemId>) {}
 
-    /// Sets the given bar as the 'root' bar in the Control Strip
+    /// Insert an item into a bar at a specific position
     ///
-    /// Registers the given bar as the 'root' bar.  This creates an icon in the
-    /// Control Strip region of the Touch Bar (the section on the right side),
-    /// and causes this bar to be displayed when the icon is pressed.
+    /// The bar updates in place, so items can be added to a bar that is
+    /// already displayed without rebuilding it and setting it as the root bar
+    /// again, which makes the whole bar flicker.
     ///
-    /// This function causes the UI to be updated and the bar to become useable
-    /// by the user.
+    /// The item is validated like in `add_items_to_bar()`, and skipped if it
+    /// can't be shown in the bar.
     ///
     /// # Arguments
     ///
-    /// * `bar_id` - The bar to present when the Control Strip icon is pressed
+    /// * `bar_id` - Bar to add the item to
+    /// * `index` - Position to insert the item at.  Indices past the end of
+    ///   the bar append the item.
+    /// * `item` - Item to add to the bar
     ///
-    fn set_bar_as_root(&mut self, bar_id: BarId) {}
+    fn insert_item_in_bar(&mut self, bar_id: &BarId, index: usize, item: ItemId) {}
 
-    /// Create a button to open a 'popover' submenu.
-    ///
-    /// Creates a button UI element that, when pressed, recursively opens
-    /// another bar as a submenu.  Popovers allow you to create infinitely
-    /// nested heirarchies of touch bar menus.  Bars registered as popovers
-    /// can themselves contain more popover items.
+    /// Change the order of the items in a bar
     ///
-    /// All buttons accept an image, text, or both.  If both are provided, they
-    /// will both be displayed at the same time.
+    /// The bar updates in place, so this can be used on a displayed bar, for
+    /// instance to let users rearrange items to their liking.
     ///
     /// # Arguments
     ///
-    /// * `image` - An image allocated with a `create_image_*` function
-    /// * `text` - Text to display on the button
-    /// * `bar_id` - Bar to present when this button is pressed
-    ///
-    /// # Returns
+    /// * `bar_id` - Bar to reorder
+    /// * `items` - Items of the bar in their new order.  Items that aren't in
+    ///   the bar are ignored, and items of the bar that aren't listed keep
+    ///   their relative order after the listed ones.
     ///
-    /// A newly allocated item which can be added to a bar.
-    fn create_popover_item(&mut self, image: Option<&TouchbarImage>,
-                           text: Option<&str>, bar_id: &BarId) -> ItemId {0}
+    fn set_bar_item_order(&mut self, bar_id: &BarId, items: Vec<ItemId>) {}
 
-    /// Create a new label
+    /// Replace an item in a bar with another item
     ///
-    /// Creates a text label, which simply displays a line of non-interactive
-    /// text.  Newlines are permitted, though only two lines will render
-    /// properly.
+    /// The new item takes the old item's position, and the bar updates in
+    /// place, so a single widget can change into another kind of item
+    /// without rebuilding the whole bar.  The old item is then freed like in
+    /// `remove_item_from_bar()`.
     ///
-    /// # Arguments
+    /// The new item is validated like in `add_items_to_bar()`.  If it can't
+    /// be shown in the bar, the bar is left unchanged.
     ///
-    /// * `text` - Text to display in label
+    /// # Arguments
     ///
-    /// # Returns
+    /// * `bar_id` - Bar containing the old item
+    /// * `old` - Item to replace
+    /// * `new` - Item to show in its place
     ///
-    /// A newly allocated label item
-    fn create_label(&mut self, text: &str) -> ItemId {0}
+    fn replace_item(&mut self, bar_id: &BarId, old: &ItemId, new: ItemId) {}
 
-    /// Changes the text in an existing label
+    /// Remove an item from a bar and free it
     ///
-    /// This changes the text of an existing label, allowing updating of text
-    /// displays without rebuilding the whole bar.
+    /// The bar updates in place, so this can be called on a bar that is
+    /// already displayed.  The item is freed unless it is persistent or still
+    /// in another bar, and must not be used again once freed.  Removing a
+    /// popover also frees the bars it opens.
     ///
     /// # Arguments
     ///
-    /// * `label_id` - Label item to change
-    /// * `text` - New text to display in the existing label
+    /// * `bar_id` - Bar to remove the item from
+    /// * `item` - Item to remove from the bar
     ///
-    fn update_label(&mut self, label_id: &ItemId, text: &str) {}
+    fn remove_item_from_bar(&mut self, bar_id: &BarId, item: &ItemId) {}
 
-    /// Changes the width of an existing label
+    /// Free an item
     ///
-    /// Set a fixed width for a label, in pixels.
+    /// Items are normally freed along with the bar they are in when it is
+    /// replaced, so an item that was never added to a bar, or whose bar is
+    /// never shown, is leaked unless it is destroyed.  The item is removed
+    /// from any bars it is in, and freed even if it is persistent.  Destroying
+    /// a popover also frees the bars it opens.
+    ///
+    /// The item must not be used again after it is destroyed.
     ///
     /// # Arguments
     ///
-    /// * `label_id` - Label item to change
-    /// * `width` - New width of label, in pixels
+    /// * `item` - Item to free
     ///
-    fn update_label_width(&mut self, label_id: &ItemId, width: u32) {}
+    fn destroy_item(&mut self, item: ItemId) {}
 
-    /// Create a horizontally scrolling 'scrubber' of text
+    /// Free a bar and its items
     ///
-    /// Creates a Scrubber, which is a  horizontally scrolling widget filled
-    /// with items which can be dynamically changed, and selected.  This is the
-    /// primary interface for choosing from a list of (possibly dynamic) options.
+    /// Reclaims a bar that was built but will never be shown, or a popover
+    /// bar that is no longer used.  Its items are freed recursively, except
+    /// for persistent items and items that are also in another bar.
     ///
-    /// Scrubbers are filled with data using a collection of callbacks.  This
-    /// is implemented by the [TScrubberData](trait.TScrubberData.html) trait,
-    /// which allows you to build a custom backing data store for scrubbers.
+    /// The root bar can't be destroyed, since it is freed when it is replaced
+    /// by `set_bar_as_root()`.  A bar must not be destroyed while a popover
+    /// item still opens it.
     ///
-    /// At creation, no item is selected.  Call `select_scrubber_item()` after
-    /// this to render an item as selected.
+    /// # Arguments
+    ///
+    /// * `bar_id` - Bar to free
+    ///
+    fn destroy_bar(&mut self, bar_id: BarId) {}
+
+    /// Take ownership of an item, freeing it when the handle is dropped
+    ///
+    /// Makes leaks of items that are never shown impossible: when the
+    /// returned handle is dropped, the item is freed unless it is in a bar.
+    /// Items in bars are still freed along with their bar.
     ///
     /// # Arguments
     ///
-    /// * `data` - An object implementing the `TScrubberData` trait, wrapped
-    ///   in a reference counter (Rc).
+    /// * `item` - Item to own
     ///
     /// # Returns
     ///
-    /// A newly allocated scrubber item
-    fn create_text_scrubber(&mut self, data: Rc<dyn TScrubberData>) -> ItemId {0}
+    /// A handle that frees the item when dropped
+    ///
+    fn own_item(&mut self, item: ItemId) -> OwnedItem { OwnedItem::new(item, Box::new(|| {})) }
 
-    /// Selects the given index in a scrubber
+    /// Take ownership of a bar, freeing it when the handle is dropped
     ///
-    /// Marks the given index in the given scrubber as selected, so that item
-    /// will be displayed with highlighting.
+    /// When the returned handle is dropped, the bar and its items are freed
+    /// as with `destroy_bar()`, unless the bar is a root bar or is opened by
+    /// a popover.  Those bars are still freed when they are replaced, or
+    /// along with their popover.
     ///
     /// # Arguments
     ///
-    /// * `scrub_id` - Scrubber to select in
-    /// * `index` - Index of the item to mark as selected
+    /// * `bar_id` - Bar to own
     ///
-    fn select_scrubber_item(&mut self, scrub_id: &ItemId, index: u32) {}
+    /// # Returns
+    ///
+    /// A handle that frees the bar when dropped
+    ///
+    fn own_bar(&mut self, bar_id: BarId) -> OwnedBar { OwnedBar::new(bar_id, Box::new(|| {})) }
 
-    /// Inform a scrubber to redraw after a change to its backing data
+    /// Count objects that are still allocated, to detect leaks
     ///
-    /// If the data store backing a scrubber (`TScrubberData`) has its data
-    /// change, this function should be called to inform the scrubber to
-    /// redraw.
+    /// Reports live Objective-C objects (with the `objc_wrapper` feature),
+    /// items that aren't reachable from the root bar, and images that were
+    /// never given to an item.  See `AllocationStats`.
     ///
-    /// # Arguments
+    /// # Returns
     ///
-    /// * `scrub_id` - Scrubber to refresh
+    /// A snapshot of the controller's allocations
     ///
-    fn refresh_scrubber(&mut self, scrub_id: &ItemId) {}
+    fn allocation_report(&self) -> AllocationStats { AllocationStats::default() }
 
-    /// Register a tap gesture handler with a Touch Bar item
+    /// Describe every allocated bar and item, for debugging
     ///
-    /// Registers a callback to be called when the given item is tapped with a
-    /// one or more fingers.  This works  even for items that are normally
-    /// non-interactive (like labels).
+    /// Returns a tree with one line per bar or item, starting from the root
+    /// bars and nesting popover bars under the items that open them.  Bars
+    /// and items that aren't reachable from a root bar are listed after it.
+    /// Each line includes the type, identifier, address, and Objective-C
+    /// retain count of the object.
     ///
-    /// Supports specifying number of taps (i.e. double- or triple-click), and
-    /// number of fingers independently.  For instance, set `taps` to 2 and
-    /// `fingers` to 3 to require double-clicking with 3 fingers at the same
-    /// time.
+    /// Useful for tracking down objects that are never freed.  See the
+    /// _Memory Allocation_ sections of `BarId` and `ItemId`.
     ///
-    /// # Arguments
+    /// # Returns
     ///
-    /// * `item_id` - Item to add the gesture detection to
-    /// * `taps` - Number of discrete taps to trigger callback
-    /// * `fingers` - Number of simultaneous fingers needed
-    /// * `cb` - Callback to call when a tap is detected
+    /// A multi-line description of the controller's bars and items, which
+    /// can be printed or logged
     ///
-    fn add_item_tap_gesture(&mut self, item_id: &ItemId, taps: u32,
-                            fingers: u32, cb: ButtonCb) {}
+    fn dump_state(&self) -> String { String::new() }
 
-    /// Register a swipe gesture handler with a Touch Bar item
+    /// Duplicate a bar and its items
     ///
-    /// Registers a callback to be called when the given item is swiped with a
-    /// horizontal motion.  This can be used to detect a finger sliding back and
-    /// forth on a Touch Bar item, even for items that are normally
-    /// non-interactive (like labels).
+    /// An item can only be on screen in one place at a time, so this builds
+    /// a copy of a bar, with new copies of its items, to show the same
+    /// layout somewhere else, such as under a second popover.  Popovers are copied
+    /// along with the bars they open.
     ///
-    /// # Arguments
-    ///
-    /// * `item` - Item to add the gesture detection to
-    /// * `cb` - Callback to call when a touch is detected
+    /// The copies share their callbacks with the original items, and are
+    /// passed the copy's `ItemId`.  Scrubbers share their data source.
+    /// Gestures, auto-repeat and confirmation holds added to the original
+    /// items are added to the copies too.
     ///
-    fn add_item_swipe_gesture(&mut self, item: &ItemId, cb: SwipeCb) {}
-
-    /// Create space between items in a bar
+    /// Buttons, labels, sliders, scrubbers, spacers and popovers can be
+    /// copied.  Other items are left out of the copy, and reported as
+    /// `TouchbarError::UnsupportedItem`.
     ///
     /// # Arguments
     ///
-    /// * `space` - The type of spacer to create
+    /// * `bar_id` - Bar to copy
     ///
     /// # Returns
     ///
-    /// A newly allocated spacer item that can be added to a bar
+    /// A new bar with copies of the original's items, or 0 if the bar
+    /// doesn't exist.
     ///
-    fn create_spacer(&mut self, space: SpacerType) -> ItemId {0}
+    fn clone_bar(&mut self, bar_id: &BarId) -> BarId { BarId(0) }
 
-    /// Create an image from a file path
+    /// Items currently in a bar
     ///
-    /// Creates an image that can be assigned to UI items that display them,
-    /// like buttons and popovers.
+    /// # Arguments
     ///
-    /// Specify a relative or absolute path to the image.
+    /// * `bar_id` - Bar to list the items of
     ///
-    /// When specifying an image, follow Apple's guidelines for icons on Retina
-    /// displays (40px x 40px @ 150dpi PNG recommended), and remember to handle
-    /// paths into the app bundle correctly.
+    /// # Returns
     ///
-    /// **WARNING** image memory is _deallocated_ after it is assigned to an
-    /// item.  Do **not** use the same image twice.  If two buttons will have
-    /// the same image, you must allocate the image twice.
+    /// The bar's items, in the order they are displayed.  Empty if the bar
+    /// doesn't exist.
+    fn bar_items(&self, bar_id: &BarId) -> Vec<ItemId> { Vec::new() }
+
+    /// Bar an item is in
     ///
     /// # Arguments
     ///
-    /// * `path` - Path to an image file
+    /// * `item` - Item to look up
     ///
     /// # Returns
     ///
-    /// A newly allocated image that can be added to an item
-    ///
-    fn create_image_from_path(&mut self, path: &str) -> TouchbarImage {0}
+    /// The bar containing the item, or `None` if it hasn't been added to a
+    /// bar.  If the item is in several bars, one of them is returned.
+    fn parent_bar(&self, item: &ItemId) -> Option<BarId> { None }
 
-    /// Create an image from a template
-    ///
-    /// Creates an image that can be assigned to UI items that display them,
-    /// like buttons and popovers.
-    ///
-    /// See `ImageTemplate` for the supported options.  These templates are
-    /// provided by Apple.
+    /// Popover item that opens a bar
     ///
-    /// **WARNING** image memory is _deallocated_ after it is assigned to an
-    /// item.  Do **not** use the same image twice.  If two buttons will have
-    /// the same image, you must allocate the image twice.
+    /// Together with `parent_bar()`, this lets a callback walk up through
+    /// nested popovers, for example to dismiss the popover it is in.
     ///
     /// # Arguments
     ///
-    /// * `template` - Identifier of the image template to use
+    /// * `bar_id` - Bar to look up
     ///
     /// # Returns
     ///
-    /// A newly allocated image that can be added to an item
-    ///
-    fn create_image_from_template(&mut self, template: ImageTemplate) -> TouchbarImage {0}
+    /// The popover item that opens the bar, either when pressed or when
+    /// held, or `None` if no popover opens it.
+    fn popover_parent(&self, bar_id: &BarId) -> Option<ItemId> { None }
 
-    /// Create a button that triggers a callback when pressed
-    ///
-    /// All buttons accept an image, text, or both.  If both are provided, they
-    /// will both be displayed at the same time.
+    /// Bar opened by a popover item
     ///
     /// # Arguments
     ///
-    /// * `image` - An image allocated with a `create_image_*` function
-    /// * `text` - Text to display on the button
-    /// * `cb` - Callback to call when the button is pressed
+    /// * `item` - Popover item to look up
     ///
     /// # Returns
     ///
-    /// A newly allocated item which can be added to a bar.
-    fn create_button(&mut self, image: Option<&TouchbarImage>, text: Option<&str>, cb: ButtonCb) -> ItemId {0}
+    /// The bar opened when the popover is pressed, or `None` if the item
+    /// isn't a popover or its bar hasn't been built yet.
+    fn popover_bar(&self, item: &ItemId) -> Option<BarId> { None }
 
-    /// Changes the image and/or text of a button
+    /// Kind of an item
     ///
     /// # Arguments
     ///
-    /// * `item` - Button item to change
-    /// * `image` - New image to draw on button (optional)
-    /// * `text` - New text to draw on button (optional)
+    /// * `item` - Item to look up
     ///
-    fn update_button(&mut self, item: &ItemId, image: Option<&TouchbarImage>, text: Option<&str>) {}
+    /// # Returns
+    ///
+    /// The kind of item, or `None` if the item doesn't exist or has been
+    /// freed.
+    fn item_type(&self, item: &ItemId) -> Option<ItemKind> { None }
 
-    /// Changes the width of an existing button
+    /// Give an item a name to look it up by later
     ///
-    /// Set a fixed width for a button, in pixels.
+    /// Lets long-lived applications find items with `item_by_name()`
+    /// instead of storing every `ItemId`.  A name refers to one item at a
+    /// time, so naming another item the same replaces it.  The name is
+    /// forgotten when the item is freed.
     ///
     /// # Arguments
     ///
-    /// * `button_id` - Button item to change
-    /// * `width` - New width of button, in pixels
+    /// * `item` - Item to name
+    /// * `name` - Name to give the item
     ///
-    fn update_button_width(&mut self, button_id: &ItemId, width: u32) {}
+    fn set_item_name(&mut self, item: &ItemId, name: &str) {}
 
-    /// Create a slider item
+    /// Find an item by the name given with `set_item_name()`
     ///
-    /// Creates an item that displays as a continuously variable horizontal
-    /// slider, reporting its value as a floating point between the provided
-    /// minimum and maximum values.
+    /// Items built from a `BarSpec` are also named, by `BarItemSpec::named()`.
     ///
-    /// When the slider value changes, the associated callback is called.  Note
-    /// that it triggers frequently as the user slides it, so 'debouncing' or
-    /// buffering might be required if high-frequency changes are not desired.
+    /// # Arguments
     ///
-    /// Newly created sliders default to the minimum value.  If you need to
-    /// change this, set the current value with `update_slider()`.
+    /// * `name` - Name of the item
+    ///
+    /// # Returns
+    ///
+    /// The named item, or `None` if no existing item has the name.
+    fn item_by_name(&self, name: &str) -> Option<ItemId> { None }
+
+    /// Name given to an item with `set_item_name()`
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - Item to look up
+    ///
+    /// # Returns
+    ///
+    /// The item's name, or `None` if it has no name.
+    fn item_name(&self, item: &ItemId) -> Option<String> { None }
+
+    /// Fixed width of an item
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - Item to look up
+    ///
+    /// # Returns
+    ///
+    /// The width set with `update_button_width()` or `update_label_width()`,
+    /// or given when creating a canvas or graph.  `None` if the item sizes
+    /// itself to its contents.
+    fn item_width(&self, item: &ItemId) -> Option<u32> { None }
+
+    /// Let the user rearrange or remove items in a bar
+    ///
+    /// Registers the bar with the standard macOS customization palette,
+    /// opened by `show_customization_palette()`.  The user can reorder the
+    /// bar's items, remove them, and add any of the `allowed` items.  Items
+    /// already in the bar are always allowed.
+    ///
+    /// Rubrail saves the user's layout in the application's user defaults
+    /// under `identifier`, and restores it the next time a bar is registered
+    /// with the same identifier.  Since the layout is saved as positions in
+    /// `allowed`, the list must be built in the same order on every launch.
+    ///
+    /// The bar owns its allowed items like the items placed in it, so items
+    /// the user removed are freed along with the bar.
+    ///
+    /// Only supported with the `public_api` feature.  Bars shown from the
+    /// Control Strip are presented by the system, outside the application's
+    /// responder chain, and the palette can't customize them.
+    ///
+    /// # Arguments
+    ///
+    /// * `bar_id` - Bar to make customizable
+    /// * `identifier` - Name the layout is saved under, unique within the
+    ///   application
+    /// * `allowed` - Items the user may place in the bar
+    ///
+    fn set_bar_customization(&mut self, bar_id: &BarId, identifier: &str, allowed: Vec<ItemId>) {}
+
+    /// Set the name shown for an item in the customization palette
+    ///
+    /// Only supported with the `public_api` feature.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - Item to name
+    /// * `label` - Name shown in the palette
+    ///
+    fn set_item_customization_label(&mut self, item: &ItemId, label: &str) {}
+
+    /// Open the macOS customization palette
+    ///
+    /// Lets the user customize bars registered with
+    /// `set_bar_customization()`.  Calling it while the palette is open
+    /// closes it.  Only supported with the `public_api` feature.
+    ///
+    fn show_customization_palette(&mut self) {}
+
+    /// Enable or disable interaction with every item in a bar
+    ///
+    /// Disabling greys out every control in the bar and turns off their
+    /// gestures, for example while the application applies settings.
+    /// Enabling restores each control to the state it had before the bar was
+    /// disabled, so controls that were already disabled stay disabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `bar_id` - Bar to change
+    /// * `enabled` - Whether the bar's items accept interaction
+    ///
+    fn set_bar_interaction_enabled(&mut self, bar_id: &BarId, enabled: bool) {}
+
+    /// Sets the principal item of a bar
+    ///
+    /// The system centers the principal item in the bar, with the other items
+    /// laid out to either side of it, and keeps it visible when space is
+    /// tight.  Useful for emphasizing a scrubber or a status label.  The item
+    /// must also be added to the bar with `add_items_to_bar()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `bar_id` - Bar to change
+    /// * `item` - Item to center, or `None` to return to normal layout
+    ///
+    fn set_principal_item(&mut self, bar_id: &BarId, item: Option<&ItemId>) {}
+
+    /// Sets the given bar as the 'root' bar in the Control Strip
+    ///
+    /// Registers the given bar as the 'root' bar.  This creates an icon in the
+    /// Control Strip region of the Touch Bar (the section on the right side),
+    /// and causes this bar to be displayed when the icon is pressed.
+    ///
+    /// This function causes the UI to be updated and the bar to become useable
+    /// by the user.
+    ///
+    /// If a root bar is already set, the new bar takes over its Control Strip
+    /// icon and replaces it on screen in place, without removing and
+    /// re-adding the icon.  Items of the old bar that are also in the new bar
+    /// are kept, and the rest are deallocated.  Setting the current root bar
+    /// again just updates its items.
+    ///
+    /// # Arguments
+    ///
+    /// * `bar_id` - The bar to present when the Control Strip icon is pressed
+    ///
+    fn set_bar_as_root(&mut self, bar_id: BarId) {}
+
+    /// Show or hide the Control Strip icon at runtime
+    ///
+    /// While disabled, the icon is removed and nothing is shown on the Touch
+    /// Bar, but every call keeps working on the controller's internal state:
+    /// bars and items can still be created and updated, and
+    /// `set_bar_as_root()` still records the root bar.  Enabling again shows
+    /// the icon for the latest root bar, with all changes applied.
+    ///
+    /// This lets an application offer a preference for hiding its Touch Bar
+    /// UI without checking the preference at every call site.  Controllers
+    /// start enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether the Control Strip icon is shown
+    ///
+    fn set_enabled(&mut self, enabled: bool) {}
+
+    /// Add another Control Strip item to this controller
+    ///
+    /// Each additional item has its own icon, identifier, and root bar, and
+    /// shares the controller's items and callbacks, so one application can
+    /// offer several independent menus.  Bars and items can be used under
+    /// any of the controller's Control Strip items.
+    ///
+    /// The item appears once a bar is set with `set_control_strip_item_bar()`,
+    /// and follows `set_enabled()` like the main item.  Only supported with
+    /// the `private_api` feature, since the public API has no Control Strip.
+    ///
+    /// # Arguments
+    ///
+    /// * `title` - Text shown in the Control Strip when no icon is set
+    ///
+    /// # Returns
+    ///
+    /// A reference to the new Control Strip item, or 0 if unsupported
+    fn create_control_strip_item(&mut self, title: &str) -> TrayId { TrayId(0) }
+
+    /// Sets the bar shown by an additional Control Strip item
+    ///
+    /// Identical to `set_bar_as_root()`, for an item added with
+    /// `create_control_strip_item()`.  The item's previous bar is replaced
+    /// the same way.
+    ///
+    /// # Arguments
+    ///
+    /// * `tray` - Control Strip item to change
+    /// * `bar_id` - The bar to present when the item's icon is pressed
+    ///
+    fn set_control_strip_item_bar(&mut self, tray: &TrayId, bar_id: BarId) {}
+
+    /// Sets the icon of an additional Control Strip item
+    ///
+    /// Identical to `set_icon()`, for an item added with
+    /// `create_control_strip_item()`.  Takes effect immediately.
+    ///
+    /// # Arguments
+    ///
+    /// * `tray` - Control Strip item to change
+    /// * `image` - Path to, or resource name of, an image following the Apple
+    ///   icon guidelines
+    ///
+    /// # Returns
+    ///
+    /// `TouchbarError::ImageUnavailable` if the image couldn't be found or
+    /// loaded, in which case the icon is unchanged
+    fn set_control_strip_item_icon(&mut self, tray: &TrayId, image: &str) -> Result<(), TouchbarError> { Ok(()) }
+
+    /// Remove an additional Control Strip item
+    ///
+    /// Removes the item's icon from the Control Strip and frees its root bar,
+    /// along with the bar's items that aren't in another bar.  The `TrayId`
+    /// must not be used again.
+    ///
+    /// # Arguments
+    ///
+    /// * `tray` - Control Strip item to remove
+    ///
+    fn remove_control_strip_item(&mut self, tray: TrayId) {}
+
+    /// Temporarily hide the Control Strip icons
+    ///
+    /// Hides the controller's icons, including ones added with
+    /// `create_control_strip_item()`, without unregistering or freeing
+    /// anything, for instance while the user is in a full-screen game.
+    /// Showing them again is instant.  Icons registered while hidden stay
+    /// hidden.  Only supported with the `private_api` feature.
+    ///
+    /// # Arguments
+    ///
+    /// * `visible` - Whether the Control Strip icons are shown
+    ///
+    fn set_control_strip_visible(&mut self, visible: bool) {}
+
+    /// Remove this controller from the Control Strip
+    ///
+    /// Removes every Control Strip item of the controller, including ones
+    /// added with `create_control_strip_item()`, and frees their root bars
+    /// along with the bars' items that aren't in another bar.  Unlike
+    /// `set_enabled()`, nothing is kept to show again.
+    ///
+    /// The controller stays usable: setting a new root bar with
+    /// `set_bar_as_root()` adds it back to the Control Strip.  This suits
+    /// applications with a preference for turning off their Touch Bar
+    /// integration.
+    fn shutdown(&mut self) {}
+
+    /// Open the root bar as if its Control Strip icon had been pressed
+    ///
+    /// Lets the application pop the bar open when something important
+    /// happens.  Does nothing if no root bar is set or the controller is
+    /// disabled.
+    fn present(&mut self) {}
+
+    /// Collapse the root bar back into its Control Strip icon
+    ///
+    /// Undoes `present()`, or closes a bar the user opened.
+    fn minimize(&mut self) {}
+
+    /// Exclude an item from deallocation when its bar is replaced
+    ///
+    /// Normally every item on a bar is deallocated when the bar is replaced by
+    /// a new root bar, unless it is also in the replacement bar.  A
+    /// _persistent_ item is skipped, and remains allocated so it can be added
+    /// to bars built later.  This makes it cheap to
+    /// rebuild a bar where only a few items change, while keeping expensive
+    /// items (like a scrubber full of images) alive.
+    ///
+    /// Persistent popover items keep their child bars alive as well.
+    ///
+    /// A persistent item that is not added to the replacement bar is leaked
+    /// unless it is later made non-persistent and added to a bar that is
+    /// replaced.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - Item to change
+    /// * `persistent` - Whether the item survives bar replacement
+    ///
+    fn set_item_persistent(&mut self, item: &ItemId, persistent: bool) {}
+
+    /// Change how an item is displayed while macOS Focus is active
+    ///
+    /// Items default to `FocusBehavior::Normal`.  The change is applied
+    /// immediately if Focus is currently active, and whenever Focus is turned
+    /// on or off afterwards.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - Item to change
+    /// * `behavior` - How the item is displayed while Focus is active
+    ///
+    fn set_item_focus_behavior(&mut self, item: &ItemId, behavior: FocusBehavior) {}
+
+    /// Register a callback for when macOS Focus (Do Not Disturb) changes
+    ///
+    /// Replaces any previously registered callback.  The current state can be
+    /// queried at any time with `util::focus_mode_active()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `cb` - Callback to call when Focus is turned on or off
+    ///
+    fn on_focus_mode_changed(&mut self, cb: FocusCb) {}
+
+    /// Whether the root bar is currently on screen
+    ///
+    /// `false` while the bar is collapsed into its Control Strip icon, while
+    /// a popover covers it, or if no root bar is set.
+    fn is_presented(&self) -> bool { false }
+
+    /// Register a callback for when the root bar is opened or closed
+    ///
+    /// Lets applications pause expensive updates, like frequently refreshed
+    /// labels, while nobody can see them.  Replaces any previously registered
+    /// callback.  The callback follows the current root bar when it is
+    /// replaced.
+    ///
+    /// # Arguments
+    ///
+    /// * `cb` - Callback to call when the root bar's visibility changes
+    ///
+    fn on_visibility_changed(&mut self, cb: VisibilityCb) {}
+
+    /// Register a callback for errors detected by the controller
+    ///
+    /// Replaces any previously registered callback.  Errors are always logged,
+    /// whether or not a callback is registered.
+    ///
+    /// # Arguments
+    ///
+    /// * `cb` - Callback to call when an error is detected
+    ///
+    fn on_error(&mut self, cb: ErrorCb) {}
+
+    /// Build a large bar in steps, yielding to the run loop between them
+    ///
+    /// Creating dozens of items and nested popovers at once blocks the main
+    /// thread, which can make app launch sluggish.  Instead, the callback is
+    /// called repeatedly, one step per pass of the run loop, until it returns
+    /// `false`.  Each step should create a batch of items, and the last step
+    /// typically sets the root bar.
+    ///
+    /// Steps only run while the application's run loop is running.
+    ///
+    /// # Arguments
+    ///
+    /// * `step` - Callback to call for each construction step
+    ///
+    fn build_incrementally(&mut self, step: BuildStepCb) {}
+
+    /// Create a button to open a 'popover' submenu.
+    ///
+    /// Creates a button UI element that, when pressed, recursively opens
+    /// another bar as a submenu.  Popovers allow you to create infinitely
+    /// nested heirarchies of touch bar menus.  Bars registered as popovers
+    /// can themselves contain more popover items.
+    ///
+    /// All buttons accept an image, text, or both.  If both are provided, they
+    /// will both be displayed at the same time.
+    ///
+    /// # Arguments
+    ///
+    /// * `image` - An image allocated with a `create_image_*` function
+    /// * `text` - Text to display on the button
+    /// * `bar_id` - Bar to present when this button is pressed
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated item which can be added to a bar.
+    #[deprecated(since = "0.9.2", note = "use TTouchbar2::new_popover()")]
+    fn create_popover_item(&mut self, image: Option<&TouchbarImage>,
+                           text: Option<&str>, bar_id: &BarId) -> ItemId { ItemId::from_raw(0) }
+
+    /// Create a popover button whose bar is built when it is opened
+    ///
+    /// Instead of building a submenu bar up front, the callback builds it the
+    /// first time the popover is opened.  This reduces startup cost for deep
+    /// menus, and with `rebuild` set, lets the submenu show fresh data each
+    /// time it opens.  When rebuilt, the previous bar and its items are freed.
+    ///
+    /// Lazy popovers open when tapped, but not with press-and-hold.
+    ///
+    /// # Arguments
+    ///
+    /// * `image` - An image allocated with a `create_image_*` function
+    /// * `text` - Text to display on the button
+    /// * `rebuild` - Whether to build a new bar every time the popover opens
+    /// * `builder` - Callback that creates and returns the popover's bar
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated popover item
+    fn create_popover_item_lazy(&mut self, image: Option<&TouchbarImage>, text: Option<&str>,
+                                rebuild: bool, builder: PopoverBuildCb) -> ItemId { ItemId::from_raw(0) }
+
+    /// Show a different bar when a popover button is pressed and held
+    ///
+    /// By default a popover shows the same bar whether it is tapped or held.
+    /// This sets a separate bar for press-and-hold, which is shown only while
+    /// the finger stays down, like the system volume control's transient
+    /// slider.  Tapping still opens the popover's normal bar.
+    ///
+    /// The popover owns the bar from then on, and frees it along with any
+    /// hold bar it replaces.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - Popover item to change
+    /// * `bar_id` - Bar to show while the button is held
+    ///
+    fn set_popover_press_and_hold_bar(&mut self, item: &ItemId, bar_id: &BarId) {}
+
+    /// Open a popover as if it had been pressed
+    ///
+    /// Lets the application open a submenu in response to something other
+    /// than a touch, like a hotkey or an incoming call.  Lazy popovers build
+    /// their bar first, as usual.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - Popover item to open
+    ///
+    fn present_popover(&mut self, item: &ItemId) {}
+
+    /// Close a popover
+    ///
+    /// Returns to the bar the popover was opened from, for instance from a
+    /// "Done" button inside the popover.  Does nothing if the popover isn't
+    /// open.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - Popover item to close
+    ///
+    fn dismiss_popover(&mut self, item: &ItemId) {}
+
+    /// Close every open popover
+    ///
+    /// Returns all the way to the root bar, however deep the open popovers
+    /// are nested.
+    fn dismiss_all_popovers(&mut self) {}
+
+    /// Replace the bar a popover opens
+    ///
+    /// Swaps the submenu behind an existing popover button, for menus whose
+    /// contents depend on application state, without recreating the button
+    /// or the bar it is in.  The old bar and its items are freed, except for
+    /// persistent items and items that are also in another bar.  A lazy
+    /// popover stops calling its builder.
+    ///
+    /// The new bar is rejected, and reported to the `on_error()` callback as
+    /// `TouchbarError::UnsupportedItem`, if it contains the popover itself.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - Popover item to change
+    /// * `bar_id` - Bar to show when the popover is pressed
+    ///
+    fn set_popover_bar(&mut self, item: &ItemId, bar_id: BarId) {}
+
+    /// Configure how easily a popover's press-and-hold gesture activates
+    ///
+    /// Besides opening when tapped, popovers open when pressed and held.  On
+    /// dense bars this can trigger accidentally, for instance when a finger
+    /// swiping a neighboring scrubber rests on the popover.  Increasing the
+    /// press duration and decreasing the allowed movement makes the gesture
+    /// harder to trigger by accident.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - Popover item to configure
+    /// * `min_duration` - How long a finger must be held down before the
+    ///   popover opens
+    /// * `allowable_movement` - How far, in pixels, the finger may move while
+    ///   held before the gesture is abandoned
+    ///
+    fn set_popover_hold_thresholds(&mut self, item: &ItemId, min_duration: Duration,
+                                   allowable_movement: f64) {}
+
+    /// Create a button that opens a short list of options
+    ///
+    /// Pressing the button opens a popover with one button per option.
+    /// Choosing an option closes the popover and calls the callback with its
+    /// index.  This is a lightweight alternative to building a popover bar by
+    /// hand for a handful of choices.
+    ///
+    /// # Arguments
+    ///
+    /// * `image` - Image to display on the button
+    /// * `text` - Text to display on the button
+    /// * `options` - Text of each option, in order
+    /// * `cb` - Callback called with the index of the chosen option
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated dropdown button item
+    fn create_dropdown_button(&mut self, image: Option<&TouchbarImage>, text: Option<&str>,
+                              options: &[&str], cb: DropdownCb) -> ItemId { ItemId::from_raw(0) }
+
+    /// Create a segmented picker for choosing one of several options
+    ///
+    /// Each segment shows the label and/or image at its index, so a picker
+    /// has as many segments as the longer of `labels` and `images`.  The
+    /// first segment is initially selected.
+    ///
+    /// On macOS 10.15 and later this uses the system picker item, which
+    /// collapses to show only the selection when space is tight.  On older
+    /// systems it falls back to a plain segmented control.
+    ///
+    /// # Arguments
+    ///
+    /// * `labels` - Text of each segment
+    /// * `images` - Image of each segment
+    /// * `cb` - Callback called when a segment is selected
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated picker item
+    fn create_picker(&mut self, labels: &[&str], images: &[TouchbarImage],
+                     cb: PickerCb) -> ItemId { ItemId::from_raw(0) }
+
+    /// Create a new label
+    ///
+    /// Creates a text label, which simply displays a line of non-interactive
+    /// text.  Newlines are permitted, though only two lines will render
+    /// properly.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - Text to display in label
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated label item
+    fn create_label(&mut self, text: &str) -> ItemId { ItemId::from_raw(0) }
+
+    /// Changes the text in an existing label
+    ///
+    /// This changes the text of an existing label, allowing updating of text
+    /// displays without rebuilding the whole bar.
+    ///
+    /// # Arguments
+    ///
+    /// * `label_id` - Label item to change
+    /// * `text` - New text to display in the existing label
+    ///
+    fn update_label(&mut self, label_id: &ItemId, text: &str) {}
+
+    /// Current text of a label
+    ///
+    /// For a scrolling marquee label, this is the full text rather than the
+    /// part currently shown.
+    ///
+    /// # Arguments
+    ///
+    /// * `label_id` - Label item to read
+    ///
+    /// # Returns
+    ///
+    /// The label's text, or an empty string if the item is not a label.
+    fn label_text(&self, label_id: &ItemId) -> String { String::new() }
+
+    /// Changes the width of an existing label
+    ///
+    /// Set a fixed width for a label, in pixels.
+    ///
+    /// # Arguments
+    ///
+    /// * `label_id` - Label item to change
+    /// * `width` - New width of label, in pixels
+    ///
+    fn update_label_width(&mut self, label_id: &ItemId, width: u32) {}
+
+    /// Changes the text in an existing label to styled text
+    ///
+    /// The spans are concatenated in order, each with its own color, weight,
+    /// and size.  This allows highlighting part of a label, such as a single
+    /// word, without splitting it into multiple items.
+    ///
+    /// # Arguments
+    ///
+    /// * `label_id` - Label item to change
+    /// * `spans` - Styled runs of text to display, in order
+    ///
+    fn update_label_attributed(&mut self, label_id: &ItemId, spans: Vec<TextSpan>) {}
+
+    /// Scroll a label's text horizontally when it is too long to fit
+    ///
+    /// With marquee enabled, text wider than the label continuously scrolls
+    /// from right to left, wrapping around.  Text that fits is displayed
+    /// normally.  Use with `update_label_width()` to fix the label's width.
+    ///
+    /// # Arguments
+    ///
+    /// * `label_id` - Label item to change
+    /// * `enabled` - Whether long text should scroll
+    ///
+    fn set_label_marquee(&mut self, label_id: &ItemId, enabled: bool) {}
+
+    /// Cycle a label through a list of strings
+    ///
+    /// The label shows each string for `interval`, then the next, wrapping
+    /// around to the first.  Intervals shorter than a quarter second are
+    /// lengthened to a quarter second.  The ticker pauses while the label is
+    /// not on screen, and resumes where it left off.
+    ///
+    /// A ticker replaces any marquee on the label, and overwrites text set
+    /// with `update_label()` on its next tick.  Pass an empty list to stop it,
+    /// leaving the current string displayed.
+    ///
+    /// # Arguments
+    ///
+    /// * `label_id` - Label item to change
+    /// * `texts` - Strings to cycle through, in order
+    /// * `interval` - How long each string is displayed
+    ///
+    fn set_label_ticker(&mut self, label_id: &ItemId, texts: &[&str], interval: Duration) {}
+
+    /// Create a label with several lines of text
+    ///
+    /// Each line is a separate text field stacked vertically with Auto
+    /// Layout, so lines can have different font sizes.  The Touch Bar is
+    /// short: two lines fit comfortably, three only with small fonts.
+    ///
+    /// # Arguments
+    ///
+    /// * `lines` - Text of each line, from top to bottom
+    /// * `style` - Font sizes and alignment of the lines
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated multi-line label item
+    fn create_multiline_label(&mut self, lines: &[&str], style: LabelStyle) -> ItemId { ItemId::from_raw(0) }
+
+    /// Changes the text in an existing multi-line label
+    ///
+    /// The number of lines is fixed when the label is created.  Extra lines
+    /// are ignored, and missing lines are left blank.
+    ///
+    /// # Arguments
+    ///
+    /// * `label_id` - Multi-line label item to change
+    /// * `lines` - New text of each line, from top to bottom
+    ///
+    fn update_multiline_label(&mut self, label_id: &ItemId, lines: &[&str]) {}
+
+    /// Create a label that counts down to zero
+    ///
+    /// Creates a label displaying the time remaining, as `M:SS` or `H:MM:SS`,
+    /// which updates itself once per second.  When the countdown reaches zero
+    /// the callback is called once, and the label stays at `0:00`.
+    ///
+    /// # Arguments
+    ///
+    /// * `duration` - Time to count down from
+    /// * `cb` - Callback called when the countdown reaches zero
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated countdown label item
+    fn create_countdown_label(&mut self, duration: Duration, cb: ButtonCb) -> ItemId { ItemId::from_raw(0) }
+
+    /// Restart a countdown label
+    ///
+    /// Starts counting down again from the given duration, whether or not the
+    /// previous countdown had finished.
+    ///
+    /// # Arguments
+    ///
+    /// * `label_id` - Countdown label to restart
+    /// * `duration` - Time to count down from
+    ///
+    fn restart_countdown(&mut self, label_id: &ItemId, duration: Duration) {}
+
+    /// Create an editable text field
+    ///
+    /// Creates a text field that accepts typed input.  While the field is
+    /// being edited, the Touch Bar shows the system's typing candidates
+    /// (autocompletion suggestions) so the user can complete words directly
+    /// on the bar.
+    ///
+    /// Note that typing requires keyboard focus, so the field can only be
+    /// edited while the application is active.
+    ///
+    /// # Arguments
+    ///
+    /// * `placeholder` - Grey text displayed while the field is empty
+    /// * `continuous` - Whether callback is called after every keystroke, or
+    ///   only when editing ends (Return is pressed or focus is lost).
+    /// * `cb` - Callback called with the edited text
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated text field item
+    fn create_text_field(&mut self, placeholder: Option<&str>,
+                         continuous: bool, cb: TextCb) -> ItemId { ItemId::from_raw(0) }
+
+    /// Changes the text in an existing text field
+    ///
+    /// This does not trigger the text field's callback.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Text field item to change
+    /// * `text` - New text of the field
+    ///
+    fn update_text_field(&mut self, id: &ItemId, text: &str) {}
+
+    /// Create a canvas item with custom-drawn contents
+    ///
+    /// Creates a fixed-width item that displays an image drawn by the
+    /// application, for things like waveforms, meters, or gauges.  The draw
+    /// callback is called once when the canvas is created, and again each time
+    /// `redraw_canvas()` is called.
+    ///
+    /// The Touch Bar is a Retina display, so the image requested from the
+    /// callback is twice the width and height of the canvas on screen.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - Width of the canvas on the bar, in pixels
+    /// * `cb` - Callback that draws the canvas contents
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated canvas item
+    fn create_canvas(&mut self, width: u32, cb: CanvasCb) -> ItemId { ItemId::from_raw(0) }
+
+    /// Redraw a canvas item
+    ///
+    /// Calls the canvas's draw callback and displays the result.
+    ///
+    /// # Arguments
+    ///
+    /// * `canvas_id` - Canvas item to redraw
+    ///
+    fn redraw_canvas(&mut self, canvas_id: &ItemId) {}
+
+    /// Create a canvas item that fills all free space in the bar
+    ///
+    /// Like `create_canvas()`, but instead of a fixed width, the canvas
+    /// stretches to take whatever width the other items of the bar leave
+    /// free, for dashboard-style layouts.  The width isn't known until the
+    /// bar is laid out, so `resize_cb` is called with the width granted
+    /// each time it changes, and the canvas is redrawn at the new size.
+    ///
+    /// # Arguments
+    ///
+    /// * `cb` - Callback that draws the canvas contents
+    /// * `resize_cb` - Callback called with the new width of the canvas
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated canvas item
+    fn create_full_width_canvas(&mut self, cb: CanvasCb, resize_cb: ResizeCb) -> ItemId { ItemId::from_raw(0) }
+
+    /// Create a sparkline graph item
+    ///
+    /// Creates a fixed-width item that draws a small line graph of a series
+    /// of values, such as a history of CPU usage.  The graph is empty until
+    /// values are provided with `update_graph()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - Width of the graph on the bar, in pixels
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated graph item
+    fn create_graph(&mut self, width: u32) -> ItemId { ItemId::from_raw(0) }
+
+    /// Replace the values drawn by a sparkline graph
+    ///
+    /// The values are spread evenly across the width of the graph, oldest on
+    /// the left, and scaled vertically so the smallest and largest values
+    /// touch the bottom and top edges.
+    ///
+    /// # Arguments
+    ///
+    /// * `graph_id` - Graph item to update
+    /// * `values` - Values to draw, in order
+    ///
+    fn update_graph(&mut self, graph_id: &ItemId, values: &[f64]) {}
+
+    /// Create a horizontally scrolling 'scrubber' of text
+    ///
+    /// Creates a Scrubber, which is a  horizontally scrolling widget filled
+    /// with items which can be dynamically changed, and selected.  This is the
+    /// primary interface for choosing from a list of (possibly dynamic) options.
+    ///
+    /// Scrubbers are filled with data using a collection of callbacks.  This
+    /// is implemented by the [TScrubberData](trait.TScrubberData.html) trait,
+    /// which allows you to build a custom backing data store for scrubbers.
+    ///
+    /// At creation, no item is selected.  Call `select_scrubber_item()` after
+    /// this to render an item as selected.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - An object implementing the `TScrubberData` trait, wrapped
+    ///   in a reference counter (Rc).
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated scrubber item
+    #[deprecated(since = "0.9.2", note = "use TTouchbar2::new_scrubber()")]
+    fn create_text_scrubber(&mut self, data: Rc<dyn TScrubberData>) -> ItemId { ItemId::from_raw(0) }
+
+    /// Create a text scrubber with a specific selection style
+    ///
+    /// Identical to `create_text_scrubber()` followed by
+    /// `set_scrubber_style()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - An object implementing the `TScrubberData` trait, wrapped
+    ///   in a reference counter (Rc).
+    /// * `style` - How to draw the selected item
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated scrubber item
+    #[deprecated(since = "0.9.2", note = "use TTouchbar2::new_scrubber()")]
+    #[allow(deprecated)]
+    fn create_text_scrubber_with_style(&mut self, data: Rc<dyn TScrubberData>,
+                                       style: ScrubberStyle) -> ItemId {
+        let item = self.create_text_scrubber(data);
+        self.set_scrubber_style(&item, style);
+        item
+    }
+
+    /// Selects the given index in a scrubber
+    ///
+    /// Marks the given index in the given scrubber as selected, so that item
+    /// will be displayed with highlighting.  If this causes a selection
+    /// callback, it goes to `TScrubberData::selected_programmatically()`
+    /// rather than `touch()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `scrub_id` - Scrubber to select in
+    /// * `index` - Index of the item to mark as selected
+    ///
+    fn select_scrubber_item(&mut self, scrub_id: &ItemId, index: u32) {}
+
+    /// Currently selected index of a scrubber
+    ///
+    /// # Arguments
+    ///
+    /// * `scrub_id` - Scrubber to read
+    ///
+    /// # Returns
+    ///
+    /// The selected index, or `None` if nothing is selected or the item is
+    /// not a scrubber.
+    fn scrubber_selected(&self, scrub_id: &ItemId) -> Option<u32> { None }
+
+    /// Scroll a scrubber so the given index is visible
+    ///
+    /// Selecting an item does not scroll to it, so this can be used after
+    /// `select_scrubber_item()` to bring the selection on screen in a long
+    /// list.  The scrubber scrolls as little as needed, and does nothing if
+    /// the item is already visible.
+    ///
+    /// # Arguments
+    ///
+    /// * `scrub_id` - Scrubber to scroll
+    /// * `index` - Index of the item to show
+    /// * `animated` - Whether to animate the scrolling
+    ///
+    fn scroll_scrubber_to(&mut self, scrub_id: &ItemId, index: u32, animated: bool) {}
+
+    /// Inform a scrubber to redraw after a change to its backing data
+    ///
+    /// If the data store backing a scrubber (`TScrubberData`) has its data
+    /// change, this function should be called to inform the scrubber to
+    /// redraw.
+    ///
+    /// # Arguments
+    ///
+    /// * `scrub_id` - Scrubber to refresh
+    ///
+    fn refresh_scrubber(&mut self, scrub_id: &ItemId) {}
+
+    /// Inform a scrubber that items were inserted into its backing data
+    ///
+    /// Unlike `refresh_scrubber()`, only the new items are loaded, and they
+    /// are animated into place without losing the scroll position.  The
+    /// `TScrubberData` must already include the new items when this is
+    /// called.
+    ///
+    /// # Arguments
+    ///
+    /// * `scrub_id` - Scrubber to update
+    /// * `index` - Index of the first inserted item
+    /// * `count` - Number of items inserted
+    ///
+    fn scrubber_insert_items(&mut self, scrub_id: &ItemId, index: u32, count: u32) {}
+
+    /// Inform a scrubber that items were removed from its backing data
+    ///
+    /// The removed items are animated out without reloading the rest of the
+    /// scrubber.  The `TScrubberData` must already exclude the removed items
+    /// when this is called.
+    ///
+    /// # Arguments
+    ///
+    /// * `scrub_id` - Scrubber to update
+    /// * `index` - Index of the first removed item
+    /// * `count` - Number of items removed
+    ///
+    fn scrubber_remove_items(&mut self, scrub_id: &ItemId, index: u32, count: u32) {}
+
+    /// Redraw every scrubber backed by the given data store
+    ///
+    /// When one `TScrubberData` backs several scrubbers, this refreshes all
+    /// of them at once, without the application keeping track of each one.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Data store whose scrubbers should be refreshed
+    ///
+    fn refresh_scrubbers_for_data(&mut self, data: &Rc<dyn TScrubberData>) {}
+
+    /// Highlights the given index in a scrubber without selecting it
+    ///
+    /// Highlighting draws an item as if a finger were resting on it, which
+    /// can be used to indicate a candidate item before committing to it with
+    /// `select_scrubber_item()`.  Highlighting does not change the selection,
+    /// and does not trigger the `touch` callback of the scrubber's data.
+    ///
+    /// Only one item can be highlighted at a time.
+    ///
+    /// # Arguments
+    ///
+    /// * `scrub_id` - Scrubber to highlight in
+    /// * `index` - Index of the item to highlight
+    ///
+    fn highlight_scrubber_item(&mut self, scrub_id: &ItemId, index: u32) {}
+
+    /// Removes any highlight from a scrubber
+    ///
+    /// # Arguments
+    ///
+    /// * `scrub_id` - Scrubber to clear the highlight of
+    ///
+    fn clear_scrubber_highlight(&mut self, scrub_id: &ItemId) {}
+
+    /// Change the scrolling mode of a scrubber
+    ///
+    /// Scrubbers are created in `DEFAULT_SCRUBBER_MODE`.  This switches an
+    /// existing scrubber between paging (`Fixed`) and momentum scrolling
+    /// (`Free`), and can be called at any time, including while the scrubber
+    /// is visible.
+    ///
+    /// # Arguments
+    ///
+    /// * `scrub_id` - Scrubber to change
+    /// * `mode` - New scrolling mode
+    ///
+    fn set_scrubber_mode(&mut self, scrub_id: &ItemId, mode: ScrubberMode) {}
+
+    /// Set whether a scrubber's selection follows the finger while scrolling
+    ///
+    /// Scrubbers are created non-continuous, so items are only selected when
+    /// tapped.  When continuous, the item under the finger is selected, and
+    /// the `touch` callback of the scrubber's data is called, as the finger
+    /// slides across the scrubber.
+    ///
+    /// # Arguments
+    ///
+    /// * `scrub_id` - Scrubber to change
+    /// * `continuous` - Whether selection changes continuously
+    ///
+    fn set_scrubber_continuous(&mut self, scrub_id: &ItemId, continuous: bool) {}
+
+    /// Show or hide arrow buttons on the ends of a scrubber
+    ///
+    /// The arrows scroll through the scrubber's items, so long lists can be
+    /// stepped through as well as swiped.  Scrubbers are created without
+    /// arrows.
+    ///
+    /// # Arguments
+    ///
+    /// * `scrub_id` - Scrubber to change
+    /// * `shown` - Whether the arrows are shown
+    ///
+    fn set_scrubber_arrow_buttons(&mut self, scrub_id: &ItemId, shown: bool) {}
+
+    /// Change how a scrubber draws its selected item
+    ///
+    /// Scrubbers are created with `DEFAULT_SCRUBBER_STYLE`, unless created
+    /// with `TTouchbar2::new_scrubber()` and another style.
+    ///
+    /// # Arguments
+    ///
+    /// * `scrub_id` - Scrubber to change
+    /// * `style` - New selection style
+    ///
+    fn set_scrubber_style(&mut self, scrub_id: &ItemId, style: ScrubberStyle) {}
+
+    /// Register a tap gesture handler with a Touch Bar item
+    ///
+    /// Registers a callback to be called when the given item is tapped with a
+    /// one or more fingers.  This works  even for items that are normally
+    /// non-interactive (like labels).
+    ///
+    /// Supports specifying number of taps (i.e. double- or triple-click), and
+    /// number of fingers independently.  For instance, set `taps` to 2 and
+    /// `fingers` to 3 to require double-clicking with 3 fingers at the same
+    /// time.
+    ///
+    /// # Arguments
+    ///
+    /// * `item_id` - Item to add the gesture detection to
+    /// * `taps` - Number of discrete taps to trigger callback
+    /// * `fingers` - Number of simultaneous fingers needed
+    /// * `cb` - Callback to call when a tap is detected
+    ///
+    fn add_item_tap_gesture(&mut self, item_id: &ItemId, taps: u32,
+                            fingers: u32, cb: ButtonCb) {}
+
+    /// Register a tap gesture handler that receives full event details
+    ///
+    /// Identical to `add_item_tap_gesture()`, except the callback receives an
+    /// `ItemEvent` containing both the item and its inner control.
+    ///
+    /// # Arguments
+    ///
+    /// * `item_id` - Item to add the gesture detection to
+    /// * `taps` - Number of discrete taps to trigger callback
+    /// * `fingers` - Number of simultaneous fingers needed
+    /// * `cb` - Callback to call when a tap is detected
+    ///
+    fn add_item_tap_gesture_with_event(&mut self, item_id: &ItemId, taps: u32,
+                                       fingers: u32, cb: EventCb) {}
+
+    /// Register a swipe gesture handler with a Touch Bar item
+    ///
+    /// Registers a callback to be called when the given item is swiped with a
+    /// horizontal motion.  This can be used to detect a finger sliding back and
+    /// forth on a Touch Bar item, even for items that are normally
+    /// non-interactive (like labels).
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - Item to add the gesture detection to
+    /// * `cb` - Callback to call when a touch is detected
+    ///
+    fn add_item_swipe_gesture(&mut self, item: &ItemId, cb: SwipeCb) {}
+
+    /// Register a handler for any supported gesture on a Touch Bar item
+    ///
+    /// This is the general form of `add_item_tap_gesture()` and
+    /// `add_item_swipe_gesture()`, supporting every type of gesture in
+    /// `GestureKind`.  Like those, it works even for items that are normally
+    /// non-interactive (like labels), and any number of gestures can be
+    /// added to the same item.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - Item to add the gesture detection to
+    /// * `kind` - Type of gesture to detect
+    /// * `config` - Options for the gesture
+    /// * `cb` - Callback to call as the gesture progresses
+    ///
+    fn add_custom_gesture(&mut self, item: &ItemId, kind: GestureKind,
+                          config: GestureConfig, cb: GestureCb) {}
+
+    /// Turn any item into a fader controlled by dragging across it
+    ///
+    /// Attaches a pan gesture that adjusts a value between `min` and `max`.
+    /// Dragging across the full width of the item covers the whole range, and
+    /// each drag continues from where the last one left off.  The value
+    /// starts at `min`.  Optionally, the item is filled from the left with a
+    /// color in proportion to the value, drawn behind the item's content.
+    /// Does nothing if `max` isn't greater than `min`.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - Item to make draggable, such as a label or image
+    /// * `min` - Minimum value
+    /// * `max` - Maximum value
+    /// * `fill` - Color of the fill as red, green, blue, and alpha values
+    ///   (0.0 - 1.0), or `None` for no fill
+    /// * `cb` - Callback called with the new value whenever it changes
+    ///
+    fn make_item_draggable_value(&mut self, item: &ItemId, min: f64, max: f64,
+                                 fill: Option<(f64, f64, f64, f64)>, cb: SliderCb) {}
+
+    /// Create space between items in a bar
+    ///
+    /// Popover bars only show spacers between other items.  Spacers at either
+    /// end of a popover bar are left out and reported to the `on_error()`
+    /// callback as `TouchbarError::UnsupportedItem`.
+    ///
+    /// # Arguments
+    ///
+    /// * `space` - The type of spacer to create
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated spacer item that can be added to a bar
+    ///
+    fn create_spacer(&mut self, space: SpacerType) -> ItemId { ItemId::from_raw(0) }
+
+    /// Create an image from a file path
+    ///
+    /// Creates an image that can be assigned to UI items that display them,
+    /// like buttons and popovers.
+    ///
+    /// Specify a relative or absolute path to the image.
+    ///
+    /// When specifying an image, follow Apple's guidelines for icons on Retina
+    /// displays (40px x 40px @ 150dpi PNG recommended), and remember to handle
+    /// paths into the app bundle correctly.
+    ///
+    /// **WARNING** image memory is _deallocated_ after it is assigned to an
+    /// item.  Do **not** use the same image twice.  If two buttons will have
+    /// the same image, you must allocate the image twice.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to an image file
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated image that can be added to an item
+    ///
+    fn create_image_from_path(&mut self, path: &str) -> TouchbarImage { TouchbarImage(0) }
+
+    /// Create an image downloaded from a URL
+    ///
+    /// Returns an empty placeholder image immediately, and downloads the image
+    /// on a background thread.  When the download finishes, the placeholder is
+    /// filled in on the main thread, updating any buttons or scrubbers already
+    /// showing it.  Downloads are cached on disk, and cached images are loaded
+    /// immediately.  Download failures are reported through `on_error()`.
+    ///
+    /// The same memory rules as `create_image_from_path()` apply.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - URL of an image file
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated image that can be added to an item
+    ///
+    fn create_image_from_url(&mut self, url: &str) -> TouchbarImage { TouchbarImage(0) }
+
+    /// Choose what image creation returns when a file can't be loaded
+    ///
+    /// Defaults to `MissingImageBehavior::Placeholder`.
+    ///
+    /// # Arguments
+    ///
+    /// * `behavior` - What `create_image_from_path()` returns on failure
+    ///
+    fn set_missing_image_behavior(&mut self, behavior: MissingImageBehavior) {}
+
+    /// Create an image from a template
+    ///
+    /// Creates an image that can be assigned to UI items that display them,
+    /// like buttons and popovers.
+    ///
+    /// See `ImageTemplate` for the supported options.  These templates are
+    /// provided by Apple.
+    ///
+    /// **WARNING** image memory is _deallocated_ after it is assigned to an
+    /// item.  Do **not** use the same image twice.  If two buttons will have
+    /// the same image, you must allocate the image twice.
+    ///
+    /// # Arguments
+    ///
+    /// * `template` - Identifier of the image template to use
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated image that can be added to an item
+    ///
+    fn create_image_from_template(&mut self, template: ImageTemplate) -> TouchbarImage { TouchbarImage(0) }
+
+    /// Create a button that triggers a callback when pressed
+    ///
+    /// All buttons accept an image, text, or both.  If both are provided, they
+    /// will both be displayed at the same time.
+    ///
+    /// # Arguments
+    ///
+    /// * `image` - An image allocated with a `create_image_*` function
+    /// * `text` - Text to display on the button
+    /// * `cb` - Callback to call when the button is pressed
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated item which can be added to a bar.
+    #[deprecated(since = "0.9.2", note = "use TTouchbar2::new_button()")]
+    fn create_button(&mut self, image: Option<&TouchbarImage>, text: Option<&str>, cb: ButtonCb) -> ItemId { ItemId::from_raw(0) }
+
+    /// Create a button whose callback receives full event details
+    ///
+    /// Identical to `create_button()`, except the callback receives an
+    /// `ItemEvent` containing both the item and its inner `NSButton` control.
+    ///
+    /// # Arguments
+    ///
+    /// * `image` - An image allocated with a `create_image_*` function
+    /// * `text` - Text to display on the button
+    /// * `cb` - Callback to call when the button is pressed
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated item which can be added to a bar.
+    fn create_button_with_event(&mut self, image: Option<&TouchbarImage>,
+                                text: Option<&str>, cb: EventCb) -> ItemId { ItemId::from_raw(0) }
+
+    /// Create a button that reveals the standard function keys
+    ///
+    /// While a Rubrail bar is presented it covers the system's Control Strip
+    /// content, such as brightness and volume.  Tapping this button minimizes
+    /// the presented bars so that content is reachable again, and optionally
+    /// presents the root bar again after a delay.
+    ///
+    /// # Arguments
+    ///
+    /// * `image` - Image to display on the button
+    /// * `text` - Text to display on the button
+    /// * `represent_after` - Delay before presenting the root bar again, or
+    ///   `None` to leave it minimized until the user opens it
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated passthrough button item
+    fn create_passthrough_button(&mut self, image: Option<&TouchbarImage>, text: Option<&str>,
+                                 represent_after: Option<Duration>) -> ItemId { ItemId::from_raw(0) }
+
+    /// Create a menu row button
+    ///
+    /// A menu row is a wide button laid out like an entry in a menu: an
+    /// optional icon and a title on the left, and optional detail text and a
+    /// chevron on the right.  Stacking rows in a popover bar makes it look
+    /// and behave like a menu, without assembling labels, buttons, and
+    /// spacers by hand.
+    ///
+    /// # Arguments
+    ///
+    /// * `icon` - Image to display before the title
+    /// * `title` - Main text of the row
+    /// * `detail` - Secondary text displayed dimmed on the right
+    /// * `chevron` - Whether to show a chevron, hinting that the row opens
+    ///   something
+    /// * `cb` - Callback to call when the row is pressed
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated menu row item
+    fn create_menu_row(&mut self, icon: Option<&TouchbarImage>, title: &str,
+                       detail: Option<&str>, chevron: bool, cb: ButtonCb) -> ItemId { ItemId::from_raw(0) }
+
+    /// Create standard media transport controls
+    ///
+    /// Creates a single item with previous, play/pause, and next buttons,
+    /// using Apple's standard images.  Presses of any of the buttons are
+    /// reported to one callback.
+    ///
+    /// # Arguments
+    ///
+    /// * `cb` - Callback called with the button that was pressed
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated media controls item
+    fn create_media_controls(&mut self, cb: MediaCb) -> ItemId { ItemId::from_raw(0) }
+
+    /// Create an item with two rows stacked vertically
+    ///
+    /// Splits the height of the bar into a top and bottom row, each holding
+    /// a small label, button, or slider.  Useful for dense widgets like
+    /// two-line meters or pairs of small buttons.  Each row calls its own
+    /// callback.
+    ///
+    /// # Arguments
+    ///
+    /// * `top` - Contents of the top row
+    /// * `bottom` - Contents of the bottom row
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated stacked item
+    fn create_stacked_item(&mut self, top: ItemSpec, bottom: ItemSpec) -> ItemId { ItemId::from_raw(0) }
+
+    /// Show whether media is playing on a media controls item
+    ///
+    /// The play/pause button shows a combined play/pause image until this
+    /// is called.  After that, it shows a pause image while playing and a
+    /// play image while paused.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Media controls item to update
+    /// * `playing` - Whether media is currently playing
+    ///
+    fn update_media_playing(&mut self, id: &ItemId, playing: bool) {}
+
+    /// Create an item showing what the system is currently playing
+    ///
+    /// Shows the title and artist of the media the system's Now Playing
+    /// information reports, from any application, and updates itself when
+    /// it changes.  Combine it with `create_media_controls()` for a complete
+    /// media bar.
+    ///
+    /// Now Playing information comes from a private framework, so this
+    /// does nothing without the `private_api` feature.
+    ///
+    /// # Arguments
+    ///
+    /// * `artwork` - Whether to show album artwork to the left of the text
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated Now Playing item
+    fn create_now_playing_item(&mut self, artwork: bool) -> ItemId { ItemId::from_raw(0) }
+
+    /// Changes the image and/or text of a button
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - Button item to change
+    /// * `image` - New image to draw on button (optional)
+    /// * `text` - New text to draw on button (optional)
+    ///
+    fn update_button(&mut self, item: &ItemId, image: Option<&TouchbarImage>, text: Option<&str>) {}
+
+    /// Current text of a button
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - Button or popover item to read
+    ///
+    /// # Returns
+    ///
+    /// The button's title, without any subtitle, or an empty string if the
+    /// item is not a button.
+    fn button_text(&self, item: &ItemId) -> String { String::new() }
+
+    /// Changes the width of an existing button
+    ///
+    /// Set a fixed width for a button, in pixels.
+    ///
+    /// # Arguments
+    ///
+    /// * `button_id` - Button item to change
+    /// * `width` - New width of button, in pixels
+    ///
+    fn update_button_width(&mut self, button_id: &ItemId, width: u32) {}
+
+    /// Show a second, smaller line of text under a button's title
+    ///
+    /// For buttons where a single short title isn't descriptive enough,
+    /// like a profile switcher showing "Profile" over the active profile's
+    /// name.  Both lines are drawn smaller than a normal title so they fit
+    /// the height of the bar.  Changing the title with `update_button()`
+    /// keeps the subtitle.
+    ///
+    /// # Arguments
+    ///
+    /// * `button_id` - Button item to change
+    /// * `subtitle` - Second line of text, or `None` to show only the title
+    ///
+    fn set_button_subtitle(&mut self, button_id: &ItemId, subtitle: Option<&str>) {}
+
+    /// Enable or disable a button
+    ///
+    /// A disabled button is drawn grayed out and ignores presses, so actions
+    /// that aren't currently valid can stay in the bar without doing
+    /// anything.  Its callback isn't called while it is disabled, including
+    /// for presses that were already being held for auto-repeat or
+    /// hold-to-confirm.
+    ///
+    /// # Arguments
+    ///
+    /// * `button_id` - Button item to change
+    /// * `enabled` - Whether the button can be pressed
+    ///
+    fn set_button_enabled(&mut self, button_id: &ItemId, enabled: bool) {}
+
+    /// Set whether a button latches when pressed
+    ///
+    /// Buttons are created momentary: they highlight only while pressed.  A
+    /// latching button toggles between highlighted and normal on each press
+    /// and stays that way, so mode buttons can show which mode is active
+    /// without swapping their images.
+    ///
+    /// # Arguments
+    ///
+    /// * `button_id` - Button item to change
+    /// * `latching` - Whether the button stays highlighted after a press
+    ///
+    fn set_button_latching(&mut self, button_id: &ItemId, latching: bool) {}
+
+    /// Repeat a button's callback while it is held down
+    ///
+    /// Once the button has been held for a moment, its callback is called
+    /// immediately and then again every `interval` until it is released,
+    /// like the volume keys.  A quick press still calls it once.
+    ///
+    /// # Arguments
+    ///
+    /// * `button_id` - Button item to change
+    /// * `interval` - Time between repeats, or `None` to stop repeating
+    ///
+    fn set_button_auto_repeat(&mut self, button_id: &ItemId, interval: Option<Duration>) {}
+
+    /// Require a button to be held down before it fires
+    ///
+    /// Guards destructive buttons, like Quit, against accidental brushes on
+    /// an always-visible bar.  The button fills from left to right while it
+    /// is held, and its callback is called once it has been held for `hold`.
+    /// Releasing it sooner cancels the press.
+    ///
+    /// # Arguments
+    ///
+    /// * `button_id` - Button item to change
+    /// * `hold` - How long the button must be held, or `None` to fire on a
+    ///   normal press again
+    ///
+    fn set_button_hold_to_confirm(&mut self, button_id: &ItemId, hold: Option<Duration>) {}
+
+    /// Highlight or clear a latching button
+    ///
+    /// Changes the state of a button made latching with
+    /// `set_button_latching()` without calling its callback, such as to clear
+    /// the other buttons of a group when one is pressed.
+    ///
+    /// # Arguments
+    ///
+    /// * `button_id` - Button item to change
+    /// * `latched` - Whether the button is highlighted
+    ///
+    fn set_button_latched(&mut self, button_id: &ItemId, latched: bool) {}
+
+    /// Draw a badge on the corner of a button's image
+    ///
+    /// Shows a count, like unread notifications, without swapping the whole
+    /// image.  A count of 0 draws a plain dot, and counts over 99 are shown as
+    /// "99+".  The badge stays when the image is changed with
+    /// `update_button()`.  Buttons without an image are not badged.
+    ///
+    /// # Arguments
+    ///
+    /// * `button_id` - Button item to change
+    /// * `badge` - Count to show, or `None` to remove the badge
+    ///
+    fn update_button_badge(&mut self, button_id: &ItemId, badge: Option<u32>) {}
+
+    /// Set light and dark variants of a button's image
+    ///
+    /// Replaces the image of a button or popover with a pair of images, one
+    /// for the light system appearance and one for dark.  Rubrail displays the
+    /// one matching the current appearance, and switches automatically when
+    /// it changes.
+    ///
+    /// Both images are consumed, like any image assigned to an item.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - Button or popover item to change
+    /// * `light` - Image displayed in light mode
+    /// * `dark` - Image displayed in dark mode
+    ///
+    fn set_button_image_variants(&mut self, item: &ItemId, light: TouchbarImage,
+                                 dark: TouchbarImage) {}
+
+    /// Mark an image as a template image
+    ///
+    /// Template images are drawn as a mask using only their alpha channel,
+    /// and are automatically tinted to contrast with the current appearance.
+    /// This is the simplest way to keep monochrome icons legible in both
+    /// light and dark mode.  The images from `create_image_from_template()`
+    /// are already template images.
+    ///
+    /// Must be called before the image is assigned to an item.
+    ///
+    /// # Arguments
+    ///
+    /// * `image` - Image to change
+    /// * `template` - Whether the image is a template
+    ///
+    fn set_image_template(&mut self, image: &TouchbarImage, template: bool) {}
+
+    /// Create a slider item
+    ///
+    /// Creates an item that displays as a continuously variable horizontal
+    /// slider, reporting its value as a floating point between the provided
+    /// minimum and maximum values.
+    ///
+    /// When the slider value changes, the associated callback is called.  Note
+    /// that it triggers frequently as the user slides it, so 'debouncing' or
+    /// buffering might be required if high-frequency changes are not desired.
+    ///
+    /// Newly created sliders default to the minimum value.  If you need to
+    /// change this, set the current value with `update_slider()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `min` - Minimum value (slider all the way left)
+    /// * `max` - Maximum value (slider all the way right)
+    /// * `label` - Text label displayed on left of slider (optional)
+    /// * `continuous` - Whether callback is called while sliding, or only
+    /// * `cb` - Callback called when the slider value is changed
+    ///   after it is released.
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated slider item
+    #[deprecated(since = "0.9.2", note = "use TTouchbar2::new_slider()")]
+    fn create_slider(&mut self, min: f64, max: f64,
+                     label: Option<&str>,
+                     continuous: bool, cb: SliderCb) -> ItemId { ItemId::from_raw(0) }
+
+    /// Create a slider bound to the system output volume
+    ///
+    /// Moving the slider sets the volume of the default output device, and
+    /// the slider follows volume changes made elsewhere, like from the
+    /// keyboard or another output device becoming the default.  The slider
+    /// ranges from 0.0 to 1.0, and can be customized like any other slider.
+    ///
+    /// Only available with the `system_controls` feature.
     ///
     /// # Arguments
     ///
-    /// * `min` - Minimum value (slider all the way left)
-    /// * `max` - Maximum value (slider all the way right)
-    /// * `label` - Text label displayed on left of slider (optional)
-    /// * `continuous` - Whether callback is called while sliding, or only
-    /// * `cb` - Callback called when the slider value is changed
-    ///   after it is released.
+    /// * `label` - Text label to display on left of the slider
     ///
     /// # Returns
     ///
     /// A newly allocated slider item
-    fn create_slider(&mut self, min: f64, max: f64,
-                     label: Option<&str>,
-                     continuous: bool, cb: SliderCb) -> ItemId {0}
+    #[cfg(feature = "system_controls")]
+    fn create_volume_slider(&mut self, label: Option<&str>) -> ItemId { ItemId::from_raw(0) }
+
+    /// Create a slider bound to the brightness of the main display
+    ///
+    /// Like the brightness control of the Control Strip: moving the slider
+    /// sets the display brightness, and the slider follows brightness
+    /// changes made elsewhere, checking about once a second while it is
+    /// displayed and not being dragged.  The slider ranges from 0.0 to 1.0.  External displays usually can't be
+    /// controlled, in which case the slider does nothing.
+    ///
+    /// Only available with the `system_controls` feature.  Display
+    /// brightness is controlled with a private framework, so this does
+    /// nothing without the `private_api` feature.
+    ///
+    /// # Arguments
+    ///
+    /// * `label` - Text label to display on left of the slider
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated slider item
+    #[cfg(feature = "system_controls")]
+    fn create_brightness_slider(&mut self, label: Option<&str>) -> ItemId { ItemId::from_raw(0) }
 
     /// Update the current position of a slider
     ///
@@ -604,8 +3109,1112 @@ pub trait TTouchbar {
     /// # Arguments
     ///
     /// * `id` - Slider item to update
-    /// * `value` - New value of the slider.  Must be between the min and max
-    ///   specified when the slider was created.
+    /// * `value` - New value of the slider.  Must be between the slider's
+    ///   current min and max.
     ///
     fn update_slider(&mut self, id: &ItemId, value: f64) {}
+
+    /// Current position of a slider
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Slider or knob item to read
+    ///
+    /// # Returns
+    ///
+    /// The slider's value, or 0 if the item is not a slider.
+    fn slider_value(&self, id: &ItemId) -> f64 { 0.0 }
+
+    /// Change the range of a slider
+    ///
+    /// The slider's current value is moved into the new range if it falls
+    /// outside of it.  The slider's callback is not called for the move.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Slider item to change
+    /// * `min` - New minimum value (slider all the way left)
+    /// * `max` - New maximum value (slider all the way right)
+    ///
+    fn update_slider_range(&mut self, id: &ItemId, min: f64, max: f64) {}
+
+    /// Change the text label displayed on the left of a slider
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Slider item to change
+    /// * `label` - New label of the slider
+    ///
+    fn update_slider_label(&mut self, id: &ItemId, label: &str) {}
+
+    /// Make a slider move in discrete steps
+    ///
+    /// Adds evenly spaced tick marks to a slider, optionally snapping the
+    /// slider to them.  With `integer_only`, values are rounded to whole
+    /// numbers and the callback is only called when the rounded value
+    /// changes, so a setting like "font size 8-24" doesn't deliver a stream of
+    /// fractional values.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Slider item to change
+    /// * `tick_marks` - Number of tick marks, including both ends, or 0 for none
+    /// * `snap` - Whether the slider can only stop on tick marks
+    /// * `integer_only` - Whether to round values to whole numbers
+    ///
+    fn set_slider_steps(&mut self, id: &ItemId, tick_marks: u32, snap: bool, integer_only: bool) {}
+
+    /// Add an accessory button to one end of a slider
+    ///
+    /// Accessories are small image buttons beside the slider, such as a mute
+    /// button next to a volume slider.  With a callback, pressing the
+    /// accessory calls it with the slider's `ItemId`.  Without one, pressing
+    /// it moves the slider one step towards that end.  Adding an accessory
+    /// to an end that already has one replaces it.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Slider item to change
+    /// * `side` - End of the slider to attach the accessory to
+    /// * `image` - Image to display on the accessory
+    /// * `cb` - Callback to call when the accessory is pressed
+    ///
+    fn set_slider_accessory(&mut self, id: &ItemId, side: SliderAccessory,
+                            image: &TouchbarImage, cb: Option<ButtonCb>) {}
+
+    /// Create a rotary knob item
+    ///
+    /// Creates a compact circular dial that reports a continuous value between
+    /// the provided minimum and maximum, like a slider that takes up much less
+    /// space on the bar.  The knob is turned by touching it and dragging a
+    /// finger horizontally: right to increase, left to decrease.
+    ///
+    /// The callback is called continuously as the knob turns.
+    ///
+    /// Newly created knobs default to the minimum value.  If you need to
+    /// change this, set the current value with `update_knob()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `min` - Minimum value (knob turned fully counter-clockwise)
+    /// * `max` - Maximum value (knob turned fully clockwise)
+    /// * `cb` - Callback called when the knob value is changed
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated knob item
+    fn create_knob(&mut self, min: f64, max: f64, cb: SliderCb) -> ItemId { ItemId::from_raw(0) }
+
+    /// Update the current position of a knob
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Knob item to update
+    /// * `value` - New value of the knob.  Must be between the min and max
+    ///   specified when the knob was created.
+    ///
+    fn update_knob(&mut self, id: &ItemId, value: f64) {}
+
+    /// Create a star rating item
+    ///
+    /// Displays a row of stars, some of them filled to show a rating.  The
+    /// user sets the rating by tapping a star, or adjusts it by sliding a
+    /// finger along the row.  Starts with no stars filled.
+    ///
+    /// # Arguments
+    ///
+    /// * `stars` - Number of stars, which is the maximum rating
+    /// * `cb` - Callback called when the user changes the rating
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated rating item
+    fn create_rating(&mut self, stars: u32, cb: RatingCb) -> ItemId { ItemId::from_raw(0) }
+
+    /// Change the rating shown by a rating item
+    ///
+    /// Does not call the item's callback.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Rating item to update
+    /// * `rating` - New rating, limited to the number of stars
+    ///
+    fn update_rating(&mut self, id: &ItemId, rating: u32) {}
+
+    /// Create a date and/or time picker item
+    ///
+    /// Creates a compact, editable date field.  Touching one of its components
+    /// (day, hour, etc) and dragging adjusts it, and the callback is called
+    /// with the complete newly selected date after every change.
+    ///
+    /// # Arguments
+    ///
+    /// * `elements` - Which components of the date to display
+    /// * `date` - Initially selected date and time
+    /// * `cb` - Callback called when the selected date is changed
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated date picker item
+    fn create_date_picker(&mut self, elements: DatePickerElements,
+                          date: SystemTime, cb: DateCb) -> ItemId { ItemId::from_raw(0) }
+
+    /// Change the selected date of a date picker
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Date picker item to update
+    /// * `date` - Newly selected date and time
+    ///
+    fn update_date_picker(&mut self, id: &ItemId, date: SystemTime) {}
+}
+
+/// Options for buttons created with `TTouchbar2`
+///
+/// At least one of `image` or `text` should be set.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct ButtonOptions {
+    /// Image to display on the button
+    pub image: Option<TouchbarImage>,
+    /// Text to display on the button
+    pub text: Option<String>,
+    /// Smaller second line of text to display under `text`.  Ignored for
+    /// popovers.
+    pub subtitle: Option<String>,
+    /// Whether the button stays highlighted after a press, toggling on each
+    /// press.  Ignored for popovers.
+    pub latching: bool,
+}
+
+/// Options for scrubbers created with `TTouchbar2`
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct ScrubberOptions {
+    /// How the selected item is drawn
+    pub style: ScrubberStyle,
+    /// Scrolling behavior, paged (`Fixed`) or free
+    pub mode: ScrubberMode,
+    /// Whether the selection follows the finger while scrolling
+    ///
+    /// When `false`, an item is only selected when tapped.
+    pub continuous: bool,
+    /// Whether arrow buttons for stepping through items are shown
+    pub arrow_buttons: bool,
+}
+
+impl Default for ScrubberOptions {
+    fn default() -> ScrubberOptions {
+        ScrubberOptions {
+            style: DEFAULT_SCRUBBER_STYLE,
+            mode: DEFAULT_SCRUBBER_MODE,
+            continuous: false,
+            arrow_buttons: false,
+        }
+    }
+}
+
+/// Options for sliders created with `TTouchbar2`
+#[derive(PartialEq, Debug, Clone)]
+pub struct SliderOptions {
+    /// Minimum value (slider all the way left)
+    pub min: f64,
+    /// Maximum value (slider all the way right)
+    pub max: f64,
+    /// Text label displayed on left of slider
+    pub label: Option<String>,
+    /// Whether the callback is called while sliding, or only after release
+    pub continuous: bool,
+    /// Number of tick marks to draw, or 0 for none
+    pub tick_marks: u32,
+    /// Whether the knob snaps to the tick marks
+    pub snap: bool,
+    /// Whether values are rounded to whole numbers
+    pub integer_only: bool,
+}
+
+impl Default for SliderOptions {
+    fn default() -> SliderOptions {
+        SliderOptions {
+            min: 0.0,
+            max: 1.0,
+            label: None,
+            continuous: true,
+            tick_marks: 0,
+            snap: false,
+            integer_only: false,
+        }
+    }
+}
+
+/// Description of a bar's items, in display order, built by
+/// `TTouchbar2::build()`
+pub type BarSpec = Vec<BarItemSpec>;
+
+/// Description of one item in a `BarSpec`
+pub enum BarItemSpec {
+    /// A button, as created by `TTouchbar2::new_button()`
+    Button(ButtonOptions, ButtonCb),
+    /// Static text, as created by `TTouchbar::create_label()`
+    Label(String),
+    /// A slider, as created by `TTouchbar2::new_slider()`
+    Slider(SliderOptions, SliderCb),
+    /// A scrubber, as created by `TTouchbar2::new_scrubber()`
+    Scrubber(Rc<dyn TScrubberData>, ScrubberOptions),
+    /// Space between items
+    Spacer(SpacerType),
+    /// A button that opens a popover bar with the given items
+    Popover(ButtonOptions, BarSpec),
+    /// Two rows stacked vertically, as created by
+    /// `TTouchbar::create_stacked_item()`
+    Stacked(ItemSpec, ItemSpec),
+    /// An item whose `ItemId` is returned by `build()` under a name
+    Named(String, Box<BarItemSpec>),
+}
+
+impl BarItemSpec {
+    /// Give an item a name, to find its `ItemId` after building
+    pub fn named(self, name: &str) -> BarItemSpec {
+        BarItemSpec::Named(name.to_string(), Box::new(self))
+    }
+}
+
+/// Description of a bar's current layout, returned by
+/// `TTouchbar2::export_spec()`
+///
+/// Unlike a `BarSpec`, a layout holds only plain data and no callbacks, so it
+/// can be compared, stored and restored.  A `BarSpec` can't be exported:
+/// callbacks and scrubber data are owned by the controller once an item is
+/// built and can't be cloned back out, and images are consumed.  A layout
+/// records only what can be read back from the items.  It converts to a
+/// `BarSpec` with placeholder callbacks, or an application can map each item
+/// to a `BarItemSpec` with its own callbacks.
+///
+/// Its `Display` form is an indented
+/// tree with one item per line, which is also accepted by `parse()`:
+///
+/// ```text
+/// Label text="Volume"
+/// Slider name="volume"
+/// Popover text="More"
+///   Button text="Quit" width=80
+/// ```
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct LayoutSpec {
+    /// Items of the bar, in display order
+    pub items: Vec<LayoutItem>,
+}
+
+/// Description of one item in a `LayoutSpec`
+#[derive(PartialEq, Debug, Clone)]
+pub struct LayoutItem {
+    /// Kind of item
+    pub kind: ItemKind,
+    /// Name given with `set_item_name()`
+    pub name: Option<String>,
+    /// Text of a button, popover or label
+    pub text: Option<String>,
+    /// Fixed width, as returned by `item_width()`
+    pub width: Option<u32>,
+    /// Items of a popover's bar.  Empty for other kinds of items, and for
+    /// lazy popovers that haven't been opened yet.
+    pub popover: Vec<LayoutItem>,
+}
+
+impl LayoutItem {
+    /// An item of the given kind, with no other details
+    pub fn new(kind: ItemKind) -> LayoutItem {
+        LayoutItem { kind, name: None, text: None, width: None, popover: Vec::new() }
+    }
+
+    fn write(&self, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+        write!(f, "{}{}", "  ".repeat(depth), self.kind.as_str())?;
+        if let Some(ref name) = self.name {
+            write!(f, " name={:?}", name)?;
+        }
+        if let Some(ref text) = self.text {
+            write!(f, " text={:?}", text)?;
+        }
+        if let Some(width) = self.width {
+            write!(f, " width={}", width)?;
+        }
+        writeln!(f)?;
+        for item in &self.popover {
+            item.write(f, depth + 1)?;
+        }
+        Ok(())
+    }
+
+    fn parse(line: &str) -> Option<LayoutItem> {
+        let (kind, mut rest) = match line.find(' ') {
+            Some(idx) => (&line[..idx], &line[idx..]),
+            None => (line, ""),
+        };
+        let kind = kind.parse().ok()?;
+        let mut item = LayoutItem::new(kind);
+        loop {
+            rest = rest.trim_start();
+            if rest.is_empty() {
+                return Some(item);
+            }
+            let eq = rest.find('=')?;
+            let (key, value) = (&rest[..eq], &rest[eq + 1..]);
+            if key == "width" {
+                let end = value.find(' ').unwrap_or(value.len());
+                item.width = Some(value[..end].parse().ok()?);
+                rest = &value[end..];
+                continue;
+            }
+            let (text, len) = parse_quoted(value)?;
+            match key {
+                "name" => item.name = Some(text),
+                "text" => item.text = Some(text),
+                _ => return None,
+            }
+            rest = &value[len..];
+        }
+    }
+}
+
+// Parse a string quoted and escaped by Debug formatting, returning it and
+// the number of bytes it took up
+fn parse_quoted(s: &str) -> Option<(String, usize)> {
+    let mut chars = s.char_indices();
+    if chars.next()?.1 != '"' {
+        return None;
+    }
+    let mut text = String::new();
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '"' => return Some((text, idx + 1)),
+            '\\' => {
+                let c = match chars.next()?.1 {
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    '0' => '\0',
+                    'u' => {
+                        if chars.next()?.1 != '{' {
+                            return None;
+                        }
+                        let mut code = String::new();
+                        loop {
+                            match chars.next()?.1 {
+                                '}' => break,
+                                c => code.push(c),
+                            }
+                        }
+                        ::std::char::from_u32(u32::from_str_radix(&code, 16).ok()?)?
+                    },
+                    c => c,
+                };
+                text.push(c);
+            },
+            c => text.push(c),
+        }
+    }
+    None
+}
+
+impl fmt::Display for LayoutSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for item in &self.items {
+            item.write(f, 0)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for LayoutSpec {
+    type Err = TouchbarError;
+    fn from_str(s: &str) -> Result<LayoutSpec, TouchbarError> {
+        // Items at each level of popovers currently being parsed
+        let mut levels: Vec<Vec<LayoutItem>> = vec![Vec::new()];
+        for line in s.lines().filter(|l| !l.trim().is_empty()) {
+            let invalid = || TouchbarError::InvalidLayout(line.to_string());
+            let trimmed = line.trim_start_matches(' ');
+            let indent = line.len() - trimmed.len();
+            let depth = indent / 2;
+            if indent % 2 != 0 || depth > levels.len() {
+                return Err(invalid());
+            }
+            if depth == levels.len() {
+                // First item of a popover
+                match levels[depth - 1].last() {
+                    Some(parent) if parent.kind == ItemKind::Popover => levels.push(Vec::new()),
+                    _ => return Err(invalid()),
+                }
+            }
+            while levels.len() > depth + 1 {
+                let items = levels.pop().unwrap();
+                levels.last_mut().unwrap().last_mut().unwrap().popover = items;
+            }
+            let item = LayoutItem::parse(trimmed.trim_end()).ok_or_else(invalid)?;
+            levels[depth].push(item);
+        }
+        while levels.len() > 1 {
+            let items = levels.pop().unwrap();
+            levels.last_mut().unwrap().last_mut().unwrap().popover = items;
+        }
+        Ok(LayoutSpec { items: levels.pop().unwrap() })
+    }
+}
+
+/// Converts a layout to a spec that rebuilds it with `TTouchbar2::build()`
+///
+/// Callbacks are no-ops and scrubbers have no items, so the application
+/// usually replaces them after building.  Spacers are small, since their size
+/// isn't recorded, and widths are dropped.  Kinds a `BarItemSpec` can't
+/// describe are left out.
+impl From<LayoutSpec> for BarSpec {
+    fn from(layout: LayoutSpec) -> BarSpec {
+        layout_to_spec(layout.items)
+    }
+}
+
+// Scrubber data for scrubbers rebuilt from a layout, which has no items
+struct EmptyScrubber;
+
+impl TScrubberData for EmptyScrubber {
+    fn count(&self, _item: ItemId) -> u32 { 0 }
+    fn text(&self, _item: ItemId, _idx: u32) -> String { String::new() }
+    fn width(&self, _item: ItemId, _idx: u32) -> u32 { 0 }
+    fn touch(&self, _item: ItemId, _idx: u32) {}
+}
+
+fn layout_to_spec(items: Vec<LayoutItem>) -> BarSpec {
+    items.into_iter().filter_map(|item| {
+        let options = ButtonOptions { text: item.text.clone(), ..Default::default() };
+        let spec = match item.kind {
+            ItemKind::Button => BarItemSpec::Button(options, Box::new(|_| {})),
+            ItemKind::Label => BarItemSpec::Label(item.text.unwrap_or_default()),
+            ItemKind::Slider => BarItemSpec::Slider(Default::default(), Box::new(|_, _| {})),
+            ItemKind::Scrubber => BarItemSpec::Scrubber(Rc::new(EmptyScrubber), Default::default()),
+            ItemKind::Spacer => BarItemSpec::Spacer(SpacerType::Small),
+            ItemKind::Popover => BarItemSpec::Popover(options, layout_to_spec(item.popover)),
+            _ => return None,
+        };
+        Some(match item.name {
+            Some(name) => spec.named(&name),
+            None => spec,
+        })
+    }).collect()
+}
+
+/// Builder for buttons, returned by `TTouchbar2::button()`
+///
+/// Set the button's options by chaining methods, then create it with
+/// `build()`:
+///
+/// ```
+/// # use rubrail::prelude::*;
+/// # let mut tb = Touchbar::alloc("test");
+/// let quit = tb.button()
+///     .text("Quit")
+///     .image_template(ImageTemplate::DeleteTemplate)
+///     .width(80)
+///     .on_press(Box::new(|_| {}))
+///     .build();
+/// ```
+pub struct ButtonBuilder<'a, T: ?Sized + 'a> {
+    tb: &'a mut T,
+    options: ButtonOptions,
+    template: Option<ImageTemplate>,
+    width: Option<u32>,
+    cb: Option<ButtonCb>,
+}
+
+impl<'a, T: TTouchbar2 + ?Sized> ButtonBuilder<'a, T> {
+    /// Text to display on the button
+    pub fn text(mut self, text: &str) -> Self {
+        self.options.text = Some(text.to_string());
+        self
+    }
+    /// Smaller second line of text to display under the text
+    pub fn subtitle(mut self, subtitle: &str) -> Self {
+        self.options.subtitle = Some(subtitle.to_string());
+        self
+    }
+    /// Image to display on the button
+    pub fn image(mut self, image: TouchbarImage) -> Self {
+        self.options.image = Some(image);
+        self.template = None;
+        self
+    }
+    /// Standard system image to display on the button
+    pub fn image_template(mut self, template: ImageTemplate) -> Self {
+        self.options.image = None;
+        self.template = Some(template);
+        self
+    }
+    /// Fixed width of the button, in pixels
+    pub fn width(mut self, width: u32) -> Self {
+        self.width = Some(width);
+        self
+    }
+    /// Whether the button stays highlighted after a press, toggling on each
+    /// press
+    pub fn latching(mut self, latching: bool) -> Self {
+        self.options.latching = latching;
+        self
+    }
+    /// Callback to call when the button is pressed
+    pub fn on_press(mut self, cb: ButtonCb) -> Self {
+        self.cb = Some(cb);
+        self
+    }
+    /// Create the button
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated button item
+    pub fn build(self) -> ItemId {
+        let mut options = self.options;
+        if let Some(template) = self.template {
+            options.image = Some(self.tb.create_image_from_template(template));
+        }
+        let cb = self.cb.unwrap_or_else(|| Box::new(|_| {}));
+        let item = self.tb.new_button(options, cb);
+        if let Some(width) = self.width {
+            self.tb.update_button_width(&item, width);
+        }
+        item
+    }
+}
+
+/// Builder for labels, returned by `TTouchbar2::label()`
+pub struct LabelBuilder<'a, T: ?Sized + 'a> {
+    tb: &'a mut T,
+    text: String,
+    width: Option<u32>,
+    marquee: bool,
+}
+
+impl<'a, T: TTouchbar2 + ?Sized> LabelBuilder<'a, T> {
+    /// Text to display
+    pub fn text(mut self, text: &str) -> Self {
+        self.text = text.to_string();
+        self
+    }
+    /// Fixed width of the label, in pixels
+    pub fn width(mut self, width: u32) -> Self {
+        self.width = Some(width);
+        self
+    }
+    /// Whether text too long for the label scrolls by, as with
+    /// `TTouchbar::set_label_marquee()`
+    pub fn marquee(mut self, marquee: bool) -> Self {
+        self.marquee = marquee;
+        self
+    }
+    /// Create the label
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated label item
+    pub fn build(self) -> ItemId {
+        let item = self.tb.create_label(&self.text);
+        if let Some(width) = self.width {
+            self.tb.update_label_width(&item, width);
+        }
+        if self.marquee {
+            self.tb.set_label_marquee(&item, true);
+        }
+        item
+    }
+}
+
+/// Builder for sliders, returned by `TTouchbar2::slider()`
+pub struct SliderBuilder<'a, T: ?Sized + 'a> {
+    tb: &'a mut T,
+    options: SliderOptions,
+    value: Option<f64>,
+    cb: Option<SliderCb>,
+}
+
+impl<'a, T: TTouchbar2 + ?Sized> SliderBuilder<'a, T> {
+    /// Minimum and maximum values of the slider
+    pub fn range(mut self, min: f64, max: f64) -> Self {
+        self.options.min = min;
+        self.options.max = max;
+        self
+    }
+    /// Text label displayed on left of slider
+    pub fn label(mut self, label: &str) -> Self {
+        self.options.label = Some(label.to_string());
+        self
+    }
+    /// Whether the callback is called while sliding, or only after release
+    pub fn continuous(mut self, continuous: bool) -> Self {
+        self.options.continuous = continuous;
+        self
+    }
+    /// Number of tick marks to draw, and whether the knob snaps to them
+    pub fn tick_marks(mut self, tick_marks: u32, snap: bool) -> Self {
+        self.options.tick_marks = tick_marks;
+        self.options.snap = snap;
+        self
+    }
+    /// Whether values are rounded to whole numbers
+    pub fn integer_only(mut self, integer_only: bool) -> Self {
+        self.options.integer_only = integer_only;
+        self
+    }
+    /// Initial value of the slider
+    pub fn value(mut self, value: f64) -> Self {
+        self.value = Some(value);
+        self
+    }
+    /// Callback called when the slider value is changed
+    pub fn on_change(mut self, cb: SliderCb) -> Self {
+        self.cb = Some(cb);
+        self
+    }
+    /// Create the slider
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated slider item
+    pub fn build(self) -> ItemId {
+        let cb = self.cb.unwrap_or_else(|| Box::new(|_, _| {}));
+        let item = self.tb.new_slider(self.options, cb);
+        if let Some(value) = self.value {
+            self.tb.update_slider(&item, value);
+        }
+        item
+    }
+}
+
+/// Builder for text scrubbers, returned by `TTouchbar2::scrubber()`
+pub struct ScrubberBuilder<'a, T: ?Sized + 'a> {
+    tb: &'a mut T,
+    data: Rc<dyn TScrubberData>,
+    options: ScrubberOptions,
+    selected: Option<u32>,
+}
+
+impl<'a, T: TTouchbar2 + ?Sized> ScrubberBuilder<'a, T> {
+    /// How the selected item is drawn
+    pub fn style(mut self, style: ScrubberStyle) -> Self {
+        self.options.style = style;
+        self
+    }
+    /// Scrolling behavior, paged (`Fixed`) or free
+    pub fn mode(mut self, mode: ScrubberMode) -> Self {
+        self.options.mode = mode;
+        self
+    }
+    /// Whether the selection follows the finger while scrolling
+    pub fn continuous(mut self, continuous: bool) -> Self {
+        self.options.continuous = continuous;
+        self
+    }
+    /// Whether arrow buttons for stepping through items are shown
+    pub fn arrow_buttons(mut self, arrow_buttons: bool) -> Self {
+        self.options.arrow_buttons = arrow_buttons;
+        self
+    }
+    /// Index of the initially selected item
+    pub fn selected(mut self, index: u32) -> Self {
+        self.selected = Some(index);
+        self
+    }
+    /// Create the scrubber
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated scrubber item
+    pub fn build(self) -> ItemId {
+        let item = self.tb.new_scrubber(self.data, self.options);
+        if let Some(index) = self.selected {
+            self.tb.select_scrubber_item(&item, index);
+        }
+        item
+    }
+}
+
+/// Options-based API for creating Touch Bar items
+///
+/// `TTouchbar2` is implemented for every `TTouchbar`, so it is available on
+/// both the default and the 'dummy' implementations without extra work.
+///
+/// Its methods take option structs instead of long lists of arguments.  New
+/// options are added as fields with defaults, so they can be introduced
+/// without breaking existing code.  Start from the option struct's
+/// `default()` and change only the fields you need.
+///
+/// Common items can also be created with builders, such as
+/// `tb.button().text("Quit").on_press(cb).build()`, which set the same
+/// options one method at a time.
+///
+/// Older `TTouchbar` methods that are superseded here are marked deprecated,
+/// but keep working.
+///
+pub trait TTouchbar2: TTouchbar {
+    /// Create a button that calls a callback when pressed
+    ///
+    /// Equivalent to `TTouchbar::create_button()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - Image and text of the button
+    /// * `cb` - Callback to call when the button is pressed
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated button item
+    #[allow(deprecated)]
+    fn new_button(&mut self, options: ButtonOptions, cb: ButtonCb) -> ItemId {
+        let item = self.create_button(options.image.as_ref(), options.text.as_deref(), cb);
+        if let Some(ref subtitle) = options.subtitle {
+            self.set_button_subtitle(&item, Some(subtitle));
+        }
+        if options.latching {
+            self.set_button_latching(&item, true);
+        }
+        item
+    }
+
+    /// Create a button that opens a popover bar when pressed
+    ///
+    /// Equivalent to `TTouchbar::create_popover_item()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - Image and text of the button
+    /// * `bar_id` - Bar to open when the button is pressed
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated popover item
+    #[allow(deprecated)]
+    fn new_popover(&mut self, options: ButtonOptions, bar_id: &BarId) -> ItemId {
+        self.create_popover_item(options.image.as_ref(), options.text.as_deref(), bar_id)
+    }
+
+    /// Create a horizontally scrolling 'scrubber' of text
+    ///
+    /// Equivalent to `TTouchbar::create_text_scrubber()`, with the selection
+    /// style and scrolling mode chosen at creation.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - An object implementing the `TScrubberData` trait, wrapped
+    ///   in a reference counter (Rc).
+    /// * `options` - Appearance and behavior of the scrubber
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated scrubber item
+    #[allow(deprecated)]
+    fn new_scrubber(&mut self, data: Rc<dyn TScrubberData>, options: ScrubberOptions) -> ItemId {
+        let item = self.create_text_scrubber(data);
+        if options.style != DEFAULT_SCRUBBER_STYLE {
+            self.set_scrubber_style(&item, options.style);
+        }
+        self.set_scrubber_mode(&item, options.mode);
+        if options.continuous {
+            self.set_scrubber_continuous(&item, true);
+        }
+        if options.arrow_buttons {
+            self.set_scrubber_arrow_buttons(&item, true);
+        }
+        item
+    }
+
+    /// Create a slider item
+    ///
+    /// Equivalent to `TTouchbar::create_slider()` followed by
+    /// `TTouchbar::set_slider_steps()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - Range, label, and stepping of the slider
+    /// * `cb` - Callback called when the slider value is changed
+    ///
+    /// # Returns
+    ///
+    /// A newly allocated slider item
+    #[allow(deprecated)]
+    fn new_slider(&mut self, options: SliderOptions, cb: SliderCb) -> ItemId {
+        let item = self.create_slider(options.min, options.max, options.label.as_deref(),
+                                      options.continuous, cb);
+        if options.tick_marks > 0 || options.snap || options.integer_only {
+            self.set_slider_steps(&item, options.tick_marks, options.snap, options.integer_only);
+        }
+        item
+    }
+
+    /// Create a bar and all of its items from a description
+    ///
+    /// Replaces the usual sequence of creating each item, creating the
+    /// bars, and adding the items to them.  Popovers in the description are
+    /// built along with their bars.  The returned bar can then be made the
+    /// root bar with `set_bar_as_root()`.
+    ///
+    /// Items that need further setup, such as gestures or an initial value,
+    /// can be given a name with `BarItemSpec::named()` and looked up in the
+    /// returned map, or later with `item_by_name()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `spec` - Items of the bar, in display order
+    ///
+    /// # Returns
+    ///
+    /// A tuple of the newly allocated bar, and the `ItemId` of every named
+    /// item, including those in popovers
+    fn build(&mut self, spec: BarSpec) -> (BarId, BTreeMap<String, ItemId>) {
+        let mut names = BTreeMap::new();
+        let bar = build_bar(self, spec, &mut names);
+        (bar, names)
+    }
+
+    /// Describe the items currently in a bar
+    ///
+    /// The inverse of `build()`: lists the kind, name, text and width of
+    /// every item, including the items of popover bars.  Callbacks can't be
+    /// described, so the layout can't be built directly.  It is meant for
+    /// snapshot tests, and for saving a layout to restore it later.
+    ///
+    /// # Arguments
+    ///
+    /// * `bar_id` - Bar to describe, usually the root bar
+    ///
+    /// # Returns
+    ///
+    /// A description of the bar's items.  Empty if the bar doesn't exist.
+    /// Convert it with `BarSpec::from()` to build a copy of the bar.
+    fn export_spec(&self, bar_id: &BarId) -> LayoutSpec {
+        LayoutSpec { items: export_bar(self, bar_id, &mut Vec::new()) }
+    }
+
+    /// Start building a button
+    ///
+    /// Chain options on the returned `ButtonBuilder`, then call `build()`
+    /// to create the button.  A button with no `on_press()` callback does
+    /// nothing when pressed.
+    fn button(&mut self) -> ButtonBuilder<'_, Self> {
+        ButtonBuilder {
+            tb: self,
+            options: ButtonOptions::default(),
+            template: None,
+            width: None,
+            cb: None,
+        }
+    }
+
+    /// Start building a label
+    ///
+    /// Chain options on the returned `LabelBuilder`, then call `build()` to
+    /// create the label.
+    fn label(&mut self) -> LabelBuilder<'_, Self> {
+        LabelBuilder {
+            tb: self,
+            text: String::new(),
+            width: None,
+            marquee: false,
+        }
+    }
+
+    /// Start building a slider
+    ///
+    /// Chain options on the returned `SliderBuilder`, then call `build()` to
+    /// create the slider.  Options not set are taken from
+    /// `SliderOptions::default()`.
+    fn slider(&mut self) -> SliderBuilder<'_, Self> {
+        SliderBuilder {
+            tb: self,
+            options: SliderOptions::default(),
+            value: None,
+            cb: None,
+        }
+    }
+
+    /// Start building a text scrubber
+    ///
+    /// Chain options on the returned `ScrubberBuilder`, then call `build()`
+    /// to create the scrubber.  Options not set are taken from
+    /// `ScrubberOptions::default()`.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - An object implementing the `TScrubberData` trait, wrapped
+    ///   in a reference counter (Rc).
+    fn scrubber(&mut self, data: Rc<dyn TScrubberData>) -> ScrubberBuilder<'_, Self> {
+        ScrubberBuilder {
+            tb: self,
+            data,
+            options: ScrubberOptions::default(),
+            selected: None,
+        }
+    }
+}
+
+fn build_bar<T: TTouchbar2 + ?Sized>(tb: &mut T, spec: BarSpec,
+                                     names: &mut BTreeMap<String, ItemId>) -> BarId {
+    let items = spec.into_iter().map(|item| build_item(tb, item, names)).collect();
+    let bar = tb.create_bar();
+    tb.add_items_to_bar(&bar, items);
+    bar
+}
+
+fn export_bar<T: TTouchbar2 + ?Sized>(tb: &T, bar_id: &BarId,
+                                      visited: &mut Vec<BarId>) -> Vec<LayoutItem> {
+    // A bar can only appear once in the tree, even if popovers loop
+    if visited.contains(bar_id) {
+        return Vec::new();
+    }
+    visited.push(*bar_id);
+    let items = tb.bar_items(bar_id).iter().filter_map(|item| {
+        let kind = tb.item_type(item)?;
+        let text = match kind {
+            ItemKind::Button | ItemKind::Popover => Some(tb.button_text(item)),
+            ItemKind::Label => Some(tb.label_text(item)),
+            _ => None,
+        };
+        let popover = match tb.popover_bar(item) {
+            Some(bar) => export_bar(tb, &bar, visited),
+            None => Vec::new(),
+        };
+        Some(LayoutItem {
+            kind,
+            name: tb.item_name(item),
+            text,
+            width: tb.item_width(item),
+            popover,
+        })
+    }).collect();
+    visited.pop();
+    items
+}
+
+fn build_item<T: TTouchbar2 + ?Sized>(tb: &mut T, spec: BarItemSpec,
+                                      names: &mut BTreeMap<String, ItemId>) -> ItemId {
+    match spec {
+        BarItemSpec::Button(options, cb) => tb.new_button(options, cb),
+        BarItemSpec::Label(text) => tb.create_label(&text),
+        BarItemSpec::Slider(options, cb) => tb.new_slider(options, cb),
+        BarItemSpec::Scrubber(data, options) => tb.new_scrubber(data, options),
+        BarItemSpec::Spacer(space) => tb.create_spacer(space),
+        BarItemSpec::Popover(options, spec) => {
+            let bar = build_bar(tb, spec, names);
+            tb.new_popover(options, &bar)
+        },
+        BarItemSpec::Stacked(top, bottom) => tb.create_stacked_item(top, bottom),
+        BarItemSpec::Named(name, spec) => {
+            let item = build_item(tb, *spec, names);
+            tb.set_item_name(&item, &name);
+            names.insert(name, item);
+            item
+        },
+    }
+}
+
+impl<T: TTouchbar> TTouchbar2 for T {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(kind: ItemKind, name: Option<&str>, text: Option<&str>) -> LayoutItem {
+        LayoutItem {
+            name: name.map(str::to_string),
+            text: text.map(str::to_string),
+            ..LayoutItem::new(kind)
+        }
+    }
+
+    #[test]
+    fn test_item_kind_names() {
+        let kinds = [
+            ItemKind::Button, ItemKind::Label, ItemKind::MultilineLabel, ItemKind::Countdown,
+            ItemKind::Slider, ItemKind::Knob, ItemKind::Scrubber, ItemKind::Popover,
+            ItemKind::Spacer, ItemKind::DatePicker, ItemKind::TextField, ItemKind::Canvas,
+            ItemKind::Graph, ItemKind::Passthrough, ItemKind::Picker, ItemKind::Rating,
+            ItemKind::MediaControls, ItemKind::Stacked, ItemKind::NowPlaying,
+        ];
+        for kind in kinds.iter() {
+            assert_eq!(kind.as_str().parse::<ItemKind>().unwrap(), *kind);
+        }
+        assert!("button".parse::<ItemKind>().is_err());
+    }
+
+    #[test]
+    fn test_layout_round_trip() {
+        let mut popover = item(ItemKind::Popover, Some("outer"), Some("More"));
+        let mut inner = item(ItemKind::Popover, None, Some("Even more"));
+        inner.popover = vec![item(ItemKind::Button, None, Some("Deep"))];
+        popover.popover = vec![
+            item(ItemKind::Label, None, Some("say \"hi\"\\ now\n\ttabbed")),
+            inner,
+            item(ItemKind::Spacer, None, None),
+        ];
+        let mut slider = item(ItemKind::Slider, Some("volume"), None);
+        slider.width = Some(120);
+        let layout = LayoutSpec { items: vec![
+            item(ItemKind::Label, None, Some("esc \u{1b} bell \u{7} null \0 snow \u{2603}")),
+            popover,
+            slider,
+        ]};
+        let text = layout.to_string();
+        assert_eq!(text.lines().nth(4), Some("    Button text=\"Deep\""));
+        assert_eq!(text.parse::<LayoutSpec>().unwrap(), layout);
+    }
+
+    #[test]
+    fn test_layout_parse() {
+        let text = "Label text=\"a\\u{263a}\"\n\nPopover\n  Button name=\"b\" width=5\nSpacer\n";
+        let layout: LayoutSpec = text.parse().unwrap();
+        let mut popover = LayoutItem::new(ItemKind::Popover);
+        let mut button = item(ItemKind::Button, Some("b"), None);
+        button.width = Some(5);
+        popover.popover = vec![button];
+        assert_eq!(layout.items, vec![item(ItemKind::Label, None, Some("a\u{263a}")),
+                                      popover, LayoutItem::new(ItemKind::Spacer)]);
+    }
+
+    #[test]
+    fn test_layout_to_bar_spec() {
+        let text = "Label text=\"a\"\nPopover text=\"More\"\n  Button name=\"b\"\nKnob\n";
+        let spec = BarSpec::from(text.parse::<LayoutSpec>().unwrap());
+        assert_eq!(spec.len(), 2);
+        match spec[0] {
+            BarItemSpec::Label(ref text) => assert_eq!(text, "a"),
+            _ => panic!("expected a label"),
+        }
+        match spec[1] {
+            BarItemSpec::Popover(ref options, ref items) => {
+                assert_eq!(options.text, Some("More".to_string()));
+                match items[..] {
+                    [BarItemSpec::Named(ref name, _)] => assert_eq!(name, "b"),
+                    _ => panic!("expected a named button"),
+                }
+            },
+            _ => panic!("expected a popover"),
+        }
+    }
+
+    #[test]
+    fn test_layout_parse_errors() {
+        let invalid = [
+            // Odd indentation, skipped level, and children of a non-popover
+            "Popover\n Button",
+            "Popover\n    Button",
+            "Label\n  Button",
+            "  Button",
+            // Unknown kinds and keys, and malformed values
+            "Buttons",
+            "Button color=\"red\"",
+            "Button text=unquoted",
+            "Button text=\"unterminated",
+            "Button text=\"\\u{zz}\"",
+            "Button width=wide",
+        ];
+        for layout in invalid.iter() {
+            match layout.parse::<LayoutSpec>() {
+                Err(TouchbarError::InvalidLayout(_)) => {},
+                other => panic!("{:?} parsed as {:?}", layout, other),
+            }
+        }
+    }
 }
@@ -49,15 +49,61 @@
 //! the bar and its subbars, and then deallocating the bar itself.
 //!
 //! Any objects created with a _create*()_ function that are never added to a
-//! bar that is set as the system bar will be leaked.
+//! bar that is set as the system bar will be leaked, unless they are freed
+//! explicitly with _destroy_item()_ or _destroy_bar()_, or owned by a handle
+//! from _own_item()_ or _own_bar()_ that frees them when dropped.
+//!
+//! # Exiting
+//!
+//! The Control Strip keeps showing an icon until it is explicitly removed, so
+//! Rubrail removes its icon when the process exits, including when it is
+//! ended by `SIGHUP`, `SIGINT`, `SIGQUIT` or `SIGTERM`.  Signal handlers are
+//! only installed for signals the application hasn't already set handlers
+//! for.  An icon is left behind if the process is killed with `SIGKILL` or
+//! crashes, since cleaning up from a crash handler could deadlock instead of
+//! letting the process die.
+//!
+//! # Backends
+//!
+//! Which implementation of [`Touchbar`](type.Touchbar.html) is built depends on
+//! the platform and Cargo features:
+//!
+//! * `private_api` (default) - The full Mac implementation.  Bars are shown
+//!   from an icon in the Control Strip, which requires private frameworks, so
+//!   it can't be distributed through the App Store.
+//! * `public_api` - A Mac implementation using only public APIs.  Build with
+//!   `--no-default-features --features public_api`.  The root bar is shown as
+//!   the application's own Touch Bar, so it is only visible while the
+//!   application is frontmost, and there is no Control Strip icon.  Popovers
+//!   open with their standard animation.
+//! * Neither, or not on a Mac - A 'dummy' implementation that does nothing.
+//!
+//! # Optional Features
+//!
+//! * `system_controls` - Items bound to system settings: volume and display
+//!   brightness sliders that follow and set the system values.
+//! * `benchmark` - The [`benchmark`](benchmark/index.html) module, for
+//!   measuring update throughput.
 //!
 #![deny(missing_docs)]
 
+#[macro_use]
+mod macros;
+
 #[allow(dead_code)]
 #[allow(unused_variables)]
+#[macro_use]
 mod interface;
 pub use interface::*;
 
+mod virtual_bar;
+pub use virtual_bar::VirtualBar;
+
+pub mod prelude;
+
+#[cfg(feature = "benchmark")]
+pub mod benchmark;
+
 #[allow(unused_imports)]
 #[macro_use]
 extern crate log;
@@ -66,39 +112,44 @@ extern crate log;
 // Mac+TouchBar imports
 //
 #[cfg(target_os = "macos")]
-#[cfg(feature = "private_api")]
+#[cfg(any(feature = "private_api", feature = "public_api"))]
 #[macro_use]
 mod wrapper;
 
 #[cfg(target_os = "macos")]
-#[cfg(feature = "private_api")]
+#[cfg(any(feature = "private_api", feature = "public_api"))]
 #[macro_use]
 extern crate objc;
 
 #[cfg(target_os = "macos")]
-#[cfg(feature = "private_api")]
+#[cfg(any(feature = "private_api", feature = "public_api"))]
 #[macro_use]
 mod touchbar;
 
 #[cfg(target_os = "macos")]
-#[cfg(feature = "private_api")]
+#[cfg(any(feature = "private_api", feature = "public_api"))]
 pub use touchbar::Touchbar as Touchbar;
 
 #[cfg(target_os = "macos")]
-#[cfg(feature = "private_api")]
+#[cfg(any(feature = "private_api", feature = "public_api"))]
+#[cfg(feature = "system_controls")]
+mod system;
+
+#[cfg(target_os = "macos")]
+#[cfg(any(feature = "private_api", feature = "public_api"))]
 pub use touchbar::util;
 
 
 //
 // Non-Mac/Dummy TouchBar imports
 //
-#[cfg(not(all(target_os = "macos", feature = "private_api")))]
+#[cfg(not(all(target_os = "macos", any(feature = "private_api", feature = "public_api"))))]
 mod dummy;
 
-#[cfg(not(all(target_os = "macos", feature = "private_api")))]
+#[cfg(not(all(target_os = "macos", any(feature = "private_api", feature = "public_api"))))]
 pub use dummy::DummyTouchbar as Touchbar;
 
-#[cfg(not(all(target_os = "macos", feature = "private_api")))]
+#[cfg(not(all(target_os = "macos", any(feature = "private_api", feature = "public_api"))))]
 pub use dummy::util;
 
 #[cfg(test)]
@@ -110,4 +161,17 @@ mod tests {
         let mut tb = Touchbar::alloc("test");
         let _ = tb.create_bar();
     }
+    #[test]
+    #[cfg(not(all(target_os = "macos", any(feature = "private_api", feature = "public_api"))))]
+    fn test_dummy_util_records() {
+        use util;
+        use interface::ItemId;
+        let item = ItemId::from(42);
+        assert_eq!(util::last_text_color(&item), None);
+        unsafe { util::set_text_color(&item, 1., 0.5, 0., 1.); }
+        assert_eq!(util::last_text_color(&item), Some((1., 0.5, 0., 1.)));
+        assert_eq!(util::last_bg_color(&item), None);
+        util::register_bundled_resource("icon", "png", "/tmp/icon.png");
+        assert_eq!(util::bundled_resource_path("icon", "png"), Some("/tmp/icon.png".to_string()));
+    }
 }
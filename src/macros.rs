@@ -0,0 +1,90 @@
+/// Declare a whole bar hierarchy and make it the root bar
+///
+/// Expands to the `TTouchbar2::new_*()` and `create_label()`/`create_spacer()`
+/// calls for every item, `create_bar()` and
+/// `add_items_to_bar()` for the root bar and every popover bar, and finally
+/// `set_bar_as_root()`.  Evaluates to the `BarId` of the new root bar.
+///
+/// The first argument is the `TTouchbar` to create the items with, followed by
+/// a list of items in display order.  Each item is one of:
+///
+/// * `button(text, cb)` - A button with text, calling `cb` when pressed, as
+///   with `new_button()`
+/// * `label(text)` - Static text, as with `create_label()`
+/// * `slider(min, max, cb)` - A continuous slider, calling `cb` when its
+///   value changes, as with `new_slider()`
+/// * `scrubber(data)` - A text scrubber showing `data`, an
+///   `Rc<dyn TScrubberData>`, as with `new_scrubber()`
+/// * `spacer(space)` - Empty space of a `SpacerType`, as with
+///   `create_spacer()`
+/// * `popover(text, [items])` - A button opening a popover bar with its own
+///   list of items, as with `create_popover_item()`
+///
+/// Callbacks are closures, and are boxed by the macro.
+///
+/// # Example
+///
+/// ```
+/// #[macro_use]
+/// extern crate rubrail;
+/// use rubrail::Touchbar;
+/// use rubrail::TTouchbar;
+/// use rubrail::SpacerType;
+///
+/// fn main() {
+///     let mut tb = Touchbar::alloc("test");
+///     let _root = touchbar!(tb, [
+///         label("Volume"),
+///         slider(0.0, 1.0, |_, value| println!("volume: {}", value)),
+///         spacer(SpacerType::Small),
+///         popover("More", [
+///             button("Hello", |_| println!("hello")),
+///             button("Goodbye", |_| println!("goodbye")),
+///         ]),
+///         button("Quit", |_| std::process::exit(0)),
+///     ]);
+/// }
+/// ```
+#[macro_export]
+macro_rules! touchbar {
+    (@item $tb:ident, button($text:expr, $cb:expr $(,)*)) => {
+        $crate::TTouchbar2::new_button(
+            &mut *$tb,
+            $crate::ButtonOptions { text: Some(String::from($text)), ..Default::default() },
+            Box::new($cb))
+    };
+    (@item $tb:ident, label($text:expr $(,)*)) => {
+        $crate::TTouchbar::create_label(&mut *$tb, $text)
+    };
+    (@item $tb:ident, slider($min:expr, $max:expr, $cb:expr $(,)*)) => {
+        $crate::TTouchbar2::new_slider(
+            &mut *$tb,
+            $crate::SliderOptions { min: $min, max: $max, ..Default::default() },
+            Box::new($cb))
+    };
+    (@item $tb:ident, scrubber($data:expr $(,)*)) => {
+        $crate::TTouchbar2::new_scrubber(&mut *$tb, $data, Default::default())
+    };
+    (@item $tb:ident, spacer($space:expr $(,)*)) => {
+        $crate::TTouchbar::create_spacer(&mut *$tb, $space)
+    };
+    (@item $tb:ident, popover($text:expr, [ $($items:tt)* ] $(,)*)) => {{
+        let bar = touchbar!(@bar $tb, [ $($items)* ]);
+        $crate::TTouchbar2::new_popover(
+            &mut *$tb,
+            $crate::ButtonOptions { text: Some(String::from($text)), ..Default::default() },
+            &bar)
+    }};
+    (@bar $tb:ident, [ $( $kind:ident ( $($args:tt)* ) ),* $(,)* ]) => {{
+        let items = vec![ $( touchbar!(@item $tb, $kind ( $($args)* )) ),* ];
+        let bar = $crate::TTouchbar::create_bar(&mut *$tb);
+        $crate::TTouchbar::add_items_to_bar(&mut *$tb, &bar, items);
+        bar
+    }};
+    ($tb:expr, [ $($items:tt)* ]) => {{
+        let tb = &mut $tb;
+        let bar = touchbar!(@bar tb, [ $($items)* ]);
+        $crate::TTouchbar::set_bar_as_root(&mut *tb, bar);
+        bar
+    }};
+}
@@ -0,0 +1,39 @@
+//! Convenient single import for applications using Rubrail
+//!
+//! Everything an application typically needs to build and interact with a
+//! Touch Bar UI can be imported with one line:
+//!
+//! ```
+//! use rubrail::prelude::*;
+//! ```
+
+// Controller
+pub use Touchbar;
+pub use interface::TTouchbar;
+pub use interface::TTouchbar2;
+pub use interface::TScrubberData;
+
+// Handles
+pub use interface::{BarId, ItemId, ControlId, TrayId, TouchbarImage};
+pub use interface::{OwnedBar, OwnedItem};
+pub use interface::AllocationStats;
+pub use interface::ItemKind;
+
+// Events and callbacks
+pub use interface::{ItemEvent, GestureEvent, MediaEvent};
+pub use interface::{ButtonCb, EventCb, DropdownCb, PickerCb, RatingCb, MediaCb, PopoverBuildCb, SliderCb, DateCb, TextCb, CanvasCb, ResizeCb, SwipeCb, GestureCb, FocusCb, VisibilityCb};
+pub use interface::BuildStepCb;
+pub use interface::{ErrorCb, TouchbarError};
+
+// Item options
+pub use interface::{SpacerType, SwipeState, ImageTemplate};
+pub use interface::{ScrubberMode, ScrubberStyle, DatePickerElements, SliderAccessory};
+pub use interface::{GestureKind, GestureConfig};
+pub use interface::{FocusBehavior, MissingImageBehavior};
+pub use interface::{LabelStyle, VerticalAlignment, TextSpan};
+pub use interface::ItemSpec;
+pub use interface::{BarSpec, BarItemSpec};
+pub use interface::{LayoutSpec, LayoutItem};
+pub use VirtualBar;
+pub use interface::{ButtonOptions, ScrubberOptions, SliderOptions};
+pub use interface::{ButtonBuilder, LabelBuilder, SliderBuilder, ScrubberBuilder};
@@ -0,0 +1,189 @@
+//! Bindings to system settings controlled by built-in items
+//!
+//! Only built with the `system_controls` feature.
+
+extern crate libc;
+
+use objc::runtime::Object;
+
+type AudioObjectID = u32;
+type OSStatus = i32;
+
+#[repr(C)]
+struct AudioObjectPropertyAddress {
+    selector: u32,
+    scope: u32,
+    element: u32,
+}
+
+type AudioObjectPropertyListenerProc = extern "C" fn(AudioObjectID, u32,
+                                                     *const AudioObjectPropertyAddress,
+                                                     *mut libc::c_void) -> OSStatus;
+
+#[link(name = "CoreAudio", kind = "framework")]
+extern {
+    fn AudioObjectGetPropertyData(id: AudioObjectID, address: *const AudioObjectPropertyAddress,
+                                  qualifier_size: u32, qualifier: *const libc::c_void,
+                                  size: *mut u32, data: *mut libc::c_void) -> OSStatus;
+    fn AudioObjectSetPropertyData(id: AudioObjectID, address: *const AudioObjectPropertyAddress,
+                                  qualifier_size: u32, qualifier: *const libc::c_void,
+                                  size: u32, data: *const libc::c_void) -> OSStatus;
+    fn AudioObjectAddPropertyListener(id: AudioObjectID, address: *const AudioObjectPropertyAddress,
+                                      listener: AudioObjectPropertyListenerProc,
+                                      client: *mut libc::c_void) -> OSStatus;
+    fn AudioObjectRemovePropertyListener(id: AudioObjectID, address: *const AudioObjectPropertyAddress,
+                                         listener: AudioObjectPropertyListenerProc,
+                                         client: *mut libc::c_void) -> OSStatus;
+}
+
+#[cfg(feature = "private_api")]
+#[link(name = "DisplayServices", kind = "framework")]
+extern {
+    fn DisplayServicesGetBrightness(display: u32, brightness: *mut f32) -> i32;
+    fn DisplayServicesSetBrightness(display: u32, brightness: f32) -> i32;
+}
+
+#[cfg(feature = "private_api")]
+#[link(name = "CoreGraphics", kind = "framework")]
+extern {
+    fn CGMainDisplayID() -> u32;
+}
+
+const SYSTEM_OBJECT: AudioObjectID = 1;
+const SCOPE_GLOBAL: u32 = 0x676c6f62; // 'glob'
+const SCOPE_OUTPUT: u32 = 0x6f757470; // 'outp'
+const ELEMENT_MAIN: u32 = 0;
+const DEFAULT_OUTPUT_DEVICE: u32 = 0x644f7574; // 'dOut'
+const VIRTUAL_MAIN_VOLUME: u32 = 0x766d7663; // 'vmvc'
+
+const DEFAULT_OUTPUT_ADDRESS: AudioObjectPropertyAddress = AudioObjectPropertyAddress {
+    selector: DEFAULT_OUTPUT_DEVICE,
+    scope: SCOPE_GLOBAL,
+    element: ELEMENT_MAIN,
+};
+const VOLUME_ADDRESS: AudioObjectPropertyAddress = AudioObjectPropertyAddress {
+    selector: VIRTUAL_MAIN_VOLUME,
+    scope: SCOPE_OUTPUT,
+    element: ELEMENT_MAIN,
+};
+
+/// Current default audio output device
+pub fn default_output_device() -> Option<u32> {
+    let mut device: AudioObjectID = 0;
+    let mut size = ::std::mem::size_of::<AudioObjectID>() as u32;
+    let status = unsafe {
+        AudioObjectGetPropertyData(SYSTEM_OBJECT, &DEFAULT_OUTPUT_ADDRESS, 0,
+                                   ::std::ptr::null(), &mut size,
+                                   &mut device as *mut AudioObjectID as *mut libc::c_void)
+    };
+    match status == 0 && device != 0 {
+        true => Some(device),
+        false => None,
+    }
+}
+
+/// Volume of the default output device, from 0.0 to 1.0
+pub fn output_volume() -> Option<f64> {
+    let device = default_output_device()?;
+    let mut volume: f32 = 0.;
+    let mut size = ::std::mem::size_of::<f32>() as u32;
+    let status = unsafe {
+        AudioObjectGetPropertyData(device, &VOLUME_ADDRESS, 0, ::std::ptr::null(), &mut size,
+                                   &mut volume as *mut f32 as *mut libc::c_void)
+    };
+    match status {
+        0 => Some(volume as f64),
+        _ => None,
+    }
+}
+
+/// Set the volume of the default output device, from 0.0 to 1.0
+pub fn set_output_volume(volume: f64) {
+    let device = match default_output_device() {
+        Some(device) => device,
+        None => return,
+    };
+    let volume = volume.max(0.).min(1.) as f32;
+    let status = unsafe {
+        AudioObjectSetPropertyData(device, &VOLUME_ADDRESS, 0, ::std::ptr::null(),
+                                   ::std::mem::size_of::<f32>() as u32,
+                                   &volume as *const f32 as *const libc::c_void)
+    };
+    if status != 0 {
+        warn!("Failed to set output volume: {}", status);
+    }
+}
+
+// Called by CoreAudio on its own thread.  The client is the app delegate,
+// which is told to refresh its volume sliders on the main thread.
+extern "C" fn volume_listener(_id: AudioObjectID, _count: u32,
+                              _addresses: *const AudioObjectPropertyAddress,
+                              client: *mut libc::c_void) -> OSStatus {
+    unsafe {
+        let _:() = msg_send![client as *mut Object,
+                             performSelectorOnMainThread: sel!(volumeChanged:)
+                             withObject: 0 as *mut Object
+                             waitUntilDone: 0i8];
+    }
+    0
+}
+
+/// Send `volumeChanged:` to `delegate` when the output device or its volume
+/// changes
+///
+/// Listens to the volume of `device`, which must be moved to the new
+/// default device with `move_volume_listener()` after the default changes.
+pub unsafe fn add_volume_listener(delegate: *mut Object, device: Option<u32>) {
+    let client = delegate as *mut libc::c_void;
+    AudioObjectAddPropertyListener(SYSTEM_OBJECT, &DEFAULT_OUTPUT_ADDRESS, volume_listener, client);
+    if let Some(device) = device {
+        AudioObjectAddPropertyListener(device, &VOLUME_ADDRESS, volume_listener, client);
+    }
+}
+
+/// Move a volume listener from one output device to another
+pub unsafe fn move_volume_listener(delegate: *mut Object, from: Option<u32>, to: Option<u32>) {
+    let client = delegate as *mut libc::c_void;
+    if let Some(from) = from {
+        AudioObjectRemovePropertyListener(from, &VOLUME_ADDRESS, volume_listener, client);
+    }
+    if let Some(to) = to {
+        AudioObjectAddPropertyListener(to, &VOLUME_ADDRESS, volume_listener, client);
+    }
+}
+
+/// Stop sending `volumeChanged:` to `delegate`
+///
+/// `device` is the output device last passed to `add_volume_listener()` or
+/// `move_volume_listener()`.
+pub unsafe fn remove_volume_listener(delegate: *mut Object, device: Option<u32>) {
+    let client = delegate as *mut libc::c_void;
+    AudioObjectRemovePropertyListener(SYSTEM_OBJECT, &DEFAULT_OUTPUT_ADDRESS, volume_listener, client);
+    if let Some(device) = device {
+        AudioObjectRemovePropertyListener(device, &VOLUME_ADDRESS, volume_listener, client);
+    }
+}
+
+/// Brightness of the main display, from 0.0 to 1.0
+///
+/// `None` if the display's brightness can't be controlled, as with most
+/// external displays.
+#[cfg(feature = "private_api")]
+pub fn display_brightness() -> Option<f64> {
+    let mut brightness: f32 = 0.;
+    let status = unsafe { DisplayServicesGetBrightness(CGMainDisplayID(), &mut brightness) };
+    match status {
+        0 => Some(brightness as f64),
+        _ => None,
+    }
+}
+
+/// Set the brightness of the main display, from 0.0 to 1.0
+#[cfg(feature = "private_api")]
+pub fn set_display_brightness(brightness: f64) {
+    let brightness = brightness.max(0.).min(1.) as f32;
+    let status = unsafe { DisplayServicesSetBrightness(CGMainDisplayID(), brightness) };
+    if status != 0 {
+        warn!("Failed to set display brightness: {}", status);
+    }
+}
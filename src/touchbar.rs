@@ -1,14 +1,28 @@
 extern crate objc;
 extern crate objc_foundation;
 extern crate objc_id;
+#[cfg(feature = "private_api")]
+extern crate block;
 
 use super::interface::*;
 
+use std::cmp;
 use std::fmt;
+use std::fmt::Write;
+use std::mem;
 use std::rc::Rc;
-use std::cell::Cell;
-use std::sync::Once;
-use std::collections::BTreeMap;
+use std::cell::{Cell, RefCell};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::slice;
+use std::thread;
+use std::sync::{Arc, Mutex, Once};
+use std::collections::{BTreeMap, BTreeSet};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use objc::Message;
 use objc::declare::ClassDecl;
@@ -22,9 +36,173 @@ use super::wrapper::RRCustomTouchBarItem;
 use super::wrapper::RRScrubber;
 use super::wrapper::RRPopoverTouchBarItem;
 use super::wrapper::RRSliderTouchBarItem;
+use super::wrapper;
+
+#[cfg(feature = "system_controls")]
+use super::system;
 
 const IDENT_PREFIX: &'static str = "com.trevorbentley.";
 
+/// Control Strip identifiers registered by every controller in this process,
+/// with the address of the delegate that owns each one.
+static TRAY_IDENTS: Mutex<Vec<(String, u64)>> = Mutex::new(Vec::new());
+
+/// Control Strip items currently registered, as the delegate that owns each
+/// one, the tray item, its identifier, and the bar it presents.  Removed on
+/// exit by `exit_cleanup()`.
+#[cfg(feature = "private_api")]
+static LIVE_TRAY_ITEMS: Mutex<Vec<(u64, u64, u64, u64)>> = Mutex::new(Vec::new());
+#[cfg(feature = "private_api")]
+static EXIT_CLEANUP_REGISTER: Once = Once::new();
+
+// Signals that end the process, after which the system would otherwise keep
+// showing a dead Control Strip item.  The application may handle these to
+// keep running, so they are only hooked if it doesn't.
+#[cfg(feature = "private_api")]
+const EXIT_SIGNALS: [libc::c_int; 4] = [libc::SIGHUP, libc::SIGINT, libc::SIGQUIT, libc::SIGTERM];
+
+/// Removes every registered Control Strip item and dismisses its bar
+///
+/// Best effort: this runs from `atexit()` and from handlers for termination
+/// signals, where little is guaranteed to be safe.  It gives up rather than
+/// wait on a lock held by the interrupted code.  Crash signals aren't
+/// hooked, since the crash may have left malloc or the Objective-C runtime
+/// in a state where this would deadlock.
+#[cfg(feature = "private_api")]
+fn exit_cleanup() {
+    let items: Vec<(u64, u64, u64, u64)> = match LIVE_TRAY_ITEMS.try_lock() {
+        Ok(mut items) => items.drain(..).collect(),
+        Err(_) => return,
+    };
+    for (_, tray_item, ident, bar) in items {
+        unsafe { remove_tray_item(tray_item, ident, bar); }
+    }
+}
+
+/// Removes a Control Strip item, and dismisses its bar if it is presented
+#[cfg(feature = "private_api")]
+unsafe fn remove_tray_item(tray_item: u64, ident: u64, bar: u64) {
+    DFRElementSetControlStripPresenceForIdentifier(ident as *mut Object, NO);
+    let cls = util::objc_class("NSTouchBarItem").unwrap();
+    let _:() = msg_send![cls, removeSystemTrayItem: tray_item as *mut Object];
+    if bar != 0 {
+        let cls = util::objc_class("NSTouchBar").unwrap();
+        match util::get_appkit_version() >= AppKitVersion::from_tuple(10, 14) {
+            true => { let _: () = msg_send![cls, dismissSystemModalTouchBar: bar as *mut Object]; },
+            false => { let _: () = msg_send![cls, dismissSystemModalFunctionBar: bar as *mut Object]; },
+        }
+    }
+}
+
+#[cfg(feature = "private_api")]
+extern "C" fn exit_cleanup_atexit() {
+    exit_cleanup();
+}
+
+#[cfg(feature = "private_api")]
+extern "C" fn exit_cleanup_signal(signal: libc::c_int) {
+    exit_cleanup();
+    // Terminate the way the signal would have without this handler
+    unsafe {
+        libc::signal(signal, libc::SIG_DFL);
+        libc::raise(signal);
+    }
+}
+
+/// Change which bar is dismissed at exit for a delegate's Control Strip item
+#[cfg(feature = "private_api")]
+fn update_exit_cleanup_bar(delegate: u64, bar: u64) {
+    let mut items = LIVE_TRAY_ITEMS.lock().unwrap();
+    for item in items.iter_mut().filter(|&&mut (owner, _, _, _)| owner == delegate) {
+        item.3 = bar;
+    }
+}
+
+/// Track a Control Strip item so it is removed if the process exits
+///
+/// Replaces any item previously registered by the same delegate.  The first
+/// call installs the exit hooks.  Termination signals the application
+/// already handles are left alone.
+#[cfg(feature = "private_api")]
+fn register_exit_cleanup(delegate: u64, tray_item: u64, ident: u64, bar: u64) {
+    {
+        let mut items = LIVE_TRAY_ITEMS.lock().unwrap();
+        items.retain(|&(owner, _, _, _)| owner != delegate);
+        items.push((delegate, tray_item, ident, bar));
+    }
+    EXIT_CLEANUP_REGISTER.call_once(|| unsafe {
+        libc::atexit(exit_cleanup_atexit);
+        let mut action: libc::sigaction = mem::zeroed();
+        action.sa_sigaction = exit_cleanup_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        libc::sigemptyset(&mut action.sa_mask);
+        for signal in EXIT_SIGNALS.iter() {
+            let mut previous: libc::sigaction = mem::zeroed();
+            libc::sigaction(*signal, &action, &mut previous);
+            if previous.sa_sigaction != libc::SIG_DFL {
+                libc::sigaction(*signal, &previous, std::ptr::null_mut());
+            }
+        }
+    });
+}
+
+/// Images finished downloading on background threads, waiting to be handed
+/// to the main thread: placeholder image, URL, and cached file if successful.
+type LoadedImages = Mutex<Vec<(u64, String, Option<PathBuf>)>>;
+
+/// Scrubber item identifier for items showing an image instead of text
+const SCRUBBER_IMAGE_IDENT: &'static str = "rubrail.scrubber.image";
+
+/// Scrubber item identifier for items showing an icon and text
+const SCRUBBER_ICON_IDENT: &'static str = "rubrail.scrubber.icon";
+
+// Height of the Touch Bar, in points, and its Retina scaling factor.
+const BAR_HEIGHT: u32 = 30;
+const BAR_SCALE: u32 = 2;
+
+// Opacity of items with FocusBehavior::Dim while Focus is active.
+const FOCUS_DIM_ALPHA: f64 = 0.35;
+
+// Width of each star of a rating item.
+const RATING_STAR_WIDTH: f64 = 20.;
+
+// Distance, in pixels, a finger must drag to turn a knob from min to max.
+const KNOB_DRAG_RANGE: f64 = 150.;
+
+// Marquee labels advance one character per tick, with a gap between the end
+// of the text and its wrapped-around start.
+const MARQUEE_INTERVAL_MS: u64 = 150;
+const MARQUEE_GAP: &'static str = "     ";
+
+// Shortest time a ticker label shows each string, so it stays readable.
+const TICKER_MIN_INTERVAL_MS: u64 = 250;
+
+// How long a button must be held before it starts repeating.
+const AUTO_REPEAT_DELAY_MS: u64 = 400;
+
+// How often the fill of a hold-to-confirm button grows while it is held.
+const HOLD_CONFIRM_TICK_MS: u64 = 30;
+
+// Font sizes of the two lines of a button with a subtitle, small enough that
+// both fit in the bar.
+const BUTTON_TITLE_FONT_SIZE: f64 = 11.;
+const BUTTON_SUBTITLE_FONT_SIZE: f64 = 9.;
+
+// Minimum width of menu rows, and padding between their contents and edges.
+const MENU_ROW_WIDTH: f64 = 180.;
+const MENU_ROW_PADDING: f64 = 8.;
+
+// How often brightness sliders check for changes made elsewhere, since there
+// is no notification for them.
+#[cfg(all(feature = "system_controls", feature = "private_api"))]
+const BRIGHTNESS_POLL_INTERVAL_MS: u64 = 1000;
+
+// Height of each row of a stacked item: half the bar.
+const STACKED_ROW_HEIGHT: f64 = 15.;
+
+// Delay between steps of an incremental bar construction, leaving the run loop
+// free to handle events in between.
+const BUILD_STEP_INTERVAL_MS: u64 = 10;
+
 /// Controller for creating and using Touch Bar UIs
 ///
 /// The `Touchbar` type provides the interface between Rust and the Apple Touch
@@ -71,7 +249,7 @@ const NO: i8 = 0i8;
 
 #[allow(non_snake_case)]
 unsafe fn NSApp() -> *mut Object {
-    let cls = Class::get("NSApplication").unwrap();
+    let cls = util::objc_class("NSApplication").unwrap();
     msg_send![cls, sharedApplication]
 }
 
@@ -79,7 +257,7 @@ unsafe fn NSApp() -> *mut Object {
 struct NSString(*mut Object);
 impl NSString {
     unsafe fn alloc(_: *mut Object) -> NSString {
-        let cls = Class::get("NSString").unwrap();
+        let cls = util::objc_class("NSString").unwrap();
         NSString(msg_send![cls, alloc])
     }
     unsafe fn init_str(self, s: &str) -> *mut Object {
@@ -91,7 +269,7 @@ impl NSString {
 struct NSImage(*mut Object);
 impl NSImage {
     unsafe fn alloc(_: *mut Object) -> NSImage {
-        let cls = Class::get("NSImage").unwrap();
+        let cls = util::objc_class("NSImage").unwrap();
         NSImage(msg_send![cls, alloc])
     }
     #[allow(non_snake_case)]
@@ -163,23 +341,67 @@ impl NSRect {
         }
     }
 }
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct NSRange {
+    pub location: u64,
+    pub length: u64,
+}
+unsafe impl objc::Encode for NSRange {
+    fn encode() -> objc::Encoding {
+        let encoding = format!("{{_NSRange={}{}}}",
+                               u64::encode().as_str(),
+                               u64::encode().as_str());
+        unsafe { objc::Encoding::from_str(&encoding) }
+    }
+}
 
 /////
 ///// End simulate 'cocoa' crate
 /////
 
 
-pub type Ident = u64;
+objc_handle! {
+    /// Identifier of an item or bar, as a raw pointer to an `NSString`
+    Ident
+}
 
+#[cfg(feature = "private_api")]
 #[link(name = "DFRFoundation", kind = "framework")]
 extern {
     pub fn DFRSystemModalShowsCloseBoxWhenFrontMost(x: i8);
     pub fn DFRElementSetControlStripPresenceForIdentifier(n: *mut Object, x: i8);
 }
 
+#[cfg(feature = "private_api")]
+#[link(name = "MediaRemote", kind = "framework")]
+extern {
+    pub fn MRMediaRemoteRegisterForNowPlayingNotifications(queue: *mut Object);
+    pub fn MRMediaRemoteUnregisterForNowPlayingNotifications();
+    // handler is a block taking the info dictionary
+    pub fn MRMediaRemoteGetNowPlayingInfo(queue: *mut Object, handler: *mut Object);
+}
+
+#[cfg(feature = "private_api")]
+extern {
+    // Expanded from the dispatch_get_main_queue() macro
+    static _dispatch_main_q: Object;
+}
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern {
+    pub static kCFPreferencesCurrentUser: *mut Object;
+    pub static kCFPreferencesCurrentHost: *mut Object;
+    pub fn CFPreferencesCopyValue(key: *mut Object, app: *mut Object,
+                                  user: *mut Object, host: *mut Object) -> *mut Object;
+}
+
 #[link(name = "AppKit", kind = "framework")]
 extern {
     pub static NSAppKitVersionNumber: f64;
+    pub static NSFontAttributeName: *mut Object;
+    pub static NSForegroundColorAttributeName: *mut Object;
+    pub static NSParagraphStyleAttributeName: *mut Object;
 }
 
 #[derive(PartialEq)]
@@ -234,7 +456,10 @@ pub mod util {
 
     extern crate libc;
     use super::ItemId;
+    use super::TouchbarError;
     use std::ptr;
+    use std::cell::RefCell;
+    use std::collections::BTreeMap;
     use std::ffi::CStr;
     use objc::runtime::Object;
     use objc::runtime::Class;
@@ -242,6 +467,29 @@ pub mod util {
     use super::nil;
     use super::AppKitVersion;
 
+    thread_local! {
+        static CLASS_CACHE: RefCell<BTreeMap<String, &'static Class>> = const { RefCell::new(BTreeMap::new()) };
+    }
+
+    /// Look up an Objective-C class by name
+    ///
+    /// Found classes are cached.  Missing classes are not, since classes can
+    /// be registered at runtime.
+    pub fn objc_class(name: &str) -> Result<&'static Class, TouchbarError> {
+        CLASS_CACHE.with(|cache| {
+            if let Some(cls) = cache.borrow().get(name) {
+                return Ok(*cls);
+            }
+            match Class::get(name) {
+                Some(cls) => {
+                    cache.borrow_mut().insert(name.to_string(), cls);
+                    Ok(cls)
+                },
+                None => Err(TouchbarError::ClassUnavailable(name.to_string())),
+            }
+        })
+    }
+
     #[allow(dead_code)]
     /// Print an NSString object to the global logger
     pub fn print_nsstring(str: *mut Object) {
@@ -268,7 +516,7 @@ pub mod util {
     /// extension in the bundled Resources directory.
     pub fn bundled_resource_path(name: &str, extension: &str) -> Option<String> {
         unsafe {
-            let cls = Class::get("NSBundle").unwrap();
+            let cls = objc_class("NSBundle").unwrap();
             let bundle: *mut Object = msg_send![cls, mainBundle];
             let res = NSString::alloc(nil).init_str(name);
             let ext = NSString::alloc(nil).init_str(extension);
@@ -301,9 +549,9 @@ pub mod util {
     /// * `b` - Blue value (0.0 - 1.0)
     /// * `alpha` - Alpha value (0.0 - 1.0)
     pub unsafe fn set_bg_color(item: &ItemId, r: f64, g: f64, b: f64, alpha: f64) {
-        let item = *item as *mut Object;
+        let item = item.as_raw() as *mut Object;
         let view: *mut Object = msg_send![item, view];
-        let cls = Class::get("NSColor").unwrap();
+        let cls = objc_class("NSColor").unwrap();
         let color: *mut Object = msg_send![
             cls, colorWithRed: r green: g blue: b alpha: alpha];
         let _:() = msg_send![view, setBackgroundColor: color];
@@ -325,14 +573,56 @@ pub mod util {
     /// * `b` - Blue value (0.0 - 1.0)
     /// * `alpha` - Alpha value (0.0 - 1.0)
     pub unsafe fn set_text_color(item: &ItemId, r: f64, g: f64, b: f64, alpha: f64) {
-        let item = *item as *mut Object;
+        let item = item.as_raw() as *mut Object;
         let view: *mut Object = msg_send![item, view];
-        let cls = Class::get("NSColor").unwrap();
+        let cls = objc_class("NSColor").unwrap();
         let color: *mut Object = msg_send![
             cls, colorWithRed: r green: g blue: b alpha: alpha];
         let _:() = msg_send![view, setTextColor: color];
     }
 
+    /// Whether macOS Focus (Do Not Disturb) is currently active
+    ///
+    /// Checks the Focus assertion database used since macOS 12, and the Do
+    /// Not Disturb preference used by earlier versions.
+    pub fn focus_mode_active() -> bool {
+        // macOS 12+ records active Focus modes as assertions in a JSON file
+        if let Some(home) = std::env::var_os("HOME") {
+            let db = std::path::Path::new(&home)
+                .join("Library/DoNotDisturb/DB/Assertions.json");
+            if let Ok(contents) = std::fs::read_to_string(db) {
+                return contents.contains("assertionDetailsModeIdentifier");
+            }
+        }
+        unsafe {
+            let key = NSString::alloc(nil).init_str("doNotDisturb");
+            let app = NSString::alloc(nil).init_str("com.apple.notificationcenterui");
+            let value = super::CFPreferencesCopyValue(key, app,
+                                                      super::kCFPreferencesCurrentUser,
+                                                      super::kCFPreferencesCurrentHost);
+            let _:() = msg_send![key, release];
+            let _:() = msg_send![app, release];
+            if value == nil {
+                return false;
+            }
+            let enabled: bool = msg_send![value, boolValue];
+            let _:() = msg_send![value, release];
+            enabled
+        }
+    }
+
+    /// Whether the system is currently using the dark appearance
+    pub fn dark_mode_active() -> bool {
+        unsafe {
+            let cls = objc_class("NSUserDefaults").unwrap();
+            let defaults: *mut Object = msg_send![cls, standardUserDefaults];
+            let key = NSString::alloc(nil).init_str("AppleInterfaceStyle");
+            let style: *mut Object = msg_send![defaults, stringForKey: key];
+            let _:() = msg_send![key, release];
+            style != nil && nsstring_decode(style) == "Dark"
+        }
+    }
+
     /// Get version of linked Apple AppKit framework
     pub fn get_appkit_version() -> AppKitVersion {
         unsafe { AppKitVersion::from_f64(super::NSAppKitVersionNumber) }
@@ -436,7 +726,38 @@ impl ImageTemplate {
     }
 }
 
-#[derive(PartialEq, Debug)]
+impl DatePickerElements {
+    fn objc(elements: DatePickerElements) -> u64 {
+        match elements {
+            DatePickerElements::Date => 0x00e0, // NSDatePickerElementFlagYearMonthDay
+            DatePickerElements::Time => 0x000c, // NSDatePickerElementFlagHourMinute
+            DatePickerElements::DateAndTime => 0x00ec, // YearMonthDay | HourMinute
+        }
+    }
+}
+
+impl ScrubberMode {
+    fn objc(mode: ScrubberMode) -> i64 {
+        match mode {
+            ScrubberMode::Fixed => 0, // NSScrubberModeFixed
+            ScrubberMode::Free => 1, // NSScrubberModeFree
+        }
+    }
+}
+
+impl ScrubberStyle {
+    // Returns the (overlay, background) selection styles
+    unsafe fn objc(style: ScrubberStyle) -> (*mut Object, *mut Object) {
+        let cls = util::objc_class("NSScrubberSelectionStyle").unwrap();
+        match style {
+            ScrubberStyle::OutlineOverlay => (msg_send![cls, outlineOverlayStyle], nil),
+            ScrubberStyle::RoundedBackground => (nil, msg_send![cls, roundedBackgroundStyle]),
+            ScrubberStyle::None => (nil, nil),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
 enum ItemType {
     Button,
     Label,
@@ -444,64 +765,221 @@ enum ItemType {
     Scrubber,
     Popover,
     Spacer,
+    Knob,
+    DatePicker,
+    TextField,
+    Canvas,
+    Graph,
+    Countdown,
+    MultilineLabel,
+    Passthrough,
+    Picker,
+    Rating,
+    MediaControls,
+    Stacked,
+    #[cfg(feature = "private_api")]
+    NowPlaying,
+}
+
+// System setting a slider is bound to
+#[cfg(feature = "system_controls")]
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum SystemControl {
+    Volume,
+    #[cfg(all(feature = "system_controls", feature = "private_api"))]
+    Brightness,
+}
+
+// State of a button that must be held before it fires
+struct HoldConfirm {
+    recognizer: *mut Object,
+    hold: Duration,
+    // Layer showing progress, owned by the button's layer
+    fill: *mut Object,
+    started: Option<Instant>,
+}
+
+// Callback of one row of a stacked item
+enum RowCb {
+    Button(ButtonCb),
+    Slider(SliderCb),
 }
 
 struct InternalBar {
     view: *mut Object,
     ident: Ident,
     items: Vec<ItemId>,
+    // Controls and gestures disabled by set_bar_interaction_enabled(), with
+    // their previous enabled state.
+    disabled: Option<Vec<(*mut Object, bool)>>,
+    // Name of the saved layout and the items the user may add, set by
+    // set_bar_customization()
+    customization: Option<(String, Vec<ItemId>)>,
 }
 
 impl fmt::Display for InternalBar {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Bar[{}] ({}) <{:x}>",
                self.items.len(),
-               util::nsstring_decode(self.ident as *mut Object),
+               util::nsstring_decode(self.ident.as_raw() as *mut Object),
                self.view as u64)
     }
 }
 
+struct InternalGesture {
+    recognizer: *mut Object,
+    kind: GestureKind,
+    config: GestureConfig,
+    cb: Rc<GestureCb>,
+    last_value: Cell<f64>,
+}
+
 struct InternalItem {
     _type: ItemType,
     view: *mut Object,
     ident: Ident,
     control: Option<*mut Object>,
     scrubber: Option<Rc<dyn TScrubberData>>,
-    button_cb: Option<ButtonCb>,
-    slider_cb: Option<SliderCb>,
-    swipe_cb: Option<SwipeCb>,
-    tap_cb: Option<ButtonCb>,
-    child_bar: Option<ItemId>,
+    button_cb: Option<Rc<EventCb>>,
+    slider_cb: Option<Rc<SliderCb>>,
+    accessory_cbs: Vec<(*mut Object, ButtonCb)>,
+    date_cb: Option<DateCb>,
+    text_cb: Option<TextCb>,
+    canvas_cb: Option<CanvasCb>,
+    resize_cb: Option<ResizeCb>,
+    picker_cb: Option<PickerCb>,
+    rating_cb: Option<RatingCb>,
+    media_cb: Option<MediaCb>,
+    row_cbs: Vec<(*mut Object, RowCb)>,
+    auto_repeat: Option<(*mut Object, Duration)>,
+    hold_confirm: Option<HoldConfirm>,
+    gestures: Vec<InternalGesture>,
+    child_bar: Option<BarId>,
+    lazy_bar: Option<(Rc<PopoverBuildCb>, bool)>,
+    hold_bar: Option<BarId>,
+    represent_after: Option<Duration>,
+    integer_only: bool,
+    last_value: Option<f64>,
+    width: Option<u32>,
+    persistent: bool,
+    focus_behavior: FocusBehavior,
+    image_variants: Option<(*mut Object, *mut Object)>,
+    // Button disabled by set_button_enabled(), which gets no callbacks
+    disabled: bool,
+    // Title and subtitle of a button with two lines of text
+    subtitle: Option<(String, String)>,
+    // Unbadged image and count of a button with a badge
+    badge: Option<(*mut Object, u32)>,
+    // Indices of a scrubber on screen, as a half-open range
+    visible: (u32, u32),
+    timer: Option<*mut Object>,
+    deadline: Option<Instant>,
+    done_cb: Option<ButtonCb>,
+    marquee: Option<(String, usize)>,
+    ticker: Option<(Vec<String>, usize)>,
+    #[cfg(feature = "system_controls")]
+    system_control: Option<SystemControl>,
+    // Last time the user moved a slider
+    #[cfg(all(feature = "system_controls", feature = "private_api"))]
+    touched: Option<Instant>,
+    // Stamped by insert_item(), to catch ItemIds used after being freed
+    generation: u32,
 }
 
 impl fmt::Display for InternalItem {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:?} ({}) <{:x}>", self._type,
-               util::nsstring_decode(self.ident as *mut Object),
+               util::nsstring_decode(self.ident.as_raw() as *mut Object),
                self.view as u64)
     }
 }
 
 impl InternalItem {
+    fn new(_type: ItemType, view: *mut Object, ident: Ident,
+           control: Option<*mut Object>) -> InternalItem {
+        InternalItem {
+            _type: _type,
+            view: view,
+            ident: ident,
+            control: control,
+            scrubber: None,
+            button_cb: None,
+            slider_cb: None,
+            accessory_cbs: Vec::new(),
+            date_cb: None,
+            text_cb: None,
+            canvas_cb: None,
+            resize_cb: None,
+            picker_cb: None,
+            rating_cb: None,
+            media_cb: None,
+            row_cbs: Vec::new(),
+            auto_repeat: None,
+            hold_confirm: None,
+            gestures: Vec::new(),
+            child_bar: None,
+            lazy_bar: None,
+            hold_bar: None,
+            represent_after: None,
+            integer_only: false,
+            last_value: None,
+            width: None,
+            persistent: false,
+            focus_behavior: FocusBehavior::Normal,
+            image_variants: None,
+            disabled: false,
+            subtitle: None,
+            badge: None,
+            visible: (0, 0),
+            timer: None,
+            deadline: None,
+            done_cb: None,
+            marquee: None,
+            ticker: None,
+            #[cfg(feature = "system_controls")]
+            system_control: None,
+            #[cfg(all(feature = "system_controls", feature = "private_api"))]
+            touched: None,
+            generation: 0,
+        }
+    }
     fn free_objc_allocations(&mut self) {
         unsafe {
+            if let Some(timer) = self.timer {
+                RustTouchbarDelegateWrapper::cancel_timer(timer);
+            }
             if let Some(obj) = self.control {
                 // Sliders don't allocate their control
                 if self._type != ItemType::Slider {
                     let _:() = msg_send![obj, release];
                 }
             }
+            if let Some((light, dark)) = self.image_variants {
+                let _:() = msg_send![light, release];
+                let _:() = msg_send![dark, release];
+            }
+            if let Some((base, _)) = self.badge {
+                let _:() = msg_send![base, release];
+            }
             let _:() = msg_send![self.view, release];
-            let ident = self.ident as *mut Object;
+            let ident = self.ident.as_raw() as *mut Object;
             let _:() = msg_send![ident, release];
             self.view = nil;
-            self.ident = 0;
+            self.ident = Ident::from_raw(0);
             self.control = None;
             self.scrubber = None;
             self.button_cb = None;
-            self.swipe_cb = None;
-            self.tap_cb = None;
+            self.gestures.clear();
+            self.lazy_bar = None;
             self.slider_cb = None;
+            self.accessory_cbs.clear();
+            self.date_cb = None;
+            self.text_cb = None;
+            self.canvas_cb = None;
+            self.image_variants = None;
+            self.badge = None;
+            self.timer = None;
+            self.done_cb = None;
         }
     }
 }
@@ -509,33 +987,351 @@ impl InternalItem {
 pub struct RustTouchbarDelegateWrapper {
     objc: Id<ObjcAppDelegate, Shared>,
     next_item_id: Cell<u64>,
-    bar_map: BTreeMap<ItemId, InternalBar>,
+    next_generation: u32,
+    bar_map: BTreeMap<BarId, InternalBar>,
     item_map: BTreeMap<ItemId, InternalItem>,
+    focus_active: bool,
+    focus_cb: Option<FocusCb>,
+    // Last visibility of the root bar reported to visibility_cb
+    root_visible: bool,
+    visibility_cb: Option<VisibilityCb>,
+    // Names given to items with set_item_name()
+    item_names: BTreeMap<String, ItemId>,
+    // Items and bars owned by handles from own_item() and own_bar(), with
+    // the token of the handle that owns each one
+    owned: BTreeMap<u64, u64>,
+    next_owner: u64,
+    // Handles dropped since the last run loop pass, as (id, token)
+    dropped: Rc<RefCell<Vec<(u64, u64)>>>,
+    // Downloads from create_image_from_url() waiting for the main thread
+    loaded_images: Arc<LoadedImages>,
+    icon_variants: Option<(String, String)>,
+    ident_prefix: String,
+    error_cb: Option<ErrorCb>,
+    build_steps: BTreeMap<u64, (usize, BuildStepCb)>,
+    missing_image: MissingImageBehavior,
+    enabled: bool,
+    // Scrubber currently being selected by select_scrubber_item()
+    selecting_scrubber: Cell<Option<u64>>,
+    // Images from create_image_*() that haven't been given to an item yet
+    unused_images: RefCell<BTreeSet<TouchbarImage>>,
+    // Output device whose volume is being listened to, once a volume slider
+    // has been created
+    #[cfg(feature = "system_controls")]
+    volume_device: Option<Option<u32>>,
+    #[cfg(feature = "private_api")]
+    now_playing_registered: bool,
+    // Whether Control Strip icons are shown, set by set_control_strip_visible()
+    #[cfg(feature = "private_api")]
+    control_strip_visible: bool,
+    // Delegates of Control Strip items added with create_control_strip_item()
+    trays: Vec<Id<ObjcAppDelegate, Shared>>,
 }
 
 impl RustTouchbarDelegateWrapper {
-    fn generate_ident(&mut self) -> u64 {
+    fn alloc_touchbar(title: &str, prefix: Option<&str>) -> Touchbar {
+        let objc = ObjcAppDelegate::new().share();
+        let rust = Box::new(RustTouchbarDelegateWrapper {
+            objc: objc.clone(),
+            next_item_id: Cell::new(0),
+            next_generation: 1,
+            item_map: BTreeMap::<ItemId, InternalItem>::new(),
+            bar_map: BTreeMap::<BarId, InternalBar>::new(),
+            focus_active: util::focus_mode_active(),
+            focus_cb: None,
+            root_visible: false,
+            visibility_cb: None,
+            item_names: BTreeMap::new(),
+            owned: BTreeMap::new(),
+            next_owner: 0,
+            dropped: Rc::new(RefCell::new(Vec::new())),
+            loaded_images: Arc::new(Mutex::new(Vec::new())),
+            icon_variants: None,
+            ident_prefix: RustTouchbarDelegateWrapper::ident_namespace(prefix),
+            error_cb: None,
+            build_steps: BTreeMap::new(),
+            missing_image: MissingImageBehavior::Placeholder,
+            enabled: true,
+            selecting_scrubber: Cell::new(None),
+            unused_images: RefCell::new(BTreeSet::new()),
+            #[cfg(feature = "system_controls")]
+            volume_device: None,
+            #[cfg(feature = "private_api")]
+            now_playing_registered: false,
+            #[cfg(feature = "private_api")]
+            control_strip_visible: true,
+            trays: Vec::new(),
+        });
+        unsafe {
+            let ptr: u64 = &*rust as *const RustTouchbarDelegateWrapper as u64;
+            let _:() = msg_send![rust.objc, setRustWrapper: ptr];
+            let objc_title = NSString::alloc(nil).init_str(title);
+            let _:() = msg_send![rust.objc, setTitle: objc_title];
+            let _:() = msg_send![objc_title, release];
+
+            // Focus changes are announced on the distributed notification
+            // center, with different names across macOS versions.
+            let cls = util::objc_class("NSDistributedNotificationCenter").unwrap();
+            let center: *mut Object = msg_send![cls, defaultCenter];
+            for name in &["com.apple.notificationcenterui.dndprefs_changed",
+                          "_NSDoNotDisturbEnabledNotification",
+                          "_NSDoNotDisturbDisabledNotification"] {
+                let name = NSString::alloc(nil).init_str(name);
+                let _:() = msg_send![center,
+                                     addObserver: rust.objc.clone()
+                                     selector: sel!(focusModeChanged:)
+                                     name: name
+                                     object: nil];
+                let _:() = msg_send![name, release];
+            }
+            let name = NSString::alloc(nil).init_str("AppleInterfaceThemeChangedNotification");
+            let _:() = msg_send![center,
+                                 addObserver: rust.objc.clone()
+                                 selector: sel!(appearanceChanged:)
+                                 name: name
+                                 object: nil];
+            let _:() = msg_send![name, release];
+        }
+        return rust
+    }
+    fn ident_namespace(prefix: Option<&str>) -> String {
+        // Identifiers are shared system-wide through the Control Strip, so
+        // include the bundle, process, and a random suffix to keep multiple
+        // apps, and multiple instances of one app, from colliding.
+        let bundle = match prefix {
+            Some(prefix) => prefix.trim_end_matches('.').to_string(),
+            None => RustTouchbarDelegateWrapper::bundle_namespace(),
+        };
+        let mut hasher = RandomState::new().build_hasher();
+        if let Ok(now) = SystemTime::now().duration_since(UNIX_EPOCH) {
+            hasher.write_u32(now.subsec_nanos());
+        }
+        format!("{}.rubrail.{}-{:08x}.", bundle, process::id(), hasher.finish() as u32)
+    }
+    fn bundle_namespace() -> String {
+        unsafe {
+            let cls = util::objc_class("NSBundle").unwrap();
+            let bundle: *mut Object = msg_send![cls, mainBundle];
+            let ident: *mut Object = msg_send![bundle, bundleIdentifier];
+            match ident != nil {
+                true => util::nsstring_decode(ident),
+                false => IDENT_PREFIX.trim_end_matches('.').to_string(),
+            }
+        }
+    }
+    fn classes_available(&self, names: &[&str]) -> bool {
+        let mut available = true;
+        for name in names {
+            if let Err(err) = util::objc_class(name) {
+                self.report_error(err);
+                available = false;
+            }
+        }
+        available
+    }
+    fn report_error(&self, err: TouchbarError) {
+        error!("{}", err);
+        if let Some(ref cb) = self.error_cb {
+            cb(&err);
+        }
+    }
+    fn claim_tray_ident(&self, delegate: *mut Object, ident: *mut Object) {
+        let ident = util::nsstring_decode(ident);
+        let owner = delegate as u64;
+        let collision = {
+            let mut idents = TRAY_IDENTS.lock().unwrap();
+            idents.retain(|&(_, o)| o != owner);
+            let collision = idents.iter().any(|&(ref i, _)| *i == ident);
+            idents.push((ident.clone(), owner));
+            collision
+        };
+        if collision {
+            self.report_error(TouchbarError::IdentifierCollision(ident));
+        }
+    }
+    fn generate_ident(&mut self) -> Ident {
         unsafe {
             // Create string identifier
             let next_item_id = self.next_item_id.get();
             self.next_item_id.set(next_item_id + 1);
-            let ident = format!("{}{}", IDENT_PREFIX, next_item_id);
+            let ident = format!("{}{}", self.ident_prefix, next_item_id);
             let objc_ident = NSString::alloc(nil).init_str(&ident);
-            objc_ident as u64
+            Ident::from_raw(objc_ident as u64)
+        }
+    }
+    // Register a newly created item, stamping it with a new generation
+    fn insert_item(&mut self, view: *mut Object, mut internal: InternalItem) -> ItemId {
+        let generation = self.next_generation;
+        self.next_generation = self.next_generation.wrapping_add(1).max(1);
+        internal.generation = generation;
+        let item = ItemId::with_generation(view as u64, generation);
+        // Replace the key too, since it carries the old generation
+        self.item_map.remove(&item);
+        self.item_map.insert(item, internal);
+        item
+    }
+    // Whether an item is still allocated, reporting an error if it isn't
+    // ItemId of the item allocated at `raw`, stamped with its live generation
+    fn live_item(&self, raw: u64) -> ItemId {
+        match self.item_map.get(&ItemId::from_raw(raw)) {
+            Some(x) => ItemId::with_generation(raw, x.generation),
+            None => ItemId::from_raw(raw),
+        }
+    }
+    fn check_item(&self, item: &ItemId) -> bool {
+        // Raw ids carry no generation, so take the one of the live item
+        let generation = match item.generation() {
+            0 => self.live_item(item.as_raw()).generation(),
+            generation => generation,
+        };
+        let live = match self.item_map.get(item) {
+            Some(x) => generation == x.generation,
+            None => false,
+        };
+        if !live {
+            self.report_error(TouchbarError::StaleItem(*item));
+        }
+        live
+    }
+    fn track_image(&self, image: TouchbarImage) -> TouchbarImage {
+        if image != TouchbarImage::from_raw(0) {
+            self.unused_images.borrow_mut().insert(image);
+        }
+        image
+    }
+    fn consume_image(&self, image: &TouchbarImage) {
+        self.unused_images.borrow_mut().remove(image);
+    }
+    // Items in the root bars and every popover bar reachable from them
+    fn reachable_items(&self) -> BTreeSet<ItemId> {
+        let mut bars: Vec<BarId> = self.delegates().into_iter().map(|delegate| unsafe {
+            let root: *mut Object = msg_send![delegate, groupTouchBar];
+            BarId::from_raw(root as u64)
+        }).collect();
+        let mut visited: Vec<BarId> = Vec::new();
+        let mut items = BTreeSet::new();
+        while let Some(bar) = bars.pop() {
+            if visited.contains(&bar) {
+                continue;
+            }
+            visited.push(bar);
+            let internal_bar = match self.bar_map.get(&bar) {
+                Some(b) => b,
+                None => continue,
+            };
+            for item in &internal_bar.items {
+                items.insert(*item);
+                if let Some(internal_item) = self.item_map.get(item) {
+                    bars.extend(internal_item.child_bar);
+                    bars.extend(internal_item.hold_bar);
+                }
+            }
+        }
+        items
+    }
+    fn retain_count(obj: *mut Object) -> u64 {
+        unsafe { msg_send![obj, retainCount] }
+    }
+    // Write a bar and its items, recursing into popover bars
+    fn dump_bar(&self, out: &mut String, label: &str, bar: &BarId, depth: usize,
+                visited: &mut Vec<BarId>) {
+        let indent = "  ".repeat(depth);
+        let internal_bar = match self.bar_map.get(bar) {
+            Some(b) => b,
+            None => {
+                let _ = writeln!(out, "{}{}: <{:x}> (unknown)", indent, label, bar.as_raw());
+                return;
+            },
+        };
+        let _ = writeln!(out, "{}{}: {} retain {}", indent, label, internal_bar,
+                         RustTouchbarDelegateWrapper::retain_count(internal_bar.view));
+        if visited.contains(bar) {
+            return;
+        }
+        visited.push(*bar);
+        for item in &internal_bar.items {
+            self.dump_item(out, item, depth + 1, visited);
+        }
+    }
+    fn dump_item(&self, out: &mut String, item: &ItemId, depth: usize,
+                 visited: &mut Vec<BarId>) {
+        let indent = "  ".repeat(depth);
+        let internal_item = match self.item_map.get(item) {
+            Some(x) => x,
+            None => {
+                let _ = writeln!(out, "{}<{:x}> (unknown)", indent, item.as_raw());
+                return;
+            },
+        };
+        let _ = writeln!(out, "{}{} retain {}", indent, internal_item,
+                         RustTouchbarDelegateWrapper::retain_count(internal_item.view));
+        if let Some(ref bar) = internal_item.child_bar {
+            self.dump_bar(out, "Popover bar", bar, depth + 1, visited);
+        }
+        if let Some(ref bar) = internal_item.hold_bar {
+            self.dump_bar(out, "Hold bar", bar, depth + 1, visited);
+        }
+    }
+    fn is_popover_bar(&self, bar: &BarId) -> bool {
+        self.item_map.values().any(|item| {
+            item.child_bar == Some(*bar) || item.hold_bar == Some(*bar)
+        })
+    }
+    fn bar_reaches_bar(&self, outer: &BarId, inner: &BarId, visited: &mut Vec<BarId>) -> bool {
+        if outer == inner {
+            return true;
         }
+        // Lazy popovers build their bars outside of add_items_to_bar(), so a
+        // cycle could already exist.
+        if visited.contains(outer) {
+            return false;
+        }
+        visited.push(*outer);
+        let internal_bar = match self.bar_map.get(outer) {
+            Some(x) => x,
+            None => return false,
+        };
+        internal_bar.items.iter().filter_map(|item| self.item_map.get(item)).any(|item| {
+            item.child_bar.iter().chain(item.hold_bar.iter())
+                .any(|child| self.bar_reaches_bar(child, inner, visited))
+        })
+    }
+    fn bar_item_conflict(&self, bar: &BarId, item: &ItemId, added: &[ItemId]) -> Option<String> {
+        let internal_item = self.item_map.get(item)?;
+        let ident = util::nsstring_decode(internal_item.ident.as_raw() as *mut Object);
+        if internal_item.child_bar.iter().chain(internal_item.hold_bar.iter())
+            .any(|child| self.bar_reaches_bar(child, bar, &mut Vec::new())) {
+            return Some(format!("popover {} contains its own bar", ident));
+        }
+        if internal_item._type == ItemType::Spacer {
+            return None;
+        }
+        if added.contains(item) {
+            return Some(format!("{} is already in this bar", ident));
+        }
+        // Root bars commonly share items, since only one shows at a time, but
+        // a popover bar shows on top of the bar that opened it.
+        if self.is_popover_bar(bar) &&
+            self.bar_map.iter().any(|(id, b)| id != bar && b.items.contains(item)) {
+            warn!("{} is in a popover bar and another bar, and only shows in one", ident);
+        }
+        None
     }
     fn alloc_button(&mut self, image: Option<&TouchbarImage>, text: Option<&str>,
                     target: *mut Object, sel: Sel) -> *mut Object {
+        if let Some(image) = image {
+            self.consume_image(image);
+        }
         unsafe {
             let text = match text {
                 Some(s) => NSString::alloc(nil).init_str(s),
                 None => nil,
             };
             let image = match image {
-                Some(i) => *i as *mut Object,
+                Some(i) => i.as_raw() as *mut Object,
                 None => nil,
             };
-            let cls = Class::get("NSButton").unwrap();
+            let cls = util::objc_class("NSButton").unwrap();
             let btn: *mut Object;
             // Match on (image, text) as booleans.   false == null.
             match ((image as u64) != 0, (text as u64) != 0) {
@@ -574,8 +1370,8 @@ impl RustTouchbarDelegateWrapper {
     fn find_view(&self, ident: Ident) -> Option<*mut Object> {
         match self.item_map.values().into_iter().filter(|x| {
             unsafe {
-                let id = ident as *mut Object;
-                let equal: bool = msg_send![id, isEqualToString: x.ident];
+                let id = ident.as_raw() as *mut Object;
+                let equal: bool = msg_send![id, isEqualToString: x.ident.as_raw()];
                 equal
             }
         }).next() {
@@ -585,15 +1381,15 @@ impl RustTouchbarDelegateWrapper {
     }
     fn find_view_from_control(&self, item: &ItemId) -> Option<*mut Object> {
         match self.item_map.values().into_iter().filter(|x| {
-            x.control.is_some() && x.control.unwrap() as ItemId == *item
+            x.control.is_some() && ItemId::from_raw(x.control.unwrap() as u64) == *item
         }).next() {
             Some(item) => Some(item.view),
             None => None,
         }
     }
-    fn find_bar_ident(&self, bar: &ItemId) -> Option<Ident> {
+    fn find_bar_ident(&self, bar: &BarId) -> Option<Ident> {
         match self.bar_map.values().into_iter().filter(|x| {
-            x.view as ItemId == *bar
+            BarId::from_raw(x.view as u64) == *bar
         }).next() {
             Some(item) => Some(item.ident),
             None => None,
@@ -601,7 +1397,7 @@ impl RustTouchbarDelegateWrapper {
     }
     fn find_ident(&self, item: &ItemId) -> Option<Ident> {
         match self.item_map.values().into_iter().filter(|x| {
-            x.view as ItemId == *item
+            ItemId::from_raw(x.view as u64) == *item
         }).next() {
             Some(item) => Some(item.ident),
             None => None,
@@ -609,41 +1405,142 @@ impl RustTouchbarDelegateWrapper {
     }
     fn find_ident_from_control(&self, item: &ItemId) -> Option<Ident> {
         match self.item_map.values().into_iter().filter(|x| {
-            x.control.is_some() && x.control.unwrap() as ItemId == *item
+            x.control.is_some() && ItemId::from_raw(x.control.unwrap() as u64) == *item
         }).next() {
             Some(item) => Some(item.ident),
             None => None,
         }
     }
-    fn find_button_cb(&self, btn: u64) -> Option<&ButtonCb> {
+    fn find_button_cb(&self, btn: u64) -> Option<&EventCb> {
+        match self.item_map.values().into_iter().filter(|x| {
+            x._type == ItemType::Button && x.control.unwrap() as u64 == btn &&
+                x.hold_confirm.is_none() && !x.disabled
+        }).next() {
+            Some(item) => item.button_cb.as_ref().map(|cb| &**cb),
+            None => None,
+        }
+    }
+    fn find_accessory_cb(&self, accessory: u64) -> Option<(ItemId, &ButtonCb)> {
+        for item in self.item_map.values() {
+            for &(obj, ref cb) in &item.accessory_cbs {
+                if obj as u64 == accessory {
+                    return Some((self.live_item(item.view as u64), cb));
+                }
+            }
+        }
+        None
+    }
+    fn find_row_cb(&self, control: u64) -> Option<(ItemId, &RowCb)> {
+        for item in self.item_map.values().filter(|x| x._type == ItemType::Stacked) {
+            for &(obj, ref cb) in &item.row_cbs {
+                if obj as u64 == control {
+                    return Some((self.live_item(item.view as u64), cb));
+                }
+            }
+        }
+        None
+    }
+    fn alloc_stacked_row(&mut self, spec: ItemSpec) -> (*mut Object, Option<RowCb>) {
+        unsafe {
+            let target = (&*self.objc.clone()) as *const ObjcAppDelegate as *mut Object;
+            let font_cls = util::objc_class("NSFont").unwrap();
+            let small_size: f64 = msg_send![font_cls, smallSystemFontSize];
+            let font: *mut Object = msg_send![font_cls, systemFontOfSize: small_size];
+            match spec {
+                ItemSpec::Label(text) => {
+                    let cls = util::objc_class("NSTextField").unwrap();
+                    let text = NSString::alloc(nil).init_str(&text);
+                    let label: *mut Object = msg_send![cls, labelWithString: text];
+                    let _:() = msg_send![text, release];
+                    let _:() = msg_send![label, setFont: font];
+                    (label, None)
+                },
+                ItemSpec::Button { image, text, cb } => {
+                    let btn = self.alloc_button(image.as_ref(), text.as_deref(),
+                                                target, sel!(stackedRow:));
+                    let _:() = msg_send![btn, setControlSize: 1u64]; // NSControlSizeSmall
+                    let _:() = msg_send![btn, setFont: font];
+                    let _:() = msg_send![btn, autorelease];
+                    (btn, Some(RowCb::Button(cb)))
+                },
+                ItemSpec::Slider { min, max, cb } => {
+                    let cls = util::objc_class("NSSlider").unwrap();
+                    let slider: *mut Object = msg_send![cls,
+                                                        sliderWithValue: min
+                                                        minValue: min
+                                                        maxValue: max
+                                                        target: target
+                                                        action: sel!(stackedRow:)];
+                    let _:() = msg_send![slider, setControlSize: 1u64]; // NSControlSizeSmall
+                    (slider, Some(RowCb::Slider(cb)))
+                },
+            }
+        }
+    }
+    fn find_gesture(&self, recognizer: u64) -> Option<(ItemId, &InternalGesture)> {
+        for item in self.item_map.values() {
+            if let Some(gesture) = item.gestures.iter().filter(|g| {
+                g.recognizer as u64 == recognizer
+            }).next() {
+                return Some((self.live_item(item.view as u64), gesture));
+            }
+        }
+        None
+    }
+    fn find_media_control(&self, btn: u64) -> Option<(ItemId, MediaEvent, &MediaCb)> {
+        for item in self.item_map.values().filter(|x| x._type == ItemType::MediaControls) {
+            let index: u64 = unsafe {
+                let buttons: *mut Object = msg_send![item.control.unwrap(), arrangedSubviews];
+                msg_send![buttons, indexOfObject: btn as *mut Object]
+            };
+            let event = match index {
+                0 => MediaEvent::Previous,
+                1 => MediaEvent::PlayPause,
+                2 => MediaEvent::Next,
+                _ => continue,
+            };
+            if let Some(ref cb) = item.media_cb {
+                return Some((self.live_item(item.view as u64), event, cb));
+            }
+        }
+        None
+    }
+    fn find_picker(&self, sender: u64) -> Option<(ItemId, &PickerCb)> {
+        self.item_map.values().filter(|x| {
+            x._type == ItemType::Picker &&
+                (x.view as u64 == sender || x.control.map_or(false, |c| c as u64 == sender))
+        }).next().and_then(|x| x.picker_cb.as_ref().map(|cb| (self.live_item(x.view as u64), cb)))
+    }
+    fn find_slider_cb(&self, sldr: u64) -> Option<&SliderCb> {
         match self.item_map.values().into_iter().filter(|x| {
-            x._type == ItemType::Button && x.control.unwrap() as u64 == btn
+            (x._type == ItemType::Slider || x._type == ItemType::Knob) &&
+                x.view as u64 == sldr
         }).next() {
-            Some(item) => item.button_cb.as_ref(),
+            Some(item) => item.slider_cb.as_ref().map(|cb| &**cb),
             None => None,
         }
     }
-    fn find_swipe_cb(&self, item: u64) -> Option<&SwipeCb> {
+    fn find_date_cb(&self, picker: u64) -> Option<&DateCb> {
         match self.item_map.values().into_iter().filter(|x| {
-            x.control.is_some() && x.control.unwrap() as u64 == item
+            x._type == ItemType::DatePicker && x.control.unwrap() as u64 == picker
         }).next() {
-            Some(item) => item.swipe_cb.as_ref(),
+            Some(item) => item.date_cb.as_ref(),
             None => None,
         }
     }
-    fn find_tap_cb(&self, item: u64) -> Option<&ButtonCb> {
+    fn find_text_cb(&self, field: u64) -> Option<&TextCb> {
         match self.item_map.values().into_iter().filter(|x| {
-            x.control.is_some() && x.control.unwrap() as u64 == item
+            x._type == ItemType::TextField && x.control.unwrap() as u64 == field
         }).next() {
-            Some(item) => item.tap_cb.as_ref(),
+            Some(item) => item.text_cb.as_ref(),
             None => None,
         }
     }
-    fn find_slider_cb(&self, sldr: u64) -> Option<&SliderCb> {
+    fn find_timer_item(&self, timer: u64) -> Option<ItemId> {
         match self.item_map.values().into_iter().filter(|x| {
-            x._type == ItemType::Slider && x.view as u64 == sldr
+            x.timer.is_some() && x.timer.unwrap() as u64 == timer
         }).next() {
-            Some(item) => item.slider_cb.as_ref(),
+            Some(item) => Some(self.live_item(item.view as u64)),
             None => None,
         }
     }
@@ -651,7 +1548,7 @@ impl RustTouchbarDelegateWrapper {
         match self.item_map.values().into_iter().filter(|x| {
             x._type == ItemType::Scrubber && x.control.unwrap() as u64 == scrubber
         }).next() {
-            Some(item) => Some(item.view as ItemId),
+            Some(item) => Some(self.live_item(item.view as u64)),
             None => None,
         }
     }
@@ -663,505 +1560,4540 @@ impl RustTouchbarDelegateWrapper {
             None => None,
         }
     }
+    fn scrubber_visible_range_changed(&mut self, scrubber: u64, range: NSRange) {
+        let (start, end) = (range.location as u32, (range.location + range.length) as u32);
+        let (item, data, (old_start, old_end)) = match self.item_map.iter_mut().filter(|&(_, ref x)| {
+            x._type == ItemType::Scrubber && x.control.unwrap() as u64 == scrubber
+        }).next() {
+            Some((id, x)) => match x.scrubber.clone() {
+                Some(data) => (*id, data, mem::replace(&mut x.visible, (start, end))),
+                None => return,
+            },
+            None => return,
+        };
+        for idx in (old_start..old_end).filter(|i| *i < start || *i >= end) {
+            data.did_end_display(item, idx);
+        }
+        for idx in (start..end).filter(|i| *i < old_start || *i >= old_end) {
+            data.will_display(item, idx);
+        }
+    }
     fn find_popover(&self, button: u64) -> Option<ItemId> {
         match self.item_map.values().into_iter().filter(|x| {
             x._type == ItemType::Popover && x.control.unwrap() as u64 == button
         }).next() {
-            Some(item) => Some(item.view as ItemId),
+            Some(item) => Some(self.live_item(item.view as u64)),
             None => None,
         }
     }
-    fn free_bar_allocations(&mut self, bar: *mut Object) {
-        let bar_id = bar as u64;
-        let mut subbars = Vec::<*mut Object>::new();
-        let items = self.bar_map.get(&bar_id).unwrap().items.clone();
-        for item in items.iter() {
-            let mut internal_item = self.item_map.remove(&item).unwrap();
-            if internal_item._type == ItemType::Popover {
-                subbars.push(internal_item.child_bar.unwrap() as *mut Object);
+    fn free_item_allocations(&mut self, item: &ItemId) {
+        let mut internal_item = match self.item_map.remove(item) {
+            Some(x) => x,
+            None => return,
+        };
+        self.item_names.retain(|_, x| x != item);
+        self.owned.remove(&(*item).as_raw());
+        let mut subbars = Vec::<BarId>::new();
+        for bar in internal_item.child_bar.iter().chain(internal_item.hold_bar.iter()) {
+            if !subbars.contains(bar) {
+                subbars.push(*bar);
             }
-            internal_item.free_objc_allocations();
         }
-        self.bar_map.get_mut(&bar_id).unwrap().items.clear();
+        internal_item.free_objc_allocations();
         for subbar in subbars {
-            self.free_bar_allocations(subbar);
+            // Leave bars that another popover still opens
+            if !self.is_popover_bar(&subbar) {
+                self.free_bar(subbar);
+            }
         }
-    }
-    unsafe fn set_label_font_for_text(label: *mut Object, text: &str) {
-        //let constraints: *mut Object = msg_send![label, constraints];
-        //let height_constraint: *mut Object = msg_send![constraints, firstObject];
-        //if height_constraint != nil {
-        //    // TODO: if re-enabled, must use NSLayoutConstraint identifier to
-        //    // make sure the correct constraint is removed
-        //    msg_send![label, removeConstraint: height_constraint];
-        //}
-        if text.contains("\n") {
-            // Shrink font for multi-line labels.  This makes for quite small text.
-            let cls = Class::get("NSFont").unwrap();
-            let default_size:f64 = msg_send![cls, systemFontSize];
-            let custom_font: *mut Object = msg_send![cls, systemFontOfSize: default_size - 3.0];
-            let _:() = msg_send![label, setFont: custom_font];
-
-            //let anchor: *mut Object = msg_send![label, heightAnchor];
-            //let constraint: *mut Object = msg_send![anchor, constraintEqualToConstant: 30. as f64];
-            //let _:() = msg_send![constraint, setActive: YES];
+        #[cfg(feature = "system_controls")]
+        {
+            if internal_item.system_control == Some(SystemControl::Volume) &&
+                !self.item_map.values().any(|x| x.system_control == Some(SystemControl::Volume)) {
+                self.stop_volume_listener();
+            }
         }
-        else {
-            // Enlarge the font for single lines.  For some reason it defaults
-            // to smaller than other elements.
-            let cls = Class::get("NSFont").unwrap();
-            let default_size:f64 = msg_send![cls, systemFontSize];
-            let custom_font: *mut Object = msg_send![cls, systemFontOfSize: default_size + 3.0];
-            let _:() = msg_send![label, setFont: custom_font];
+        #[cfg(feature = "private_api")]
+        {
+            if internal_item._type == ItemType::NowPlaying &&
+                !self.item_map.values().any(|x| x._type == ItemType::NowPlaying) {
+                self.stop_now_playing();
+            }
         }
     }
-}
-
-impl TTouchbar for Touchbar {
-    type T = Touchbar;
-    fn alloc(title: &str) -> Touchbar {
-        let objc = ObjcAppDelegate::new().share();
-        let rust = Box::new(RustTouchbarDelegateWrapper {
-            objc: objc.clone(),
-            next_item_id: Cell::new(0),
-            item_map: BTreeMap::<ItemId, InternalItem>::new(),
-            bar_map: BTreeMap::<ItemId, InternalBar>::new(),
-        });
+    // Stop observing Now Playing information, once no Now Playing item is left
+    #[cfg(feature = "private_api")]
+    fn stop_now_playing(&mut self) {
+        if !self.now_playing_registered {
+            return;
+        }
+        self.now_playing_registered = false;
         unsafe {
-            let ptr: u64 = &*rust as *const RustTouchbarDelegateWrapper as u64;
-            let _:() = msg_send![rust.objc, setRustWrapper: ptr];
-            let objc_title = NSString::alloc(nil).init_str(title);
-            let _:() = msg_send![rust.objc, setTitle: objc_title];
-            let _:() = msg_send![objc_title, release];
+            MRMediaRemoteUnregisterForNowPlayingNotifications();
+            let cls = util::objc_class("NSNotificationCenter").unwrap();
+            let center: *mut Object = msg_send![cls, defaultCenter];
+            let name = NSString::alloc(nil).init_str("kMRMediaRemoteNowPlayingInfoDidChangeNotification");
+            let _:() = msg_send![center,
+                                 removeObserver: self.main_delegate()
+                                 name: name
+                                 object: nil];
+            let _:() = msg_send![name, release];
         }
-        return rust
     }
-    fn set_icon(&self, image: &str) {
-        unsafe {
-            let filename = NSString::alloc(nil).init_str(image);
-            let objc_image = NSImage::alloc(nil).initWithContentsOfFile_(filename);
-            let _:() = msg_send![self.objc, setIcon: objc_image];
-            let _:() = msg_send![filename, release];
+    // Stop listening to the output volume, once no volume slider is left
+    #[cfg(feature = "system_controls")]
+    fn stop_volume_listener(&mut self) {
+        if let Some(device) = self.volume_device.take() {
+            let delegate = &*self.objc as *const ObjcAppDelegate as *mut Object;
+            unsafe { system::remove_volume_listener(delegate, device); }
         }
     }
-
-    fn create_bar(&mut self) -> BarId {
-        unsafe {
-            let ident = self.generate_ident();
-            // Create touchbar
-            let cls = RRTouchBar::class();
-            let bar: *mut Object = msg_send![cls, alloc];
-            let bar: *mut objc::runtime::Object = msg_send![bar, init];
-            let _ : () = msg_send![bar, setDelegate: self.objc.clone()];
-            let internal = InternalBar {
-                view: bar,
-                ident: ident,
-                items: Vec::<ItemId>::new(),
-            };
-            self.bar_map.insert(bar as u64, internal);
-            bar as u64
+    fn free_bar_allocations(&mut self, bar: *mut Object) {
+        let bar_id = bar as u64;
+        self.owned.remove(&bar_id);
+        let allowed = self.forget_customization(bar);
+        let items = match self.bar_map.get_mut(&BarId::from_raw(bar_id)) {
+            Some(internal_bar) => mem::replace(&mut internal_bar.items, Vec::new()),
+            None => Vec::new(),
+        };
+        for item in items.iter() {
+            // Persistent items, and everything under them, are kept alive so
+            // they can be reused in the replacement bar.
+            if self.item_map.get(&item).map_or(false, |x| x.persistent) {
+                continue;
+            }
+            self.free_item_allocations(item);
+        }
+        for item in allowed.iter() {
+            self.free_removed_item(item);
         }
     }
-    fn create_popover_item(&mut self, image: Option<&TouchbarImage>,
-                           text: Option<&str>, bar_id: &BarId) -> ItemId {
-        unsafe {
-            let bar = *bar_id as *mut Object;
-            let ident = self.generate_ident();
-            let cls = RRPopoverTouchBarItem::class();
-            let item: *mut Object = msg_send![cls, alloc];
-            let item: *mut Object = msg_send![item, initWithIdentifier: ident];
-
-            let target = (&*self.objc.clone()) as *const ObjcAppDelegate as *mut Object;
-            let btn = self.alloc_button(image, text,
-                                        target,
-                                        sel!(popbar:));
-            let _:() = msg_send![item, setShowsCloseButton: YES];
-            let gesture: *mut Object = msg_send![item, makeStandardActivatePopoverGestureRecognizer];
-            let _:() = msg_send![btn, addGestureRecognizer: gesture];
-            let _:() = msg_send![item, setCollapsedRepresentation: btn];
-            let _:() = msg_send![item, setPopoverTouchBar: bar];
-            let _:() = msg_send![item, setPressAndHoldTouchBar: bar];
-
-            let internal = InternalItem {
-                _type: ItemType::Popover,
-                view: item,
-                ident: ident,
-                control: Some(btn),
-                scrubber: None,
-                button_cb: None,
-                slider_cb: None,
-                swipe_cb: None,
-                tap_cb: None,
-                child_bar: Some(bar as ItemId),
-            };
-            self.item_map.insert(item as u64, internal);
-            item as u64
+    fn customization_key(identifier: &str) -> *mut Object {
+        let key = format!("rubrail.customization.{}", identifier);
+        unsafe { NSString::alloc(nil).init_str(&key) }
+    }
+    // Arrange a customizable bar the way the user last left it
+    #[cfg(not(feature = "private_api"))]
+    fn load_customization(&mut self, bar_id: &BarId) {
+        let (identifier, allowed) = match self.bar_map.get(bar_id).and_then(|b| b.customization.clone()) {
+            Some(customization) => customization,
+            None => return,
+        };
+        let saved = unsafe {
+            let cls = util::objc_class("NSUserDefaults").unwrap();
+            let defaults: *mut Object = msg_send![cls, standardUserDefaults];
+            let key = RustTouchbarDelegateWrapper::customization_key(&identifier);
+            let saved: *mut Object = msg_send![defaults, stringForKey: key];
+            let _:() = msg_send![key, release];
+            match saved != nil {
+                true => util::nsstring_decode(saved),
+                false => return,
+            }
+        };
+        let items: Vec<ItemId> = saved.split(',')
+            .filter_map(|idx| idx.parse::<usize>().ok())
+            .filter_map(|idx| allowed.get(idx).cloned())
+            .collect();
+        match self.bar_map.get_mut(bar_id) {
+            Some(internal_bar) => internal_bar.items = items,
+            None => return,
         }
+        self.sync_bar_identifiers(bar_id);
     }
-    fn add_items_to_bar(&mut self, bar_id: &BarId, items: Vec<ItemId>) {
+    // Save the layout of a bar the user customized
+    fn bar_customized(&mut self, bar: u64) {
+        let (identifier, allowed) = match self.bar_map.get(&BarId::from_raw(bar)).and_then(|b| b.customization.clone()) {
+            Some(customization) => customization,
+            None => return,
+        };
+        let idents: Vec<String> = unsafe {
+            let idents: *mut Object = msg_send![bar as *mut Object, itemIdentifiers];
+            let count: u64 = msg_send![idents, count];
+            (0..count).map(|i| {
+                let ident: *mut Object = msg_send![idents, objectAtIndex: i];
+                util::nsstring_decode(ident)
+            }).collect()
+        };
+        let items: Vec<ItemId> = idents.iter().filter_map(|ident| {
+            allowed.iter().cloned().find(|item| {
+                self.find_ident(item).map_or(false, |i| util::nsstring_decode(i.as_raw() as *mut Object) == *ident)
+            })
+        }).collect();
+        let saved = items.iter()
+            .filter_map(|item| allowed.iter().position(|x| x == item))
+            .map(|idx| idx.to_string())
+            .collect::<Vec<String>>().join(",");
+        match self.bar_map.get_mut(&BarId::from_raw(bar)) {
+            Some(internal_bar) => internal_bar.items = items,
+            None => return,
+        }
         unsafe {
-            let cls = Class::get("NSMutableArray").unwrap();
-            let idents: *mut Object = msg_send![cls, alloc];
-            let idents: *mut Object = msg_send![idents, initWithCapacity: items.len()];
-            for item in items {
-                if let Some(ident) = self.find_ident(&item) {
-                    let ident = ident as *mut Object;
-                    let _ : () = msg_send![idents, addObject: ident];
-                    self.bar_map.get_mut(&bar_id).unwrap().items.push(item);
+            let cls = util::objc_class("NSUserDefaults").unwrap();
+            let defaults: *mut Object = msg_send![cls, standardUserDefaults];
+            let key = RustTouchbarDelegateWrapper::customization_key(&identifier);
+            let value = NSString::alloc(nil).init_str(&saved);
+            let _:() = msg_send![defaults, setObject: value forKey: key];
+            let _:() = msg_send![value, release];
+            let _:() = msg_send![key, release];
+        }
+    }
+    // Stop watching a customizable bar before it is released.  Returns the
+    // items that were allowed in it, for the caller to free.
+    fn forget_customization(&mut self, bar: *mut Object) -> Vec<ItemId> {
+        let customization = self.bar_map.get_mut(&BarId::from_raw(bar as u64))
+            .and_then(|b| b.customization.take());
+        match customization {
+            Some((_, allowed)) => {
+                unsafe {
+                    let key = NSString::alloc(nil).init_str("itemIdentifiers");
+                    let _:() = msg_send![bar, removeObserver: self.objc.clone() forKeyPath: key];
+                    let _:() = msg_send![key, release];
                 }
+                allowed
+            },
+            None => Vec::new(),
+        }
+    }
+    // Free a bar and its items that aren't needed elsewhere
+    fn free_bar(&mut self, bar_id: BarId) {
+        let allowed = self.forget_customization(bar_id.as_raw() as *mut Object);
+        let items = match self.bar_map.get_mut(&bar_id) {
+            Some(internal_bar) => mem::replace(&mut internal_bar.items, Vec::new()),
+            None => return,
+        };
+        // Items the user removed from a customizable bar belong to it too
+        for item in items.iter().chain(allowed.iter()) {
+            self.free_removed_item(item);
+        }
+        if let Some(internal_bar) = self.bar_map.remove(&bar_id) {
+            self.owned.remove(&bar_id.as_raw());
+            unsafe {
+                let _:() = msg_send![internal_bar.ident.as_raw() as *mut Object, release];
+                let _:() = msg_send![bar_id.as_raw() as *mut Object, release];
             }
-            let bar = *bar_id as *mut Object;
-            let _ : () = msg_send![bar, setDefaultItemIdentifiers: idents];
-            let _:() = msg_send![idents, release];
         }
     }
-    fn set_bar_as_root(&mut self, bar_id: BarId) {
-        unsafe {
-            let old_bar: *mut Object = msg_send![self.objc, groupTouchBar];
-            if old_bar != nil {
-                let cls = Class::get("NSTouchBar").unwrap();
-                match util::get_appkit_version() >= AppKitVersion::from_tuple(10, 14) {
-                    true => {
-                        let _: () = msg_send![cls, minimizeSystemModalTouchBar: old_bar];
-                        let _: () = msg_send![cls, dismissSystemModalTouchBar: old_bar];
-                    },
-                    false => {
-                        let _: () = msg_send![cls, minimizeSystemModalFunctionBar: old_bar];
-                        let _: () = msg_send![cls, dismissSystemModalFunctionBar: old_bar];
-                    },
+    // Track ownership of an item or bar by a handle, returning the handle's
+    // release function
+    fn own(&mut self, id: u64) -> Box<dyn FnOnce()> {
+        self.next_owner += 1;
+        let token = self.next_owner;
+        self.owned.insert(id, token);
+        // Only a weak reference, so handles outliving the controller do
+        // nothing.  The delegate lives as long as the controller does.
+        let dropped = Rc::downgrade(&self.dropped);
+        let delegate = self.main_delegate() as u64;
+        Box::new(move || {
+            let dropped = match dropped.upgrade() {
+                Some(dropped) => dropped,
+                None => return,
+            };
+            let mut dropped = dropped.borrow_mut();
+            // Free on the next run loop pass, since the handle may be dropped
+            // inside a callback while the controller is in use.
+            if dropped.is_empty() {
+                unsafe {
+                    let _:() = msg_send![delegate as *mut Object,
+                                         performSelector: sel!(releaseDropped:)
+                                         withObject: nil
+                                         afterDelay: 0.0f64];
                 }
-                self.free_bar_allocations(old_bar);
-                let _: () = msg_send![old_bar, release];
             }
-            let _ : () = msg_send![self.objc, setGroupTouchBar: bar_id];
-            let ident = self.find_bar_ident(&bar_id).unwrap();
-            let _ : () = msg_send![self.objc, setGroupIdent: ident];
-            let _ : () = msg_send![self.objc, applicationDidFinishLaunching: 0];
+            dropped.push((id, token));
+        })
+    }
+    fn release_dropped(&mut self) {
+        let dropped = mem::replace(&mut *self.dropped.borrow_mut(), Vec::new());
+        for (id, token) in dropped {
+            // Skip handles whose item was already freed with its bar
+            if self.owned.get(&id) != Some(&token) {
+                continue;
+            }
+            self.owned.remove(&id);
+            if self.item_map.contains_key(&ItemId::from_raw(id)) {
+                if !self.bar_map.values().any(|b| b.items.contains(&ItemId::from_raw(id))) {
+                    self.forget_disabled_controls(&ItemId::from_raw(id));
+                    self.free_item_allocations(&ItemId::from_raw(id));
+                }
+            }
+            else if self.bar_map.contains_key(&BarId::from_raw(id)) && !self.is_root_bar(&BarId::from_raw(id)) &&
+                !self.is_popover_bar(&BarId::from_raw(id)) {
+                self.free_bar(BarId::from_raw(id));
+            }
         }
     }
-    fn create_label(&mut self, text: &str) -> ItemId {
-        unsafe {
-            let frame = NSRect::new(NSPoint::new(0., 0.), NSSize::new(0., 40.));
-            let cls = Class::get("NSTextField").unwrap();
-            let label: *mut Object = msg_send![cls, alloc];
-            let label: *mut Object = msg_send![label, initWithFrame: frame];
-            RustTouchbarDelegateWrapper::set_label_font_for_text(label, text);
-            let _:() = msg_send![label, setEditable: NO];
-            let cell: *mut Object = msg_send![label, cell];
-            let _:() = msg_send![cell, setWraps: NO];
-            let text = NSString::alloc(nil).init_str(text);
-            let _:() = msg_send![label, setStringValue: text];
-            let _:() = msg_send![text, release];
-
-            let ident = self.generate_ident();
-            let cls = RRCustomTouchBarItem::class();
-            let item: *mut Object = msg_send![cls, alloc];
-            let item: *mut Object = msg_send![item, initWithIdentifier: ident];
-            let _:() = msg_send![item, setView: label];
-
-            let internal = InternalItem {
-                _type: ItemType::Label,
-                view: item,
-                ident: ident,
-                control: Some(label),
-                scrubber: None,
-                button_cb: None,
-                slider_cb: None,
-                swipe_cb: None,
-                tap_cb: None,
-                child_bar: None
-            };
-            self.item_map.insert(item as u64, internal);
-            item as u64
+    // Free an item that was taken out of a bar, unless it's still needed
+    fn free_removed_item(&mut self, item: &ItemId) {
+        if self.item_map.get(item).map_or(true, |x| x.persistent) ||
+            self.bar_map.values().any(|b| b.items.contains(item) ||
+                                      b.customization.as_ref().map_or(false, |c| c.1.contains(item))) {
+            return;
         }
+        self.forget_disabled_controls(item);
+        self.free_item_allocations(item);
     }
-    fn update_label(&mut self, label_id: &ItemId, text: &str) {
-        unsafe {
-            let item: *mut Object = *label_id as *mut Object;
-            let label: *mut Object = msg_send![item, view];
-            RustTouchbarDelegateWrapper::set_label_font_for_text(label, text);
-            let text = NSString::alloc(nil).init_str(text);
-            let _:() = msg_send![label, setStringValue: text];
-            let _:() = msg_send![text, release];
+    // Keep bars with interaction disabled from re-enabling an item's controls
+    // after it is freed
+    fn forget_disabled_controls(&mut self, item: &ItemId) {
+        let objs: Vec<*mut Object> = match self.item_map.get(item) {
+            Some(internal_item) => internal_item.control.iter().cloned()
+                .chain(internal_item.gestures.iter().map(|g| g.recognizer))
+                .chain(internal_item.auto_repeat.iter().map(|&(r, _)| r))
+                .chain(internal_item.hold_confirm.iter().map(|h| h.recognizer))
+                .collect(),
+            None => return,
+        };
+        for disabled in self.bar_map.values_mut().filter_map(|b| b.disabled.as_mut()) {
+            disabled.retain(|&(obj, _)| !objs.contains(&obj));
         }
     }
-    fn update_label_width(&mut self, label_id: &ItemId, width: u32) {
+    fn sync_bar_identifiers(&self, bar_id: &BarId) {
+        let internal_bar = match self.bar_map.get(bar_id) {
+            Some(x) => x,
+            None => return,
+        };
         unsafe {
-            //let _:() = msg_send![label, setAutoresizingMask: 0];
-            //let _:() = msg_send![label, setFrameSize: NSSize::new(600., 10.)];
-            //let _:() = msg_send![label, setBoundsSize: NSSize::new(600., 10.)];
-            //let _:() = msg_send![item, setFrameSize: NSSize::new(600., 30.)];
-            //let _:() = msg_send![label, setPreferredMaxLayoutWidth: 500.];
-            //let constraints: *mut Object = msg_send![label, constraints];
-            //let count: u32 = msg_send![constraints, count];
-            //info!("CONSTRAINTS: {}", count);
-            let item: *mut Object = *label_id as *mut Object;
-            let label: *mut Object = msg_send![item, view];
-            let anchor: *mut Object = msg_send![label, widthAnchor];
-            let constraint: *mut Object = msg_send![anchor, constraintEqualToConstant: width as f64];
-            let _:() = msg_send![constraint, setActive: YES];
+            let cls = util::objc_class("NSMutableArray").unwrap();
+            let idents: *mut Object = msg_send![cls, alloc];
+            let idents: *mut Object = msg_send![idents, initWithCapacity: internal_bar.items.len()];
+            for ident in self.shown_bar_items(bar_id, &internal_bar.items).iter()
+                .filter_map(|item| self.find_ident(item)) {
+                let _:() = msg_send![idents, addObject: ident.as_raw() as *mut Object];
+            }
+            let _:() = msg_send![bar_id.as_raw() as *mut Object, setDefaultItemIdentifiers: idents];
+            let _:() = msg_send![idents, release];
         }
     }
-
-    fn create_text_scrubber(&mut self, data: Rc<dyn TScrubberData>) -> ItemId {
-        unsafe {
-            let ident = self.generate_ident();
-            let cls = RRCustomTouchBarItem::class();
-            let item: *mut Object = msg_send![cls, alloc];
-            let item: *mut Object = msg_send![item, initWithIdentifier: ident];
-
-            // note: frame is ignored, but must be provided.
-            let frame = NSRect::new(NSPoint::new(0., 0.), NSSize::new(0., 30.));
-            let cls = RRScrubber::class();
-            let scrubber: *mut Object = msg_send![cls, alloc];
-            let scrubber: *mut Object = msg_send![scrubber, initWithFrame: frame];
-
-            let cls = Class::get("NSScrubberSelectionStyle").unwrap();
-            let style: *mut Object = msg_send![cls, outlineOverlayStyle];
-
-            let cls = Class::get("NSScrubberTextItemView").unwrap();
-            let _:() = msg_send![scrubber, registerClass: cls forItemIdentifier: ident];
-            let _:() = msg_send![scrubber, setDelegate: self.objc.clone()];
-            let _:() = msg_send![scrubber, setDataSource: self.objc.clone()];
-            let _:() = msg_send![scrubber, setSelectionOverlayStyle: style];
-            let _:() = msg_send![scrubber, setMode: 1]; // NSScrubberModeFree
-            let _:() = msg_send![item, setView: scrubber];
-
-            let internal = InternalItem {
-                _type: ItemType::Scrubber,
-                view: item,
-                ident: ident,
-                control: Some(scrubber),
-                scrubber: Some(data),
-                button_cb: None,
-                slider_cb: None,
-                swipe_cb: None,
-                tap_cb: None,
-                child_bar: None,
-            };
-            self.item_map.insert(item as u64, internal);
-            item as u64
+    // Items of a bar that are given to AppKit.  Popover bars sit beside their
+    // close button and size themselves to their items, so spacers at either
+    // end only add empty slots.  They are left out and reported, but kept in
+    // the bar in case it stops being a popover bar.
+    // Range of a bar's items left after trimming spacers from the ends of
+    // popover bars
+    fn shown_bar_span(&self, bar_id: &BarId, items: &[ItemId]) -> (usize, usize) {
+        if !self.is_popover_bar(bar_id) {
+            return (0, items.len());
         }
+        let is_spacer = |item: &ItemId| {
+            self.item_map.get(item).map_or(false, |x| x._type == ItemType::Spacer)
+        };
+        let start = items.iter().position(|x| !is_spacer(x)).unwrap_or(items.len());
+        let end = items.iter().rposition(|x| !is_spacer(x)).map_or(start, |idx| idx + 1);
+        (start, end)
     }
-    fn select_scrubber_item(&mut self, scrub_id: &ItemId, index: u32) {
-        unsafe {
-            let item = *scrub_id as *mut Object;
-            let scrubber: *mut Object = msg_send![item, view];
-            let _:() = msg_send![scrubber, setSelectedIndex: index];
+    fn shown_bar_items(&self, bar_id: &BarId, items: &[ItemId]) -> Vec<ItemId> {
+        let (start, end) = self.shown_bar_span(bar_id, items);
+        items[start..end].to_vec()
+    }
+    // Report spacers among `added` that landed at the end of a popover bar,
+    // where they are left out
+    fn report_edge_spacers(&self, bar_id: &BarId, added: &[ItemId]) {
+        let items = match self.bar_map.get(bar_id) {
+            Some(internal_bar) => &internal_bar.items,
+            None => return,
+        };
+        let (start, end) = self.shown_bar_span(bar_id, items);
+        for item in items[..start].iter().chain(items[end..].iter()).filter(|x| added.contains(x)) {
+            let ident = match self.item_map.get(item) {
+                Some(x) => util::nsstring_decode(x.ident.as_raw() as *mut Object),
+                None => continue,
+            };
+            let reason = format!("spacer {} at the end of a popover bar", ident);
+            self.report_error(TouchbarError::UnsupportedItem(reason));
         }
     }
-    fn refresh_scrubber(&mut self, scrub_id: &ItemId) {
+    fn build_lazy_popover(&mut self, item: ItemId) {
+        // Take the builder out while it runs, since it calls back into this
+        // controller to create the bar.
+        let (builder, rebuild) = match self.item_map.get_mut(&item) {
+            Some(x) => match x.lazy_bar.take() {
+                Some(lazy) => lazy,
+                None => return,
+            },
+            None => return,
+        };
+        let bar = builder(&item);
+        let old_bar = match self.item_map.get_mut(&item) {
+            Some(internal_item) => {
+                if rebuild {
+                    internal_item.lazy_bar = Some((builder, rebuild));
+                }
+                internal_item.child_bar.replace(bar)
+            },
+            None => return,
+        };
         unsafe {
-            let item = *scrub_id as *mut Object;
-            let scrubber: *mut Object = msg_send![item, view];
-            let sel_idx: i32 = msg_send![scrubber, selectedIndex];
-            let pre_count: i32 = msg_send![scrubber, numberOfItems];
-            let _:() = msg_send![scrubber, reloadData];
-            let post_count: i32 = msg_send![scrubber, numberOfItems];
-            // reload clears the selected item.  re-select it.
-            if sel_idx >= 0 && pre_count == post_count {
-                let _:() = msg_send![scrubber, setSelectedIndex: sel_idx];
-            }
+            let _:() = msg_send![item.as_raw() as *mut Object, setPopoverTouchBar: bar.as_raw() as *mut Object];
+        }
+        self.sync_bar_identifiers(&bar);
+        if let Some(old_bar) = old_bar.filter(|old_bar| *old_bar != bar) {
+            self.free_bar(old_bar);
         }
     }
-
-    fn add_item_swipe_gesture(&mut self, item_id: &ItemId, cb: SwipeCb) {
+    // Attach a gesture to an item's view.  The callback is shared with any
+    // clones of the item.
+    fn add_gesture_recognizer(&mut self, item_id: &ItemId, kind: GestureKind,
+                              config: GestureConfig, cb: Rc<GestureCb>) {
         unsafe {
-            let item = *item_id as *mut Object;
+            let item = item_id.as_raw() as *mut Object;
             let view: *mut Object = msg_send![item, view];
-            if view == nil {
+            if view == nil || !self.item_map.contains_key(item_id) {
                 return;
             }
             let _:() = msg_send![view, setAllowedTouchTypes: 1]; // NSTouchTypeMaskDirect
-            let cls = Class::get("NSPanGestureRecognizer").unwrap();
+            let name = match kind {
+                GestureKind::Click => "NSClickGestureRecognizer",
+                GestureKind::Press => "NSPressGestureRecognizer",
+                GestureKind::Pan => "NSPanGestureRecognizer",
+                GestureKind::Magnify => "NSMagnificationGestureRecognizer",
+                GestureKind::Rotate => "NSRotationGestureRecognizer",
+            };
+            let cls = match util::objc_class(name) {
+                Ok(cls) => cls,
+                Err(err) => {
+                    self.report_error(err);
+                    return;
+                },
+            };
             let gesture: *mut Object = msg_send![cls, alloc];
             let gesture: *mut Object = msg_send![gesture,
                                                  initWithTarget: self.objc.clone()
-                                                 action: sel!(swipeGesture:)];
+                                                 action: sel!(gesture:)];
             let _:() = msg_send![gesture, setAllowedTouchTypes: 1]; // NSTouchTypeMaskDirect
+            match kind {
+                GestureKind::Click => {
+                    let _:() = msg_send![gesture, setNumberOfTouchesRequired: config.fingers];
+                    let _:() = msg_send![gesture, setNumberOfClicksRequired: config.clicks];
+                },
+                GestureKind::Press => {
+                    let secs = config.min_duration.as_secs() as f64 +
+                        config.min_duration.subsec_nanos() as f64 * 1e-9;
+                    let _:() = msg_send![gesture, setNumberOfTouchesRequired: config.fingers];
+                    let _:() = msg_send![gesture, setMinimumPressDuration: secs];
+                },
+                GestureKind::Pan => {
+                    let _:() = msg_send![gesture, setNumberOfTouchesRequired: config.fingers];
+                },
+                GestureKind::Magnify | GestureKind::Rotate => {},
+            }
             let _:() = msg_send![view, addGestureRecognizer: gesture];
             let _:() = msg_send![gesture, release];
-            let mut internal_item = self.item_map.remove(item_id).unwrap();
-            internal_item.swipe_cb = Some(cb);
-            self.item_map.insert(*item_id, internal_item);
+            self.item_map.get_mut(item_id).unwrap().gestures.push(InternalGesture {
+                recognizer: gesture,
+                kind: kind,
+                config: config,
+                cb: cb,
+                last_value: Cell::new(0.),
+            });
+        }
+    }
+    // Copy a view and its subviews.  The copy is retained, and its controls
+    // are left without a target.
+    unsafe fn copy_view(view: *mut Object) -> *mut Object {
+        let cls = util::objc_class("NSKeyedArchiver").unwrap();
+        let data: *mut Object = msg_send![cls, archivedDataWithRootObject: view];
+        let cls = util::objc_class("NSKeyedUnarchiver").unwrap();
+        let copy: *mut Object = msg_send![cls, unarchiveObjectWithData: data];
+        let _:() = msg_send![copy, retain];
+        // Copied gesture recognizers have lost their targets too
+        let recognizers: *mut Object = msg_send![copy, gestureRecognizers];
+        let recognizers: *mut Object = msg_send![recognizers, copy];
+        let count: u64 = msg_send![recognizers, count];
+        for i in 0..count {
+            let recognizer: *mut Object = msg_send![recognizers, objectAtIndex: i];
+            let _:() = msg_send![copy, removeGestureRecognizer: recognizer];
         }
+        let _:() = msg_send![recognizers, release];
+        copy
     }
+    // Duplicate a button or label, whose state is mostly in its view.  The
+    // gestures that copy_view() drops are added to the copy again, except for
+    // auto-repeat and confirmation holds, which clone_bar() sets up.
+    fn clone_view_item(&mut self, item: &ItemId) -> ItemId {
+        let ident = self.generate_ident();
+        let internal_item = self.item_map.get(item).unwrap();
+        let gestures: Vec<(GestureKind, GestureConfig, Rc<GestureCb>)> = internal_item.gestures.iter()
+            .map(|g| (g.kind, g.config, g.cb.clone())).collect();
+        let clone = unsafe {
+            let copy = RustTouchbarDelegateWrapper::copy_view(internal_item.control.unwrap());
+            let cls = RRCustomTouchBarItem::class();
+            let new_item: *mut Object = msg_send![cls, alloc];
+            let new_item: *mut Object = msg_send![new_item, initWithIdentifier: ident.as_raw()];
+            let _:() = msg_send![new_item, setView: copy];
 
-    fn add_item_tap_gesture(&mut self, item_id: &ItemId, taps: u32,
-                            fingers: u32, cb: ButtonCb) {
-        unsafe {
-            let item = *item_id as *mut Object;
-            let view: *mut Object = msg_send![item, view];
-            if view == nil {
-                return;
+            let mut internal = InternalItem::new(internal_item._type, new_item, ident, Some(copy));
+            if internal_item._type == ItemType::Button {
+                let target = (&*self.objc.clone()) as *const ObjcAppDelegate as *mut Object;
+                let _:() = msg_send![copy, setTarget: target];
+                internal.button_cb = internal_item.button_cb.clone();
+                internal.disabled = internal_item.disabled;
+                internal.subtitle = internal_item.subtitle.clone();
+                if let Some((base, count)) = internal_item.badge {
+                    let _:() = msg_send![base, retain];
+                    internal.badge = Some((base, count));
+                }
+                if let Some((light, dark)) = internal_item.image_variants {
+                    let _:() = msg_send![light, retain];
+                    let _:() = msg_send![dark, retain];
+                    internal.image_variants = Some((light, dark));
+                }
             }
-            let _:() = msg_send![view, setAllowedTouchTypes: 1]; // NSTouchTypeMaskDirect
-            let cls = Class::get("NSClickGestureRecognizer").unwrap();
-            let gesture: *mut Object = msg_send![cls, alloc];
-            let gesture: *mut Object = msg_send![gesture,
-                                                 initWithTarget: self.objc.clone()
-                                                 action: sel!(tapGesture:)];
-            let _:() = msg_send![gesture, setAllowedTouchTypes: 1]; // NSTouchTypeMaskDirect
-            let _:() = msg_send![gesture, setNumberOfTouchesRequired: fingers];
-            let _:() = msg_send![gesture, setNumberOfClicksRequired: taps];
-            let _:() = msg_send![view, addGestureRecognizer: gesture];
-            let _:() = msg_send![gesture, release];
-            let mut internal_item = self.item_map.remove(item_id).unwrap();
-            internal_item.tap_cb = Some(cb);
-            self.item_map.insert(*item_id, internal_item);
+            internal.width = internal_item.width;
+            internal.focus_behavior = internal_item.focus_behavior;
+            self.insert_item(new_item, internal)
+        };
+        for (kind, config, cb) in gestures {
+            self.add_gesture_recognizer(&clone, kind, config, cb);
         }
+        clone
     }
-
-    fn create_spacer(&mut self, space: SpacerType) -> ItemId {
+    // Spacers are their own identifier, so this is read back from the string
+    fn spacer_type(item: &ItemId) -> SpacerType {
+        match util::nsstring_decode(item.as_raw() as *mut Object).as_str() {
+            "NSTouchBarItemIdentifierFixedSpaceSmall" => SpacerType::Small,
+            "NSTouchBarItemIdentifierFixedSpaceLarge" => SpacerType::Large,
+            _ => SpacerType::Flexible,
+        }
+    }
+    fn clone_slider(&mut self, item: &ItemId) -> ItemId {
+        let ident = self.generate_ident();
+        let internal_item = self.item_map.get(item).unwrap();
         unsafe {
-            let s = match space {
-                SpacerType::Small =>
-                    NSString::alloc(nil).init_str("NSTouchBarItemIdentifierFixedSpaceSmall"),
-                SpacerType::Large =>
-                    NSString::alloc(nil).init_str("NSTouchBarItemIdentifierFixedSpaceLarge"),
-                SpacerType::Flexible =>
-                    NSString::alloc(nil).init_str("NSTouchBarItemIdentifierFlexibleSpace"),
-            };
-            // This is really stupid, since it's just a string, but to fit into the
-            // rest of the system we go ahead and allocate a whole internal item for it.
+            let old_item = item.as_raw() as *mut Object;
+            let old_slider = internal_item.control.unwrap();
+            let cls = RRSliderTouchBarItem::class();
+            let new_item: *mut Object = msg_send![cls, alloc];
+            let new_item: *mut Object = msg_send![new_item, initWithIdentifier: ident.as_raw()];
+            let slider: *mut Object = msg_send![new_item, slider];
+            let label: *mut Object = msg_send![old_item, label];
+            let _:() = msg_send![new_item, setLabel: label];
+            let min: f64 = msg_send![old_slider, minValue];
+            let max: f64 = msg_send![old_slider, maxValue];
+            let continuous: bool = msg_send![old_slider, isContinuous];
+            let value: f64 = msg_send![old_slider, doubleValue];
+            let _:() = msg_send![slider, setMinValue: min];
+            let _:() = msg_send![slider, setMaxValue: max];
+            let _:() = msg_send![slider, setContinuous: continuous];
+            let _:() = msg_send![slider, setDoubleValue: value];
+            let _:() = msg_send![new_item, setTarget: self.objc.clone()];
+            let _:() = msg_send![new_item, setAction: sel!(slider:)];
 
-            // And since it doesn't have an ident, just use it as its own ident.  Both
-            // the view and ident will be sent a release at shutdown, so retain it an
-            // extra time here to keep the references balanced.
-            let _:() = msg_send![s, retain];
+            let mut internal = InternalItem::new(ItemType::Slider, new_item, ident, Some(slider));
+            internal.slider_cb = internal_item.slider_cb.clone();
+            internal.integer_only = internal_item.integer_only;
+            internal.last_value = internal_item.last_value;
+            internal.width = internal_item.width;
+            internal.focus_behavior = internal_item.focus_behavior;
+            #[cfg(feature = "system_controls")]
+            {
+                internal.system_control = internal_item.system_control;
+            }
+            self.insert_item(new_item, internal)
+        }
+    }
+    fn copy_scrubber_state(old: &ItemId, new: &ItemId) {
+        unsafe {
+            let old: *mut Object = msg_send![old.as_raw() as *mut Object, view];
+            let new: *mut Object = msg_send![new.as_raw() as *mut Object, view];
+            let mode: i64 = msg_send![old, mode];
+            let continuous: bool = msg_send![old, isContinuous];
+            let arrows: bool = msg_send![old, showsArrowButtons];
+            let overlay: *mut Object = msg_send![old, selectionOverlayStyle];
+            let background: *mut Object = msg_send![old, selectionBackgroundStyle];
+            let selected: i64 = msg_send![old, selectedIndex];
+            let _:() = msg_send![new, setMode: mode];
+            let _:() = msg_send![new, setContinuous: continuous];
+            let _:() = msg_send![new, setShowsArrowButtons: arrows];
+            let _:() = msg_send![new, setSelectionOverlayStyle: overlay];
+            let _:() = msg_send![new, setSelectionBackgroundStyle: background];
+            let _:() = msg_send![new, setSelectedIndex: selected];
+        }
+    }
+    fn clone_popover(&mut self, item: &ItemId, child_bar: BarId, hold_bar: Option<BarId>) -> ItemId {
+        let ident = self.generate_ident();
+        let internal_item = self.item_map.get(item).unwrap();
+        unsafe {
+            let cls = RRPopoverTouchBarItem::class();
+            let new_item: *mut Object = msg_send![cls, alloc];
+            let new_item: *mut Object = msg_send![new_item, initWithIdentifier: ident.as_raw()];
 
-            let internal = InternalItem {
-                _type: ItemType::Spacer,
-                view: s,
-                ident: s as u64,
-                control: None,
-                scrubber: None,
-                button_cb: None,
-                slider_cb: None,
-                swipe_cb: None,
-                tap_cb: None,
-                child_bar: None,
+            let btn = RustTouchbarDelegateWrapper::copy_view(internal_item.control.unwrap());
+            let target = (&*self.objc.clone()) as *const ObjcAppDelegate as *mut Object;
+            let _:() = msg_send![btn, setTarget: target];
+            let _:() = msg_send![new_item, setShowsCloseButton: YES];
+            let gesture: *mut Object = msg_send![new_item, makeStandardActivatePopoverGestureRecognizer];
+            let _:() = msg_send![btn, addGestureRecognizer: gesture];
+            let _:() = msg_send![new_item, setCollapsedRepresentation: btn];
+            let _:() = msg_send![new_item, setPopoverTouchBar: child_bar.as_raw() as *mut Object];
+            // Popovers open their own bar on press-and-hold unless given
+            // another one, or none for lazy popovers
+            let old_hold: *mut Object = msg_send![item.as_raw() as *mut Object, pressAndHoldTouchBar];
+            let hold = match hold_bar {
+                Some(hold_bar) => hold_bar.as_raw() as *mut Object,
+                None if old_hold != nil => child_bar.as_raw() as *mut Object,
+                None => nil,
             };
-            self.item_map.insert(s as u64, internal);
-            s as ItemId
+            let _:() = msg_send![new_item, setPressAndHoldTouchBar: hold];
+
+            let mut internal = InternalItem::new(ItemType::Popover, new_item, ident, Some(btn));
+            internal.child_bar = Some(child_bar);
+            internal.hold_bar = hold_bar;
+            internal.lazy_bar = internal_item.lazy_bar.clone();
+            internal.represent_after = internal_item.represent_after;
+            internal.width = internal_item.width;
+            internal.focus_behavior = internal_item.focus_behavior;
+            self.insert_item(new_item, internal)
         }
     }
-
-    fn create_image_from_path(&mut self, path: &str) -> TouchbarImage {
+    fn open_popover(&mut self, item: ItemId) {
+        if !self.item_map.get(&item).map_or(false, |x| x._type == ItemType::Popover) {
+            return;
+        }
+        self.build_lazy_popover(item);
         unsafe {
-            let filename = NSString::alloc(nil).init_str(path);
-            let objc_image = NSImage::alloc(nil).initWithContentsOfFile_(filename);
-            let _:() = msg_send![filename, release];
-            objc_image as TouchbarImage
+            // Present the request popover.  This must be done instead of
+            // using the popover's built-in showPopover because that pops
+            // _under_ a system function bar.
+            #[cfg(feature = "private_api")]
+            {
+                let ident = self.find_ident(&item).unwrap().as_raw() as *mut Object;
+                let bar: *mut Object = msg_send![item.as_raw() as *mut Object, popoverTouchBar];
+                RustTouchbarDelegateWrapper::present_bar(bar, ident);
+                let app = NSApp();
+                let _:() = msg_send![app, setTouchBar: nil];
+            }
+            // Without a system function bar, the built-in one works.
+            #[cfg(not(feature = "private_api"))]
+            {
+                let item = item.as_raw() as *mut Object;
+                let _:() = msg_send![item, showPopover: item];
+            }
+        }
+        self.resume_brightness_polls();
+    }
+    fn close_popover(&self, item: ItemId) {
+        let internal_item = match self.item_map.get(&item) {
+            Some(x) if x._type == ItemType::Popover => x,
+            _ => return,
+        };
+        let bars: Vec<BarId> = internal_item.child_bar.iter()
+            .chain(internal_item.hold_bar.iter()).cloned().collect();
+        unsafe {
+            RustTouchbarDelegateWrapper::dismiss_popover_bars(internal_item.view, &bars);
+        }
+    }
+    // Close a popover showing one of its bars.  Doesn't need the wrapper, so
+    // it can also be called from item callbacks.
+    unsafe fn dismiss_popover_bars(popover: *mut Object, bars: &[BarId]) {
+        #[cfg(feature = "private_api")]
+        {
+            let _ = popover;
+            for bar in bars {
+                RustTouchbarDelegateWrapper::dismiss_bar(bar.as_raw() as *mut Object);
+            }
+        }
+        #[cfg(not(feature = "private_api"))]
+        {
+            let _ = bars;
+            let _:() = msg_send![popover, dismissPopover: nil];
+        }
+    }
+    // Move the visibility observer from the old root bar to the new one
+    unsafe fn observe_root_bar(&self, old_bar: *mut Object, new_bar: *mut Object) {
+        let key = NSString::alloc(nil).init_str("visible");
+        if old_bar != nil {
+            let _:() = msg_send![old_bar, removeObserver: self.objc.clone() forKeyPath: key];
+        }
+        if new_bar != nil {
+            let _:() = msg_send![new_bar,
+                                 addObserver: self.objc.clone()
+                                 forKeyPath: key
+                                 options: 1u64 // NSKeyValueObservingOptionNew
+                                 context: nil];
+        }
+        let _:() = msg_send![key, release];
+    }
+    fn root_bar_visible(&self) -> bool {
+        unsafe {
+            let bar: *mut Object = msg_send![self.objc, groupTouchBar];
+            if bar == nil {
+                return false;
+            }
+            msg_send![bar, isVisible]
+        }
+    }
+    fn root_visibility_changed(&mut self) {
+        self.resume_brightness_polls();
+        let visible = self.root_bar_visible();
+        if visible == self.root_visible {
+            return;
+        }
+        self.root_visible = visible;
+        if let Some(ref cb) = self.visibility_cb {
+            cb(visible);
         }
     }
+    fn apply_focus_behavior(&self, item: &InternalItem) {
+        unsafe {
+            let view = match item.control {
+                Some(view) => view,
+                None => return,
+            };
+            let (alpha, hidden) = match (item.focus_behavior, self.focus_active) {
+                (FocusBehavior::Dim, true) => (FOCUS_DIM_ALPHA, NO),
+                (FocusBehavior::Hide, true) => (1.0, YES),
+                _ => (1.0, NO),
+            };
+            let _:() = msg_send![view, setAlphaValue: alpha];
+            let _:() = msg_send![view, setHidden: hidden];
+        }
+    }
+    fn resolve_icon(image: &str) -> Option<(PathBuf, Option<PathBuf>)> {
+        let given = Path::new(image);
+        let stem = given.file_stem()?.to_string_lossy().into_owned();
+        let ext = given.extension().map_or("png".to_string(), |e| e.to_string_lossy().into_owned());
+        let path = match given.is_absolute() || given.exists() {
+            true => given.to_path_buf(),
+            false => PathBuf::from(util::bundled_resource_path(&stem, &ext)?),
+        };
+        if !path.exists() {
+            return None;
+        }
+        let retina = path.with_file_name(format!("{}@2x.{}", stem, ext));
+        match retina.exists() {
+            true => Some((path, Some(retina))),
+            false => Some((path, None)),
+        }
+    }
+    fn load_icon(&self, delegate: *mut Object, image: &str) -> Result<(), TouchbarError> {
+        let unavailable = || TouchbarError::ImageUnavailable(image.to_string());
+        let (path, retina) = RustTouchbarDelegateWrapper::resolve_icon(image).ok_or_else(unavailable)?;
+        unsafe {
+            let filename = NSString::alloc(nil).init_str(&path.to_string_lossy());
+            let objc_image = NSImage::alloc(nil).initWithContentsOfFile_(filename);
+            let _:() = msg_send![filename, release];
+            if objc_image == nil {
+                return Err(TouchbarError::ImageUnavailable(path.to_string_lossy().into_owned()));
+            }
+            if let Some(retina) = retina {
+                let filename = NSString::alloc(nil).init_str(&retina.to_string_lossy());
+                let cls = util::objc_class("NSImageRep").unwrap();
+                let rep: *mut Object = msg_send![cls, imageRepWithContentsOfFile: filename];
+                let _:() = msg_send![filename, release];
+                if rep != nil {
+                    let size: NSSize = msg_send![objc_image, size];
+                    let _:() = msg_send![rep, setSize: size];
+                    let _:() = msg_send![objc_image, addRepresentation: rep];
+                }
+            }
+            let _:() = msg_send![delegate, setIcon: objc_image];
+        }
+        Ok(())
+    }
+    fn apply_appearance(&mut self) {
+        unsafe {
+            let dark = util::dark_mode_active();
+            for item in self.item_map.values() {
+                if let (Some((light_img, dark_img)), Some(btn)) = (item.image_variants, item.control) {
+                    let image = match dark {
+                        true => dark_img,
+                        false => light_img,
+                    };
+                    let _:() = msg_send![btn, setImage: image];
+                }
+            }
+            if let Some((light_icon, dark_icon)) = self.icon_variants.clone() {
+                let result = match dark {
+                    true => self.load_icon(self.main_delegate(), &dark_icon),
+                    false => self.load_icon(self.main_delegate(), &light_icon),
+                };
+                if let Err(err) = result {
+                    self.report_error(err);
+                }
+                // Rebuild the Control Strip button if it is already registered,
+                // unless set_enabled() has hidden it
+                let bar: *mut Object = msg_send![self.objc, groupTouchBar];
+                if bar != nil && self.enabled {
+                    let _ : () = msg_send![self.objc, applicationDidFinishLaunching: 0];
+                }
+            }
+        }
+    }
+    fn schedule_timer(&self, interval: Duration) -> *mut Object {
+        unsafe {
+            let secs = interval.as_secs() as f64 + interval.subsec_nanos() as f64 * 1e-9;
+            let cls = util::objc_class("NSTimer").unwrap();
+            let timer: *mut Object = msg_send![cls,
+                                               scheduledTimerWithTimeInterval: secs
+                                               target: self.objc.clone()
+                                               selector: sel!(timer:)
+                                               userInfo: nil
+                                               repeats: YES];
+            // Scheduled timers are owned by the run loop.  Keep a reference
+            // so the pointer stays valid until the timer is cancelled.
+            let _:() = msg_send![timer, retain];
+            timer
+        }
+    }
+    unsafe fn cancel_timer(timer: *mut Object) {
+        let _:() = msg_send![timer, invalidate];
+        let _:() = msg_send![timer, release];
+    }
+    fn timer_fired(&mut self, item: ItemId) {
+        let _type = match self.item_map.get(&item) {
+            Some(x) => &x._type,
+            None => return,
+        };
+        let ticker = self.item_map[&item].ticker.is_some();
+        let confirm = self.item_map[&item].hold_confirm.is_some();
+        match *_type {
+            ItemType::Countdown => self.countdown_tick(item),
+            ItemType::Button if confirm => self.hold_confirm_tick(item),
+            ItemType::Button => self.repeat_button(item),
+            ItemType::Label if ticker => self.ticker_tick(item),
+            ItemType::Label => self.marquee_tick(item),
+            ItemType::Passthrough => self.represent(item),
+            #[cfg(all(feature = "system_controls", feature = "private_api"))]
+            ItemType::Slider => self.brightness_tick(item),
+            _ => {},
+        }
+    }
+    #[cfg(feature = "private_api")]
+    unsafe fn present_bar(bar: *mut Object, ident: *mut Object) {
+        let cls = util::objc_class("NSTouchBar").unwrap();
+        match util::get_appkit_version() >= AppKitVersion::from_tuple(10, 14) {
+            true => {
+                let _:() = msg_send![cls,
+                                     presentSystemModalTouchBar: bar
+                                     systemTrayItemIdentifier: ident];
+            },
+            false => {
+                let _:() = msg_send![cls,
+                                     presentSystemModalFunctionBar: bar
+                                     systemTrayItemIdentifier: ident];
+            },
+        }
+    }
+    // Without the Control Strip, bars are shown as the app's own bar.
+    #[cfg(not(feature = "private_api"))]
+    unsafe fn present_bar(bar: *mut Object, _ident: *mut Object) {
+        let app = NSApp();
+        let _:() = msg_send![app, setTouchBar: bar];
+    }
+    // Show a new root bar in place of the old one, under the same Control
+    // Strip item, without removing the item.
+    #[cfg(feature = "private_api")]
+    unsafe fn swap_root_bar(delegate: *mut Object, old_bar: *mut Object, new_bar: *mut Object,
+                            ident: *mut Object) {
+        let visible: bool = msg_send![old_bar, isVisible];
+        if visible {
+            RustTouchbarDelegateWrapper::present_bar(new_bar, ident);
+        }
+        let cls = util::objc_class("NSTouchBar").unwrap();
+        match util::get_appkit_version() >= AppKitVersion::from_tuple(10, 14) {
+            true => { let _: () = msg_send![cls, dismissSystemModalTouchBar: old_bar]; },
+            false => { let _: () = msg_send![cls, dismissSystemModalFunctionBar: old_bar]; },
+        }
+        update_exit_cleanup_bar(delegate as u64, new_bar as u64);
+    }
+    #[cfg(not(feature = "private_api"))]
+    unsafe fn swap_root_bar(_delegate: *mut Object, old_bar: *mut Object, new_bar: *mut Object,
+                            ident: *mut Object) {
+        let app = NSApp();
+        let shown: *mut Object = msg_send![app, touchBar];
+        if shown == old_bar {
+            RustTouchbarDelegateWrapper::present_bar(new_bar, ident);
+        }
+    }
+    #[cfg(feature = "private_api")]
+    unsafe fn minimize_bar(bar: *mut Object) {
+        let cls = util::objc_class("NSTouchBar").unwrap();
+        match util::get_appkit_version() >= AppKitVersion::from_tuple(10, 14) {
+            true => { let _: () = msg_send![cls, minimizeSystemModalTouchBar: bar]; },
+            false => { let _: () = msg_send![cls, minimizeSystemModalFunctionBar: bar]; },
+        }
+    }
+    #[cfg(not(feature = "private_api"))]
+    unsafe fn minimize_bar(bar: *mut Object) {
+        let app = NSApp();
+        let shown: *mut Object = msg_send![app, touchBar];
+        if shown == bar {
+            let _:() = msg_send![app, setTouchBar: nil];
+        }
+    }
+    #[cfg(feature = "private_api")]
+    unsafe fn dismiss_bar(bar: *mut Object) {
+        let cls = util::objc_class("NSTouchBar").unwrap();
+        match util::get_appkit_version() >= AppKitVersion::from_tuple(10, 14) {
+            true => {
+                let _: () = msg_send![cls, minimizeSystemModalTouchBar: bar];
+                let _: () = msg_send![cls, dismissSystemModalTouchBar: bar];
+            },
+            false => {
+                let _: () = msg_send![cls, minimizeSystemModalFunctionBar: bar];
+                let _: () = msg_send![cls, dismissSystemModalFunctionBar: bar];
+            },
+        }
+    }
+    #[cfg(not(feature = "private_api"))]
+    unsafe fn dismiss_bar(bar: *mut Object) {
+        RustTouchbarDelegateWrapper::minimize_bar(bar);
+    }
+    // Remove a delegate's icon from the Control Strip
+    #[cfg(feature = "private_api")]
+    unsafe fn hide_root_bar(delegate: *mut Object) {
+        let delegate = delegate as u64;
+        let item = {
+            let mut items = LIVE_TRAY_ITEMS.lock().unwrap();
+            let idx = items.iter().position(|&(owner, _, _, _)| owner == delegate);
+            idx.map(|idx| items.remove(idx))
+        };
+        if let Some((_, tray_item, ident, bar)) = item {
+            remove_tray_item(tray_item, ident, bar);
+        }
+    }
+    #[cfg(not(feature = "private_api"))]
+    unsafe fn hide_root_bar(delegate: *mut Object) {
+        let bar: *mut Object = msg_send![delegate, groupTouchBar];
+        RustTouchbarDelegateWrapper::minimize_bar(bar);
+    }
+    fn main_delegate(&self) -> *mut Object {
+        &*self.objc as *const ObjcAppDelegate as *mut Object
+    }
+    // Delegates of every Control Strip item, starting with the main one
+    fn delegates(&self) -> Vec<*mut Object> {
+        let trays = self.trays.iter().map(|t| &**t as *const ObjcAppDelegate as *mut Object);
+        Some(self.main_delegate()).into_iter().chain(trays).collect()
+    }
+    #[cfg(feature = "private_api")]
+    fn find_tray(&self, tray: &TrayId) -> Option<*mut Object> {
+        self.delegates().into_iter().skip(1).find(|d| TrayId::from_raw(*d as u64) == *tray)
+    }
+    fn is_root_bar(&self, bar_id: &BarId) -> bool {
+        self.delegates().into_iter().any(|delegate| unsafe {
+            let root: *mut Object = msg_send![delegate, groupTouchBar];
+            BarId::from_raw(root as u64) == *bar_id
+        })
+    }
+    // Remove a delegate's Control Strip item and free its root bar, leaving
+    // the delegate ready for a new root bar
+    unsafe fn unregister_tray(&mut self, delegate: *mut Object) {
+        RustTouchbarDelegateWrapper::hide_root_bar(delegate);
+        let tray_item: u64 = *(*delegate).get_ivar("_tray_item");
+        if tray_item != 0 {
+            let _:() = msg_send![tray_item as *mut Object, release];
+            (*delegate).set_ivar("_tray_item", 0u64);
+        }
+        let bar: *mut Object = msg_send![delegate, groupTouchBar];
+        if bar != nil {
+            if delegate == self.main_delegate() {
+                self.observe_root_bar(bar, nil);
+            }
+            let _:() = msg_send![delegate, setGroupTouchBar: nil];
+            let _:() = msg_send![delegate, setGroupIdent: nil];
+            self.free_bar(BarId::from_raw(bar as u64));
+        }
+        TRAY_IDENTS.lock().unwrap().retain(|&(_, owner)| owner != delegate as u64);
+    }
+    // Set the bar shown by a delegate's Control Strip item
+    fn set_root_bar(&mut self, delegate: *mut Object, bar_id: BarId) {
+        // Only the main root bar's visibility is reported
+        let main = delegate == self.main_delegate();
+        unsafe {
+            let old_bar: *mut Object = msg_send![delegate, groupTouchBar];
+            if BarId::from_raw(old_bar as u64) == bar_id {
+                self.sync_bar_identifiers(&bar_id);
+                return;
+            }
+            // Replace a registered root bar under its existing Control Strip
+            // item, rather than tearing the item down and flickering.  Items
+            // in both bars are kept.
+            let allowed = match self.bar_map.contains_key(&bar_id) {
+                true => self.forget_customization(old_bar),
+                false => Vec::new(),
+            };
+            if let Some(old_internal) = self.bar_map.remove(&BarId::from_raw(old_bar as u64)) {
+                let new_ident = match self.bar_map.get_mut(&bar_id) {
+                    Some(internal_bar) => mem::replace(&mut internal_bar.ident, old_internal.ident),
+                    None => {
+                        self.bar_map.insert(BarId::from_raw(old_bar as u64), old_internal);
+                        return;
+                    },
+                };
+                let _:() = msg_send![new_ident.as_raw() as *mut Object, release];
+                let ident = old_internal.ident.as_raw() as *mut Object;
+                let _ : () = msg_send![delegate, setGroupTouchBar: bar_id.as_raw()];
+                RustTouchbarDelegateWrapper::swap_root_bar(delegate, old_bar, bar_id.as_raw() as *mut Object, ident);
+                if main {
+                    self.observe_root_bar(old_bar, bar_id.as_raw() as *mut Object);
+                }
+                for item in old_internal.items.iter().chain(allowed.iter()) {
+                    self.free_removed_item(item);
+                }
+                self.owned.remove(&(BarId::from_raw(old_bar as u64)).as_raw());
+                let _: () = msg_send![old_bar, release];
+                if main {
+                    self.root_visibility_changed();
+                }
+                return;
+            }
+            if main {
+                self.observe_root_bar(old_bar, bar_id.as_raw() as *mut Object);
+            }
+            if old_bar != nil {
+                RustTouchbarDelegateWrapper::dismiss_bar(old_bar);
+                self.free_bar_allocations(old_bar);
+                let _: () = msg_send![old_bar, release];
+            }
+            let _ : () = msg_send![delegate, setGroupTouchBar: bar_id.as_raw()];
+            let ident = self.find_bar_ident(&bar_id).unwrap();
+            let _ : () = msg_send![delegate, setGroupIdent: ident.as_raw()];
+            self.claim_tray_ident(delegate, ident.as_raw() as *mut Object);
+            if self.enabled {
+                let _ : () = msg_send![delegate, applicationDidFinishLaunching: 0];
+            }
+        }
+    }
+    fn passthrough(&mut self, btn: u64) {
+        let item = match self.find_view_from_control(&ItemId::from_raw(btn)) {
+            Some(item) => self.live_item(item as u64),
+            None => return,
+        };
+        unsafe {
+            // Minimize the bar holding the button, which may be a popover,
+            // as well as the root bar.
+            let root: *mut Object = msg_send![self.objc, groupTouchBar];
+            for bar in self.bar_map.values().filter(|b| b.items.contains(&item)) {
+                if bar.view != root {
+                    RustTouchbarDelegateWrapper::minimize_bar(bar.view);
+                }
+            }
+            if root != nil {
+                RustTouchbarDelegateWrapper::minimize_bar(root);
+            }
+        }
+        let delay = match self.item_map.get(&item).and_then(|x| x.represent_after) {
+            Some(delay) => delay,
+            None => return,
+        };
+        let timer = self.schedule_timer(delay);
+        let internal_item = self.item_map.get_mut(&item).unwrap();
+        if let Some(old_timer) = internal_item.timer.replace(timer) {
+            unsafe { RustTouchbarDelegateWrapper::cancel_timer(old_timer); }
+        }
+    }
+    fn represent(&mut self, item: ItemId) {
+        if let Some(timer) = self.item_map.get_mut(&item).and_then(|x| x.timer.take()) {
+            unsafe { RustTouchbarDelegateWrapper::cancel_timer(timer); }
+        }
+        if !self.enabled {
+            return;
+        }
+        unsafe {
+            let _:() = msg_send![self.objc, present: nil];
+        }
+    }
+    fn image_cache_path(url: &str) -> PathBuf {
+        let dir = match env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join("Library/Caches"),
+            None => env::temp_dir(),
+        };
+        // FNV-1a, which unlike DefaultHasher is the same in every Rust release,
+        // so the cache survives rebuilds
+        let hash = url.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+        dir.join("rubrail-images").join(format!("{:016x}", hash))
+    }
+    // Download to a temporary file first, so other downloads and launches
+    // never see a partial image at the cached path
+    fn download_image(url: &str, tmp: PathBuf, path: PathBuf) -> Option<PathBuf> {
+        unsafe {
+            // Runs on a background thread, which has no autorelease pool
+            let cls = util::objc_class("NSAutoreleasePool").unwrap();
+            let pool: *mut Object = msg_send![cls, alloc];
+            let pool: *mut Object = msg_send![pool, init];
+            let objc_url = NSString::alloc(nil).init_str(url);
+            let cls = util::objc_class("NSURL").unwrap();
+            let nsurl: *mut Object = msg_send![cls, URLWithString: objc_url];
+            let _:() = msg_send![objc_url, release];
+            let data: *mut Object = match nsurl != nil {
+                true => {
+                    let cls = util::objc_class("NSData").unwrap();
+                    msg_send![cls, dataWithContentsOfURL: nsurl]
+                },
+                false => nil,
+            };
+            let result = match data != nil {
+                true => {
+                    let len: usize = msg_send![data, length];
+                    let bytes: *const u8 = msg_send![data, bytes];
+                    let bytes = slice::from_raw_parts(bytes, len);
+                    let written = match path.parent() {
+                        Some(dir) => fs::create_dir_all(dir).and_then(|_| fs::write(&tmp, bytes)),
+                        None => fs::write(&tmp, bytes),
+                    };
+                    match written.and_then(|_| fs::rename(&tmp, &path)) {
+                        Ok(_) => Some(path),
+                        Err(_) => {
+                            let _ = fs::remove_file(&tmp);
+                            None
+                        },
+                    }
+                },
+                false => None,
+            };
+            let _:() = msg_send![pool, drain];
+            result
+        }
+    }
+    #[cfg(feature = "system_controls")]
+    fn volume_changed(&mut self) {
+        if let Some(old_device) = self.volume_device {
+            let device = system::default_output_device();
+            if device != old_device {
+                let delegate = &*self.objc as *const ObjcAppDelegate as *mut Object;
+                unsafe { system::move_volume_listener(delegate, old_device, device); }
+                self.volume_device = Some(device);
+            }
+        }
+        let volume = match system::output_volume() {
+            Some(volume) => volume,
+            None => return,
+        };
+        let sliders: Vec<ItemId> = self.item_map.iter().filter(|&(_, x)| {
+            x.system_control == Some(SystemControl::Volume)
+        }).map(|(id, _)| *id).collect();
+        for slider in sliders {
+            RustTouchbarDelegateWrapper::set_slider_value(&slider, volume);
+        }
+    }
+    #[cfg(all(feature = "system_controls", feature = "private_api"))]
+    fn brightness_tick(&mut self, item: ItemId) {
+        let internal_item = match self.item_map.get_mut(&item) {
+            Some(x) => x,
+            None => return,
+        };
+        // Leave the slider alone while the user drags it, and until the
+        // display has caught up
+        let interval = Duration::from_millis(BRIGHTNESS_POLL_INTERVAL_MS);
+        if internal_item.touched.map_or(false, |t| t.elapsed() < interval) {
+            return;
+        }
+        // Stop polling while the slider isn't displayed, until
+        // resume_brightness_polls()
+        let window: *mut Object = unsafe {
+            let slider: *mut Object = msg_send![internal_item.control.unwrap(), slider];
+            msg_send![slider, window]
+        };
+        if window == nil {
+            if let Some(timer) = internal_item.timer.take() {
+                unsafe { RustTouchbarDelegateWrapper::cancel_timer(timer); }
+            }
+            return;
+        }
+        RustTouchbarDelegateWrapper::show_brightness(&item);
+    }
+    #[cfg(all(feature = "system_controls", feature = "private_api"))]
+    fn show_brightness(item: &ItemId) {
+        if let Some(brightness) = system::display_brightness() {
+            RustTouchbarDelegateWrapper::set_slider_value(item, brightness);
+        }
+    }
+    // Restart polling for brightness sliders that stopped while hidden, when
+    // a bar may have been shown.  Sliders that are still hidden stop again on
+    // their next tick.
+    fn resume_brightness_polls(&mut self) {
+        #[cfg(all(feature = "system_controls", feature = "private_api"))]
+        {
+            let sliders: Vec<ItemId> = self.item_map.iter().filter(|&(_, x)| {
+                x.system_control == Some(SystemControl::Brightness) && x.timer.is_none()
+            }).map(|(id, _)| *id).collect();
+            for slider in sliders {
+                RustTouchbarDelegateWrapper::show_brightness(&slider);
+                let timer = self.schedule_timer(Duration::from_millis(BRIGHTNESS_POLL_INTERVAL_MS));
+                self.item_map.get_mut(&slider).unwrap().timer = Some(timer);
+            }
+        }
+    }
+    // Ask for the current Now Playing information, which is delivered to
+    // now_playing_info() asynchronously on the main thread.
+    #[cfg(feature = "private_api")]
+    fn request_now_playing(&self) {
+        let delegate = &*self.objc as *const ObjcAppDelegate as u64;
+        let handler = block::ConcreteBlock::new(move |info: *mut Object| {
+            unsafe {
+                let ptr: u64 = *(*(delegate as *mut Object)).get_ivar("_rust_wrapper");
+                let wrapper = &mut *(ptr as *mut RustTouchbarDelegateWrapper);
+                wrapper.now_playing_info(info);
+            }
+        }).copy();
+        unsafe {
+            let queue = &_dispatch_main_q as *const Object as *mut Object;
+            MRMediaRemoteGetNowPlayingInfo(queue, &*handler as *const block::Block<(*mut Object,), ()>
+                                           as *mut Object);
+        }
+    }
+    #[cfg(feature = "private_api")]
+    fn now_playing_info(&mut self, info: *mut Object) {
+        unsafe {
+            let value_for_key = |key: &str| -> *mut Object {
+                if info == nil {
+                    return nil;
+                }
+                let key = NSString::alloc(nil).init_str(key);
+                let value: *mut Object = msg_send![info, objectForKey: key];
+                let _:() = msg_send![key, release];
+                value
+            };
+            let title = value_for_key("kMRMediaRemoteNowPlayingInfoTitle");
+            let artist = value_for_key("kMRMediaRemoteNowPlayingInfoArtist");
+            let artwork = value_for_key("kMRMediaRemoteNowPlayingInfoArtworkData");
+            let text = match (title != nil, artist != nil) {
+                (true, true) => format!("{} - {}", util::nsstring_decode(title),
+                                        util::nsstring_decode(artist)),
+                (true, false) => util::nsstring_decode(title),
+                (false, true) => util::nsstring_decode(artist),
+                (false, false) => String::new(),
+            };
+            let text = NSString::alloc(nil).init_str(&text);
+            let image: *mut Object = match artwork {
+                x if x == nil => nil,
+                data => {
+                    let cls = util::objc_class("NSImage").unwrap();
+                    let image: *mut Object = msg_send![cls, alloc];
+                    msg_send![image, initWithData: data]
+                },
+            };
+            for item in self.item_map.values().filter(|x| x._type == ItemType::NowPlaying) {
+                let stack = item.control.unwrap();
+                let views: *mut Object = msg_send![stack, arrangedSubviews];
+                let count: u64 = msg_send![views, count];
+                let label: *mut Object = msg_send![views, lastObject];
+                let _:() = msg_send![label, setStringValue: text];
+                if count > 1 {
+                    let image_view: *mut Object = msg_send![views, firstObject];
+                    let _:() = msg_send![image_view, setImage: image];
+                }
+            }
+            let _:() = msg_send![text, release];
+            if image != nil {
+                let _:() = msg_send![image, release];
+            }
+        }
+    }
+    fn draw_canvas(&self, canvas_id: &ItemId) {
+        unsafe {
+            let internal_item = match self.item_map.get(canvas_id) {
+                Some(x) if x._type == ItemType::Canvas => x,
+                _ => return,
+            };
+            // Full-width canvases have no width until they are laid out
+            let width = match internal_item.width {
+                Some(width) if width > 0 => width * BAR_SCALE,
+                _ => return,
+            };
+            let height = BAR_HEIGHT * BAR_SCALE;
+            let pixels = (internal_item.canvas_cb.as_ref().unwrap())(canvas_id, width, height);
+            let image = RustTouchbarDelegateWrapper::image_from_rgba(&pixels, width, height);
+            if image == nil {
+                return;
+            }
+            let view = internal_item.control.unwrap();
+            let _:() = msg_send![view, setImage: image];
+            let _:() = msg_send![image, release];
+        }
+    }
+    fn canvas_resized(&mut self, view: u64, width: f64) {
+        let item = match self.item_map.iter_mut().filter(|&(_, ref x)| {
+            x._type == ItemType::Canvas && x.control.map_or(false, |c| c as u64 == view)
+        }).next() {
+            Some((id, internal_item)) => {
+                let width = width.round() as u32;
+                if internal_item.width == Some(width) {
+                    return;
+                }
+                internal_item.width = Some(width);
+                *id
+            },
+            None => return,
+        };
+        let width = self.item_map[&item].width.unwrap();
+        if let Some(ref cb) = self.item_map[&item].resize_cb {
+            cb(&item, width);
+        }
+        self.draw_canvas(&item);
+    }
+    fn images_loaded(&mut self) {
+        let loaded: Vec<(u64, String, Option<PathBuf>)> =
+            self.loaded_images.lock().unwrap().drain(..).collect();
+        for (placeholder, url, path) in loaded {
+            let placeholder = placeholder as *mut Object;
+            unsafe {
+                let image = match path {
+                    Some(path) => {
+                        let filename = NSString::alloc(nil).init_str(&path.to_string_lossy());
+                        let image = NSImage::alloc(nil).initWithContentsOfFile_(filename);
+                        let _:() = msg_send![filename, release];
+                        image
+                    },
+                    None => nil,
+                };
+                if image == nil {
+                    self.report_error(TouchbarError::ImageUnavailable(url));
+                    let _:() = msg_send![placeholder, release];
+                    continue;
+                }
+                // Fill in the placeholder, since items already hold it
+                let reps: *mut Object = msg_send![image, representations];
+                let _:() = msg_send![placeholder, addRepresentations: reps];
+                let size: NSSize = msg_send![image, size];
+                let _:() = msg_send![placeholder, setSize: size];
+                let _:() = msg_send![image, release];
+                for item in self.item_map.values() {
+                    let control = match item.control {
+                        Some(control) => control,
+                        None => continue,
+                    };
+                    let responds: bool = msg_send![control, respondsToSelector: sel!(image)];
+                    if responds {
+                        let current: *mut Object = msg_send![control, image];
+                        if current == placeholder {
+                            let _:() = msg_send![control, setImage: nil];
+                            let _:() = msg_send![control, setImage: placeholder];
+                        }
+                    }
+                }
+                // Done with the download's reference
+                let _:() = msg_send![placeholder, release];
+            }
+        }
+        let scrubbers: Vec<ItemId> = self.item_map.iter()
+            .filter(|&(_, x)| x.scrubber.is_some())
+            .map(|(id, _)| *id).collect();
+        for scrubber in scrubbers {
+            self.reload_scrubber(&scrubber);
+        }
+    }
+    unsafe fn set_two_line_title(btn: *mut Object, title: &str, subtitle: &str) {
+        let cls = util::objc_class("NSMutableParagraphStyle").unwrap();
+        let style: *mut Object = msg_send![cls, alloc];
+        let style: *mut Object = msg_send![style, init];
+        let _:() = msg_send![style, setAlignment: 2u64]; // NSTextAlignmentCenter
+        let cls = util::objc_class("NSMutableAttributedString").unwrap();
+        let string: *mut Object = msg_send![cls, alloc];
+        let string: *mut Object = msg_send![string, init];
+        let lines = [(format!("{}\n", title), BUTTON_TITLE_FONT_SIZE, false),
+                     (subtitle.to_string(), BUTTON_SUBTITLE_FONT_SIZE, true)];
+        for &(ref text, size, secondary) in lines.iter() {
+            let cls = util::objc_class("NSMutableDictionary").unwrap();
+            let attrs: *mut Object = msg_send![cls, dictionary];
+            let cls = util::objc_class("NSFont").unwrap();
+            let font: *mut Object = msg_send![cls, systemFontOfSize: size];
+            let _:() = msg_send![attrs, setObject: font forKey: NSFontAttributeName];
+            let _:() = msg_send![attrs, setObject: style forKey: NSParagraphStyleAttributeName];
+            if secondary {
+                let cls = util::objc_class("NSColor").unwrap();
+                let color: *mut Object = msg_send![cls, secondaryLabelColor];
+                let _:() = msg_send![attrs, setObject: color forKey: NSForegroundColorAttributeName];
+            }
+            let text = NSString::alloc(nil).init_str(text);
+            let cls = util::objc_class("NSAttributedString").unwrap();
+            let part: *mut Object = msg_send![cls, alloc];
+            let part: *mut Object = msg_send![part, initWithString: text attributes: attrs];
+            let _:() = msg_send![string, appendAttributedString: part];
+            let _:() = msg_send![part, release];
+            let _:() = msg_send![text, release];
+        }
+        let _:() = msg_send![btn, setAttributedTitle: string];
+        let _:() = msg_send![string, release];
+        let _:() = msg_send![style, release];
+    }
+    unsafe fn decorated_text(template: ImageTemplate, text: &str) -> *mut Object {
+        // The glyph is a text attachment, so it lives in the standard text
+        // item view and follows the text's color.
+        let name = ImageTemplate::objc(template);
+        let cls = util::objc_class("NSImage").unwrap();
+        let image: *mut Object = msg_send![cls, imageNamed: name];
+        let _:() = msg_send![name, release];
+        let cls = util::objc_class("NSTextAttachment").unwrap();
+        let attachment: *mut Object = msg_send![cls, alloc];
+        let attachment: *mut Object = msg_send![attachment, init];
+        let _:() = msg_send![attachment, setImage: image];
+        let cls = util::objc_class("NSAttributedString").unwrap();
+        let glyph: *mut Object = msg_send![cls, attributedStringWithAttachment: attachment];
+        let _:() = msg_send![attachment, release];
+
+        let cls = util::objc_class("NSMutableAttributedString").unwrap();
+        let string: *mut Object = msg_send![cls, alloc];
+        let string: *mut Object = msg_send![string, initWithAttributedString: glyph];
+        let objc_text = NSString::alloc(nil).init_str(&format!(" {}", text));
+        let cls = util::objc_class("NSAttributedString").unwrap();
+        let part: *mut Object = msg_send![cls, alloc];
+        let part: *mut Object = msg_send![part, initWithString: objc_text];
+        let _:() = msg_send![string, appendAttributedString: part];
+        let _:() = msg_send![part, release];
+        let _:() = msg_send![objc_text, release];
+        string
+    }
+    unsafe fn fill_icon_text_view(view: *mut Object, icon: TouchbarImage, text: &str) {
+        let mut stack = *(*view).get_ivar::<u64>("_stack") as *mut Object;
+        if stack == nil {
+            let cls = util::objc_class("NSStackView").unwrap();
+            stack = msg_send![cls, alloc];
+            stack = msg_send![stack, init];
+            let _:() = msg_send![stack, setOrientation: 0]; // NSUserInterfaceLayoutOrientationHorizontal
+            let _:() = msg_send![stack, setAlignment: 10]; // NSLayoutAttributeCenterY
+            let _:() = msg_send![stack, setSpacing: 4.];
+            let _:() = msg_send![stack, setTranslatesAutoresizingMaskIntoConstraints: NO];
+            let cls = util::objc_class("NSImageView").unwrap();
+            let image_view: *mut Object = msg_send![cls, alloc];
+            let image_view: *mut Object = msg_send![image_view, init];
+            let _:() = msg_send![stack, addArrangedSubview: image_view];
+            let _:() = msg_send![image_view, release];
+            let cls = util::objc_class("NSTextField").unwrap();
+            let empty = NSString::alloc(nil).init_str("");
+            let label: *mut Object = msg_send![cls, labelWithString: empty];
+            let _:() = msg_send![empty, release];
+            let _:() = msg_send![stack, addArrangedSubview: label];
+            let _:() = msg_send![view, addSubview: stack];
+            // The view keeps the stack alive as its subview
+            let _:() = msg_send![stack, release];
+            (*view).set_ivar("_stack", stack as u64);
+            for anchor in &[sel!(leadingAnchor), sel!(trailingAnchor),
+                            sel!(topAnchor), sel!(bottomAnchor)] {
+                let inner: *mut Object = msg_send![stack, performSelector: *anchor];
+                let outer: *mut Object = msg_send![view, performSelector: *anchor];
+                let constraint: *mut Object = msg_send![inner, constraintEqualToAnchor: outer];
+                let _:() = msg_send![constraint, setActive: YES];
+            }
+        }
+        let parts: *mut Object = msg_send![stack, arrangedSubviews];
+        let image_view: *mut Object = msg_send![parts, objectAtIndex: 0u64];
+        let label: *mut Object = msg_send![parts, objectAtIndex: 1u64];
+        let _:() = msg_send![image_view, setImage: icon.as_raw() as *mut Object];
+        let objc_text = NSString::alloc(nil).init_str(text);
+        let _:() = msg_send![label, setStringValue: objc_text];
+        let _:() = msg_send![objc_text, release];
+    }
+    /// Returns a new, retained copy of `base` with a badge in the top-right
+    /// corner
+    unsafe fn badge_image(base: *mut Object, count: u32) -> *mut Object {
+        let size: NSSize = msg_send![base, size];
+        let cls = util::objc_class("NSImage").unwrap();
+        let image: *mut Object = msg_send![cls, alloc];
+        let image: *mut Object = msg_send![image, initWithSize: size];
+        let full = NSRect::new(NSPoint::new(0., 0.), size);
+        let zero = NSRect::new(NSPoint::new(0., 0.), NSSize::new(0., 0.));
+        let path_cls = util::objc_class("NSBezierPath").unwrap();
+        let color_cls = util::objc_class("NSColor").unwrap();
+        let _:() = msg_send![image, lockFocus];
+        // NSCompositingOperationSourceOver
+        let _:() = msg_send![base, drawInRect: full fromRect: zero operation: 2u64 fraction: 1.0f64];
+        let is_template: bool = msg_send![base, isTemplate];
+        if is_template {
+            // The badged image can't be a template, or the badge would lose
+            // its color, so draw the template the way the Touch Bar would.
+            let cls = util::objc_class("NSGraphicsContext").unwrap();
+            let context: *mut Object = msg_send![cls, currentContext];
+            let white: *mut Object = msg_send![color_cls, whiteColor];
+            let _:() = msg_send![white, set];
+            let _:() = msg_send![context, setCompositingOperation: 3u64]; // NSCompositingOperationSourceAtop
+            let _:() = msg_send![path_cls, fillRect: full];
+            let _:() = msg_send![context, setCompositingOperation: 2u64]; // NSCompositingOperationSourceOver
+        }
+        let text = match count {
+            0 => None,
+            1..=99 => Some(count.to_string()),
+            _ => Some("99+".to_string()),
+        };
+        let diameter = match text {
+            Some(_) => size.height * 0.55,
+            None => size.height * 0.35,
+        };
+        let attrs: *mut Object = msg_send![util::objc_class("NSMutableDictionary").unwrap(), dictionary];
+        let font_cls = util::objc_class("NSFont").unwrap();
+        let font: *mut Object = msg_send![font_cls, boldSystemFontOfSize: diameter * 0.75];
+        let _:() = msg_send![attrs, setObject: font forKey: NSFontAttributeName];
+        let white: *mut Object = msg_send![color_cls, whiteColor];
+        let _:() = msg_send![attrs, setObject: white forKey: NSForegroundColorAttributeName];
+        let text = text.map(|t| NSString::alloc(nil).init_str(&t));
+        let text_size = match text {
+            Some(text) => msg_send![text, sizeWithAttributes: attrs],
+            None => NSSize::new(0., 0.),
+        };
+        let width = f64::max(diameter, text_size.width + diameter * 0.4);
+        let badge = NSRect::new(NSPoint::new(size.width - width, size.height - diameter),
+                                NSSize::new(width, diameter));
+        let red: *mut Object = msg_send![color_cls, systemRedColor];
+        let _:() = msg_send![red, set];
+        let path: *mut Object = msg_send![path_cls, bezierPathWithRoundedRect: badge
+                                          xRadius: diameter / 2. yRadius: diameter / 2.];
+        let _:() = msg_send![path, fill];
+        if let Some(text) = text {
+            let origin = NSPoint::new(badge.origin.x + (width - text_size.width) / 2.,
+                                      badge.origin.y + (diameter - text_size.height) / 2.);
+            let _:() = msg_send![text, drawAtPoint: origin withAttributes: attrs];
+            let _:() = msg_send![text, release];
+        }
+        let _:() = msg_send![image, unlockFocus];
+        let _:() = msg_send![image, setTemplate: NO];
+        image
+    }
+    fn reload_scrubber(&self, scrub_id: &ItemId) {
+        unsafe {
+            let item = scrub_id.as_raw() as *mut Object;
+            let scrubber: *mut Object = msg_send![item, view];
+            let sel_idx: i32 = msg_send![scrubber, selectedIndex];
+            let pre_count: i32 = msg_send![scrubber, numberOfItems];
+            // Restoring the selection after a reload is not a user touch
+            self.selecting_scrubber.set(Some(scrubber as u64));
+            let _:() = msg_send![scrubber, reloadData];
+            let post_count: i32 = msg_send![scrubber, numberOfItems];
+            // reload clears the selected item.  re-select it.
+            if sel_idx >= 0 && pre_count == post_count {
+                let _:() = msg_send![scrubber, setSelectedIndex: sel_idx];
+            }
+            self.selecting_scrubber.set(None);
+        }
+    }
+    // Insert or remove a range of scrubber items with animation
+    fn scrubber_batch_update(&self, scrub_id: &ItemId, update: Sel, index: u32, count: u32) {
+        if !self.item_map.get(scrub_id).map_or(false, |x| x._type == ItemType::Scrubber) || count == 0 {
+            return;
+        }
+        unsafe {
+            let scrubber: *mut Object = msg_send![scrub_id.as_raw() as *mut Object, view];
+            let cls = util::objc_class("NSIndexSet").unwrap();
+            let range = NSRange { location: index as u64, length: count as u64 };
+            let indexes: *mut Object = msg_send![cls, indexSetWithIndexesInRange: range];
+            // Moving the selection to follow its item is not a user touch
+            self.selecting_scrubber.set(Some(scrubber as u64));
+            let _:() = msg_send![scrubber, performSelector: update withObject: indexes];
+            self.selecting_scrubber.set(None);
+        }
+    }
+    fn set_slider_value(id: &ItemId, value: f64) {
+        unsafe {
+            let item = id.as_raw() as *mut Object;
+            let slider: *mut Object = msg_send![item, slider];
+            let _:() = msg_send![slider, setDoubleValue: value];
+        }
+    }
+    fn filter_slider_value(&mut self, item: u64, value: f64) -> Option<f64> {
+        let internal_item = self.item_map.get_mut(&ItemId::from_raw(item))?;
+        #[cfg(all(feature = "system_controls", feature = "private_api"))]
+        {
+            internal_item.touched = Some(Instant::now());
+        }
+        if !internal_item.integer_only {
+            return Some(value);
+        }
+        let value = value.round();
+        if internal_item.last_value == Some(value) {
+            return None;
+        }
+        internal_item.last_value = Some(value);
+        Some(value)
+    }
+    fn build_step(&mut self, timer: u64) {
+        // Take the step out while it runs, since it is likely to call back
+        // into this controller.
+        let (index, mut step) = match self.build_steps.remove(&timer) {
+            Some(x) => x,
+            None => return,
+        };
+        match step(index) {
+            true => { self.build_steps.insert(timer, (index + 1, step)); },
+            false => unsafe { RustTouchbarDelegateWrapper::cancel_timer(timer as *mut Object); },
+        }
+    }
+    fn marquee_tick(&mut self, item: ItemId) {
+        let internal_item = match self.item_map.get_mut(&item) {
+            Some(x) => x,
+            None => return,
+        };
+        let label = internal_item.control.unwrap();
+        let (text, offset) = match internal_item.marquee {
+            Some((ref text, ref mut offset)) => (text, offset),
+            None => return,
+        };
+        unsafe {
+            if *offset == 0 {
+                // The label is showing the plain text, so this is a good time
+                // to check whether it needs to scroll at all.
+                let text_size: NSSize = msg_send![label, intrinsicContentSize];
+                let frame: NSRect = msg_send![label, frame];
+                if text_size.width <= frame.size.width || frame.size.width <= 0. {
+                    return;
+                }
+            }
+            let chars: Vec<char> = text.chars().chain(MARQUEE_GAP.chars()).collect();
+            *offset = (*offset + 1) % chars.len();
+            let shown: String = match *offset {
+                0 => text.clone(),
+                n => chars[n..].iter().chain(chars[..n].iter()).collect(),
+            };
+            let shown = NSString::alloc(nil).init_str(&shown);
+            let _:() = msg_send![label, setStringValue: shown];
+            let _:() = msg_send![shown, release];
+        }
+    }
+    fn repeat_button(&self, item: ItemId) {
+        if let Some(internal_item) = self.item_map.get(&item).filter(|x| !x.disabled) {
+            if let Some(ref cb) = internal_item.button_cb {
+                cb(&ItemEvent { item: item, control: ControlId::from_raw(internal_item.control.unwrap() as u64) });
+            }
+        }
+    }
+    fn button_hold(&mut self, recognizer: u64) {
+        let item = match self.item_map.iter().filter(|&(_, x)| {
+            x.auto_repeat.map_or(false, |(r, _)| r as u64 == recognizer)
+        }).next() {
+            Some((id, _)) => *id,
+            None => return,
+        };
+        let state: u32 = unsafe { msg_send![recognizer as *mut Object, state] };
+        match state {
+            // NSGestureRecognizerStateBegan
+            1 => {
+                let interval = self.item_map[&item].auto_repeat.unwrap().1;
+                let timer = self.schedule_timer(interval);
+                if let Some(old_timer) = self.item_map.get_mut(&item).unwrap().timer.replace(timer) {
+                    unsafe { RustTouchbarDelegateWrapper::cancel_timer(old_timer); }
+                }
+                self.repeat_button(item);
+            },
+            // NSGestureRecognizerStateChanged
+            2 => {},
+            _ => {
+                if let Some(timer) = self.item_map.get_mut(&item).unwrap().timer.take() {
+                    unsafe { RustTouchbarDelegateWrapper::cancel_timer(timer); }
+                }
+            },
+        }
+    }
+    fn button_confirm(&mut self, recognizer: u64) {
+        let item = match self.item_map.iter().filter(|&(_, x)| {
+            x.hold_confirm.as_ref().map_or(false, |h| h.recognizer as u64 == recognizer)
+        }).next() {
+            Some((id, _)) => *id,
+            None => return,
+        };
+        let state: u32 = unsafe { msg_send![recognizer as *mut Object, state] };
+        match state {
+            // NSGestureRecognizerStateBegan
+            1 => {
+                let timer = self.schedule_timer(Duration::from_millis(HOLD_CONFIRM_TICK_MS));
+                let internal_item = self.item_map.get_mut(&item).unwrap();
+                if let Some(old_timer) = internal_item.timer.replace(timer) {
+                    unsafe { RustTouchbarDelegateWrapper::cancel_timer(old_timer); }
+                }
+                internal_item.hold_confirm.as_mut().unwrap().started = Some(Instant::now());
+            },
+            // NSGestureRecognizerStateChanged
+            2 => {},
+            _ => self.reset_hold_confirm(item),
+        }
+    }
+    fn hold_confirm_tick(&mut self, item: ItemId) {
+        let (btn, fill, fraction) = match self.item_map.get(&item) {
+            Some(x) => match x.hold_confirm {
+                Some(HoldConfirm { fill, hold, started: Some(started), .. }) => {
+                    let elapsed = started.elapsed();
+                    let fraction = (elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9) /
+                        (hold.as_secs() as f64 + hold.subsec_nanos() as f64 * 1e-9);
+                    (x.control.unwrap(), fill, fraction.min(1.))
+                },
+                _ => return,
+            },
+            None => return,
+        };
+        if fraction >= 1. {
+            self.reset_hold_confirm(item);
+            self.repeat_button(item);
+            return;
+        }
+        unsafe {
+            let bounds: NSRect = msg_send![btn, bounds];
+            let frame = NSRect::new(NSPoint::new(0., 0.),
+                                    NSSize::new(bounds.size.width * fraction, bounds.size.height));
+            let _:() = msg_send![fill, setFrame: frame];
+        }
+    }
+    fn reset_hold_confirm(&mut self, item: ItemId) {
+        let internal_item = match self.item_map.get_mut(&item) {
+            Some(x) => x,
+            None => return,
+        };
+        if let Some(timer) = internal_item.timer.take() {
+            unsafe { RustTouchbarDelegateWrapper::cancel_timer(timer); }
+        }
+        if let Some(ref mut hold_confirm) = internal_item.hold_confirm {
+            hold_confirm.started = None;
+            let frame = NSRect::new(NSPoint::new(0., 0.), NSSize::new(0., 0.));
+            unsafe { let _:() = msg_send![hold_confirm.fill, setFrame: frame]; }
+        }
+    }
+    fn ticker_tick(&mut self, item: ItemId) {
+        let internal_item = match self.item_map.get_mut(&item) {
+            Some(x) => x,
+            None => return,
+        };
+        let label = internal_item.control.unwrap();
+        let (texts, index) = match internal_item.ticker {
+            Some((ref texts, ref mut index)) => (texts, index),
+            None => return,
+        };
+        unsafe {
+            // Labels without a window aren't on screen, so stay on the
+            // current string until they are.
+            let window: *mut Object = msg_send![label, window];
+            if window == nil {
+                return;
+            }
+            *index = (*index + 1) % texts.len();
+            let shown = NSString::alloc(nil).init_str(&texts[*index]);
+            let _:() = msg_send![label, setStringValue: shown];
+            let _:() = msg_send![shown, release];
+        }
+    }
+    fn format_countdown(remaining: Duration) -> String {
+        // Round up, so the label reads 0:00 only once time is up
+        let secs = remaining.as_secs() + if remaining.subsec_nanos() > 0 { 1 } else { 0 };
+        match secs >= 3600 {
+            true => format!("{}:{:02}:{:02}", secs / 3600, (secs / 60) % 60, secs % 60),
+            false => format!("{}:{:02}", secs / 60, secs % 60),
+        }
+    }
+    fn countdown_tick(&mut self, item: ItemId) {
+        let (label, deadline) = match self.item_map.get(&item) {
+            Some(x) => (x.control.unwrap(), x.deadline.unwrap()),
+            None => return,
+        };
+        let now = Instant::now();
+        let remaining = match deadline > now {
+            true => deadline - now,
+            false => Duration::from_secs(0),
+        };
+        unsafe {
+            let text = RustTouchbarDelegateWrapper::format_countdown(remaining);
+            let text = NSString::alloc(nil).init_str(&text);
+            let _:() = msg_send![label, setStringValue: text];
+            let _:() = msg_send![text, release];
+        }
+        if remaining == Duration::from_secs(0) {
+            let internal_item = self.item_map.get_mut(&item).unwrap();
+            if let Some(timer) = internal_item.timer.take() {
+                unsafe { RustTouchbarDelegateWrapper::cancel_timer(timer); }
+            }
+            // Take the callback while calling it, so it is free to restart
+            // the countdown.
+            if let Some(cb) = internal_item.done_cb.take() {
+                cb(&item);
+                if let Some(internal_item) = self.item_map.get_mut(&item) {
+                    if internal_item.done_cb.is_none() {
+                        internal_item.done_cb = Some(cb);
+                    }
+                }
+            }
+        }
+    }
+    unsafe fn alloc_image_view(width: u32) -> *mut Object {
+        let frame = NSRect::new(NSPoint::new(0., 0.),
+                                NSSize::new(width as f64, BAR_HEIGHT as f64));
+        let cls = util::objc_class("NSImageView").unwrap();
+        let view: *mut Object = msg_send![cls, alloc];
+        let view: *mut Object = msg_send![view, initWithFrame: frame];
+        let _:() = msg_send![view, setImageScaling: 0]; // NSImageScaleProportionallyDown
+        let anchor: *mut Object = msg_send![view, widthAnchor];
+        let constraint: *mut Object = msg_send![anchor, constraintEqualToConstant: width as f64];
+        let _:() = msg_send![constraint, setActive: YES];
+        view
+    }
+    fn render_sparkline(values: &[f64], width: u32, height: u32) -> Vec<u8> {
+        let mut pixels = vec![0u8; (width * height * 4) as usize];
+        if values.is_empty() || width < 2 {
+            return pixels;
+        }
+        let min = values.iter().cloned().fold(std::f64::INFINITY, f64::min);
+        let max = values.iter().cloned().fold(std::f64::NEG_INFINITY, f64::max);
+        let range = if max > min { max - min } else { 1. };
+        // Row of the line at each column, with 0 at the top.  Leave a margin
+        // so the line isn't clipped at the edges.
+        let margin = BAR_SCALE as f64 * 2.;
+        let row_at = |x: u32| -> f64 {
+            let pos = x as f64 * (values.len() - 1) as f64 / (width - 1) as f64;
+            let idx = (pos.floor() as usize).min(values.len() - 1);
+            let next = (idx + 1).min(values.len() - 1);
+            let value = values[idx] + (values[next] - values[idx]) * (pos - idx as f64);
+            let frac = (value - min) / range;
+            margin + (1. - frac) * (height as f64 - 1. - 2. * margin)
+        };
+        let mut prev = row_at(0);
+        for x in 0..width {
+            let row = row_at(x);
+            let (top, bottom) = (prev.min(row).round() as u32, prev.max(row).round() as u32);
+            for y in 0..height {
+                let alpha = match y {
+                    // Line, joined vertically to the previous column
+                    y if y + BAR_SCALE / 2 >= top && y <= bottom + BAR_SCALE / 2 => 0xff,
+                    // Faint fill under the line
+                    y if y > bottom => 0x40,
+                    _ => continue,
+                };
+                let offset = ((y * width + x) * 4) as usize;
+                pixels[offset..offset + 4].copy_from_slice(&[0xff, 0xff, 0xff, alpha]);
+            }
+            prev = row;
+        }
+        pixels
+    }
+    unsafe fn image_from_rgba(pixels: &[u8], width: u32, height: u32) -> *mut Object {
+        if pixels.len() != (width * height * 4) as usize {
+            warn!("Canvas image is {} bytes, expected {}x{}x4",
+                  pixels.len(), width, height);
+            return nil;
+        }
+        let color_space = NSString::alloc(nil).init_str("NSDeviceRGBColorSpace");
+        let cls = util::objc_class("NSBitmapImageRep").unwrap();
+        let rep: *mut Object = msg_send![cls, alloc];
+        let planes: *mut *mut u8 = std::ptr::null_mut();
+        let rep: *mut Object = msg_send![rep,
+                                         initWithBitmapDataPlanes: planes
+                                         pixelsWide: width as i64
+                                         pixelsHigh: height as i64
+                                         bitsPerSample: 8i64
+                                         samplesPerPixel: 4i64
+                                         hasAlpha: YES
+                                         isPlanar: NO
+                                         colorSpaceName: color_space
+                                         bytesPerRow: (width * 4) as i64
+                                         bitsPerPixel: 32i64];
+        let _:() = msg_send![color_space, release];
+        let data: *mut u8 = msg_send![rep, bitmapData];
+        std::ptr::copy_nonoverlapping(pixels.as_ptr(), data, pixels.len());
+        let size = NSSize::new((width / BAR_SCALE) as f64, (height / BAR_SCALE) as f64);
+        let cls = util::objc_class("NSImage").unwrap();
+        let image: *mut Object = msg_send![cls, alloc];
+        let image: *mut Object = msg_send![image, initWithSize: size];
+        let _:() = msg_send![image, addRepresentation: rep];
+        let _:() = msg_send![rep, release];
+        image
+    }
+    unsafe fn nsdate_from_system_time(date: SystemTime) -> *mut Object {
+        let secs = match date.duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_secs() as f64 + d.subsec_nanos() as f64 * 1e-9,
+            Err(e) => -(e.duration().as_secs() as f64),
+        };
+        let cls = util::objc_class("NSDate").unwrap();
+        msg_send![cls, dateWithTimeIntervalSince1970: secs]
+    }
+    unsafe fn system_time_from_nsdate(date: *mut Object) -> SystemTime {
+        let secs: f64 = msg_send![date, timeIntervalSince1970];
+        match secs >= 0. {
+            true => UNIX_EPOCH + Duration::from_millis((secs * 1000.) as u64),
+            false => UNIX_EPOCH - Duration::from_millis((-secs * 1000.) as u64),
+        }
+    }
+    unsafe fn set_label_font_for_text(label: *mut Object, text: &str) {
+        //let constraints: *mut Object = msg_send![label, constraints];
+        //let height_constraint: *mut Object = msg_send![constraints, firstObject];
+        //if height_constraint != nil {
+        //    // TODO: if re-enabled, must use NSLayoutConstraint identifier to
+        //    // make sure the correct constraint is removed
+        //    msg_send![label, removeConstraint: height_constraint];
+        //}
+        if text.contains("\n") {
+            // Shrink font for multi-line labels.  This makes for quite small text.
+            let cls = util::objc_class("NSFont").unwrap();
+            let default_size:f64 = msg_send![cls, systemFontSize];
+            let custom_font: *mut Object = msg_send![cls, systemFontOfSize: default_size - 3.0];
+            let _:() = msg_send![label, setFont: custom_font];
+
+            //let anchor: *mut Object = msg_send![label, heightAnchor];
+            //let constraint: *mut Object = msg_send![anchor, constraintEqualToConstant: 30. as f64];
+            //let _:() = msg_send![constraint, setActive: YES];
+        }
+        else {
+            // Enlarge the font for single lines.  For some reason it defaults
+            // to smaller than other elements.
+            let cls = util::objc_class("NSFont").unwrap();
+            let default_size:f64 = msg_send![cls, systemFontSize];
+            let custom_font: *mut Object = msg_send![cls, systemFontOfSize: default_size + 3.0];
+            let _:() = msg_send![label, setFont: custom_font];
+        }
+    }
+}
+
+// Fill drawn behind an item made draggable with make_item_draggable_value().
+// Sublayers of the item's own layer draw over the item's content, so the
+// fill goes in the layer behind the item, once the item is displayed in one.
+struct DragFill(*mut Object);
+
+impl DragFill {
+    // Cover the given fraction of the view, from the left
+    unsafe fn show(&self, view: *mut Object, fraction: f64) {
+        let layer: *mut Object = msg_send![view, layer];
+        let superlayer: *mut Object = msg_send![layer, superlayer];
+        if superlayer == nil {
+            return;
+        }
+        let current: *mut Object = msg_send![self.0, superlayer];
+        if current != superlayer {
+            let _:() = msg_send![self.0, removeFromSuperlayer];
+            let _:() = msg_send![superlayer, insertSublayer: self.0 below: layer];
+        }
+        let frame: NSRect = msg_send![layer, frame];
+        let frame = NSRect::new(frame.origin,
+                                NSSize::new(frame.size.width * fraction, frame.size.height));
+        let _:() = msg_send![self.0, setFrame: frame];
+    }
+}
+
+impl Drop for DragFill {
+    fn drop(&mut self) {
+        unsafe {
+            let _:() = msg_send![self.0, removeFromSuperlayer];
+            let _:() = msg_send![self.0, release];
+        }
+    }
+}
+
+impl Drop for RustTouchbarDelegateWrapper {
+    fn drop(&mut self) {
+        // The delegates may outlive the controller, so nothing queued on
+        // them may call back into it.
+        self.dropped.borrow_mut().clear();
+        unsafe {
+            let cls = util::objc_class("NSObject").unwrap();
+            for delegate in self.delegates() {
+                let _:() = msg_send![cls, cancelPreviousPerformRequestsWithTarget: delegate];
+                // Downloads finishing later still message the delegate
+                let _:() = msg_send![delegate, setRustWrapper: 0u64];
+            }
+            // Registered in alloc_touchbar()
+            let cls = util::objc_class("NSDistributedNotificationCenter").unwrap();
+            let center: *mut Object = msg_send![cls, defaultCenter];
+            let _:() = msg_send![center, removeObserver: self.main_delegate()];
+            for (placeholder, _, _) in self.loaded_images.lock().unwrap().drain(..) {
+                let _:() = msg_send![placeholder as *mut Object, release];
+            }
+        }
+        #[cfg(feature = "system_controls")]
+        self.stop_volume_listener();
+        #[cfg(feature = "private_api")]
+        self.stop_now_playing();
+    }
+}
+
+impl TTouchbar for Touchbar {
+    type T = Touchbar;
+    fn alloc(title: &str) -> Touchbar {
+        RustTouchbarDelegateWrapper::alloc_touchbar(title, None)
+    }
+    fn alloc_with_prefix(title: &str, prefix: &str) -> Touchbar {
+        RustTouchbarDelegateWrapper::alloc_touchbar(title, Some(prefix))
+    }
+    fn set_icon(&self, image: &str) -> Result<(), TouchbarError> {
+        let result = self.load_icon(self.main_delegate(), image);
+        if let Err(ref err) = result {
+            error!("{}", err);
+        }
+        result
+    }
+    fn set_icon_variants(&mut self, light: &str, dark: &str) {
+        self.icon_variants = Some((light.to_string(), dark.to_string()));
+        self.apply_appearance();
+    }
+
+    fn create_bar(&mut self) -> BarId {
+        unsafe {
+            let ident = self.generate_ident();
+            // Create touchbar
+            let cls = RRTouchBar::class();
+            let bar: *mut Object = msg_send![cls, alloc];
+            let bar: *mut objc::runtime::Object = msg_send![bar, init];
+            let _ : () = msg_send![bar, setDelegate: self.objc.clone()];
+            let internal = InternalBar {
+                view: bar,
+                ident: ident,
+                items: Vec::<ItemId>::new(),
+                disabled: None,
+                customization: None,
+            };
+            self.bar_map.insert(BarId::from_raw(bar as u64), internal);
+            BarId::from_raw(bar as u64)
+        }
+    }
+    fn create_popover_item(&mut self, image: Option<&TouchbarImage>,
+                           text: Option<&str>, bar_id: &BarId) -> ItemId {
+        if !self.classes_available(&["NSPopoverTouchBarItem"]) {
+            return ItemId::from_raw(0);
+        }
+        unsafe {
+            let bar = bar_id.as_raw() as *mut Object;
+            let ident = self.generate_ident();
+            let cls = RRPopoverTouchBarItem::class();
+            let item: *mut Object = msg_send![cls, alloc];
+            let item: *mut Object = msg_send![item, initWithIdentifier: ident.as_raw()];
+
+            let target = (&*self.objc.clone()) as *const ObjcAppDelegate as *mut Object;
+            let btn = self.alloc_button(image, text,
+                                        target,
+                                        sel!(popbar:));
+            let _:() = msg_send![item, setShowsCloseButton: YES];
+            let gesture: *mut Object = msg_send![item, makeStandardActivatePopoverGestureRecognizer];
+            let _:() = msg_send![btn, addGestureRecognizer: gesture];
+            let _:() = msg_send![item, setCollapsedRepresentation: btn];
+            let _:() = msg_send![item, setPopoverTouchBar: bar];
+            let _:() = msg_send![item, setPressAndHoldTouchBar: bar];
+
+            let mut internal = InternalItem::new(ItemType::Popover, item, ident, Some(btn));
+            internal.child_bar = Some(BarId::from_raw(bar as u64));
+            let item = self.insert_item(item, internal);
+            self.sync_bar_identifiers(bar_id);
+            item
+        }
+    }
+    fn create_popover_item_lazy(&mut self, image: Option<&TouchbarImage>, text: Option<&str>,
+                                rebuild: bool, builder: PopoverBuildCb) -> ItemId {
+        if !self.classes_available(&["NSPopoverTouchBarItem"]) {
+            return ItemId::from_raw(0);
+        }
+        // Start with an empty bar, replaced when the popover is first opened
+        let bar = self.create_bar();
+        #[allow(deprecated)]
+        let item = self.create_popover_item(image, text, &bar);
+        unsafe {
+            let _:() = msg_send![item.as_raw() as *mut Object, setPressAndHoldTouchBar: nil];
+        }
+        let internal_item = self.item_map.get_mut(&item).unwrap();
+        internal_item.lazy_bar = Some((Rc::new(builder), rebuild));
+        item
+    }
+    fn create_dropdown_button(&mut self, image: Option<&TouchbarImage>, text: Option<&str>,
+                              options: &[&str], cb: DropdownCb) -> ItemId {
+        if !self.classes_available(&["NSPopoverTouchBarItem"]) {
+            return ItemId::from_raw(0);
+        }
+        let bar = self.create_bar();
+        #[allow(deprecated)]
+        let dropdown = self.create_popover_item(image, text, &bar);
+        let cb = Rc::new(cb);
+        let buttons = options.iter().enumerate().map(|(i, option)| {
+            let cb = cb.clone();
+            self.create_button_with_event(None, Some(option), Box::new(move |_| {
+                cb(&dropdown, i as u32);
+                unsafe {
+                    RustTouchbarDelegateWrapper::dismiss_popover_bars(
+                        dropdown.as_raw() as *mut Object, &[bar]);
+                }
+            }))
+        }).collect();
+        self.add_items_to_bar(&bar, buttons);
+        dropdown
+    }
+    fn create_picker(&mut self, labels: &[&str], images: &[TouchbarImage],
+                     cb: PickerCb) -> ItemId {
+        for image in images {
+            self.consume_image(image);
+        }
+        let native = util::get_appkit_version() >= AppKitVersion::from_tuple(10, 15) &&
+            util::objc_class("NSPickerTouchBarItem").is_ok();
+        if !native && !self.classes_available(&["NSSegmentedControl"]) {
+            return ItemId::from_raw(0);
+        }
+        unsafe {
+            let ident = self.generate_ident();
+            let target = (&*self.objc.clone()) as *const ObjcAppDelegate as *mut Object;
+            let count = cmp::max(labels.len(), images.len());
+            let cls = util::objc_class("NSMutableArray").unwrap();
+            let objc_labels: *mut Object = msg_send![cls, arrayWithCapacity: count];
+            for i in 0..count {
+                let label = NSString::alloc(nil).init_str(labels.get(i).unwrap_or(&""));
+                let _:() = msg_send![objc_labels, addObject: label];
+                let _:() = msg_send![label, release];
+            }
+            let (item, control) = match native {
+                true => {
+                    let cls = util::objc_class("NSPickerTouchBarItem").unwrap();
+                    // NSPickerTouchBarItemSelectionModeSelectOne
+                    let picker: *mut Object = msg_send![
+                        cls, pickerTouchBarItemWithIdentifier: ident.as_raw() labels: objc_labels
+                        selectionMode: 0i64 target: target action: sel!(picker:)];
+                    let _:() = msg_send![picker, retain];
+                    for (i, image) in images.iter().enumerate() {
+                        let _:() = msg_send![picker, setImage: image.as_raw() as *mut Object atIndex: i as i64];
+                    }
+                    let _:() = msg_send![picker, setSelectedIndex: 0i64];
+                    (picker, None)
+                },
+                false => {
+                    let cls = util::objc_class("NSSegmentedControl").unwrap();
+                    // NSSegmentSwitchTrackingSelectOne
+                    let control: *mut Object = msg_send![
+                        cls, segmentedControlWithLabels: objc_labels trackingMode: 0u64
+                        target: target action: sel!(picker:)];
+                    let _:() = msg_send![control, retain];
+                    for (i, image) in images.iter().enumerate() {
+                        let _:() = msg_send![control, setImage: image.as_raw() as *mut Object forSegment: i as i64];
+                    }
+                    let _:() = msg_send![control, setSelectedSegment: 0i64];
+                    let cls = RRCustomTouchBarItem::class();
+                    let item: *mut Object = msg_send![cls, alloc];
+                    let item: *mut Object = msg_send![item, initWithIdentifier: ident.as_raw()];
+                    let _:() = msg_send![item, setView: control];
+                    (item, Some(control))
+                },
+            };
+            let mut internal = InternalItem::new(ItemType::Picker, item, ident, control);
+            internal.picker_cb = Some(cb);
+            self.insert_item(item, internal)
+        }
+    }
+    fn set_popover_bar(&mut self, item: &ItemId, bar_id: BarId) {
+        if !self.check_item(item) {
+            return;
+        }
+        let ident = match self.item_map.get(item) {
+            Some(x) if x._type == ItemType::Popover => x.ident,
+            _ => return,
+        };
+        if self.bar_map.iter().filter(|&(_, b)| b.items.contains(item))
+            .any(|(parent, _)| self.bar_reaches_bar(&bar_id, parent, &mut Vec::new())) {
+            let ident = util::nsstring_decode(ident.as_raw() as *mut Object);
+            let reason = format!("popover {} contains its own bar", ident);
+            self.report_error(TouchbarError::UnsupportedItem(reason));
+            return;
+        }
+        let old_bar = {
+            let internal_item = self.item_map.get_mut(item).unwrap();
+            internal_item.lazy_bar = None;
+            internal_item.child_bar.replace(bar_id)
+        };
+        unsafe {
+            let _:() = msg_send![item.as_raw() as *mut Object, setPopoverTouchBar: bar_id.as_raw() as *mut Object];
+        }
+        self.sync_bar_identifiers(&bar_id);
+        if let Some(old_bar) = old_bar.filter(|old_bar| *old_bar != bar_id) {
+            self.free_bar(old_bar);
+        }
+    }
+    fn present_popover(&mut self, item: &ItemId) {
+        if !self.check_item(item) {
+            return;
+        }
+        self.open_popover(*item);
+    }
+    fn dismiss_popover(&mut self, item: &ItemId) {
+        if !self.check_item(item) {
+            return;
+        }
+        self.close_popover(*item);
+    }
+    fn dismiss_all_popovers(&mut self) {
+        let popovers: Vec<ItemId> = self.item_map.iter()
+            .filter(|&(_, x)| x._type == ItemType::Popover)
+            .map(|(id, _)| *id).collect();
+        for item in popovers {
+            self.close_popover(item);
+        }
+    }
+    fn set_popover_press_and_hold_bar(&mut self, item: &ItemId, bar_id: &BarId) {
+        if !self.check_item(item) {
+            return;
+        }
+        let old = match self.item_map.get_mut(item) {
+            Some(x) if x._type == ItemType::Popover => mem::replace(&mut x.hold_bar, Some(*bar_id)),
+            _ => return,
+        };
+        unsafe {
+            let _:() = msg_send![item.as_raw() as *mut Object, setPressAndHoldTouchBar: bar_id.as_raw() as *mut Object];
+        }
+        self.sync_bar_identifiers(bar_id);
+        // The popover owns its hold bar, like its main bar, so the one it
+        // replaces is freed
+        if let Some(old) = old.filter(|old| old != bar_id) {
+            self.free_bar(old);
+        }
+    }
+    fn set_popover_hold_thresholds(&mut self, item: &ItemId, min_duration: Duration,
+                                   allowable_movement: f64) {
+        if !self.check_item(item) {
+            return;
+        }
+        if !self.classes_available(&["NSPressGestureRecognizer"]) {
+            return;
+        }
+        unsafe {
+            let btn = match self.item_map.get(item) {
+                Some(x) if x._type == ItemType::Popover => x.control.unwrap(),
+                _ => return,
+            };
+            // The standard popover gesture is the only press recognizer
+            // attached to the button.
+            let cls = util::objc_class("NSPressGestureRecognizer").unwrap();
+            let gestures: *mut Object = msg_send![btn, gestureRecognizers];
+            let count: u64 = msg_send![gestures, count];
+            for i in 0..count {
+                let gesture: *mut Object = msg_send![gestures, objectAtIndex: i];
+                let is_press: bool = msg_send![gesture, isKindOfClass: cls];
+                if is_press {
+                    let secs = min_duration.as_secs() as f64 +
+                        min_duration.subsec_nanos() as f64 * 1e-9;
+                    let _:() = msg_send![gesture, setMinimumPressDuration: secs];
+                    let _:() = msg_send![gesture, setAllowableMovement: allowable_movement];
+                }
+            }
+        }
+    }
+    fn add_items_to_bar(&mut self, bar_id: &BarId, items: Vec<ItemId>) {
+        if !self.bar_map.contains_key(bar_id) {
+            warn!("Not adding items to unknown bar <{:x}>", bar_id.as_raw());
+            return;
+        }
+        let mut added = Vec::new();
+        for item in items {
+            if !self.check_item(&item) {
+                continue;
+            }
+            if let Some(reason) = self.bar_item_conflict(bar_id, &item, &added) {
+                self.report_error(TouchbarError::UnsupportedItem(reason));
+                continue;
+            }
+            if self.find_ident(&item).is_none() {
+                continue;
+            }
+            if let Some(internal_bar) = self.bar_map.get_mut(bar_id) {
+                added.push(item);
+                internal_bar.items.push(item);
+            }
+        }
+        self.report_edge_spacers(bar_id, &added);
+        self.sync_bar_identifiers(bar_id);
+    }
+    fn insert_item_in_bar(&mut self, bar_id: &BarId, index: usize, item: ItemId) {
+        if !self.check_item(&item) {
+            return;
+        }
+        let items = match self.bar_map.get(bar_id) {
+            Some(internal_bar) => internal_bar.items.clone(),
+            None => {
+                warn!("Not inserting item into unknown bar <{:x}>", bar_id.as_raw());
+                return;
+            },
+        };
+        if let Some(reason) = self.bar_item_conflict(bar_id, &item, &items) {
+            self.report_error(TouchbarError::UnsupportedItem(reason));
+            return;
+        }
+        if self.find_ident(&item).is_none() {
+            return;
+        }
+        let index = cmp::min(index, items.len());
+        match self.bar_map.get_mut(bar_id) {
+            Some(internal_bar) => internal_bar.items.insert(index, item),
+            None => return,
+        }
+        self.report_edge_spacers(bar_id, &[item]);
+        self.sync_bar_identifiers(bar_id);
+    }
+    fn set_bar_item_order(&mut self, bar_id: &BarId, items: Vec<ItemId>) {
+        if !items.iter().all(|item| self.check_item(item)) {
+            return;
+        }
+        match self.bar_map.get_mut(bar_id) {
+            Some(internal_bar) => {
+                let mut remaining = mem::replace(&mut internal_bar.items, Vec::new());
+                for item in items {
+                    if let Some(idx) = remaining.iter().position(|x| *x == item) {
+                        internal_bar.items.push(remaining.remove(idx));
+                    }
+                }
+                internal_bar.items.append(&mut remaining);
+            },
+            None => return,
+        }
+        self.sync_bar_identifiers(bar_id);
+    }
+    fn remove_item_from_bar(&mut self, bar_id: &BarId, item: &ItemId) {
+        if !self.check_item(item) {
+            return;
+        }
+        match self.bar_map.get_mut(bar_id) {
+            Some(internal_bar) if internal_bar.items.contains(item) => {
+                internal_bar.items.retain(|x| x != item);
+            },
+            _ => return,
+        }
+        self.sync_bar_identifiers(bar_id);
+        self.free_removed_item(item);
+    }
+    fn replace_item(&mut self, bar_id: &BarId, old: &ItemId, new: ItemId) {
+        if !self.check_item(old) || !self.check_item(&new) {
+            return;
+        }
+        let others: Vec<ItemId> = match self.bar_map.get(bar_id) {
+            Some(internal_bar) if internal_bar.items.contains(old) && *old != new => {
+                internal_bar.items.iter().cloned().filter(|x| x != old).collect()
+            },
+            _ => return,
+        };
+        if let Some(reason) = self.bar_item_conflict(bar_id, &new, &others) {
+            self.report_error(TouchbarError::UnsupportedItem(reason));
+            return;
+        }
+        if self.find_ident(&new).is_none() {
+            return;
+        }
+        match self.bar_map.get_mut(bar_id) {
+            Some(internal_bar) => {
+                for item in internal_bar.items.iter_mut().filter(|x| *x == old) {
+                    *item = new;
+                }
+            },
+            None => return,
+        }
+        self.report_edge_spacers(bar_id, &[new]);
+        self.sync_bar_identifiers(bar_id);
+        self.free_removed_item(old);
+    }
+    fn destroy_item(&mut self, item: ItemId) {
+        if !self.check_item(&item) {
+            return;
+        }
+        let bars: Vec<BarId> = self.bar_map.iter_mut().filter(|&(_, ref b)| b.items.contains(&item))
+            .map(|(id, b)| {
+                b.items.retain(|x| *x != item);
+                *id
+            }).collect();
+        for bar in bars {
+            self.sync_bar_identifiers(&bar);
+        }
+        self.forget_disabled_controls(&item);
+        self.free_item_allocations(&item);
+    }
+    fn destroy_bar(&mut self, bar_id: BarId) {
+        if !self.bar_map.contains_key(&bar_id) {
+            return;
+        }
+        if self.is_root_bar(&bar_id) {
+            warn!("Not destroying a root bar; replace it with set_bar_as_root()");
+            return;
+        }
+        self.free_bar(bar_id);
+    }
+    fn own_item(&mut self, item: ItemId) -> OwnedItem {
+        match self.check_item(&item) {
+            true => OwnedItem::new(item, self.own(item.as_raw())),
+            false => OwnedItem::new(item, Box::new(|| {})),
+        }
+    }
+    fn own_bar(&mut self, bar_id: BarId) -> OwnedBar {
+        match self.bar_map.contains_key(&bar_id) {
+            true => OwnedBar::new(bar_id, self.own(bar_id.as_raw())),
+            false => OwnedBar::new(bar_id, Box::new(|| {})),
+        }
+    }
+    fn allocation_report(&self) -> AllocationStats {
+        let reachable = self.reachable_items();
+        AllocationStats {
+            live_objects: wrapper::live_objects().into_iter()
+                .map(|(name, count)| (name.to_string(), count)).collect(),
+            unattached_items: self.item_map.keys()
+                .filter(|item| !reachable.contains(item)).cloned().collect(),
+            unused_images: self.unused_images.borrow().iter().cloned().collect(),
+        }
+    }
+    fn dump_state(&self) -> String {
+        let mut out = String::new();
+        let mut visited = Vec::new();
+        let roots: Vec<BarId> = self.delegates().into_iter().map(|delegate| unsafe {
+            let root: *mut Object = msg_send![delegate, groupTouchBar];
+            BarId::from_raw(root as u64)
+        }).filter(|bar| *bar != BarId::from_raw(0)).collect();
+        for bar in &roots {
+            self.dump_bar(&mut out, "Root bar", bar, 0, &mut visited);
+        }
+        let detached: Vec<BarId> = self.bar_map.keys()
+            .filter(|bar| !visited.contains(bar) && !self.is_popover_bar(bar))
+            .cloned().collect();
+        for bar in &detached {
+            self.dump_bar(&mut out, "Bar", bar, 0, &mut visited);
+        }
+        let unattached: Vec<ItemId> = self.item_map.keys()
+            .filter(|item| !self.bar_map.values().any(|b| b.items.contains(item)))
+            .cloned().collect();
+        if !unattached.is_empty() {
+            let _ = writeln!(out, "Unattached items:");
+            for item in &unattached {
+                self.dump_item(&mut out, item, 1, &mut visited);
+            }
+        }
+        // Popover bars whose popover item was already freed
+        let orphans: Vec<BarId> = self.bar_map.keys()
+            .filter(|bar| !visited.contains(bar)).cloned().collect();
+        for bar in &orphans {
+            self.dump_bar(&mut out, "Popover bar", bar, 0, &mut visited);
+        }
+        out
+    }
+    fn clone_bar(&mut self, bar_id: &BarId) -> BarId {
+        let items = match self.bar_map.get(bar_id) {
+            Some(internal_bar) => internal_bar.items.clone(),
+            None => return BarId::from_raw(0),
+        };
+        let mut clones = Vec::<ItemId>::new();
+        for item in items.iter() {
+            let internal_item = match self.item_map.get(item) {
+                Some(x) => x,
+                None => continue,
+            };
+            let clone = match internal_item._type {
+                ItemType::Button => {
+                    let auto_repeat = internal_item.auto_repeat.map(|(_, interval)| interval);
+                    let hold_confirm = internal_item.hold_confirm.as_ref().map(|h| h.hold);
+                    let clone = self.clone_view_item(item);
+                    // The confirmation hold must be in place before the copy
+                    // can be touched, or its callback would run on a tap.
+                    if hold_confirm.is_some() {
+                        self.set_button_hold_to_confirm(&clone, hold_confirm);
+                    }
+                    if auto_repeat.is_some() {
+                        self.set_button_auto_repeat(&clone, auto_repeat);
+                    }
+                    clone
+                },
+                ItemType::Label | ItemType::MultilineLabel => self.clone_view_item(item),
+                ItemType::Spacer => {
+                    let space = RustTouchbarDelegateWrapper::spacer_type(item);
+                    self.create_spacer(space)
+                },
+                ItemType::Slider => self.clone_slider(item),
+                ItemType::Scrubber => {
+                    let data = internal_item.scrubber.clone().unwrap();
+                    #[allow(deprecated)]
+                    let clone = self.create_text_scrubber(data);
+                    if clone != ItemId::from_raw(0) {
+                        RustTouchbarDelegateWrapper::copy_scrubber_state(item, &clone);
+                    }
+                    clone
+                },
+                ItemType::Popover => {
+                    let (child_bar, hold_bar) = (internal_item.child_bar.unwrap(), internal_item.hold_bar);
+                    let child_bar = self.clone_bar(&child_bar);
+                    let hold_bar = hold_bar.map(|hold_bar| self.clone_bar(&hold_bar));
+                    self.clone_popover(item, child_bar, hold_bar)
+                },
+                _ => {
+                    let reason = format!("{} can't be cloned", internal_item);
+                    self.report_error(TouchbarError::UnsupportedItem(reason));
+                    continue;
+                },
+            };
+            if clone != ItemId::from_raw(0) {
+                clones.push(clone);
+            }
+        }
+        let bar = self.create_bar();
+        self.add_items_to_bar(&bar, clones);
+        bar
+    }
+    fn bar_items(&self, bar_id: &BarId) -> Vec<ItemId> {
+        match self.bar_map.get(bar_id) {
+            Some(x) => x.items.iter()
+                .filter(|item| self.check_item(item))
+                .map(|item| self.live_item(item.as_raw()))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+    fn parent_bar(&self, item: &ItemId) -> Option<BarId> {
+        if !self.check_item(item) {
+            return None;
+        }
+        self.bar_map.iter()
+            .find(|&(_, b)| b.items.contains(item))
+            .map(|(bar, _)| *bar)
+    }
+    fn popover_parent(&self, bar_id: &BarId) -> Option<ItemId> {
+        self.item_map.iter().find(|&(_, x)| {
+            x.child_bar == Some(*bar_id) || x.hold_bar == Some(*bar_id)
+        }).map(|(item, _)| *item)
+    }
+    fn popover_bar(&self, item: &ItemId) -> Option<BarId> {
+        if !self.check_item(item) {
+            return None;
+        }
+        self.item_map.get(item).and_then(|x| x.child_bar)
+    }
+    fn item_type(&self, item: &ItemId) -> Option<ItemKind> {
+        if !self.check_item(item) {
+            return None;
+        }
+        let kind = match self.item_map.get(item)?._type {
+            ItemType::Button => ItemKind::Button,
+            ItemType::Label => ItemKind::Label,
+            ItemType::Slider => ItemKind::Slider,
+            ItemType::Scrubber => ItemKind::Scrubber,
+            ItemType::Popover => ItemKind::Popover,
+            ItemType::Spacer => ItemKind::Spacer,
+            ItemType::Knob => ItemKind::Knob,
+            ItemType::DatePicker => ItemKind::DatePicker,
+            ItemType::TextField => ItemKind::TextField,
+            ItemType::Canvas => ItemKind::Canvas,
+            ItemType::Graph => ItemKind::Graph,
+            ItemType::Countdown => ItemKind::Countdown,
+            ItemType::MultilineLabel => ItemKind::MultilineLabel,
+            ItemType::Passthrough => ItemKind::Passthrough,
+            ItemType::Picker => ItemKind::Picker,
+            ItemType::Rating => ItemKind::Rating,
+            ItemType::MediaControls => ItemKind::MediaControls,
+            ItemType::Stacked => ItemKind::Stacked,
+            #[cfg(feature = "private_api")]
+            ItemType::NowPlaying => ItemKind::NowPlaying,
+        };
+        Some(kind)
+    }
+    fn set_item_name(&mut self, item: &ItemId, name: &str) {
+        if !self.check_item(item) {
+            return;
+        }
+        self.item_names.insert(name.to_string(), *item);
+    }
+    fn item_by_name(&self, name: &str) -> Option<ItemId> {
+        self.item_names.get(name).cloned()
+    }
+    fn item_name(&self, item: &ItemId) -> Option<String> {
+        if !self.check_item(item) {
+            return None;
+        }
+        self.item_names.iter().find(|&(_, i)| i == item).map(|(name, _)| name.clone())
+    }
+    fn item_width(&self, item: &ItemId) -> Option<u32> {
+        if !self.check_item(item) {
+            return None;
+        }
+        self.item_map.get(item).and_then(|x| x.width)
+    }
+    #[cfg(not(feature = "private_api"))]
+    fn set_bar_customization(&mut self, bar_id: &BarId, identifier: &str, allowed: Vec<ItemId>) {
+        let mut allowed: Vec<ItemId> = allowed.into_iter().filter(|item| self.check_item(item)).collect();
+        match self.bar_map.get(bar_id) {
+            Some(internal_bar) => {
+                for item in internal_bar.items.iter() {
+                    if !allowed.contains(item) {
+                        allowed.push(*item);
+                    }
+                }
+            },
+            None => return,
+        }
+        let previous = self.forget_customization(bar_id.as_raw() as *mut Object);
+        unsafe {
+            let bar = bar_id.as_raw() as *mut Object;
+            let cls = util::objc_class("NSMutableArray").unwrap();
+            let idents: *mut Object = msg_send![cls, alloc];
+            let idents: *mut Object = msg_send![idents, initWithCapacity: allowed.len()];
+            for ident in allowed.iter().filter_map(|item| self.find_ident(item)) {
+                let _:() = msg_send![idents, addObject: ident.as_raw() as *mut Object];
+            }
+            let _:() = msg_send![bar, setCustomizationAllowedItemIdentifiers: idents];
+            let _:() = msg_send![idents, release];
+            let name = NSString::alloc(nil).init_str(identifier);
+            let _:() = msg_send![bar, setCustomizationIdentifier: name];
+            let _:() = msg_send![name, release];
+        }
+        if let Some(internal_bar) = self.bar_map.get_mut(bar_id) {
+            internal_bar.customization = Some((identifier.to_string(), allowed));
+        }
+        for item in previous.iter() {
+            self.free_removed_item(item);
+        }
+        self.load_customization(bar_id);
+        unsafe {
+            let key = NSString::alloc(nil).init_str("itemIdentifiers");
+            let _:() = msg_send![bar_id.as_raw() as *mut Object,
+                                 addObserver: self.objc.clone()
+                                 forKeyPath: key
+                                 options: 1u64 // NSKeyValueObservingOptionNew
+                                 context: nil];
+            let _:() = msg_send![key, release];
+        }
+    }
+    #[cfg(not(feature = "private_api"))]
+    fn set_item_customization_label(&mut self, item: &ItemId, label: &str) {
+        if !self.check_item(item) {
+            return;
+        }
+        unsafe {
+            let label = NSString::alloc(nil).init_str(label);
+            let _:() = msg_send![item.as_raw() as *mut Object, setCustomizationLabel: label];
+            let _:() = msg_send![label, release];
+        }
+    }
+    #[cfg(not(feature = "private_api"))]
+    fn show_customization_palette(&mut self) {
+        unsafe {
+            let _:() = msg_send![NSApp(), toggleTouchBarCustomizationPalette: nil];
+        }
+    }
+    fn set_bar_interaction_enabled(&mut self, bar_id: &BarId, enabled: bool) {
+        let internal_bar = match self.bar_map.get_mut(bar_id) {
+            Some(x) => x,
+            None => return,
+        };
+        unsafe {
+            match (enabled, internal_bar.disabled.take()) {
+                (true, Some(disabled)) => {
+                    for (obj, was_enabled) in disabled {
+                        let was_enabled = if was_enabled { YES } else { NO };
+                        let _:() = msg_send![obj, setEnabled: was_enabled];
+                    }
+                },
+                (false, None) => {
+                    let mut disabled = Vec::new();
+                    for item in &internal_bar.items {
+                        let internal_item = match self.item_map.get(item) {
+                            Some(x) => x,
+                            None => continue,
+                        };
+                        let objs = internal_item.control.iter().cloned()
+                            .chain(internal_item.gestures.iter().map(|g| g.recognizer))
+                            .chain(internal_item.auto_repeat.iter().map(|&(r, _)| r))
+                            .chain(internal_item.hold_confirm.iter().map(|h| h.recognizer));
+                        for obj in objs {
+                            let responds: bool = msg_send![obj, respondsToSelector: sel!(setEnabled:)];
+                            if !responds {
+                                continue;
+                            }
+                            let was_enabled: bool = msg_send![obj, isEnabled];
+                            let _:() = msg_send![obj, setEnabled: NO];
+                            disabled.push((obj, was_enabled));
+                        }
+                    }
+                    internal_bar.disabled = Some(disabled);
+                },
+                // Already in the requested state
+                (_, disabled) => internal_bar.disabled = disabled,
+            }
+        }
+    }
+    fn set_principal_item(&mut self, bar_id: &BarId, item: Option<&ItemId>) {
+        if !self.bar_map.contains_key(bar_id) {
+            return;
+        }
+        if let Some(item) = item {
+            if !self.check_item(item) {
+                return;
+            }
+        }
+        let ident = match item {
+            Some(item) => match self.find_ident(item) {
+                Some(ident) => ident.as_raw() as *mut Object,
+                None => return,
+            },
+            None => nil,
+        };
+        unsafe {
+            let _:() = msg_send![bar_id.as_raw() as *mut Object, setPrincipalItemIdentifier: ident];
+        }
+    }
+    fn set_bar_as_root(&mut self, bar_id: BarId) {
+        let delegate = self.main_delegate();
+        self.set_root_bar(delegate, bar_id);
+    }
+    fn set_enabled(&mut self, enabled: bool) {
+        if enabled == self.enabled {
+            return;
+        }
+        self.enabled = enabled;
+        unsafe {
+            for delegate in self.delegates() {
+                match enabled {
+                    true => {
+                        // Nothing to show until a root bar is set
+                        let bar: *mut Object = msg_send![delegate, groupTouchBar];
+                        if bar != nil {
+                            let _ : () = msg_send![delegate, applicationDidFinishLaunching: 0];
+                        }
+                    },
+                    false => RustTouchbarDelegateWrapper::hide_root_bar(delegate),
+                }
+            }
+        }
+    }
+    #[cfg(feature = "private_api")]
+    fn set_control_strip_visible(&mut self, visible: bool) {
+        self.control_strip_visible = visible;
+        let presence = if visible { YES } else { NO };
+        for delegate in self.delegates() {
+            unsafe {
+                let tray_item: u64 = *(*delegate).get_ivar("_tray_item");
+                let ident: u64 = *(*delegate).get_ivar("_group_id");
+                if tray_item != 0 && ident != 0 {
+                    DFRElementSetControlStripPresenceForIdentifier(ident as *mut Object, presence);
+                }
+            }
+        }
+    }
+    fn shutdown(&mut self) {
+        let trays: Vec<TrayId> = self.delegates().into_iter().skip(1).map(|d| TrayId::from_raw(d as u64)).collect();
+        for tray in trays {
+            self.remove_control_strip_item(tray);
+        }
+        unsafe {
+            let delegate = self.main_delegate();
+            self.unregister_tray(delegate);
+        }
+        self.root_visibility_changed();
+    }
+    fn present(&mut self) {
+        unsafe {
+            let bar: *mut Object = msg_send![self.objc, groupTouchBar];
+            if bar != nil && self.enabled {
+                let _:() = msg_send![self.objc, present: 0u64];
+            }
+        }
+    }
+    fn minimize(&mut self) {
+        unsafe {
+            let bar: *mut Object = msg_send![self.objc, groupTouchBar];
+            if bar != nil {
+                RustTouchbarDelegateWrapper::minimize_bar(bar);
+            }
+        }
+    }
+    #[cfg(feature = "private_api")]
+    fn create_control_strip_item(&mut self, title: &str) -> TrayId {
+        let objc = ObjcAppDelegate::new().share();
+        unsafe {
+            let ptr = &**self as *const RustTouchbarDelegateWrapper as u64;
+            let _:() = msg_send![objc, setRustWrapper: ptr];
+            let objc_title = NSString::alloc(nil).init_str(title);
+            let _:() = msg_send![objc, setTitle: objc_title];
+        }
+        let tray = TrayId::from_raw(&*objc as *const ObjcAppDelegate as u64);
+        self.trays.push(objc);
+        tray
+    }
+    #[cfg(feature = "private_api")]
+    fn set_control_strip_item_bar(&mut self, tray: &TrayId, bar_id: BarId) {
+        if let Some(delegate) = self.find_tray(tray) {
+            self.set_root_bar(delegate, bar_id);
+        }
+    }
+    #[cfg(feature = "private_api")]
+    fn set_control_strip_item_icon(&mut self, tray: &TrayId, image: &str) -> Result<(), TouchbarError> {
+        let delegate = match self.find_tray(tray) {
+            Some(delegate) => delegate,
+            None => return Ok(()),
+        };
+        let result = self.load_icon(delegate, image);
+        match result {
+            Err(ref err) => error!("{}", err),
+            Ok(_) => unsafe {
+                // Rebuild the Control Strip button if it is already registered
+                let bar: *mut Object = msg_send![delegate, groupTouchBar];
+                if bar != nil && self.enabled {
+                    let _ : () = msg_send![delegate, applicationDidFinishLaunching: 0];
+                }
+            },
+        }
+        result
+    }
+    #[cfg(feature = "private_api")]
+    fn remove_control_strip_item(&mut self, tray: TrayId) {
+        let delegate = match self.find_tray(&tray) {
+            Some(delegate) => delegate,
+            None => return,
+        };
+        unsafe { self.unregister_tray(delegate); }
+        self.trays.retain(|t| TrayId::from_raw(&**t as *const ObjcAppDelegate as u64) != tray);
+    }
+    fn set_item_persistent(&mut self, item: &ItemId, persistent: bool) {
+        if !self.check_item(item) {
+            return;
+        }
+        if let Some(internal_item) = self.item_map.get_mut(item) {
+            internal_item.persistent = persistent;
+        }
+    }
+    fn set_item_focus_behavior(&mut self, item: &ItemId, behavior: FocusBehavior) {
+        if !self.check_item(item) {
+            return;
+        }
+        if let Some(internal_item) = self.item_map.get_mut(item) {
+            internal_item.focus_behavior = behavior;
+        }
+        if let Some(internal_item) = self.item_map.get(item) {
+            self.apply_focus_behavior(internal_item);
+        }
+    }
+    fn is_presented(&self) -> bool {
+        self.root_bar_visible()
+    }
+    fn on_visibility_changed(&mut self, cb: VisibilityCb) {
+        self.root_visible = self.root_bar_visible();
+        self.visibility_cb = Some(cb);
+    }
+    fn on_focus_mode_changed(&mut self, cb: FocusCb) {
+        self.focus_cb = Some(cb);
+    }
+    fn on_error(&mut self, cb: ErrorCb) {
+        self.error_cb = Some(cb);
+    }
+    fn build_incrementally(&mut self, step: BuildStepCb) {
+        let timer = self.schedule_timer(Duration::from_millis(BUILD_STEP_INTERVAL_MS));
+        self.build_steps.insert(timer as u64, (0, step));
+    }
+    fn create_label(&mut self, text: &str) -> ItemId {
+        unsafe {
+            let frame = NSRect::new(NSPoint::new(0., 0.), NSSize::new(0., 40.));
+            let cls = util::objc_class("NSTextField").unwrap();
+            let label: *mut Object = msg_send![cls, alloc];
+            let label: *mut Object = msg_send![label, initWithFrame: frame];
+            RustTouchbarDelegateWrapper::set_label_font_for_text(label, text);
+            let _:() = msg_send![label, setEditable: NO];
+            let cell: *mut Object = msg_send![label, cell];
+            let _:() = msg_send![cell, setWraps: NO];
+            let text = NSString::alloc(nil).init_str(text);
+            let _:() = msg_send![label, setStringValue: text];
+            let _:() = msg_send![text, release];
+
+            let ident = self.generate_ident();
+            let cls = RRCustomTouchBarItem::class();
+            let item: *mut Object = msg_send![cls, alloc];
+            let item: *mut Object = msg_send![item, initWithIdentifier: ident.as_raw()];
+            let _:() = msg_send![item, setView: label];
+
+            let internal = InternalItem::new(ItemType::Label, item, ident, Some(label));
+            self.insert_item(item, internal)
+        }
+    }
+    fn update_label(&mut self, label_id: &ItemId, text: &str) {
+        if !self.check_item(label_id) {
+            return;
+        }
+        unsafe {
+            let item: *mut Object = label_id.as_raw() as *mut Object;
+            let label: *mut Object = msg_send![item, view];
+            RustTouchbarDelegateWrapper::set_label_font_for_text(label, text);
+            if let Some(internal_item) = self.item_map.get_mut(label_id) {
+                if internal_item.marquee.is_some() {
+                    internal_item.marquee = Some((text.to_string(), 0));
+                }
+            }
+            let text = NSString::alloc(nil).init_str(text);
+            let _:() = msg_send![label, setStringValue: text];
+            let _:() = msg_send![text, release];
+        }
+    }
+    fn label_text(&self, label_id: &ItemId) -> String {
+        if !self.check_item(label_id) {
+            return String::new();
+        }
+        let internal_item = match self.item_map.get(label_id) {
+            Some(x) if x._type == ItemType::Label => x,
+            _ => return String::new(),
+        };
+        if let Some((ref text, _)) = internal_item.marquee {
+            return text.clone();
+        }
+        unsafe {
+            let text: *mut Object = msg_send![internal_item.control.unwrap(), stringValue];
+            util::nsstring_decode(text)
+        }
+    }
+    fn update_label_attributed(&mut self, label_id: &ItemId, spans: Vec<TextSpan>) {
+        if !self.check_item(label_id) {
+            return;
+        }
+        unsafe {
+            let item: *mut Object = label_id.as_raw() as *mut Object;
+            let label: *mut Object = msg_send![item, view];
+            let label_font: *mut Object = msg_send![label, font];
+            let label_size: f64 = msg_send![label_font, pointSize];
+            let cls = util::objc_class("NSMutableAttributedString").unwrap();
+            let string: *mut Object = msg_send![cls, alloc];
+            let string: *mut Object = msg_send![string, init];
+            for span in spans {
+                let cls = util::objc_class("NSMutableDictionary").unwrap();
+                let attrs: *mut Object = msg_send![cls, dictionary];
+                let cls = util::objc_class("NSFont").unwrap();
+                let size = span.font_size.unwrap_or(label_size);
+                let font: *mut Object = match span.bold {
+                    true => msg_send![cls, boldSystemFontOfSize: size],
+                    false => msg_send![cls, systemFontOfSize: size],
+                };
+                let _:() = msg_send![attrs, setObject: font forKey: NSFontAttributeName];
+                if let Some((r, g, b, alpha)) = span.color {
+                    let cls = util::objc_class("NSColor").unwrap();
+                    let color: *mut Object = msg_send![
+                        cls, colorWithRed: r green: g blue: b alpha: alpha];
+                    let _:() = msg_send![attrs, setObject: color
+                                         forKey: NSForegroundColorAttributeName];
+                }
+                let text = NSString::alloc(nil).init_str(&span.text);
+                let cls = util::objc_class("NSAttributedString").unwrap();
+                let part: *mut Object = msg_send![cls, alloc];
+                let part: *mut Object = msg_send![part, initWithString: text attributes: attrs];
+                let _:() = msg_send![string, appendAttributedString: part];
+                let _:() = msg_send![part, release];
+                let _:() = msg_send![text, release];
+            }
+            let _:() = msg_send![label, setAttributedStringValue: string];
+            let _:() = msg_send![string, release];
+        }
+    }
+    fn set_label_marquee(&mut self, label_id: &ItemId, enabled: bool) {
+        if !self.check_item(label_id) {
+            return;
+        }
+        let timer = match enabled {
+            true => Some(self.schedule_timer(Duration::from_millis(MARQUEE_INTERVAL_MS))),
+            false => None,
+        };
+        unsafe {
+            let internal_item = match self.item_map.get_mut(label_id) {
+                Some(x) if x._type == ItemType::Label => x,
+                _ => {
+                    if let Some(timer) = timer {
+                        RustTouchbarDelegateWrapper::cancel_timer(timer);
+                    }
+                    return;
+                },
+            };
+            if let Some(old_timer) = internal_item.timer.take() {
+                RustTouchbarDelegateWrapper::cancel_timer(old_timer);
+            }
+            internal_item.ticker = None;
+            let label = internal_item.control.unwrap();
+            let text: *mut Object = msg_send![label, stringValue];
+            let text = match internal_item.marquee.take() {
+                Some((text, _)) => text,
+                None => util::nsstring_decode(text),
+            };
+            // Restore the unscrolled text
+            let objc_text = NSString::alloc(nil).init_str(&text);
+            let _:() = msg_send![label, setStringValue: objc_text];
+            let _:() = msg_send![objc_text, release];
+            if enabled {
+                internal_item.marquee = Some((text, 0));
+                internal_item.timer = timer;
+            }
+        }
+    }
+    fn set_label_ticker(&mut self, label_id: &ItemId, texts: &[&str], interval: Duration) {
+        if !self.check_item(label_id) {
+            return;
+        }
+        match self.item_map.get(label_id) {
+            Some(x) if x._type == ItemType::Label => {},
+            _ => return,
+        }
+        // Stops any marquee or existing ticker
+        self.set_label_marquee(label_id, false);
+        if texts.is_empty() {
+            return;
+        }
+        let interval = cmp::max(interval, Duration::from_millis(TICKER_MIN_INTERVAL_MS));
+        let timer = self.schedule_timer(interval);
+        let internal_item = self.item_map.get_mut(label_id).unwrap();
+        let label = internal_item.control.unwrap();
+        unsafe {
+            let shown = NSString::alloc(nil).init_str(texts[0]);
+            let _:() = msg_send![label, setStringValue: shown];
+            let _:() = msg_send![shown, release];
+        }
+        internal_item.ticker = Some((texts.iter().map(|s| s.to_string()).collect(), 0));
+        internal_item.timer = Some(timer);
+    }
+    fn create_multiline_label(&mut self, lines: &[&str], style: LabelStyle) -> ItemId {
+        if !self.classes_available(&["NSStackView"]) {
+            return ItemId::from_raw(0);
+        }
+        unsafe {
+            let frame = NSRect::new(NSPoint::new(0., 0.), NSSize::new(0., BAR_HEIGHT as f64));
+            let cls = util::objc_class("NSView").unwrap();
+            let container: *mut Object = msg_send![cls, alloc];
+            let container: *mut Object = msg_send![container, initWithFrame: frame];
+
+            let cls = util::objc_class("NSStackView").unwrap();
+            let stack: *mut Object = msg_send![cls, alloc];
+            let stack: *mut Object = msg_send![stack, initWithFrame: frame];
+            let _:() = msg_send![stack, setOrientation: 1]; // NSUserInterfaceLayoutOrientationVertical
+            let _:() = msg_send![stack, setAlignment: 5]; // NSLayoutAttributeLeading
+            let _:() = msg_send![stack, setSpacing: 0.];
+            let _:() = msg_send![stack, setTranslatesAutoresizingMaskIntoConstraints: NO];
+
+            let font_cls = util::objc_class("NSFont").unwrap();
+            let default_size: f64 = msg_send![font_cls, systemFontSize];
+            let cls = util::objc_class("NSTextField").unwrap();
+            for (i, line) in lines.iter().enumerate() {
+                let size = match style.font_sizes.get(i).or(style.font_sizes.last()) {
+                    Some(size) => *size,
+                    None => default_size,
+                };
+                let label: *mut Object = msg_send![cls, alloc];
+                let label: *mut Object = msg_send![label, initWithFrame: frame];
+                let font: *mut Object = msg_send![font_cls, systemFontOfSize: size];
+                let _:() = msg_send![label, setFont: font];
+                let _:() = msg_send![label, setEditable: NO];
+                let cell: *mut Object = msg_send![label, cell];
+                let _:() = msg_send![cell, setWraps: NO];
+                let text = NSString::alloc(nil).init_str(line);
+                let _:() = msg_send![label, setStringValue: text];
+                let _:() = msg_send![text, release];
+                let _:() = msg_send![stack, addArrangedSubview: label];
+                let _:() = msg_send![label, release];
+            }
+            let _:() = msg_send![container, addSubview: stack];
+            let _:() = msg_send![stack, release];
+
+            // Pin the stack horizontally, and vertically as requested
+            let anchors: Vec<Sel> = match style.alignment {
+                VerticalAlignment::Top => vec![sel!(topAnchor)],
+                VerticalAlignment::Center => vec![sel!(centerYAnchor)],
+                VerticalAlignment::Bottom => vec![sel!(bottomAnchor)],
+            };
+            for anchor in anchors.iter().chain([sel!(leadingAnchor), sel!(trailingAnchor)].iter()) {
+                let inner: *mut Object = msg_send![stack, performSelector: *anchor];
+                let outer: *mut Object = msg_send![container, performSelector: *anchor];
+                let constraint: *mut Object = msg_send![inner, constraintEqualToAnchor: outer];
+                let _:() = msg_send![constraint, setActive: YES];
+            }
+            let inner: *mut Object = msg_send![stack, heightAnchor];
+            let outer: *mut Object = msg_send![container, heightAnchor];
+            let constraint: *mut Object = msg_send![inner, constraintLessThanOrEqualToAnchor: outer];
+            let _:() = msg_send![constraint, setActive: YES];
+
+            let ident = self.generate_ident();
+            let cls = RRCustomTouchBarItem::class();
+            let item: *mut Object = msg_send![cls, alloc];
+            let item: *mut Object = msg_send![item, initWithIdentifier: ident.as_raw()];
+            let _:() = msg_send![item, setView: container];
+
+            let internal = InternalItem::new(ItemType::MultilineLabel, item, ident, Some(container));
+            self.insert_item(item, internal)
+        }
+    }
+    fn update_multiline_label(&mut self, label_id: &ItemId, lines: &[&str]) {
+        if !self.check_item(label_id) {
+            return;
+        }
+        let container = match self.item_map.get(label_id) {
+            Some(x) if x._type == ItemType::MultilineLabel => x.control.unwrap(),
+            _ => return,
+        };
+        unsafe {
+            let subviews: *mut Object = msg_send![container, subviews];
+            let stack: *mut Object = msg_send![subviews, firstObject];
+            let labels: *mut Object = msg_send![stack, arrangedSubviews];
+            let count: u64 = msg_send![labels, count];
+            for i in 0..count {
+                let label: *mut Object = msg_send![labels, objectAtIndex: i];
+                let text = NSString::alloc(nil).init_str(lines.get(i as usize).unwrap_or(&""));
+                let _:() = msg_send![label, setStringValue: text];
+                let _:() = msg_send![text, release];
+            }
+        }
+    }
+    fn update_label_width(&mut self, label_id: &ItemId, width: u32) {
+        if !self.check_item(label_id) {
+            return;
+        }
+        unsafe {
+            //let _:() = msg_send![label, setAutoresizingMask: 0];
+            //let _:() = msg_send![label, setFrameSize: NSSize::new(600., 10.)];
+            //let _:() = msg_send![label, setBoundsSize: NSSize::new(600., 10.)];
+            //let _:() = msg_send![item, setFrameSize: NSSize::new(600., 30.)];
+            //let _:() = msg_send![label, setPreferredMaxLayoutWidth: 500.];
+            //let constraints: *mut Object = msg_send![label, constraints];
+            //let count: u32 = msg_send![constraints, count];
+            //info!("CONSTRAINTS: {}", count);
+            let item: *mut Object = label_id.as_raw() as *mut Object;
+            let label: *mut Object = msg_send![item, view];
+            let anchor: *mut Object = msg_send![label, widthAnchor];
+            let constraint: *mut Object = msg_send![anchor, constraintEqualToConstant: width as f64];
+            let _:() = msg_send![constraint, setActive: YES];
+        }
+        if let Some(internal_item) = self.item_map.get_mut(label_id) {
+            internal_item.width = Some(width);
+        }
+    }
+
+    fn create_countdown_label(&mut self, duration: Duration, cb: ButtonCb) -> ItemId {
+        let text = RustTouchbarDelegateWrapper::format_countdown(duration);
+        let item = self.create_label(&text);
+        let internal_item = self.item_map.get_mut(&item).unwrap();
+        internal_item._type = ItemType::Countdown;
+        internal_item.done_cb = Some(cb);
+        self.restart_countdown(&item, duration);
+        item
+    }
+    fn restart_countdown(&mut self, label_id: &ItemId, duration: Duration) {
+        if !self.check_item(label_id) {
+            return;
+        }
+        let timer = self.schedule_timer(Duration::from_secs(1));
+        match self.item_map.get_mut(label_id) {
+            Some(internal_item) if internal_item._type == ItemType::Countdown => {
+                if let Some(old_timer) = internal_item.timer.take() {
+                    unsafe { RustTouchbarDelegateWrapper::cancel_timer(old_timer); }
+                }
+                internal_item.timer = Some(timer);
+                internal_item.deadline = Some(Instant::now() + duration);
+            },
+            _ => {
+                unsafe { RustTouchbarDelegateWrapper::cancel_timer(timer); }
+                return;
+            },
+        }
+        self.countdown_tick(*label_id);
+    }
+
+    fn create_text_field(&mut self, placeholder: Option<&str>,
+                         continuous: bool, cb: TextCb) -> ItemId {
+        unsafe {
+            let frame = NSRect::new(NSPoint::new(0., 0.), NSSize::new(0., 30.));
+            let cls = util::objc_class("NSTextField").unwrap();
+            let field: *mut Object = msg_send![cls, alloc];
+            let field: *mut Object = msg_send![field, initWithFrame: frame];
+            let _:() = msg_send![field, setEditable: YES];
+            let _:() = msg_send![field, setSelectable: YES];
+            let _:() = msg_send![field, setAutomaticTextCompletionEnabled: YES];
+            if let Some(placeholder) = placeholder {
+                let objc_text = NSString::alloc(nil).init_str(placeholder);
+                let _:() = msg_send![field, setPlaceholderString: objc_text];
+                let _:() = msg_send![objc_text, release];
+            }
+            let cell: *mut Object = msg_send![field, cell];
+            let _:() = msg_send![cell, setWraps: NO];
+            let _:() = msg_send![cell, setScrollable: YES];
+            let _:() = msg_send![field, setContinuous: continuous];
+            let _:() = msg_send![field, setTarget: self.objc.clone()];
+            let _:() = msg_send![field, setAction: sel!(textField:)];
+
+            let ident = self.generate_ident();
+            let cls = RRCustomTouchBarItem::class();
+            let item: *mut Object = msg_send![cls, alloc];
+            let item: *mut Object = msg_send![item, initWithIdentifier: ident.as_raw()];
+            let _:() = msg_send![item, setView: field];
+
+            let mut internal = InternalItem::new(ItemType::TextField, item, ident, Some(field));
+            internal.text_cb = Some(cb);
+            self.insert_item(item, internal)
+        }
+    }
+    fn update_text_field(&mut self, id: &ItemId, text: &str) {
+        if !self.check_item(id) {
+            return;
+        }
+        unsafe {
+            let item: *mut Object = id.as_raw() as *mut Object;
+            let field: *mut Object = msg_send![item, view];
+            let text = NSString::alloc(nil).init_str(text);
+            let _:() = msg_send![field, setStringValue: text];
+            let _:() = msg_send![text, release];
+        }
+    }
+
+    fn create_canvas(&mut self, width: u32, cb: CanvasCb) -> ItemId {
+        unsafe {
+            let view = RustTouchbarDelegateWrapper::alloc_image_view(width);
+            let ident = self.generate_ident();
+            let cls = RRCustomTouchBarItem::class();
+            let item: *mut Object = msg_send![cls, alloc];
+            let item: *mut Object = msg_send![item, initWithIdentifier: ident.as_raw()];
+            let _:() = msg_send![item, setView: view];
+
+            let mut internal = InternalItem::new(ItemType::Canvas, item, ident, Some(view));
+            internal.canvas_cb = Some(cb);
+            internal.width = Some(width);
+            let item = self.insert_item(item, internal);
+            self.redraw_canvas(&item);
+            item
+        }
+    }
+    fn redraw_canvas(&mut self, canvas_id: &ItemId) {
+        if !self.check_item(canvas_id) {
+            return;
+        }
+        self.draw_canvas(canvas_id);
+    }
+
+    fn create_full_width_canvas(&mut self, cb: CanvasCb, resize_cb: ResizeCb) -> ItemId {
+        let cls = match full_width_view_class() {
+            Some(cls) => cls,
+            None => return ItemId::from_raw(0),
+        };
+        unsafe {
+            let frame = NSRect::new(NSPoint::new(0., 0.), NSSize::new(0., BAR_HEIGHT as f64));
+            let view: *mut Object = msg_send![cls, alloc];
+            let view: *mut Object = msg_send![view, initWithFrame: frame];
+            let ptr = &**self as *const RustTouchbarDelegateWrapper as u64;
+            (*view).set_ivar("_rust_wrapper", ptr);
+            let _:() = msg_send![view, setImageScaling: 1]; // NSImageScaleAxesIndependently
+            // Give way to every other item, but take any space left over
+            let _:() = msg_send![view, setContentHuggingPriority: 1f32 forOrientation: 0];
+            let _:() = msg_send![view, setContentCompressionResistancePriority: 1f32 forOrientation: 0];
+            let anchor: *mut Object = msg_send![view, widthAnchor];
+            let constraint: *mut Object = msg_send![anchor, constraintEqualToConstant: 10000.];
+            let _:() = msg_send![constraint, setPriority: 1f32];
+            let _:() = msg_send![constraint, setActive: YES];
+
+            let ident = self.generate_ident();
+            let cls = RRCustomTouchBarItem::class();
+            let item: *mut Object = msg_send![cls, alloc];
+            let item: *mut Object = msg_send![item, initWithIdentifier: ident.as_raw()];
+            let _:() = msg_send![item, setView: view];
+
+            let mut internal = InternalItem::new(ItemType::Canvas, item, ident, Some(view));
+            internal.canvas_cb = Some(cb);
+            internal.resize_cb = Some(resize_cb);
+            internal.width = Some(0);
+            self.insert_item(item, internal)
+        }
+    }
+    fn create_graph(&mut self, width: u32) -> ItemId {
+        unsafe {
+            let view = RustTouchbarDelegateWrapper::alloc_image_view(width);
+            let ident = self.generate_ident();
+            let cls = RRCustomTouchBarItem::class();
+            let item: *mut Object = msg_send![cls, alloc];
+            let item: *mut Object = msg_send![item, initWithIdentifier: ident.as_raw()];
+            let _:() = msg_send![item, setView: view];
+
+            let mut internal = InternalItem::new(ItemType::Graph, item, ident, Some(view));
+            internal.width = Some(width);
+            self.insert_item(item, internal)
+        }
+    }
+    fn update_graph(&mut self, graph_id: &ItemId, values: &[f64]) {
+        if !self.check_item(graph_id) {
+            return;
+        }
+        unsafe {
+            let internal_item = match self.item_map.get(graph_id) {
+                Some(x) if x._type == ItemType::Graph => x,
+                _ => return,
+            };
+            let width = internal_item.width.unwrap() * BAR_SCALE;
+            let height = BAR_HEIGHT * BAR_SCALE;
+            let pixels = RustTouchbarDelegateWrapper::render_sparkline(values, width, height);
+            let image = RustTouchbarDelegateWrapper::image_from_rgba(&pixels, width, height);
+            let view = internal_item.control.unwrap();
+            let _:() = msg_send![view, setImage: image];
+            let _:() = msg_send![image, release];
+        }
+    }
+
+    fn create_text_scrubber(&mut self, data: Rc<dyn TScrubberData>) -> ItemId {
+        if !self.classes_available(&["NSScrubber", "NSScrubberSelectionStyle", "NSScrubberTextItemView",
+                                     "NSScrubberImageItemView"]) {
+            return ItemId::from_raw(0);
+        }
+        unsafe {
+            let ident = self.generate_ident();
+            let cls = RRCustomTouchBarItem::class();
+            let item: *mut Object = msg_send![cls, alloc];
+            let item: *mut Object = msg_send![item, initWithIdentifier: ident.as_raw()];
+
+            // note: frame is ignored, but must be provided.
+            let frame = NSRect::new(NSPoint::new(0., 0.), NSSize::new(0., 30.));
+            let cls = RRScrubber::class();
+            let scrubber: *mut Object = msg_send![cls, alloc];
+            let scrubber: *mut Object = msg_send![scrubber, initWithFrame: frame];
+
+            let cls = util::objc_class("NSScrubberTextItemView").unwrap();
+            let _:() = msg_send![scrubber, registerClass: cls forItemIdentifier: ident.as_raw()];
+            let cls = util::objc_class("NSScrubberImageItemView").unwrap();
+            let image_ident = NSString::alloc(nil).init_str(SCRUBBER_IMAGE_IDENT);
+            let _:() = msg_send![scrubber, registerClass: cls forItemIdentifier: image_ident];
+            let _:() = msg_send![image_ident, release];
+            if let Some(cls) = scrubber_icon_view_class() {
+                let icon_ident = NSString::alloc(nil).init_str(SCRUBBER_ICON_IDENT);
+                let _:() = msg_send![scrubber, registerClass: cls forItemIdentifier: icon_ident];
+                let _:() = msg_send![icon_ident, release];
+            }
+            let _:() = msg_send![scrubber, setDelegate: self.objc.clone()];
+            let _:() = msg_send![scrubber, setDataSource: self.objc.clone()];
+            let (overlay, background) = ScrubberStyle::objc(DEFAULT_SCRUBBER_STYLE);
+            let _:() = msg_send![scrubber, setSelectionOverlayStyle: overlay];
+            let _:() = msg_send![scrubber, setSelectionBackgroundStyle: background];
+            let _:() = msg_send![scrubber, setMode: ScrubberMode::objc(DEFAULT_SCRUBBER_MODE)];
+            let _:() = msg_send![item, setView: scrubber];
+
+            let mut internal = InternalItem::new(ItemType::Scrubber, item, ident, Some(scrubber));
+            internal.scrubber = Some(data);
+            self.insert_item(item, internal)
+        }
+    }
+    fn scrubber_selected(&self, scrub_id: &ItemId) -> Option<u32> {
+        if !self.check_item(scrub_id) {
+            return None;
+        }
+        let internal_item = self.item_map.get(scrub_id).filter(|x| x._type == ItemType::Scrubber)?;
+        let index: i64 = unsafe { msg_send![internal_item.control.unwrap(), selectedIndex] };
+        match index >= 0 {
+            true => Some(index as u32),
+            false => None,
+        }
+    }
+    fn select_scrubber_item(&mut self, scrub_id: &ItemId, index: u32) {
+        if !self.check_item(scrub_id) {
+            return;
+        }
+        unsafe {
+            let item = scrub_id.as_raw() as *mut Object;
+            let scrubber: *mut Object = msg_send![item, view];
+            self.selecting_scrubber.set(Some(scrubber as u64));
+            let _:() = msg_send![scrubber, setSelectedIndex: index];
+            self.selecting_scrubber.set(None);
+        }
+    }
+    fn scroll_scrubber_to(&mut self, scrub_id: &ItemId, index: u32, animated: bool) {
+        if !self.check_item(scrub_id) {
+            return;
+        }
+        if !self.item_map.get(scrub_id).map_or(false, |x| x._type == ItemType::Scrubber) {
+            return;
+        }
+        unsafe {
+            let scrubber: *mut Object = msg_send![scrub_id.as_raw() as *mut Object, view];
+            let scrubber: *mut Object = match animated {
+                true => msg_send![scrubber, animator],
+                false => scrubber,
+            };
+            // NSScrubberAlignmentNone, which scrolls only as far as needed
+            let _:() = msg_send![scrubber, scrollItemAtIndex: index as i64 toAlignment: 0i64];
+        }
+    }
+    fn refresh_scrubber(&mut self, scrub_id: &ItemId) {
+        if !self.check_item(scrub_id) {
+            return;
+        }
+        self.reload_scrubber(scrub_id);
+    }
+    fn scrubber_insert_items(&mut self, scrub_id: &ItemId, index: u32, count: u32) {
+        if !self.check_item(scrub_id) {
+            return;
+        }
+        self.scrubber_batch_update(scrub_id, sel!(insertItemsAtIndexes:), index, count);
+    }
+    fn scrubber_remove_items(&mut self, scrub_id: &ItemId, index: u32, count: u32) {
+        if !self.check_item(scrub_id) {
+            return;
+        }
+        self.scrubber_batch_update(scrub_id, sel!(removeItemsAtIndexes:), index, count);
+    }
+    fn refresh_scrubbers_for_data(&mut self, data: &Rc<dyn TScrubberData>) {
+        let scrubbers: Vec<ItemId> = self.item_map.iter().filter(|&(_, x)| {
+            x.scrubber.as_ref().map_or(false, |d| Rc::ptr_eq(d, data))
+        }).map(|(id, _)| *id).collect();
+        for scrubber in scrubbers {
+            self.refresh_scrubber(&scrubber);
+        }
+    }
+    fn highlight_scrubber_item(&mut self, scrub_id: &ItemId, index: u32) {
+        if !self.check_item(scrub_id) {
+            return;
+        }
+        unsafe {
+            let item = scrub_id.as_raw() as *mut Object;
+            let scrubber: *mut Object = msg_send![item, view];
+            let _:() = msg_send![scrubber, setHighlightedIndex: index as i64];
+        }
+    }
+    fn clear_scrubber_highlight(&mut self, scrub_id: &ItemId) {
+        if !self.check_item(scrub_id) {
+            return;
+        }
+        unsafe {
+            let item = scrub_id.as_raw() as *mut Object;
+            let scrubber: *mut Object = msg_send![item, view];
+            let _:() = msg_send![scrubber, setHighlightedIndex: -1i64];
+        }
+    }
+    fn set_scrubber_mode(&mut self, scrub_id: &ItemId, mode: ScrubberMode) {
+        if !self.check_item(scrub_id) {
+            return;
+        }
+        unsafe {
+            let item = scrub_id.as_raw() as *mut Object;
+            let scrubber: *mut Object = msg_send![item, view];
+            let _:() = msg_send![scrubber, setMode: ScrubberMode::objc(mode)];
+        }
+    }
+    fn set_scrubber_continuous(&mut self, scrub_id: &ItemId, continuous: bool) {
+        if !self.check_item(scrub_id) {
+            return;
+        }
+        unsafe {
+            let item = scrub_id.as_raw() as *mut Object;
+            let scrubber: *mut Object = msg_send![item, view];
+            let continuous = if continuous { YES } else { NO };
+            let _:() = msg_send![scrubber, setContinuous: continuous];
+        }
+    }
+    fn set_scrubber_arrow_buttons(&mut self, scrub_id: &ItemId, shown: bool) {
+        if !self.check_item(scrub_id) {
+            return;
+        }
+        unsafe {
+            let item = scrub_id.as_raw() as *mut Object;
+            let scrubber: *mut Object = msg_send![item, view];
+            let shown = if shown { YES } else { NO };
+            let _:() = msg_send![scrubber, setShowsArrowButtons: shown];
+        }
+    }
+    fn set_scrubber_style(&mut self, scrub_id: &ItemId, style: ScrubberStyle) {
+        if !self.check_item(scrub_id) {
+            return;
+        }
+        unsafe {
+            let item = scrub_id.as_raw() as *mut Object;
+            let scrubber: *mut Object = msg_send![item, view];
+            let (overlay, background) = ScrubberStyle::objc(style);
+            let _:() = msg_send![scrubber, setSelectionOverlayStyle: overlay];
+            let _:() = msg_send![scrubber, setSelectionBackgroundStyle: background];
+        }
+    }
+
+    fn add_item_swipe_gesture(&mut self, item_id: &ItemId, cb: SwipeCb) {
+        if !self.check_item(item_id) {
+            return;
+        }
+        self.add_custom_gesture(item_id, GestureKind::Pan, GestureConfig::default(),
+                                Box::new(move |ev| cb(&ev.item, ev.state, ev.value)));
+    }
+
+    fn make_item_draggable_value(&mut self, item_id: &ItemId, min: f64, max: f64,
+                                 fill: Option<(f64, f64, f64, f64)>, cb: SliderCb) {
+        if !self.check_item(item_id) {
+            return;
+        }
+        // Also rejects NaN
+        if !(max > min) {
+            return;
+        }
+        let fill_layer = match fill {
+            Some((r, g, b, alpha)) => unsafe {
+                let item = item_id.as_raw() as *mut Object;
+                let view: *mut Object = msg_send![item, view];
+                let _:() = msg_send![view, setWantsLayer: YES];
+                let cls = util::objc_class("CALayer").unwrap();
+                let fill_layer: *mut Object = msg_send![cls, new];
+                let cls = util::objc_class("NSColor").unwrap();
+                let color: *mut Object = msg_send![
+                    cls, colorWithRed: r green: g blue: b alpha: alpha];
+                let cg_color: *mut Object = msg_send![color, CGColor];
+                let _:() = msg_send![fill_layer, setBackgroundColor: cg_color];
+                Some(DragFill(fill_layer))
+            },
+            None => None,
+        };
+        let value = Cell::new(min);
+        let start = Cell::new(min);
+        self.add_custom_gesture(item_id, GestureKind::Pan, GestureConfig::default(), Box::new(move |ev| {
+            let bounds: NSRect = unsafe { msg_send![ev.control.as_raw() as *mut Object, bounds] };
+            let width = match bounds.size.width > 0. {
+                true => bounds.size.width,
+                false => return,
+            };
+            match ev.state {
+                SwipeState::Began => start.set(value.get()),
+                SwipeState::Changed | SwipeState::Ended => {
+                    let new_value = (start.get() + ev.value / width * (max - min)).max(min).min(max);
+                    if new_value == value.get() {
+                        return;
+                    }
+                    value.set(new_value);
+                    if let Some(ref fill_layer) = fill_layer {
+                        let fraction = (new_value - min) / (max - min);
+                        unsafe { fill_layer.show(ev.control.as_raw() as *mut Object, fraction); }
+                    }
+                    cb(&ev.item, new_value);
+                },
+                _ => {},
+            }
+        }));
+    }
+
+    fn add_item_tap_gesture(&mut self, item_id: &ItemId, taps: u32,
+                            fingers: u32, cb: ButtonCb) {
+        if !self.check_item(item_id) {
+            return;
+        }
+        self.add_item_tap_gesture_with_event(item_id, taps, fingers,
+                                             Box::new(move |ev| cb(&ev.item)));
+    }
+
+    fn add_item_tap_gesture_with_event(&mut self, item_id: &ItemId, taps: u32,
+                                       fingers: u32, cb: EventCb) {
+        if !self.check_item(item_id) {
+            return;
+        }
+        let config = GestureConfig {
+            fingers: fingers,
+            clicks: taps,
+            ..GestureConfig::default()
+        };
+        self.add_custom_gesture(item_id, GestureKind::Click, config, Box::new(move |ev| {
+            cb(&ItemEvent { item: ev.item, control: ev.control })
+        }));
+    }
+
+    fn add_custom_gesture(&mut self, item_id: &ItemId, kind: GestureKind,
+                          config: GestureConfig, cb: GestureCb) {
+        if !self.check_item(item_id) {
+            return;
+        }
+        self.add_gesture_recognizer(item_id, kind, config, Rc::new(cb));
+    }
+
+    fn create_spacer(&mut self, space: SpacerType) -> ItemId {
+        unsafe {
+            let s = match space {
+                SpacerType::Small =>
+                    NSString::alloc(nil).init_str("NSTouchBarItemIdentifierFixedSpaceSmall"),
+                SpacerType::Large =>
+                    NSString::alloc(nil).init_str("NSTouchBarItemIdentifierFixedSpaceLarge"),
+                SpacerType::Flexible =>
+                    NSString::alloc(nil).init_str("NSTouchBarItemIdentifierFlexibleSpace"),
+            };
+            // This is really stupid, since it's just a string, but to fit into the
+            // rest of the system we go ahead and allocate a whole internal item for it.
+
+            // And since it doesn't have an ident, just use it as its own ident.  Both
+            // the view and ident will be sent a release at shutdown, so retain it an
+            // extra time here to keep the references balanced.
+            let _:() = msg_send![s, retain];
+
+            let internal = InternalItem::new(ItemType::Spacer, s, Ident::from_raw(s as u64), None);
+            self.insert_item(s, internal)
+        }
+    }
+
+    fn create_image_from_path(&mut self, path: &str) -> TouchbarImage {
+        unsafe {
+            let filename = NSString::alloc(nil).init_str(path);
+            let objc_image = NSImage::alloc(nil).initWithContentsOfFile_(filename);
+            let _:() = msg_send![filename, release];
+            if objc_image != nil {
+                return self.track_image(TouchbarImage::from_raw(objc_image as u64));
+            }
+            // Relative paths are the usual mistake, so report where we looked
+            let resolved = match env::current_dir() {
+                Ok(dir) => dir.join(path).to_string_lossy().into_owned(),
+                Err(_) => path.to_string(),
+            };
+            self.report_error(TouchbarError::ImageUnavailable(resolved));
+            match self.missing_image {
+                MissingImageBehavior::Placeholder => {
+                    let cls = util::objc_class("NSImage").unwrap();
+                    let name = NSString::alloc(nil).init_str("NSCaution");
+                    let image: *mut Object = msg_send![cls, imageNamed: name];
+                    let _:() = msg_send![name, release];
+                    let _:() = msg_send![image, retain];
+                    self.track_image(TouchbarImage::from_raw(image as u64))
+                },
+                MissingImageBehavior::Empty => TouchbarImage::from_raw(objc_image as u64),
+            }
+        }
+    }
+    fn set_missing_image_behavior(&mut self, behavior: MissingImageBehavior) {
+        self.missing_image = behavior;
+    }
+
+    fn create_image_from_url(&mut self, url: &str) -> TouchbarImage {
+        let path = RustTouchbarDelegateWrapper::image_cache_path(url);
+        if path.exists() {
+            return self.create_image_from_path(&path.to_string_lossy());
+        }
+        unsafe {
+            // Empty placeholder, filled in when the download completes
+            let cls = util::objc_class("NSImage").unwrap();
+            let image: *mut Object = msg_send![cls, alloc];
+            let size = NSSize::new(BAR_HEIGHT as f64, BAR_HEIGHT as f64);
+            let image: *mut Object = msg_send![image, initWithSize: size];
+            // Items release their image, so keep it alive for the download
+            let _:() = msg_send![image, retain];
+            let placeholder = image as u64;
+            // Keep the delegate alive until the main thread is notified, even
+            // if the controller is dropped during the download
+            let delegate = &*self.objc as *const ObjcAppDelegate as *mut Object;
+            let _:() = msg_send![delegate, retain];
+            let delegate = delegate as u64;
+            let loaded = Arc::downgrade(&self.loaded_images);
+            let url = url.to_string();
+            let tmp = path.with_extension(format!("{:x}.part", placeholder));
+            thread::spawn(move || {
+                let path = RustTouchbarDelegateWrapper::download_image(&url, tmp, path);
+                match loaded.upgrade() {
+                    Some(loaded) => {
+                        loaded.lock().unwrap().push((placeholder, url, path));
+                        let _:() = msg_send![delegate as *mut Object,
+                                             performSelectorOnMainThread: sel!(imagesLoaded:)
+                                             withObject: nil
+                                             waitUntilDone: NO];
+                    },
+                    // The controller is gone, and nothing else can show it
+                    None => {
+                        let _:() = msg_send![placeholder as *mut Object, release];
+                    },
+                }
+                let _:() = msg_send![delegate as *mut Object, release];
+            });
+            self.track_image(TouchbarImage::from_raw(image as u64))
+        }
+    }
+
+    fn create_image_from_template(&mut self, template: ImageTemplate) -> TouchbarImage {
+        unsafe {
+            let cls = util::objc_class("NSImage").unwrap();
+            let image: *mut Object = msg_send![cls, imageNamed: ImageTemplate::objc(template)];
+            let _:() = msg_send![image, retain];
+            self.track_image(TouchbarImage::from_raw(image as u64))
+        }
+    }
+
+    fn create_button(&mut self, image: Option<&TouchbarImage>, text: Option<&str>, cb: ButtonCb) -> ItemId {
+        self.create_button_with_event(image, text, Box::new(move |ev| cb(&ev.item)))
+    }
+
+    fn create_button_with_event(&mut self, image: Option<&TouchbarImage>,
+                                text: Option<&str>, cb: EventCb) -> ItemId {
+        unsafe {
+            let ident = self.generate_ident();
+            let target = (&*self.objc.clone()) as *const ObjcAppDelegate as *mut Object;
+            let btn = self.alloc_button(image, text,
+                                        target,
+                                        sel!(button:));
+            let cls = RRCustomTouchBarItem::class();
+            let item: *mut Object = msg_send![cls, alloc];
+            let item: *mut Object = msg_send![item, initWithIdentifier: ident.as_raw()];
+            let _:() = msg_send![item, setView: btn];
+
+            let mut internal = InternalItem::new(ItemType::Button, item, ident, Some(btn));
+            internal.button_cb = Some(Rc::new(cb));
+            self.insert_item(item, internal)
+        }
+    }
+
+    fn create_menu_row(&mut self, icon: Option<&TouchbarImage>, title: &str,
+                       detail: Option<&str>, chevron: bool, cb: ButtonCb) -> ItemId {
+        if !self.classes_available(&["NSStackView", "NSImageView"]) {
+            return ItemId::from_raw(0);
+        }
+        unsafe {
+            let ident = self.generate_ident();
+            let target = (&*self.objc.clone()) as *const ObjcAppDelegate as *mut Object;
+            let btn = self.alloc_button(None, Some(""), target, sel!(button:));
+
+            let cls = util::objc_class("NSStackView").unwrap();
+            let stack: *mut Object = msg_send![cls, alloc];
+            let stack: *mut Object = msg_send![stack, init];
+            let _:() = msg_send![stack, setOrientation: 0]; // NSUserInterfaceLayoutOrientationHorizontal
+            let _:() = msg_send![stack, setAlignment: 10]; // NSLayoutAttributeCenterY
+            let _:() = msg_send![stack, setSpacing: 6.];
+            let _:() = msg_send![stack, setTranslatesAutoresizingMaskIntoConstraints: NO];
+
+            let image_cls = util::objc_class("NSImageView").unwrap();
+            let label_cls = util::objc_class("NSTextField").unwrap();
+            if let Some(icon) = icon {
+                self.consume_image(icon);
+                let image_view: *mut Object = msg_send![image_cls, imageViewWithImage: icon.as_raw() as *mut Object];
+                let _:() = msg_send![stack, addArrangedSubview: image_view];
+            }
+            let text = NSString::alloc(nil).init_str(title);
+            let label: *mut Object = msg_send![label_cls, labelWithString: text];
+            let _:() = msg_send![text, release];
+            // Let the title stretch, pushing the detail and chevron right
+            let _:() = msg_send![label, setContentHuggingPriority: 1.0f32 forOrientation: 0];
+            let _:() = msg_send![stack, addArrangedSubview: label];
+            if let Some(detail) = detail {
+                let text = NSString::alloc(nil).init_str(detail);
+                let label: *mut Object = msg_send![label_cls, labelWithString: text];
+                let _:() = msg_send![text, release];
+                let cls = util::objc_class("NSColor").unwrap();
+                let color: *mut Object = msg_send![cls, secondaryLabelColor];
+                let _:() = msg_send![label, setTextColor: color];
+                let _:() = msg_send![stack, addArrangedSubview: label];
+            }
+            if chevron {
+                let name = ImageTemplate::objc(ImageTemplate::GoForwardTemplate);
+                let cls = util::objc_class("NSImage").unwrap();
+                let image: *mut Object = msg_send![cls, imageNamed: name];
+                let _:() = msg_send![name, release];
+                let image_view: *mut Object = msg_send![image_cls, imageViewWithImage: image];
+                let _:() = msg_send![stack, addArrangedSubview: image_view];
+            }
+            let _:() = msg_send![btn, addSubview: stack];
+            let _:() = msg_send![stack, release];
+
+            let pins = [(sel!(leadingAnchor), MENU_ROW_PADDING),
+                        (sel!(trailingAnchor), -MENU_ROW_PADDING),
+                        (sel!(centerYAnchor), 0.)];
+            for &(anchor, offset) in pins.iter() {
+                let inner: *mut Object = msg_send![stack, performSelector: anchor];
+                let outer: *mut Object = msg_send![btn, performSelector: anchor];
+                let constraint: *mut Object = msg_send![inner, constraintEqualToAnchor: outer
+                                                        constant: offset];
+                let _:() = msg_send![constraint, setActive: YES];
+            }
+            let width: *mut Object = msg_send![btn, widthAnchor];
+            let constraint: *mut Object = msg_send![width, constraintGreaterThanOrEqualToConstant: MENU_ROW_WIDTH];
+            let _:() = msg_send![constraint, setActive: YES];
+
+            let cls = RRCustomTouchBarItem::class();
+            let item: *mut Object = msg_send![cls, alloc];
+            let item: *mut Object = msg_send![item, initWithIdentifier: ident.as_raw()];
+            let _:() = msg_send![item, setView: btn];
+
+            let mut internal = InternalItem::new(ItemType::Button, item, ident, Some(btn));
+            internal.button_cb = Some(Rc::new(Box::new(move |ev| cb(&ev.item))));
+            self.insert_item(item, internal)
+        }
+    }
+
+    fn create_media_controls(&mut self, cb: MediaCb) -> ItemId {
+        if !self.classes_available(&["NSStackView"]) {
+            return ItemId::from_raw(0);
+        }
+        unsafe {
+            let ident = self.generate_ident();
+            let cls = util::objc_class("NSStackView").unwrap();
+            let stack: *mut Object = msg_send![cls, alloc];
+            let stack: *mut Object = msg_send![stack, init];
+            let _:() = msg_send![stack, setOrientation: 0]; // NSUserInterfaceLayoutOrientationHorizontal
+            let _:() = msg_send![stack, setSpacing: 2.];
+            let target = (&*self.objc.clone()) as *const ObjcAppDelegate as *mut Object;
+            let templates = vec![ImageTemplate::SkipBackTemplate,
+                                 ImageTemplate::PlayPauseTemplate,
+                                 ImageTemplate::SkipAheadTemplate];
+            for template in templates {
+                let image = self.create_image_from_template(template);
+                let btn = self.alloc_button(Some(&image), None, target, sel!(mediaControl:));
+                let _:() = msg_send![stack, addArrangedSubview: btn];
+                let _:() = msg_send![btn, release];
+            }
+            let cls = RRCustomTouchBarItem::class();
+            let item: *mut Object = msg_send![cls, alloc];
+            let item: *mut Object = msg_send![item, initWithIdentifier: ident.as_raw()];
+            let _:() = msg_send![item, setView: stack];
+
+            let mut internal = InternalItem::new(ItemType::MediaControls, item, ident, Some(stack));
+            internal.media_cb = Some(cb);
+            self.insert_item(item, internal)
+        }
+    }
+
+    fn update_media_playing(&mut self, id: &ItemId, playing: bool) {
+        if !self.check_item(id) {
+            return;
+        }
+        let stack = match self.item_map.get(id) {
+            Some(x) if x._type == ItemType::MediaControls => x.control.unwrap(),
+            _ => return,
+        };
+        let template = match playing {
+            true => ImageTemplate::PauseTemplate,
+            false => ImageTemplate::PlayTemplate,
+        };
+        unsafe {
+            let buttons: *mut Object = msg_send![stack, arrangedSubviews];
+            let btn: *mut Object = msg_send![buttons, objectAtIndex: 1u64];
+            let name = ImageTemplate::objc(template);
+            let cls = util::objc_class("NSImage").unwrap();
+            let image: *mut Object = msg_send![cls, imageNamed: name];
+            let _:() = msg_send![name, release];
+            let _:() = msg_send![btn, setImage: image];
+        }
+    }
+
+    fn create_stacked_item(&mut self, top: ItemSpec, bottom: ItemSpec) -> ItemId {
+        if !self.classes_available(&["NSStackView"]) {
+            return ItemId::from_raw(0);
+        }
+        unsafe {
+            let ident = self.generate_ident();
+            let frame = NSRect::new(NSPoint::new(0., 0.), NSSize::new(0., BAR_HEIGHT as f64));
+            let cls = util::objc_class("NSStackView").unwrap();
+            let stack: *mut Object = msg_send![cls, alloc];
+            let stack: *mut Object = msg_send![stack, initWithFrame: frame];
+            let _:() = msg_send![stack, setOrientation: 1]; // NSUserInterfaceLayoutOrientationVertical
+            let _:() = msg_send![stack, setAlignment: 5]; // NSLayoutAttributeLeading
+            let _:() = msg_send![stack, setDistribution: 1]; // NSStackViewDistributionFillEqually
+            let _:() = msg_send![stack, setSpacing: 0.];
+
+            let mut row_cbs = Vec::new();
+            for spec in vec![top, bottom] {
+                let (row, cb) = self.alloc_stacked_row(spec);
+                let anchor: *mut Object = msg_send![row, heightAnchor];
+                let constraint: *mut Object = msg_send![anchor, constraintEqualToConstant: STACKED_ROW_HEIGHT];
+                let _:() = msg_send![constraint, setActive: YES];
+                let _:() = msg_send![stack, addArrangedSubview: row];
+                if let Some(cb) = cb {
+                    row_cbs.push((row, cb));
+                }
+            }
+
+            let cls = RRCustomTouchBarItem::class();
+            let item: *mut Object = msg_send![cls, alloc];
+            let item: *mut Object = msg_send![item, initWithIdentifier: ident.as_raw()];
+            let _:() = msg_send![item, setView: stack];
+
+            let mut internal = InternalItem::new(ItemType::Stacked, item, ident, Some(stack));
+            internal.row_cbs = row_cbs;
+            self.insert_item(item, internal)
+        }
+    }
+
+    #[cfg(feature = "private_api")]
+    fn create_now_playing_item(&mut self, artwork: bool) -> ItemId {
+        if !self.classes_available(&["NSStackView", "NSImageView"]) {
+            return ItemId::from_raw(0);
+        }
+        unsafe {
+            let ident = self.generate_ident();
+            let cls = util::objc_class("NSStackView").unwrap();
+            let stack: *mut Object = msg_send![cls, alloc];
+            let stack: *mut Object = msg_send![stack, init];
+            let _:() = msg_send![stack, setOrientation: 0]; // NSUserInterfaceLayoutOrientationHorizontal
+            let _:() = msg_send![stack, setSpacing: 6.];
+            if artwork {
+                let cls = util::objc_class("NSImageView").unwrap();
+                let image_view: *mut Object = msg_send![cls, alloc];
+                let image_view: *mut Object = msg_send![image_view, init];
+                let _:() = msg_send![image_view, setImageScaling: 0]; // NSImageScaleProportionallyDown
+                for anchor in [sel!(widthAnchor), sel!(heightAnchor)].iter() {
+                    let anchor: *mut Object = msg_send![image_view, performSelector: *anchor];
+                    let constraint: *mut Object = msg_send![anchor, constraintEqualToConstant: BAR_HEIGHT as f64];
+                    let _:() = msg_send![constraint, setActive: YES];
+                }
+                let _:() = msg_send![stack, addArrangedSubview: image_view];
+                let _:() = msg_send![image_view, release];
+            }
+            let cls = util::objc_class("NSTextField").unwrap();
+            let empty = NSString::alloc(nil).init_str("");
+            let label: *mut Object = msg_send![cls, labelWithString: empty];
+            let _:() = msg_send![empty, release];
+            let cell: *mut Object = msg_send![label, cell];
+            let _:() = msg_send![cell, setLineBreakMode: 4]; // NSLineBreakByTruncatingTail
+            let _:() = msg_send![stack, addArrangedSubview: label];
+
+            let cls = RRCustomTouchBarItem::class();
+            let item: *mut Object = msg_send![cls, alloc];
+            let item: *mut Object = msg_send![item, initWithIdentifier: ident.as_raw()];
+            let _:() = msg_send![item, setView: stack];
+
+            let internal = InternalItem::new(ItemType::NowPlaying, item, ident, Some(stack));
+            let item = self.insert_item(item, internal);
+
+            if !self.now_playing_registered {
+                let queue = &_dispatch_main_q as *const Object as *mut Object;
+                MRMediaRemoteRegisterForNowPlayingNotifications(queue);
+                let cls = util::objc_class("NSNotificationCenter").unwrap();
+                let center: *mut Object = msg_send![cls, defaultCenter];
+                let name = NSString::alloc(nil).init_str("kMRMediaRemoteNowPlayingInfoDidChangeNotification");
+                let _:() = msg_send![center,
+                                     addObserver: self.objc.clone()
+                                     selector: sel!(nowPlayingChanged:)
+                                     name: name
+                                     object: nil];
+                let _:() = msg_send![name, release];
+                self.now_playing_registered = true;
+            }
+            self.request_now_playing();
+            item
+        }
+    }
+
+    fn create_passthrough_button(&mut self, image: Option<&TouchbarImage>, text: Option<&str>,
+                                 represent_after: Option<Duration>) -> ItemId {
+        unsafe {
+            let ident = self.generate_ident();
+            let target = (&*self.objc.clone()) as *const ObjcAppDelegate as *mut Object;
+            let btn = self.alloc_button(image, text,
+                                        target,
+                                        sel!(passthrough:));
+            let cls = RRCustomTouchBarItem::class();
+            let item: *mut Object = msg_send![cls, alloc];
+            let item: *mut Object = msg_send![item, initWithIdentifier: ident.as_raw()];
+            let _:() = msg_send![item, setView: btn];
+
+            let mut internal = InternalItem::new(ItemType::Passthrough, item, ident, Some(btn));
+            internal.represent_after = represent_after;
+            self.insert_item(item, internal)
+        }
+    }
+
+    fn button_text(&self, item: &ItemId) -> String {
+        if !self.check_item(item) {
+            return String::new();
+        }
+        let internal_item = match self.item_map.get(item) {
+            Some(x) if x._type == ItemType::Button || x._type == ItemType::Popover => x,
+            _ => return String::new(),
+        };
+        if let Some((ref title, _)) = internal_item.subtitle {
+            return title.clone();
+        }
+        unsafe {
+            let title: *mut Object = msg_send![internal_item.control.unwrap(), title];
+            util::nsstring_decode(title)
+        }
+    }
+    fn update_button(&mut self, item: &ItemId, image: Option<&TouchbarImage>, text: Option<&str>) {
+        if !self.check_item(item) {
+            return;
+        }
+        if let Some(image) = image {
+            self.consume_image(image);
+        }
+        let badge = self.item_map.get_mut(item).and_then(|x| x.badge.as_mut());
+        unsafe {
+            let item = item.as_raw() as *mut Object;
+            let btn: *mut Object = msg_send![item, view];
+            if let Some(image) = image {
+                let image = image.as_raw() as *mut Object;
+                match badge {
+                    Some(&mut (ref mut base, count)) => {
+                        // Keep the new image as the base, and show it badged
+                        let _:() = msg_send![*base, release];
+                        *base = image;
+                        let badged = RustTouchbarDelegateWrapper::badge_image(image, count);
+                        let _:() = msg_send![btn, setImage: badged];
+                        let _:() = msg_send![badged, release];
+                    },
+                    None => {
+                        let _:() = msg_send![btn, setImage: image];
+                        let _:() = msg_send![image, release];
+                    },
+                }
+            }
+            if let Some(text) = text {
+                match self.item_map.get_mut(&ItemId::from_raw(item as u64)).and_then(|x| x.subtitle.as_mut()) {
+                    Some(&mut (ref mut title, ref subtitle)) => {
+                        *title = text.to_string();
+                        RustTouchbarDelegateWrapper::set_two_line_title(btn, title, subtitle);
+                    },
+                    None => {
+                        let objc_text = NSString::alloc(nil).init_str(text);
+                        let _:() = msg_send![btn, setTitle: objc_text];
+                        let _:() = msg_send![objc_text, release];
+                    },
+                }
+            }
+        }
+    }
+
+    fn set_button_subtitle(&mut self, button_id: &ItemId, subtitle: Option<&str>) {
+        if !self.check_item(button_id) {
+            return;
+        }
+        let internal_item = match self.item_map.get_mut(button_id) {
+            Some(x) if x._type == ItemType::Button => x,
+            _ => return,
+        };
+        let btn = internal_item.control.unwrap();
+        unsafe {
+            let title = match internal_item.subtitle.take() {
+                Some((title, _)) => title,
+                None => {
+                    let title: *mut Object = msg_send![btn, title];
+                    util::nsstring_decode(title)
+                },
+            };
+            match subtitle {
+                Some(subtitle) => {
+                    RustTouchbarDelegateWrapper::set_two_line_title(btn, &title, subtitle);
+                    internal_item.subtitle = Some((title, subtitle.to_string()));
+                },
+                None => {
+                    let objc_text = NSString::alloc(nil).init_str(&title);
+                    let _:() = msg_send![btn, setTitle: objc_text];
+                    let _:() = msg_send![objc_text, release];
+                },
+            }
+        }
+    }
+
+    fn update_button_width(&mut self, button_id: &ItemId, width: u32) {
+        if !self.check_item(button_id) {
+            return;
+        }
+        unsafe {
+            let item: *mut Object = button_id.as_raw() as *mut Object;
+            let control: *mut Object = msg_send![item, view];
+            let anchor: *mut Object = msg_send![control, widthAnchor];
+            let constraint: *mut Object = msg_send![anchor, constraintEqualToConstant: width as f64];
+            let _:() = msg_send![constraint, setActive: YES];
+        }
+        if let Some(internal_item) = self.item_map.get_mut(button_id) {
+            internal_item.width = Some(width);
+        }
+    }
+
+    fn update_button_badge(&mut self, button_id: &ItemId, badge: Option<u32>) {
+        if !self.check_item(button_id) {
+            return;
+        }
+        let internal_item = match self.item_map.get_mut(button_id) {
+            Some(x) if x._type == ItemType::Button => x,
+            _ => return,
+        };
+        let btn = internal_item.control.unwrap();
+        unsafe {
+            let base = match (internal_item.badge.take(), badge) {
+                (Some((base, _)), None) => {
+                    let _:() = msg_send![btn, setImage: base];
+                    let _:() = msg_send![base, release];
+                    return;
+                },
+                (None, None) => return,
+                (Some((base, _)), Some(_)) => base,
+                (None, Some(_)) => {
+                    let image: *mut Object = msg_send![btn, image];
+                    if image == nil {
+                        return;
+                    }
+                    let _:() = msg_send![image, retain];
+                    image
+                },
+            };
+            let count = badge.unwrap();
+            let badged = RustTouchbarDelegateWrapper::badge_image(base, count);
+            let _:() = msg_send![btn, setImage: badged];
+            let _:() = msg_send![badged, release];
+            internal_item.badge = Some((base, count));
+        }
+    }
+
+    fn set_button_enabled(&mut self, button_id: &ItemId, enabled: bool) {
+        if !self.check_item(button_id) {
+            return;
+        }
+        let objs: Vec<*mut Object> = match self.item_map.get_mut(button_id) {
+            Some(x) if x._type == ItemType::Button => {
+                x.disabled = !enabled;
+                x.control.iter().cloned()
+                    .chain(x.auto_repeat.iter().map(|&(r, _)| r))
+                    .chain(x.hold_confirm.iter().map(|h| h.recognizer))
+                    .collect()
+            },
+            _ => return,
+        };
+        if !enabled {
+            // Stop any repeat or confirmation already in progress
+            self.reset_hold_confirm(*button_id);
+        }
+        // A bar with interaction disabled restores the new state when it is
+        // enabled again, rather than enabling the button itself.
+        let mut deferred = false;
+        for internal_bar in self.bar_map.values_mut().filter(|b| b.items.contains(button_id)) {
+            if let Some(ref mut disabled) = internal_bar.disabled {
+                for entry in disabled.iter_mut().filter(|&&mut (obj, _)| objs.contains(&obj)) {
+                    entry.1 = enabled;
+                    deferred = true;
+                }
+            }
+        }
+        if deferred && enabled {
+            return;
+        }
+        unsafe {
+            let enabled = if enabled { YES } else { NO };
+            for obj in objs {
+                let _:() = msg_send![obj, setEnabled: enabled];
+            }
+        }
+    }
+
+    fn set_button_latching(&mut self, button_id: &ItemId, latching: bool) {
+        if !self.check_item(button_id) {
+            return;
+        }
+        let btn = match self.item_map.get(button_id) {
+            Some(x) if x._type == ItemType::Button => x.control.unwrap(),
+            _ => return,
+        };
+        unsafe {
+            let button_type: u64 = match latching {
+                true => 1, // NSButtonTypePushOnPushOff
+                false => 7, // NSButtonTypeMomentaryPushIn
+            };
+            let _:() = msg_send![btn, setButtonType: button_type];
+        }
+    }
+
+    fn set_button_auto_repeat(&mut self, button_id: &ItemId, interval: Option<Duration>) {
+        if !self.check_item(button_id) {
+            return;
+        }
+        if !self.classes_available(&["NSPressGestureRecognizer"]) {
+            return;
+        }
+        let internal_item = match self.item_map.get_mut(button_id) {
+            Some(x) if x._type == ItemType::Button => x,
+            _ => return,
+        };
+        let btn = internal_item.control.unwrap();
+        unsafe {
+            match (internal_item.auto_repeat, interval) {
+                (Some((recognizer, _)), Some(interval)) => {
+                    internal_item.auto_repeat = Some((recognizer, interval));
+                },
+                (Some((recognizer, _)), None) => {
+                    let _:() = msg_send![btn, removeGestureRecognizer: recognizer];
+                    internal_item.auto_repeat = None;
+                    if let Some(timer) = internal_item.timer.take() {
+                        RustTouchbarDelegateWrapper::cancel_timer(timer);
+                    }
+                },
+                (None, Some(interval)) => {
+                    let cls = util::objc_class("NSPressGestureRecognizer").unwrap();
+                    let recognizer: *mut Object = msg_send![cls, alloc];
+                    let recognizer: *mut Object = msg_send![recognizer,
+                                                            initWithTarget: self.objc.clone()
+                                                            action: sel!(buttonHold:)];
+                    let delay = AUTO_REPEAT_DELAY_MS as f64 / 1000.;
+                    let _:() = msg_send![recognizer, setAllowedTouchTypes: 1]; // NSTouchTypeMaskDirect
+                    let _:() = msg_send![recognizer, setMinimumPressDuration: delay];
+                    let _:() = msg_send![btn, addGestureRecognizer: recognizer];
+                    let _:() = msg_send![recognizer, release];
+                    internal_item.auto_repeat = Some((recognizer, interval));
+                },
+                (None, None) => {},
+            }
+        }
+    }
+
+    fn set_button_hold_to_confirm(&mut self, button_id: &ItemId, hold: Option<Duration>) {
+        if !self.check_item(button_id) {
+            return;
+        }
+        if !self.classes_available(&["NSPressGestureRecognizer", "CALayer"]) {
+            return;
+        }
+        let internal_item = match self.item_map.get_mut(button_id) {
+            Some(x) if x._type == ItemType::Button => x,
+            _ => return,
+        };
+        let btn = internal_item.control.unwrap();
+        unsafe {
+            match (internal_item.hold_confirm.take(), hold) {
+                (Some(mut hold_confirm), Some(hold)) => {
+                    hold_confirm.hold = hold;
+                    internal_item.hold_confirm = Some(hold_confirm);
+                },
+                (Some(hold_confirm), None) => {
+                    let _:() = msg_send![btn, removeGestureRecognizer: hold_confirm.recognizer];
+                    let _:() = msg_send![hold_confirm.fill, removeFromSuperlayer];
+                    if let Some(timer) = internal_item.timer.take() {
+                        RustTouchbarDelegateWrapper::cancel_timer(timer);
+                    }
+                },
+                (None, Some(hold)) => {
+                    let cls = util::objc_class("NSPressGestureRecognizer").unwrap();
+                    let recognizer: *mut Object = msg_send![cls, alloc];
+                    let recognizer: *mut Object = msg_send![recognizer,
+                                                            initWithTarget: self.objc.clone()
+                                                            action: sel!(buttonConfirm:)];
+                    let _:() = msg_send![recognizer, setAllowedTouchTypes: 1]; // NSTouchTypeMaskDirect
+                    let _:() = msg_send![recognizer, setMinimumPressDuration: 0.];
+                    let _:() = msg_send![btn, addGestureRecognizer: recognizer];
+                    let _:() = msg_send![recognizer, release];
+
+                    let _:() = msg_send![btn, setWantsLayer: YES];
+                    let layer: *mut Object = msg_send![btn, layer];
+                    let cls = util::objc_class("CALayer").unwrap();
+                    let fill: *mut Object = msg_send![cls, layer];
+                    let cls = util::objc_class("NSColor").unwrap();
+                    let color: *mut Object = msg_send![cls, systemRedColor];
+                    let color: *mut Object = msg_send![color, colorWithAlphaComponent: 0.6];
+                    let cg_color: *mut Object = msg_send![color, CGColor];
+                    let _:() = msg_send![fill, setBackgroundColor: cg_color];
+                    let _:() = msg_send![layer, addSublayer: fill];
+                    internal_item.hold_confirm = Some(HoldConfirm {
+                        recognizer: recognizer,
+                        hold: hold,
+                        fill: fill,
+                        started: None,
+                    });
+                },
+                (None, None) => {},
+            }
+        }
+    }
+
+    fn set_button_latched(&mut self, button_id: &ItemId, latched: bool) {
+        if !self.check_item(button_id) {
+            return;
+        }
+        let btn = match self.item_map.get(button_id) {
+            Some(x) if x._type == ItemType::Button => x.control.unwrap(),
+            _ => return,
+        };
+        unsafe {
+            let state: i64 = if latched { 1 } else { 0 }; // NSControlStateValueOn/Off
+            let _:() = msg_send![btn, setState: state];
+        }
+    }
+
+    fn set_button_image_variants(&mut self, item: &ItemId, light: TouchbarImage,
+                                 dark: TouchbarImage) {
+        if !self.check_item(item) {
+            return;
+        }
+        self.consume_image(&light);
+        self.consume_image(&dark);
+        unsafe {
+            let internal_item = match self.item_map.get_mut(item) {
+                Some(x) if x._type == ItemType::Button || x._type == ItemType::Popover => x,
+                _ => return,
+            };
+            if let Some((old_light, old_dark)) = internal_item.image_variants {
+                let _:() = msg_send![old_light, release];
+                let _:() = msg_send![old_dark, release];
+            }
+            internal_item.image_variants = Some((light.as_raw() as *mut Object, dark.as_raw() as *mut Object));
+        }
+        self.apply_appearance();
+    }
+
+    fn set_image_template(&mut self, image: &TouchbarImage, template: bool) {
+        if !self.unused_images.borrow().contains(image) {
+            self.report_error(TouchbarError::StaleImage(*image));
+            return;
+        }
+        unsafe {
+            let image = image.as_raw() as *mut Object;
+            let _:() = msg_send![image, setTemplate: template];
+        }
+    }
+
+    fn create_slider(&mut self, min: f64, max: f64,
+                     label: Option<&str>,
+                     continuous: bool, cb: SliderCb) -> ItemId {
+        if !self.classes_available(&["NSSliderTouchBarItem"]) {
+            return ItemId::from_raw(0);
+        }
+        unsafe {
+            let ident = self.generate_ident();
+            let cls = RRSliderTouchBarItem::class();
+            let item: *mut Object = msg_send![cls, alloc];
+            let item: *mut Object = msg_send![item, initWithIdentifier: ident.as_raw()];
+            let slider: *mut Object = msg_send![item, slider];
+            if let Some(label) = label {
+                let objc_text: *mut Object = NSString::alloc(nil).init_str(label);
+                let _:() = msg_send![item, setLabel: objc_text];
+                let _:() = msg_send![objc_text, release];
+            }
+            let _:() = msg_send![slider, setMinValue: min];
+            let _:() = msg_send![slider, setMaxValue: max];
+            let _:() = msg_send![slider, setContinuous: continuous];
+            let _:() = msg_send![item, setTarget: self.objc.clone()];
+            let _:() = msg_send![item, setAction: sel!(slider:)];
+
+            let mut internal = InternalItem::new(ItemType::Slider, item, ident, Some(slider));
+            internal.slider_cb = Some(Rc::new(cb));
+            self.insert_item(item, internal)
+        }
+    }
+    #[cfg(feature = "system_controls")]
+    fn create_volume_slider(&mut self, label: Option<&str>) -> ItemId {
+        #[allow(deprecated)]
+        let slider = self.create_slider(0., 1., label, true, Box::new(|_, value| {
+            system::set_output_volume(value);
+        }));
+        if slider == ItemId::from_raw(0) {
+            return slider;
+        }
+        if let Some(volume) = system::output_volume() {
+            self.update_slider(&slider, volume);
+        }
+        self.item_map.get_mut(&slider).unwrap().system_control = Some(SystemControl::Volume);
+        if self.volume_device.is_none() {
+            let device = system::default_output_device();
+            let delegate = &*self.objc as *const ObjcAppDelegate as *mut Object;
+            unsafe { system::add_volume_listener(delegate, device); }
+            self.volume_device = Some(device);
+        }
+        slider
+    }
+    #[cfg(all(feature = "system_controls", feature = "private_api"))]
+    fn create_brightness_slider(&mut self, label: Option<&str>) -> ItemId {
+        #[allow(deprecated)]
+        let slider = self.create_slider(0., 1., label, true, Box::new(|_, value| {
+            system::set_display_brightness(value);
+        }));
+        if slider == ItemId::from_raw(0) {
+            return slider;
+        }
+        RustTouchbarDelegateWrapper::show_brightness(&slider);
+        let timer = self.schedule_timer(Duration::from_millis(BRIGHTNESS_POLL_INTERVAL_MS));
+        let internal_item = self.item_map.get_mut(&slider).unwrap();
+        internal_item.system_control = Some(SystemControl::Brightness);
+        internal_item.timer = Some(timer);
+        slider
+    }
+    fn update_slider(&mut self, id: &ItemId, value: f64) {
+        if !self.check_item(id) {
+            return;
+        }
+        RustTouchbarDelegateWrapper::set_slider_value(id, value);
+    }
+    fn slider_value(&self, id: &ItemId) -> f64 {
+        if !self.check_item(id) {
+            return 0.0;
+        }
+        match self.item_map.get(id) {
+            Some(x) if x._type == ItemType::Slider || x._type == ItemType::Knob => unsafe {
+                msg_send![x.control.unwrap(), doubleValue]
+            },
+            _ => 0.0,
+        }
+    }
+    fn update_slider_range(&mut self, id: &ItemId, min: f64, max: f64) {
+        if !self.check_item(id) {
+            return;
+        }
+        if !self.item_map.get(id).map_or(false, |x| x._type == ItemType::Slider) {
+            return;
+        }
+        unsafe {
+            let item = id.as_raw() as *mut Object;
+            let slider: *mut Object = msg_send![item, slider];
+            let value: f64 = msg_send![slider, doubleValue];
+            let _:() = msg_send![slider, setMinValue: min];
+            let _:() = msg_send![slider, setMaxValue: max];
+            let _:() = msg_send![slider, setDoubleValue: value.max(min).min(max)];
+        }
+    }
+    fn update_slider_label(&mut self, id: &ItemId, label: &str) {
+        if !self.check_item(id) {
+            return;
+        }
+        if !self.item_map.get(id).map_or(false, |x| x._type == ItemType::Slider) {
+            return;
+        }
+        unsafe {
+            let item = id.as_raw() as *mut Object;
+            let objc_text: *mut Object = NSString::alloc(nil).init_str(label);
+            let _:() = msg_send![item, setLabel: objc_text];
+            let _:() = msg_send![objc_text, release];
+        }
+    }
+
+    fn set_slider_steps(&mut self, id: &ItemId, tick_marks: u32, snap: bool, integer_only: bool) {
+        if !self.check_item(id) {
+            return;
+        }
+        match self.item_map.get_mut(id) {
+            Some(x) if x._type == ItemType::Slider => {
+                x.integer_only = integer_only;
+                x.last_value = None;
+            },
+            _ => return,
+        }
+        unsafe {
+            let item = id.as_raw() as *mut Object;
+            let slider: *mut Object = msg_send![item, slider];
+            let snap = if snap && tick_marks > 0 { YES } else { NO };
+            let _:() = msg_send![slider, setNumberOfTickMarks: tick_marks as i64];
+            let _:() = msg_send![slider, setAllowsTickMarkValuesOnly: snap];
+        }
+    }
+    fn set_slider_accessory(&mut self, id: &ItemId, side: SliderAccessory,
+                            image: &TouchbarImage, cb: Option<ButtonCb>) {
+        if !self.check_item(id) {
+            return;
+        }
+        if !self.classes_available(&["NSSliderAccessory", "NSSliderAccessoryBehavior"]) {
+            return;
+        }
+        self.consume_image(image);
+        let internal_item = match self.item_map.get_mut(id) {
+            Some(x) if x._type == ItemType::Slider => x,
+            _ => return,
+        };
+        unsafe {
+            let image = image.as_raw() as *mut Object;
+            let cls = util::objc_class("NSSliderAccessory").unwrap();
+            let accessory: *mut Object = msg_send![cls, accessoryWithImage: image];
+            let _:() = msg_send![image, release];
+            if cb.is_some() {
+                let cls = util::objc_class("NSSliderAccessoryBehavior").unwrap();
+                let behavior: *mut Object = msg_send![cls,
+                                                      behaviorWithTarget: self.objc.clone()
+                                                      action: sel!(sliderAccessory:)];
+                let _:() = msg_send![accessory, setBehavior: behavior];
+            }
+            let item = id.as_raw() as *mut Object;
+            let old: *mut Object = match side {
+                SliderAccessory::Min => msg_send![item, minimumValueAccessory],
+                SliderAccessory::Max => msg_send![item, maximumValueAccessory],
+            };
+            internal_item.accessory_cbs.retain(|&(obj, _)| obj != old);
+            match side {
+                SliderAccessory::Min => { let _:() = msg_send![item, setMinimumValueAccessory: accessory]; },
+                SliderAccessory::Max => { let _:() = msg_send![item, setMaximumValueAccessory: accessory]; },
+            }
+            if let Some(cb) = cb {
+                internal_item.accessory_cbs.push((accessory, cb));
+            }
+        }
+    }
+    fn create_knob(&mut self, min: f64, max: f64, cb: SliderCb) -> ItemId {
+        if !self.classes_available(&["NSSlider", "NSPanGestureRecognizer"]) {
+            return ItemId::from_raw(0);
+        }
+        unsafe {
+            let ident = self.generate_ident();
+            let frame = NSRect::new(NSPoint::new(0., 0.), NSSize::new(30., 30.));
+            let cls = util::objc_class("NSSlider").unwrap();
+            let knob: *mut Object = msg_send![cls, alloc];
+            let knob: *mut Object = msg_send![knob, initWithFrame: frame];
+            let _:() = msg_send![knob, setSliderType: 1]; // NSSliderTypeCircular
+            let _:() = msg_send![knob, setMinValue: min];
+            let _:() = msg_send![knob, setMaxValue: max];
+            let _:() = msg_send![knob, setDoubleValue: min];
+
+            // The knob is turned by a pan gesture rather than by the slider's
+            // own tracking, which doesn't support circular dragging on the bar.
+            let _:() = msg_send![knob, setAllowedTouchTypes: 1]; // NSTouchTypeMaskDirect
+            let cls = util::objc_class("NSPanGestureRecognizer").unwrap();
+            let gesture: *mut Object = msg_send![cls, alloc];
+            let gesture: *mut Object = msg_send![gesture,
+                                                 initWithTarget: self.objc.clone()
+                                                 action: sel!(knobGesture:)];
+            let _:() = msg_send![gesture, setAllowedTouchTypes: 1]; // NSTouchTypeMaskDirect
+            let _:() = msg_send![knob, addGestureRecognizer: gesture];
+            let _:() = msg_send![gesture, release];
+
+            let cls = RRCustomTouchBarItem::class();
+            let item: *mut Object = msg_send![cls, alloc];
+            let item: *mut Object = msg_send![item, initWithIdentifier: ident.as_raw()];
+            let _:() = msg_send![item, setView: knob];
 
-    fn create_image_from_template(&mut self, template: ImageTemplate) -> TouchbarImage {
-        unsafe {
-            let cls = Class::get("NSImage").unwrap();
-            let image: *mut Object = msg_send![cls, imageNamed: ImageTemplate::objc(template)];
-            let _:() = msg_send![image, retain];
-            image as TouchbarImage
+            let mut internal = InternalItem::new(ItemType::Knob, item, ident, Some(knob));
+            internal.slider_cb = Some(Rc::new(cb));
+            self.insert_item(item, internal)
         }
     }
-
-    fn create_button(&mut self, image: Option<&TouchbarImage>, text: Option<&str>, cb: ButtonCb) -> ItemId {
+    fn create_rating(&mut self, stars: u32, cb: RatingCb) -> ItemId {
+        if !self.classes_available(&["NSLevelIndicator", "NSClickGestureRecognizer",
+                                     "NSPanGestureRecognizer"]) {
+            return ItemId::from_raw(0);
+        }
         unsafe {
             let ident = self.generate_ident();
-            let target = (&*self.objc.clone()) as *const ObjcAppDelegate as *mut Object;
-            let btn = self.alloc_button(image, text,
-                                        target,
-                                        sel!(button:));
+            let width = stars as f64 * RATING_STAR_WIDTH;
+            let frame = NSRect::new(NSPoint::new(0., 0.), NSSize::new(width, BAR_HEIGHT as f64));
+            let cls = util::objc_class("NSLevelIndicator").unwrap();
+            let indicator: *mut Object = msg_send![cls, alloc];
+            let indicator: *mut Object = msg_send![indicator, initWithFrame: frame];
+            let _:() = msg_send![indicator, setLevelIndicatorStyle: 3u64]; // NSLevelIndicatorStyleRating
+            let _:() = msg_send![indicator, setMinValue: 0.0f64];
+            let _:() = msg_send![indicator, setMaxValue: stars as f64];
+            let _:() = msg_send![indicator, setIntegerValue: 0i64];
+            let anchor: *mut Object = msg_send![indicator, widthAnchor];
+            let constraint: *mut Object = msg_send![anchor, constraintEqualToConstant: width];
+            let _:() = msg_send![constraint, setActive: YES];
+
+            // Taps and drags are both handled by gestures, since the
+            // indicator's own editing doesn't track touches on the bar.
+            let _:() = msg_send![indicator, setAllowedTouchTypes: 1]; // NSTouchTypeMaskDirect
+            for name in &["NSClickGestureRecognizer", "NSPanGestureRecognizer"] {
+                let cls = util::objc_class(name).unwrap();
+                let gesture: *mut Object = msg_send![cls, alloc];
+                let gesture: *mut Object = msg_send![gesture,
+                                                     initWithTarget: self.objc.clone()
+                                                     action: sel!(ratingGesture:)];
+                let _:() = msg_send![gesture, setAllowedTouchTypes: 1]; // NSTouchTypeMaskDirect
+                let _:() = msg_send![indicator, addGestureRecognizer: gesture];
+                let _:() = msg_send![gesture, release];
+            }
+
             let cls = RRCustomTouchBarItem::class();
             let item: *mut Object = msg_send![cls, alloc];
-            let item: *mut Object = msg_send![item, initWithIdentifier: ident];
-            let _:() = msg_send![item, setView: btn];
+            let item: *mut Object = msg_send![item, initWithIdentifier: ident.as_raw()];
+            let _:() = msg_send![item, setView: indicator];
 
-            let internal = InternalItem {
-                _type: ItemType::Button,
-                view: item,
-                ident: ident,
-                control: Some(btn),
-                scrubber: None,
-                button_cb: Some(cb),
-                slider_cb: None,
-                swipe_cb: None,
-                tap_cb: None,
-                child_bar: None,
-            };
-            self.item_map.insert(item as u64, internal);
-            item as u64
+            let mut internal = InternalItem::new(ItemType::Rating, item, ident, Some(indicator));
+            internal.rating_cb = Some(cb);
+            self.insert_item(item, internal)
         }
     }
-
-    fn update_button(&mut self, item: &ItemId, image: Option<&TouchbarImage>, text: Option<&str>) {
+    fn update_rating(&mut self, id: &ItemId, rating: u32) {
+        if !self.check_item(id) {
+            return;
+        }
+        let indicator = match self.item_map.get(id) {
+            Some(x) if x._type == ItemType::Rating => x.control.unwrap(),
+            _ => return,
+        };
         unsafe {
-            let item = *item as *mut Object;
-            let btn: *mut Object = msg_send![item, view];
-            if let Some(image) = image {
-                let image = *image as *mut Object;
-                let _:() = msg_send![btn, setImage: image];
-                let _:() = msg_send![image, release];
-            }
-            if let Some(text) = text {
-                let objc_text = NSString::alloc(nil).init_str(text);
-                let _:() = msg_send![btn, setTitle: objc_text];
-                let _:() = msg_send![objc_text, release];
-            }
+            let max: f64 = msg_send![indicator, maxValue];
+            let rating = cmp::min(rating as i64, max as i64);
+            let _:() = msg_send![indicator, setIntegerValue: rating];
         }
     }
-
-    fn update_button_width(&mut self, button_id: &ItemId, width: u32) {
+    fn update_knob(&mut self, id: &ItemId, value: f64) {
+        if !self.check_item(id) {
+            return;
+        }
         unsafe {
-            let item: *mut Object = *button_id as *mut Object;
-            let control: *mut Object = msg_send![item, view];
-            let anchor: *mut Object = msg_send![control, widthAnchor];
-            let constraint: *mut Object = msg_send![anchor, constraintEqualToConstant: width as f64];
-            let _:() = msg_send![constraint, setActive: YES];
+            let item = id.as_raw() as *mut Object;
+            let knob: *mut Object = msg_send![item, view];
+            let _:() = msg_send![knob, setDoubleValue: value];
         }
     }
 
-    fn create_slider(&mut self, min: f64, max: f64,
-                     label: Option<&str>,
-                     continuous: bool, cb: SliderCb) -> ItemId {
+    fn create_date_picker(&mut self, elements: DatePickerElements,
+                          date: SystemTime, cb: DateCb) -> ItemId {
+        if !self.classes_available(&["NSDatePicker"]) {
+            return ItemId::from_raw(0);
+        }
         unsafe {
             let ident = self.generate_ident();
-            let cls = RRSliderTouchBarItem::class();
+            let frame = NSRect::new(NSPoint::new(0., 0.), NSSize::new(0., 30.));
+            let cls = util::objc_class("NSDatePicker").unwrap();
+            let picker: *mut Object = msg_send![cls, alloc];
+            let picker: *mut Object = msg_send![picker, initWithFrame: frame];
+            let _:() = msg_send![picker, setDatePickerStyle: 2]; // NSDatePickerStyleTextField
+            let _:() = msg_send![picker, setDatePickerElements:
+                                 DatePickerElements::objc(elements)];
+            let _:() = msg_send![picker, setBezeled: NO];
+            let _:() = msg_send![picker, setDrawsBackground: NO];
+            let objc_date = RustTouchbarDelegateWrapper::nsdate_from_system_time(date);
+            let _:() = msg_send![picker, setDateValue: objc_date];
+            let _:() = msg_send![picker, setTarget: self.objc.clone()];
+            let _:() = msg_send![picker, setAction: sel!(datePicker:)];
+
+            let cls = RRCustomTouchBarItem::class();
             let item: *mut Object = msg_send![cls, alloc];
-            let item: *mut Object = msg_send![item, initWithIdentifier: ident];
-            let slider: *mut Object = msg_send![item, slider];
-            if let Some(label) = label {
-                let objc_text: *mut Object = NSString::alloc(nil).init_str(label);
-                let _:() = msg_send![item, setLabel: objc_text];
-                let _:() = msg_send![objc_text, release];
-            }
-            let _:() = msg_send![slider, setMinValue: min];
-            let _:() = msg_send![slider, setMaxValue: max];
-            let _:() = msg_send![slider, setContinuous: continuous];
-            let _:() = msg_send![item, setTarget: self.objc.clone()];
-            let _:() = msg_send![item, setAction: sel!(slider:)];
+            let item: *mut Object = msg_send![item, initWithIdentifier: ident.as_raw()];
+            let _:() = msg_send![item, setView: picker];
 
-            let internal = InternalItem {
-                _type: ItemType::Slider,
-                view: item,
-                ident: ident,
-                control: Some(slider),
-                scrubber: None,
-                button_cb: None,
-                slider_cb: Some(cb),
-                swipe_cb: None,
-                tap_cb: None,
-                child_bar: None,
-            };
-            self.item_map.insert(item as u64, internal);
-            item as u64
+            let mut internal = InternalItem::new(ItemType::DatePicker, item, ident, Some(picker));
+            internal.date_cb = Some(cb);
+            self.insert_item(item, internal)
         }
     }
-    fn update_slider(&mut self, id: &ItemId, value: f64) {
+    fn update_date_picker(&mut self, id: &ItemId, date: SystemTime) {
+        if !self.check_item(id) {
+            return;
+        }
         unsafe {
-            let item = *id as *mut Object;
-            let slider: *mut Object = msg_send![item, slider];
-            let _:() = msg_send![slider, setDoubleValue: value];
+            let item = id.as_raw() as *mut Object;
+            let picker: *mut Object = msg_send![item, view];
+            let objc_date = RustTouchbarDelegateWrapper::nsdate_from_system_time(date);
+            let _:() = msg_send![picker, setDateValue: objc_date];
         }
     }
 }
@@ -1176,6 +6108,53 @@ impl ObjcAppDelegate {}
 unsafe impl Message for ObjcAppDelegate { }
 
 static OBJC_SUBCLASS_REGISTER_CLASS: Once = Once::new();
+static SCRUBBER_ICON_VIEW_REGISTER_CLASS: Once = Once::new();
+static FULL_WIDTH_VIEW_REGISTER_CLASS: Once = Once::new();
+
+/// Image view of a full-width canvas, which reports changes to its width
+fn full_width_view_class() -> Option<&'static Class> {
+    extern fn objc_set_frame_size(this: &mut Object, _cmd: Sel, size: NSSize) {
+        unsafe {
+            let superclass = util::objc_class("NSImageView").unwrap();
+            let _:() = msg_send![super(this, superclass), setFrameSize: size];
+            let ptr: u64 = *this.get_ivar("_rust_wrapper");
+            if ptr != 0 {
+                let wrapper = &mut *(ptr as *mut RustTouchbarDelegateWrapper);
+                wrapper.canvas_resized(this as *mut Object as u64, size.width);
+            }
+        }
+    }
+    FULL_WIDTH_VIEW_REGISTER_CLASS.call_once(|| {
+        if let Ok(superclass) = util::objc_class("NSImageView") {
+            if let Some(mut decl) = ClassDecl::new("RRFullWidthImageView", superclass) {
+                decl.add_ivar::<u64>("_rust_wrapper");
+                unsafe {
+                    let f: extern fn(&mut Object, Sel, NSSize) = objc_set_frame_size;
+                    decl.add_method(sel!(setFrameSize:), f);
+                }
+                decl.register();
+            }
+        }
+    });
+    util::objc_class("RRFullWidthImageView").ok()
+}
+
+/// Scrubber item view showing an icon followed by text
+///
+/// Gives the scrubber a distinct class to instantiate.  Its image view and
+/// text field are added in a stack view the first time each view is used,
+/// and the stack is kept in the `_stack` ivar.
+fn scrubber_icon_view_class() -> Option<&'static Class> {
+    SCRUBBER_ICON_VIEW_REGISTER_CLASS.call_once(|| {
+        if let Ok(superclass) = util::objc_class("NSScrubberItemView") {
+            if let Some(mut decl) = ClassDecl::new("RRScrubberIconTextItemView", superclass) {
+                decl.add_ivar::<u64>("_stack");
+                decl.register();
+            }
+        }
+    });
+    util::objc_class("RRScrubberIconTextItemView").ok()
+}
 
 impl INSObject for ObjcAppDelegate {
     fn class() -> &'static Class {
@@ -1231,16 +6210,48 @@ impl INSObject for ObjcAppDelegate {
                     let scrubber = scrub as *mut Object;
                     if let Some(ref item) = wrapper.find_scrubber(scrub) {
                         if let Some(ref cbs) = wrapper.find_scrubber_callbacks(scrub) {
-                            let ident = wrapper.find_ident_from_control(&scrub).unwrap() as
+                            if let Some(image) = cbs.image(*item, idx) {
+                                wrapper.consume_image(&image);
+                                let ident = NSString::alloc(nil).init_str(SCRUBBER_IMAGE_IDENT);
+                                let view: *mut Object = msg_send![scrubber,
+                                                                  makeItemWithIdentifier:ident
+                                                                  owner:nil];
+                                let _:() = msg_send![ident, release];
+                                let _:() = msg_send![view, setImage: image.as_raw() as *mut Object];
+                                return view as u64;
+                            }
+                            if let Some(icon) = cbs.icon(*item, idx) {
+                                wrapper.consume_image(&icon);
+                                let ident = NSString::alloc(nil).init_str(SCRUBBER_ICON_IDENT);
+                                let view: *mut Object = msg_send![scrubber,
+                                                                  makeItemWithIdentifier:ident
+                                                                  owner:nil];
+                                let _:() = msg_send![ident, release];
+                                if view != nil {
+                                    let text = cbs.text(*item, idx);
+                                    RustTouchbarDelegateWrapper::fill_icon_text_view(view, icon, &text);
+                                    return view as u64;
+                                }
+                            }
+                            let ident = wrapper.find_ident_from_control(&ItemId::from_raw(scrub)).unwrap().as_raw() as
                                 *mut Object;
                             let view: *mut Object = msg_send![scrubber,
                                                               makeItemWithIdentifier:ident
                                                               owner:nil];
                             let text = cbs.text(*item, idx);
                             let text_field: *mut Object = msg_send![view, textField];
-                            let objc_text: *mut Object = NSString::alloc(nil).init_str(&text);
-                            let _:() = msg_send![text_field, setStringValue: objc_text];
-                            let _:() = msg_send![objc_text, release];
+                            match cbs.decoration(*item, idx) {
+                                Some(template) => {
+                                    let string = RustTouchbarDelegateWrapper::decorated_text(template, &text);
+                                    let _:() = msg_send![text_field, setAttributedStringValue: string];
+                                    let _:() = msg_send![string, release];
+                                },
+                                None => {
+                                    let objc_text: *mut Object = NSString::alloc(nil).init_str(&text);
+                                    let _:() = msg_send![text_field, setStringValue: objc_text];
+                                    let _:() = msg_send![objc_text, release];
+                                },
+                            }
                             return view as u64;
                         }
                     }
@@ -1269,41 +6280,30 @@ impl INSObject for ObjcAppDelegate {
                     let wrapper = &mut *(ptr as *mut RustTouchbarDelegateWrapper);
                     if let Some(ref item) = wrapper.find_scrubber(scrub) {
                         if let Some(ref cbs) = wrapper.find_scrubber_callbacks(scrub) {
-                            cbs.touch(*item, idx);
+                            match wrapper.selecting_scrubber.get() == Some(scrub) {
+                                true => cbs.selected_programmatically(*item, idx),
+                                false => cbs.touch(*item, idx),
+                            }
                         }
                     }
                 }
             }
+            extern fn objc_scrubber_did_change_visible_range(this: &mut Object, _cmd: Sel,
+                                                             scrub: u64, range: NSRange) {
+                unsafe {
+                    let ptr: u64 = *this.get_ivar("_rust_wrapper");
+                    let wrapper = &mut *(ptr as *mut RustTouchbarDelegateWrapper);
+                    wrapper.scrubber_visible_range_changed(scrub, range);
+                }
+            }
             extern fn objc_popbar(this: &mut Object, _cmd: Sel, sender: u64) {
                 unsafe {
                     let ptr: u64 = *this.get_ivar("_rust_wrapper");
                     let wrapper = &mut *(ptr as *mut RustTouchbarDelegateWrapper);
 
-                    let item = wrapper.find_popover(sender) .unwrap_or(0) as *mut Object;
-                    let ident = wrapper.find_ident_from_control(&sender) .unwrap_or(0) as *mut Object;
-                    if item == nil || ident == nil {
-                        return;
+                    if let Some(item) = wrapper.find_popover(sender) {
+                        wrapper.open_popover(item);
                     }
-                    let bar: *mut Object = msg_send![item, popoverTouchBar];
-
-                    // Present the request popover.  This must be done instead of
-                    // using the popover's built-in showPopover because that pops
-                    // _under_ a system function bar.
-                    let cls = Class::get("NSTouchBar").unwrap();
-                    match util::get_appkit_version() >= AppKitVersion::from_tuple(10, 14) {
-                        true => {
-                            let _:() = msg_send![cls,
-                                                 presentSystemModalTouchBar: bar
-                                                 systemTrayItemIdentifier: ident];
-                        },
-                        false => {
-                            let _:() = msg_send![cls,
-                                                 presentSystemModalFunctionBar: bar
-                                                 systemTrayItemIdentifier: ident];
-                        },
-                    }
-                    let app = NSApp();
-                    let _:() = msg_send![app, setTouchBar: nil];
                 }
             }
             extern fn objc_button(this: &mut Object, _cmd: Sel, sender: u64) {
@@ -1312,33 +6312,115 @@ impl INSObject for ObjcAppDelegate {
                     let wrapper = &mut *(ptr as *mut RustTouchbarDelegateWrapper);
                     if let Some(ref cb) = wrapper.find_button_cb(sender) {
                         // Sender is the button.  Find the owning touchbar item:
-                        let item = wrapper.find_view_from_control(&sender).unwrap();
-                        cb(&(item as u64));
+                        let item = wrapper.find_view_from_control(&ItemId::from_raw(sender)).unwrap();
+                        cb(&ItemEvent { item: wrapper.live_item(item as u64), control: ControlId::from_raw(sender) });
                     }
                 }
             }
-            extern fn objc_tap_gesture(this: &mut Object, _cmd: Sel, sender: u64) {
+            extern fn objc_media_control(this: &mut Object, _cmd: Sel, sender: u64) {
                 unsafe {
                     let ptr: u64 = *this.get_ivar("_rust_wrapper");
                     let wrapper = &mut *(ptr as *mut RustTouchbarDelegateWrapper);
-                    let gesture = sender as *mut Object;
-                    let view: *mut Object = msg_send![gesture, view];
-                    if let Some(ref cb) = wrapper.find_tap_cb(view as u64) {
-                        // Sender is the view.  Find the owning touchbar item:
-                        let item = wrapper.find_view_from_control(&(view as u64)).unwrap();
-                        cb(&(item as u64));
+                    if let Some((item, event, cb)) = wrapper.find_media_control(sender) {
+                        cb(&item, event);
                     }
                 }
             }
-            extern fn objc_swipe_gesture(this: &mut Object, _cmd: Sel, sender: u64) {
+            extern fn objc_stacked_row(this: &mut Object, _cmd: Sel, sender: u64) {
                 unsafe {
                     let ptr: u64 = *this.get_ivar("_rust_wrapper");
                     let wrapper = &mut *(ptr as *mut RustTouchbarDelegateWrapper);
-                    let gesture = sender as *mut Object;
-                    let view: *mut Object = msg_send![gesture, view];
-                    let translation: NSPoint = msg_send![gesture,
-                                                         translationInView: view];
-                    let gesture_state: u32 = msg_send![gesture, state];
+                    match wrapper.find_row_cb(sender) {
+                        Some((item, &RowCb::Button(ref cb))) => cb(&item),
+                        Some((item, &RowCb::Slider(ref cb))) => {
+                            let value: f64 = msg_send![sender as *mut Object, doubleValue];
+                            cb(&item, value);
+                        },
+                        None => {},
+                    }
+                }
+            }
+            extern fn objc_button_hold(this: &mut Object, _cmd: Sel, sender: u64) {
+                unsafe {
+                    let ptr: u64 = *this.get_ivar("_rust_wrapper");
+                    let wrapper = &mut *(ptr as *mut RustTouchbarDelegateWrapper);
+                    wrapper.button_hold(sender);
+                }
+            }
+            extern fn objc_button_confirm(this: &mut Object, _cmd: Sel, sender: u64) {
+                unsafe {
+                    let ptr: u64 = *this.get_ivar("_rust_wrapper");
+                    let wrapper = &mut *(ptr as *mut RustTouchbarDelegateWrapper);
+                    wrapper.button_confirm(sender);
+                }
+            }
+            extern fn objc_picker(this: &mut Object, _cmd: Sel, sender: u64) {
+                unsafe {
+                    let ptr: u64 = *this.get_ivar("_rust_wrapper");
+                    let wrapper = &mut *(ptr as *mut RustTouchbarDelegateWrapper);
+                    if let Some((item, cb)) = wrapper.find_picker(sender) {
+                        // Sender is either the system picker item or the
+                        // fallback segmented control.
+                        let index: i64 = match item == ItemId::from_raw(sender) {
+                            true => msg_send![sender as *mut Object, selectedIndex],
+                            false => msg_send![sender as *mut Object, selectedSegment],
+                        };
+                        if index >= 0 {
+                            cb(&item, index as u32);
+                        }
+                    }
+                }
+            }
+            extern fn objc_images_loaded(this: &mut Object, _cmd: Sel, _sender: u64) {
+                unsafe {
+                    let ptr: u64 = *this.get_ivar("_rust_wrapper");
+                    // Cleared when the controller is dropped
+                    if ptr == 0 {
+                        return;
+                    }
+                    let wrapper = &mut *(ptr as *mut RustTouchbarDelegateWrapper);
+                    wrapper.images_loaded();
+                }
+            }
+            #[cfg(feature = "system_controls")]
+            extern fn objc_volume_changed(this: &mut Object, _cmd: Sel, _sender: u64) {
+                unsafe {
+                    let ptr: u64 = *this.get_ivar("_rust_wrapper");
+                    let wrapper = &mut *(ptr as *mut RustTouchbarDelegateWrapper);
+                    wrapper.volume_changed();
+                }
+            }
+            #[cfg(feature = "private_api")]
+            extern fn objc_now_playing_changed(this: &mut Object, _cmd: Sel, _notification: u64) {
+                unsafe {
+                    let ptr: u64 = *this.get_ivar("_rust_wrapper");
+                    let wrapper = &mut *(ptr as *mut RustTouchbarDelegateWrapper);
+                    wrapper.request_now_playing();
+                }
+            }
+            extern fn objc_slider_accessory(this: &mut Object, _cmd: Sel, sender: u64) {
+                unsafe {
+                    let ptr: u64 = *this.get_ivar("_rust_wrapper");
+                    let wrapper = &mut *(ptr as *mut RustTouchbarDelegateWrapper);
+                    if let Some((item, cb)) = wrapper.find_accessory_cb(sender) {
+                        cb(&item);
+                    }
+                }
+            }
+            extern fn objc_passthrough(this: &mut Object, _cmd: Sel, sender: u64) {
+                unsafe {
+                    let ptr: u64 = *this.get_ivar("_rust_wrapper");
+                    let wrapper = &mut *(ptr as *mut RustTouchbarDelegateWrapper);
+                    wrapper.passthrough(sender);
+                }
+            }
+            extern fn objc_gesture(this: &mut Object, _cmd: Sel, sender: u64) {
+                unsafe {
+                    let ptr: u64 = *this.get_ivar("_rust_wrapper");
+                    let wrapper = &mut *(ptr as *mut RustTouchbarDelegateWrapper);
+                    let recognizer = sender as *mut Object;
+                    let view: *mut Object = msg_send![recognizer, view];
+                    let gesture_state: u32 = msg_send![recognizer, state];
                     let state = match gesture_state {
                         // NSGestureRecognizerStateBegan
                         1 => SwipeState::Began,
@@ -1353,12 +6435,176 @@ impl INSObject for ObjcAppDelegate {
                         // NSGestureRecognizerStatePossible,
                         _ => SwipeState::Unknown,
                     };
-                    if state != SwipeState::Unknown {
-                        if let Some(ref cb) = wrapper.find_swipe_cb(view as u64) {
-                            // Sender is the view.  Find the owning touchbar item:
-                            let item = wrapper.find_view_from_control(&(view as u64)).unwrap();
-                            cb(&(item as u64), state, translation.x);
+                    if state == SwipeState::Unknown {
+                        return;
+                    }
+                    if let Some((item, gesture)) = wrapper.find_gesture(sender) {
+                        let value: f64 = match gesture.kind {
+                            GestureKind::Pan => {
+                                let translation: NSPoint = msg_send![recognizer,
+                                                                     translationInView: view];
+                                translation.x
+                            },
+                            GestureKind::Magnify => msg_send![recognizer, magnification],
+                            GestureKind::Rotate => msg_send![recognizer, rotation],
+                            GestureKind::Click | GestureKind::Press => 0.,
+                        };
+                        if state == SwipeState::Began {
+                            gesture.last_value.set(0.);
                         }
+                        let delta = value - gesture.last_value.get();
+                        gesture.last_value.set(value);
+                        (gesture.cb)(&GestureEvent {
+                            item: item,
+                            control: ControlId::from_raw(view as u64),
+                            kind: gesture.kind,
+                            state: state,
+                            value: value,
+                            delta: delta,
+                        });
+                    }
+                }
+            }
+            extern fn objc_date_picker(this: &mut Object, _cmd: Sel, sender: u64) {
+                unsafe {
+                    let ptr: u64 = *this.get_ivar("_rust_wrapper");
+                    let wrapper = &mut *(ptr as *mut RustTouchbarDelegateWrapper);
+                    if let Some(ref cb) = wrapper.find_date_cb(sender) {
+                        // Sender is the picker.  Find the owning touchbar item:
+                        let item = wrapper.find_view_from_control(&ItemId::from_raw(sender)).unwrap();
+                        let picker = sender as *mut Object;
+                        let date: *mut Object = msg_send![picker, dateValue];
+                        cb(&wrapper.live_item(item as u64),
+                           RustTouchbarDelegateWrapper::system_time_from_nsdate(date));
+                    }
+                }
+            }
+            extern fn objc_text_field(this: &mut Object, _cmd: Sel, sender: u64) {
+                unsafe {
+                    let ptr: u64 = *this.get_ivar("_rust_wrapper");
+                    let wrapper = &mut *(ptr as *mut RustTouchbarDelegateWrapper);
+                    if let Some(ref cb) = wrapper.find_text_cb(sender) {
+                        // Sender is the text field.  Find the owning touchbar item:
+                        let item = wrapper.find_view_from_control(&ItemId::from_raw(sender)).unwrap();
+                        let field = sender as *mut Object;
+                        let text: *mut Object = msg_send![field, stringValue];
+                        cb(&wrapper.live_item(item as u64), &util::nsstring_decode(text));
+                    }
+                }
+            }
+            extern fn objc_release_dropped(this: &mut Object, _cmd: Sel, _sender: u64) {
+                unsafe {
+                    let ptr: u64 = *this.get_ivar("_rust_wrapper");
+                    let wrapper = &mut *(ptr as *mut RustTouchbarDelegateWrapper);
+                    wrapper.release_dropped();
+                }
+            }
+            extern fn objc_focus_mode_changed(this: &mut Object, _cmd: Sel, _notification: u64) {
+                unsafe {
+                    let ptr: u64 = *this.get_ivar("_rust_wrapper");
+                    let wrapper = &mut *(ptr as *mut RustTouchbarDelegateWrapper);
+                    let active = util::focus_mode_active();
+                    if active == wrapper.focus_active {
+                        return;
+                    }
+                    wrapper.focus_active = active;
+                    for item in wrapper.item_map.values().filter(|x| {
+                        x.focus_behavior != FocusBehavior::Normal
+                    }) {
+                        wrapper.apply_focus_behavior(item);
+                    }
+                    if let Some(ref cb) = wrapper.focus_cb {
+                        cb(active);
+                    }
+                }
+            }
+            extern fn objc_observe_value(this: &mut Object, _cmd: Sel, key_path: u64,
+                                         object: u64, _change: u64, _context: u64) {
+                unsafe {
+                    // The root bar's visibility, and the items of
+                    // customizable bars, are observed.
+                    let ptr: u64 = *this.get_ivar("_rust_wrapper");
+                    let wrapper = &mut *(ptr as *mut RustTouchbarDelegateWrapper);
+                    match util::nsstring_decode(key_path as *mut Object).as_str() {
+                        "itemIdentifiers" => wrapper.bar_customized(object),
+                        _ => wrapper.root_visibility_changed(),
+                    }
+                }
+            }
+            extern fn objc_appearance_changed(this: &mut Object, _cmd: Sel, _notification: u64) {
+                unsafe {
+                    let ptr: u64 = *this.get_ivar("_rust_wrapper");
+                    let wrapper = &mut *(ptr as *mut RustTouchbarDelegateWrapper);
+                    wrapper.apply_appearance();
+                }
+            }
+            extern fn objc_timer(this: &mut Object, _cmd: Sel, sender: u64) {
+                unsafe {
+                    let ptr: u64 = *this.get_ivar("_rust_wrapper");
+                    let wrapper = &mut *(ptr as *mut RustTouchbarDelegateWrapper);
+                    match wrapper.find_timer_item(sender) {
+                        Some(item) => wrapper.timer_fired(item),
+                        None => wrapper.build_step(sender),
+                    }
+                }
+            }
+            extern fn objc_knob_gesture(this: &mut Object, _cmd: Sel, sender: u64) {
+                unsafe {
+                    let ptr: u64 = *this.get_ivar("_rust_wrapper");
+                    let wrapper = &mut *(ptr as *mut RustTouchbarDelegateWrapper);
+                    let gesture = sender as *mut Object;
+                    let knob: *mut Object = msg_send![gesture, view];
+                    let item = match wrapper.find_view_from_control(&ItemId::from_raw(knob as u64)) {
+                        Some(item) => item,
+                        None => return,
+                    };
+                    // Consume the translation on every event so each callback
+                    // only sees the distance moved since the last one.
+                    let translation: NSPoint = msg_send![gesture,
+                                                         translationInView: knob];
+                    let _:() = msg_send![gesture,
+                                         setTranslation: NSPoint::new(0., 0.)
+                                         inView: knob];
+                    let min: f64 = msg_send![knob, minValue];
+                    let max: f64 = msg_send![knob, maxValue];
+                    let old_value: f64 = msg_send![knob, doubleValue];
+                    let delta = translation.x * (max - min) / KNOB_DRAG_RANGE;
+                    let value = (old_value + delta).max(min).min(max);
+                    if value == old_value {
+                        return;
+                    }
+                    let _:() = msg_send![knob, setDoubleValue: value];
+                    if let Some(ref cb) = wrapper.find_slider_cb(item as u64) {
+                        cb(&wrapper.live_item(item as u64), value);
+                    }
+                }
+            }
+            extern fn objc_rating_gesture(this: &mut Object, _cmd: Sel, sender: u64) {
+                unsafe {
+                    let ptr: u64 = *this.get_ivar("_rust_wrapper");
+                    let wrapper = &mut *(ptr as *mut RustTouchbarDelegateWrapper);
+                    let gesture = sender as *mut Object;
+                    let indicator: *mut Object = msg_send![gesture, view];
+                    let item = match wrapper.find_view_from_control(&ItemId::from_raw(indicator as u64)) {
+                        Some(item) => wrapper.live_item(item as u64),
+                        None => return,
+                    };
+                    // The star under the finger, and every star before it,
+                    // are filled.
+                    let location: NSPoint = msg_send![gesture, locationInView: indicator];
+                    let bounds: NSRect = msg_send![indicator, bounds];
+                    let max: f64 = msg_send![indicator, maxValue];
+                    if bounds.size.width <= 0. {
+                        return;
+                    }
+                    let rating = (location.x / bounds.size.width * max).ceil().max(0.).min(max) as i64;
+                    let old_rating: i64 = msg_send![indicator, integerValue];
+                    if rating == old_rating {
+                        return;
+                    }
+                    let _:() = msg_send![indicator, setIntegerValue: rating];
+                    if let Some(ref cb) = wrapper.item_map.get(&item).and_then(|x| x.rating_cb.as_ref()) {
+                        cb(&item, rating as u32);
                     }
                 }
             }
@@ -1366,11 +6612,13 @@ impl INSObject for ObjcAppDelegate {
                 unsafe {
                     let ptr: u64 = *this.get_ivar("_rust_wrapper");
                     let wrapper = &mut *(ptr as *mut RustTouchbarDelegateWrapper);
-                    if let Some(ref cb) = wrapper.find_slider_cb(sender) {
-                        let item = sender as *mut Object;
-                        let slider: *mut Object = msg_send![item, slider];
-                        let value: f64 = msg_send![slider, doubleValue];
-                        cb(&sender, value);
+                    let item = sender as *mut Object;
+                    let slider: *mut Object = msg_send![item, slider];
+                    let value: f64 = msg_send![slider, doubleValue];
+                    if let Some(value) = wrapper.filter_slider_value(sender, value) {
+                        if let Some(ref cb) = wrapper.find_slider_cb(sender) {
+                            cb(&wrapper.live_item(sender), value);
+                        }
                     }
                 }
             }
@@ -1380,19 +6628,7 @@ impl INSObject for ObjcAppDelegate {
                     let bar_int: u64 = *this.get_ivar("_group_bar");
                     let ident = ident_int as *mut Object;
                     let bar = bar_int as *mut Object;
-                    let cls = Class::get("NSTouchBar").unwrap();
-                    match util::get_appkit_version() >= AppKitVersion::from_tuple(10, 14) {
-                        true => {
-                            let _:() = msg_send![cls,
-                                                 presentSystemModalTouchBar: bar
-                                                 systemTrayItemIdentifier: ident];
-                        },
-                        false => {
-                            let _:() = msg_send![cls,
-                                                 presentSystemModalFunctionBar: bar
-                                                 systemTrayItemIdentifier: ident];
-                        },
-                    }
+                    RustTouchbarDelegateWrapper::present_bar(bar, ident);
                 }
             }
             extern fn objc_touch_bar_make_item_for_identifier(this: &mut Object, _cmd: Sel,
@@ -1403,12 +6639,15 @@ impl INSObject for ObjcAppDelegate {
                     // return it if found.
                     let ptr: u64 = *this.get_ivar("_rust_wrapper");
                     let wrapper = &mut *(ptr as *mut RustTouchbarDelegateWrapper);
-                    if let Some(obj) = wrapper.find_view(id_ptr) {
+                    if let Some(obj) = wrapper.find_view(Ident::from_raw(id_ptr)) {
                         return obj as u64;
                     }
+                    let ident = util::nsstring_decode(id_ptr as *mut Object);
+                    wrapper.report_error(TouchbarError::UnknownIdentifier(ident));
                 }
                 0
             }
+            #[cfg(feature = "private_api")]
             extern fn objc_application_did_finish_launching(this: &mut Object, _cmd: Sel, _notification: u64) {
                 unsafe {
                     DFRSystemModalShowsCloseBoxWhenFrontMost(YES);
@@ -1416,7 +6655,7 @@ impl INSObject for ObjcAppDelegate {
                     let old_item_ptr: u64 = *this.get_ivar("_tray_item");
                     let old_item = old_item_ptr as *mut Object;
                     if old_item != nil {
-                        let cls = Class::get("NSTouchBarItem").unwrap();
+                        let cls = util::objc_class("NSTouchBarItem").unwrap();
                         let _:() = msg_send![cls, removeSystemTrayItem: old_item];
                         let _:() = msg_send![old_item, release];
                     }
@@ -1426,12 +6665,15 @@ impl INSObject for ObjcAppDelegate {
 
                     let ident_int: u64 = *this.get_ivar("_group_id");
                     let ident = ident_int as *mut Object;
-                    let cls = Class::get("NSCustomTouchBarItem").unwrap();
+                    let cls = util::objc_class("NSCustomTouchBarItem").unwrap();
                     let item: *mut Object = msg_send![cls, alloc];
                     let _:() = msg_send![item, initWithIdentifier:ident];
                     this.set_ivar("_tray_item", item as u64);
+                    let delegate = &*this as *const Object as u64;
+                    let bar: u64 = *this.get_ivar("_group_bar");
+                    let ptr: u64 = *this.get_ivar("_rust_wrapper");
 
-                    let cls = Class::get("NSButton").unwrap();
+                    let cls = util::objc_class("NSButton").unwrap();
                     let icon_ptr: u64 = *this.get_ivar("_icon");
                     let title_ptr: u64 = *this.get_ivar("_title");
                     let btn: *mut Object;
@@ -1449,9 +6691,22 @@ impl INSObject for ObjcAppDelegate {
                     }
                     let _:() = msg_send![item, setView:btn];
 
-                    let cls = Class::get("NSTouchBarItem").unwrap();
+                    let cls = util::objc_class("NSTouchBarItem").unwrap();
                     let _:() = msg_send![cls, addSystemTrayItem: item];
-                    DFRElementSetControlStripPresenceForIdentifier(ident, YES);
+                    let wrapper = &*(ptr as *const RustTouchbarDelegateWrapper);
+                    let visible = if wrapper.control_strip_visible { YES } else { NO };
+                    DFRElementSetControlStripPresenceForIdentifier(ident, visible);
+                    register_exit_cleanup(delegate, item as u64, ident as u64, bar);
+                }
+            }
+            // Without the Control Strip, the root bar is the app's own bar,
+            // shown while the app is frontmost.
+            #[cfg(not(feature = "private_api"))]
+            extern fn objc_application_did_finish_launching(this: &mut Object, _cmd: Sel, _notification: u64) {
+                unsafe {
+                    let bar: u64 = *this.get_ivar("_group_bar");
+                    let app = NSApp();
+                    let _:() = msg_send![app, setTouchBar: bar as *mut Object];
                 }
             }
 
@@ -1468,15 +6723,75 @@ impl INSObject for ObjcAppDelegate {
                 let f: extern fn(&mut Object, Sel, u64) = objc_button;
                 decl.add_method(sel!(button:), f);
 
-                let f: extern fn(&mut Object, Sel, u64) = objc_tap_gesture;
-                decl.add_method(sel!(tapGesture:), f);
+                let f: extern fn(&mut Object, Sel, u64) = objc_images_loaded;
+                decl.add_method(sel!(imagesLoaded:), f);
+
+                #[cfg(feature = "private_api")]
+                {
+                    let f: extern fn(&mut Object, Sel, u64) = objc_now_playing_changed;
+                    decl.add_method(sel!(nowPlayingChanged:), f);
+                }
+
+                #[cfg(feature = "system_controls")]
+                {
+                    let f: extern fn(&mut Object, Sel, u64) = objc_volume_changed;
+                    decl.add_method(sel!(volumeChanged:), f);
+                }
+
+                let f: extern fn(&mut Object, Sel, u64) = objc_media_control;
+                decl.add_method(sel!(mediaControl:), f);
+
+                let f: extern fn(&mut Object, Sel, u64) = objc_stacked_row;
+                decl.add_method(sel!(stackedRow:), f);
+
+                let f: extern fn(&mut Object, Sel, u64) = objc_button_hold;
+                decl.add_method(sel!(buttonHold:), f);
+
+                let f: extern fn(&mut Object, Sel, u64) = objc_button_confirm;
+                decl.add_method(sel!(buttonConfirm:), f);
+
+                let f: extern fn(&mut Object, Sel, u64) = objc_picker;
+                decl.add_method(sel!(picker:), f);
+
+                let f: extern fn(&mut Object, Sel, u64) = objc_slider_accessory;
+                decl.add_method(sel!(sliderAccessory:), f);
 
-                let f: extern fn(&mut Object, Sel, u64) = objc_swipe_gesture;
-                decl.add_method(sel!(swipeGesture:), f);
+                let f: extern fn(&mut Object, Sel, u64) = objc_passthrough;
+                decl.add_method(sel!(passthrough:), f);
+
+                let f: extern fn(&mut Object, Sel, u64) = objc_gesture;
+                decl.add_method(sel!(gesture:), f);
 
                 let f: extern fn(&mut Object, Sel, u64) = objc_slider;
                 decl.add_method(sel!(slider:), f);
 
+                let f: extern fn(&mut Object, Sel, u64) = objc_focus_mode_changed;
+                decl.add_method(sel!(focusModeChanged:), f);
+
+                let f: extern fn(&mut Object, Sel, u64) = objc_release_dropped;
+                decl.add_method(sel!(releaseDropped:), f);
+
+                let f: extern fn(&mut Object, Sel, u64) = objc_appearance_changed;
+                decl.add_method(sel!(appearanceChanged:), f);
+
+                let f: extern fn(&mut Object, Sel, u64, u64, u64, u64) = objc_observe_value;
+                decl.add_method(sel!(observeValueForKeyPath:ofObject:change:context:), f);
+
+                let f: extern fn(&mut Object, Sel, u64) = objc_timer;
+                decl.add_method(sel!(timer:), f);
+
+                let f: extern fn(&mut Object, Sel, u64) = objc_knob_gesture;
+                decl.add_method(sel!(knobGesture:), f);
+
+                let f: extern fn(&mut Object, Sel, u64) = objc_rating_gesture;
+                decl.add_method(sel!(ratingGesture:), f);
+
+                let f: extern fn(&mut Object, Sel, u64) = objc_date_picker;
+                decl.add_method(sel!(datePicker:), f);
+
+                let f: extern fn(&mut Object, Sel, u64) = objc_text_field;
+                decl.add_method(sel!(textField:), f);
+
                 let f: extern fn(&mut Object, Sel, u64) = objc_popbar;
                 decl.add_method(sel!(popbar:), f);
 
@@ -1507,6 +6822,9 @@ impl INSObject for ObjcAppDelegate {
                 decl.add_method(sel!(scrubber:layout:sizeForItemAtIndex:), f);
                 let f: extern fn(&mut Object, Sel, u64, u32) = objc_scrubber_did_select_item_at_index;
                 decl.add_method(sel!(scrubber:didSelectItemAtIndex:), f);
+
+                let f: extern fn(&mut Object, Sel, u64, NSRange) = objc_scrubber_did_change_visible_range;
+                decl.add_method(sel!(scrubber:didChangeVisibleRange:), f);
             }
 
             decl.register();
@@ -1515,3 +6833,80 @@ impl INSObject for ObjcAppDelegate {
         Class::get("ObjcAppDelegate").unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_removed_popover_frees_its_bars() {
+        let mut tb = Touchbar::alloc("test");
+        let root = tb.create_bar();
+        let child = tb.create_bar();
+        let hold = tb.create_bar();
+        let label = tb.create_label("inside");
+        tb.add_items_to_bar(&child, vec![label]);
+        #[allow(deprecated)]
+        let popover = tb.create_popover_item(None, Some("open"), &child);
+        tb.set_popover_press_and_hold_bar(&popover, &hold);
+        tb.add_items_to_bar(&root, vec![popover]);
+
+        tb.remove_item_from_bar(&root, &popover);
+        assert!(tb.bar_map.contains_key(&root));
+        assert!(!tb.bar_map.contains_key(&child));
+        assert!(!tb.bar_map.contains_key(&hold));
+        assert!(!tb.item_map.contains_key(&label));
+    }
+
+    #[test]
+    fn test_destroyed_popover_frees_its_bars() {
+        let mut tb = Touchbar::alloc("test");
+        let child = tb.create_bar();
+        #[allow(deprecated)]
+        let popover = tb.create_popover_item(None, Some("open"), &child);
+        tb.destroy_item(popover);
+        assert!(!tb.bar_map.contains_key(&child));
+    }
+
+    #[test]
+    fn test_popover_edge_spacer_reported_once() {
+        use std::cell::Cell;
+        let mut tb = Touchbar::alloc("test");
+        let errors = Rc::new(Cell::new(0));
+        let counter = errors.clone();
+        tb.on_error(Box::new(move |_| counter.set(counter.get() + 1)));
+        let child = tb.create_bar();
+        #[allow(deprecated)]
+        let _popover = tb.create_popover_item(None, Some("open"), &child);
+        let spacer = tb.create_spacer(SpacerType::Small);
+        let label = tb.create_label("label");
+        tb.add_items_to_bar(&child, vec![spacer, label]);
+        assert_eq!(errors.get(), 1);
+        let other = tb.create_label("other");
+        tb.insert_item_in_bar(&child, 2, other);
+        assert_eq!(errors.get(), 1);
+        assert_eq!(tb.shown_bar_items(&child, &tb.bar_map[&child].items), vec![label, other]);
+    }
+
+    #[test]
+    fn test_unknown_bar_is_ignored() {
+        let mut tb = Touchbar::alloc("test");
+        let bar = tb.create_bar();
+        let label = tb.create_label("label");
+        tb.destroy_bar(bar);
+        tb.add_items_to_bar(&bar, vec![label]);
+        tb.insert_item_in_bar(&bar, 0, label);
+        assert!(!tb.bar_map.contains_key(&bar));
+    }
+
+    #[test]
+    fn test_raw_ids_take_live_generation() {
+        let mut tb = Touchbar::alloc("test");
+        let bar = tb.create_bar();
+        let label = tb.create_label("label");
+        tb.add_items_to_bar(&bar, vec![ItemId::from_raw(label.as_raw())]);
+        let items = tb.bar_items(&bar);
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].generation(), label.generation());
+    }
+}
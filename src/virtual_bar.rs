@@ -0,0 +1,447 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use interface::*;
+
+// Callbacks of the latest render.  Items keep calling the trampoline they
+// were created with, which looks up the current callback here.
+type ButtonSlot = Rc<RefCell<Rc<ButtonCb>>>;
+type SliderSlot = Rc<RefCell<Rc<SliderCb>>>;
+
+// What an item was last rendered from.  Button options are kept without
+// their image, which the item consumed, along with whether one is shown.
+enum Node {
+    Button(ButtonOptions, bool, ButtonSlot),
+    Label(String),
+    Slider(SliderOptions, SliderSlot),
+    Scrubber(Rc<dyn TScrubberData>, ScrubberOptions),
+    Spacer(SpacerType),
+    Popover(ButtonOptions, bool, BarId, Vec<VirtualItem>),
+    Stacked,
+}
+
+struct VirtualItem {
+    name: Option<String>,
+    item: ItemId,
+    node: Node,
+}
+
+/// A root bar that is updated in place from a new `BarSpec` on every change
+///
+/// Instead of building a new bar and setting it as the root bar whenever the
+/// application's state changes, which rebuilds every item and makes the
+/// whole bar flicker, the application describes the bar it wants with
+/// `render()`.  The description is compared with the previous one, and only
+/// what changed is updated:
+///
+/// * Changed texts, images and slider settings are applied to the existing
+///   items, with `update_label()`, `update_button()` and similar.
+/// * New items are created and inserted, and items that are no longer
+///   described are removed and freed.
+/// * Moved items are reordered without being recreated.
+///
+/// Named items (see `BarItemSpec::named()`) are matched to the previous
+/// render by name, so they keep their identity when moved.  Other items are
+/// matched in order to unnamed items of the same kind.  An item that changes
+/// kind, a button or popover whose image is removed, a slider whose
+/// `continuous` option changes, a scrubber given a different data source or
+/// options, and stacked items are replaced by new items instead.
+///
+/// Images are consumed by the item they are given to, so a button's image
+/// is applied again on every render that includes one.
+///
+/// Button and slider callbacks are taken from the latest render, so they can
+/// capture the current state.  Scrubbers aren't refreshed automatically;
+/// call `refresh_scrubber()` when their data changes.
+///
+/// The first render creates the bar and sets it as the root bar.  The bar
+/// belongs to the `VirtualBar` from then on, and must not be replaced with
+/// `set_bar_as_root()` while it is still rendered to.
+///
+/// # Example
+///
+/// ```
+/// # use rubrail::prelude::*;
+/// # let mut tb = Touchbar::alloc("test");
+/// let mut bar = VirtualBar::new();
+/// for count in 0..3 {
+///     bar.render(&mut tb, vec![
+///         BarItemSpec::Label(format!("Pressed {} times", count)),
+///         BarItemSpec::Button(ButtonOptions {
+///             text: Some("Press".to_string()),
+///             ..Default::default()
+///         }, Box::new(move |_| println!("pressed after {}", count))),
+///     ]);
+/// }
+/// ```
+#[derive(Default)]
+pub struct VirtualBar {
+    root: Option<(BarId, Vec<VirtualItem>)>,
+}
+
+impl VirtualBar {
+    /// Create a `VirtualBar` that hasn't been rendered yet
+    pub fn new() -> VirtualBar {
+        VirtualBar { root: None }
+    }
+
+    /// Update the bar to match a description
+    ///
+    /// # Arguments
+    ///
+    /// * `tb` - Touch Bar controller to create and update the items with.
+    ///   Must be the same one on every call.
+    /// * `spec` - Items the bar should have, in display order
+    ///
+    /// # Returns
+    ///
+    /// The root bar, which is the same on every call
+    pub fn render<T: TTouchbar2 + ?Sized>(&mut self, tb: &mut T, spec: BarSpec) -> BarId {
+        match self.root.take() {
+            Some((bar, items)) => {
+                let items = render_bar(tb, &bar, items, spec);
+                self.root = Some((bar, items));
+                bar
+            },
+            None => {
+                let bar = tb.create_bar();
+                let items = render_bar(tb, &bar, Vec::new(), spec);
+                tb.set_bar_as_root(bar);
+                self.root = Some((bar, items));
+                bar
+            },
+        }
+    }
+
+    /// The root bar, or `None` if nothing has been rendered yet
+    pub fn bar(&self) -> Option<BarId> {
+        self.root.as_ref().map(|&(bar, _)| bar)
+    }
+}
+
+fn render_bar<T: TTouchbar2 + ?Sized>(tb: &mut T, bar: &BarId, old: Vec<VirtualItem>,
+                                      spec: BarSpec) -> Vec<VirtualItem> {
+    let mut old: Vec<Option<VirtualItem>> = old.into_iter().map(Some).collect();
+    let mut items = Vec::new();
+    for spec in spec {
+        let (name, spec) = unwrap_named(spec);
+        let matched = old.iter().position(|x| match *x {
+            Some(ref x) => x.name == name && same_kind(&x.node, &spec),
+            None => false,
+        });
+        let item = match matched {
+            Some(idx) => update_item(tb, old[idx].take().unwrap(), spec),
+            None => create_item(tb, name, spec),
+        };
+        items.push(item);
+    }
+    for stale in old.into_iter().flatten() {
+        tb.remove_item_from_bar(bar, &stale.item);
+    }
+    let current = tb.bar_items(bar);
+    for (idx, item) in items.iter().enumerate() {
+        if !current.contains(&item.item) {
+            tb.insert_item_in_bar(bar, idx, item.item);
+        }
+    }
+    let order: Vec<ItemId> = items.iter().map(|x| x.item).collect();
+    if tb.bar_items(bar) != order {
+        tb.set_bar_item_order(bar, order);
+    }
+    items
+}
+
+fn unwrap_named(spec: BarItemSpec) -> (Option<String>, BarItemSpec) {
+    match spec {
+        BarItemSpec::Named(name, spec) => (Some(name), unwrap_named(*spec).1),
+        spec => (None, spec),
+    }
+}
+
+// Whether an item can be updated to match a spec, instead of being replaced
+fn same_kind(node: &Node, spec: &BarItemSpec) -> bool {
+    match (node, spec) {
+        (Node::Button(_, image, _), BarItemSpec::Button(new, _)) =>
+            !*image || new.image.is_some(),
+        (Node::Label(_), BarItemSpec::Label(_)) => true,
+        (Node::Slider(old, _), BarItemSpec::Slider(new, _)) =>
+            old.continuous == new.continuous,
+        (Node::Scrubber(old_data, old), BarItemSpec::Scrubber(new_data, new)) =>
+            Rc::ptr_eq(old_data, new_data) && old == new,
+        (Node::Spacer(old), BarItemSpec::Spacer(new)) => old == new,
+        (Node::Popover(_, image, ..), BarItemSpec::Popover(new, _)) =>
+            !*image || new.image.is_some(),
+        _ => false,
+    }
+}
+
+fn create_item<T: TTouchbar2 + ?Sized>(tb: &mut T, name: Option<String>,
+                                       spec: BarItemSpec) -> VirtualItem {
+    let (item, node) = match spec {
+        BarItemSpec::Button(options, cb) => {
+            let slot: ButtonSlot = Rc::new(RefCell::new(Rc::new(cb)));
+            let current = slot.clone();
+            let item = tb.new_button(options.clone(), Box::new(move |item| {
+                // Release the borrow first, in case the callback renders again
+                let cb = current.borrow().clone();
+                cb(item)
+            }));
+            let (options, image) = forget_image(options);
+            (item, Node::Button(options, image, slot))
+        },
+        BarItemSpec::Label(text) => (tb.create_label(&text), Node::Label(text)),
+        BarItemSpec::Slider(options, cb) => {
+            let slot: SliderSlot = Rc::new(RefCell::new(Rc::new(cb)));
+            let current = slot.clone();
+            let item = tb.new_slider(options.clone(), Box::new(move |item, value| {
+                let cb = current.borrow().clone();
+                cb(item, value)
+            }));
+            (item, Node::Slider(options, slot))
+        },
+        BarItemSpec::Scrubber(data, options) => {
+            (tb.new_scrubber(data.clone(), options), Node::Scrubber(data, options))
+        },
+        BarItemSpec::Spacer(space) => (tb.create_spacer(space), Node::Spacer(space)),
+        BarItemSpec::Popover(options, spec) => {
+            let bar = tb.create_bar();
+            let items = render_bar(tb, &bar, Vec::new(), spec);
+            let item = tb.new_popover(options.clone(), &bar);
+            let (options, image) = forget_image(options);
+            (item, Node::Popover(options, image, bar, items))
+        },
+        BarItemSpec::Stacked(top, bottom) => (tb.create_stacked_item(top, bottom), Node::Stacked),
+        BarItemSpec::Named(name, spec) => return create_item(tb, Some(name), *spec),
+    };
+    if let Some(ref name) = name {
+        tb.set_item_name(&item, name);
+    }
+    VirtualItem { name, item, node }
+}
+
+fn update_item<T: TTouchbar2 + ?Sized>(tb: &mut T, old: VirtualItem,
+                                       spec: BarItemSpec) -> VirtualItem {
+    let item = old.item;
+    let node = match (old.node, spec) {
+        (Node::Button(old, _, slot), BarItemSpec::Button(options, cb)) => {
+            update_button(tb, &item, &old, &options);
+            *slot.borrow_mut() = Rc::new(cb);
+            let (options, image) = forget_image(options);
+            Node::Button(options, image, slot)
+        },
+        (Node::Label(old), BarItemSpec::Label(text)) => {
+            if text != old {
+                tb.update_label(&item, &text);
+            }
+            Node::Label(text)
+        },
+        (Node::Slider(old, slot), BarItemSpec::Slider(options, cb)) => {
+            if (options.min, options.max) != (old.min, old.max) {
+                tb.update_slider_range(&item, options.min, options.max);
+            }
+            if options.label != old.label {
+                tb.update_slider_label(&item, options.label.as_deref().unwrap_or(""));
+            }
+            if (options.tick_marks, options.snap, options.integer_only) !=
+                (old.tick_marks, old.snap, old.integer_only) {
+                tb.set_slider_steps(&item, options.tick_marks, options.snap, options.integer_only);
+            }
+            *slot.borrow_mut() = Rc::new(cb);
+            Node::Slider(options, slot)
+        },
+        (Node::Popover(old, _, bar, items), BarItemSpec::Popover(options, spec)) => {
+            update_button(tb, &item, &old, &options);
+            let items = render_bar(tb, &bar, items, spec);
+            let (options, image) = forget_image(options);
+            Node::Popover(options, image, bar, items)
+        },
+        // Scrubbers and spacers only match when nothing changed
+        (node, _) => node,
+    };
+    VirtualItem { name: old.name, item, node }
+}
+
+// Options to keep for a button once its image has been given to the item,
+// and whether it shows one
+fn forget_image(options: ButtonOptions) -> (ButtonOptions, bool) {
+    let image = options.image.is_some();
+    (ButtonOptions { image: None, ..options }, image)
+}
+
+// Apply new options to a button matched by same_kind().  Any new image is a
+// fresh one, since the previous image was consumed, so it is always applied.
+fn update_button<T: TTouchbar2 + ?Sized>(tb: &mut T, item: &ItemId, old: &ButtonOptions,
+                                         new: &ButtonOptions) {
+    let image = new.image.as_ref();
+    let text = match new.text != old.text {
+        true => Some(new.text.as_deref().unwrap_or("")),
+        false => None,
+    };
+    if image.is_some() || text.is_some() {
+        tb.update_button(item, image, text);
+    }
+    if new.subtitle != old.subtitle {
+        tb.set_button_subtitle(item, new.subtitle.as_deref());
+    }
+    if new.latching != old.latching {
+        tb.set_button_latching(item, new.latching);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use interface::*;
+    use super::VirtualBar;
+
+    // Keeps the items of each bar, and records the calls that change items
+    #[derive(Default)]
+    struct Recorder {
+        next: u64,
+        bars: BTreeMap<BarId, Vec<ItemId>>,
+        calls: Vec<String>,
+    }
+
+    impl Recorder {
+        fn new_item(&mut self, kind: &str) -> ItemId {
+            self.next += 1;
+            self.calls.push(format!("create {} {}", kind, self.next));
+            ItemId::from_raw(self.next)
+        }
+        fn take_calls(&mut self) -> Vec<String> {
+            self.calls.drain(..).collect()
+        }
+    }
+
+    impl TTouchbar for Recorder {
+        type T = Recorder;
+        fn alloc(_title: &str) -> Recorder { Recorder::default() }
+        fn create_bar(&mut self) -> BarId {
+            self.next += 1;
+            let bar = BarId::from_raw(self.next);
+            self.bars.insert(bar, Vec::new());
+            bar
+        }
+        fn insert_item_in_bar(&mut self, bar_id: &BarId, index: usize, item: ItemId) {
+            let items = self.bars.get_mut(bar_id).unwrap();
+            let index = index.min(items.len());
+            items.insert(index, item);
+        }
+        fn set_bar_item_order(&mut self, bar_id: &BarId, items: Vec<ItemId>) {
+            self.calls.push("reorder".to_string());
+            self.bars.insert(*bar_id, items);
+        }
+        fn remove_item_from_bar(&mut self, bar_id: &BarId, item: &ItemId) {
+            self.calls.push(format!("remove {}", item.as_raw()));
+            self.bars.get_mut(bar_id).unwrap().retain(|x| x != item);
+        }
+        fn bar_items(&self, bar_id: &BarId) -> Vec<ItemId> {
+            self.bars[bar_id].clone()
+        }
+        fn create_label(&mut self, _text: &str) -> ItemId { self.new_item("label") }
+        fn update_label(&mut self, label_id: &ItemId, text: &str) {
+            self.calls.push(format!("label {} {}", label_id.as_raw(), text));
+        }
+        fn create_spacer(&mut self, _space: SpacerType) -> ItemId { self.new_item("spacer") }
+        fn create_button(&mut self, _image: Option<&TouchbarImage>, _text: Option<&str>,
+                         _cb: ButtonCb) -> ItemId {
+            self.new_item("button")
+        }
+        fn update_button(&mut self, item: &ItemId, image: Option<&TouchbarImage>,
+                         text: Option<&str>) {
+            self.calls.push(format!("button {} {:?} {:?}", item.as_raw(),
+                                    image.map(|x| x.as_raw()), text));
+        }
+    }
+
+    fn label(text: &str) -> BarItemSpec {
+        BarItemSpec::Label(text.to_string())
+    }
+
+    fn button(image: Option<u64>, text: &str) -> BarItemSpec {
+        BarItemSpec::Button(ButtonOptions {
+            image: image.map(TouchbarImage::from_raw),
+            text: Some(text.to_string()),
+            ..Default::default()
+        }, Box::new(|_| {}))
+    }
+
+    fn items(tb: &Recorder, bar: &BarId) -> Vec<u64> {
+        tb.bar_items(bar).iter().map(|x| x.as_raw()).collect()
+    }
+
+    #[test]
+    fn test_updates_in_place() {
+        let mut tb = Recorder::default();
+        let mut vbar = VirtualBar::new();
+        let bar = vbar.render(&mut tb, vec![label("a"), button(None, "b")]);
+        assert_eq!(tb.take_calls(), vec!["create label 2", "create button 3"]);
+        assert_eq!(vbar.render(&mut tb, vec![label("c"), button(None, "d")]), bar);
+        assert_eq!(tb.take_calls(), vec!["label 2 c", "button 3 None Some(\"d\")"]);
+        assert_eq!(items(&tb, &bar), vec![2, 3]);
+        vbar.render(&mut tb, vec![label("c"), button(None, "d")]);
+        assert!(tb.take_calls().is_empty());
+    }
+
+    #[test]
+    fn test_matches_names_then_kinds() {
+        let mut tb = Recorder::default();
+        let mut vbar = VirtualBar::new();
+        let bar = vbar.render(&mut tb, vec![
+            label("a").named("first"), label("b").named("second"), label("c"), button(None, "d"),
+        ]);
+        tb.take_calls();
+        // Named items follow their name, unnamed ones the first item of their kind
+        vbar.render(&mut tb, vec![
+            button(None, "d"), label("c"), label("b").named("second"), label("a").named("first"),
+        ]);
+        assert_eq!(tb.take_calls(), vec!["reorder"]);
+        assert_eq!(items(&tb, &bar), vec![5, 4, 3, 2]);
+        // Unnamed items are only matched to unnamed ones, and the other way
+        vbar.render(&mut tb, vec![label("a"), label("b").named("third")]);
+        assert_eq!(tb.take_calls(), vec!["label 4 a", "create label 6", "remove 5", "remove 3",
+                                         "remove 2"]);
+        assert_eq!(items(&tb, &bar), vec![4, 6]);
+    }
+
+    #[test]
+    fn test_removes_stale_items() {
+        let mut tb = Recorder::default();
+        let mut vbar = VirtualBar::new();
+        let bar = vbar.render(&mut tb, vec![label("a"), label("b"), label("c")]);
+        tb.take_calls();
+        vbar.render(&mut tb, vec![label("a"), label("b")]);
+        assert_eq!(tb.take_calls(), vec!["remove 4"]);
+        assert_eq!(items(&tb, &bar), vec![2, 3]);
+    }
+
+    #[test]
+    fn test_replaces_on_kind_change() {
+        let mut tb = Recorder::default();
+        let mut vbar = VirtualBar::new();
+        let bar = vbar.render(&mut tb, vec![label("a"), label("b")]);
+        tb.take_calls();
+        vbar.render(&mut tb, vec![label("a"), BarItemSpec::Spacer(SpacerType::Small)]);
+        assert_eq!(tb.take_calls(), vec!["create spacer 4", "remove 3"]);
+        assert_eq!(items(&tb, &bar), vec![2, 4]);
+    }
+
+    #[test]
+    fn test_button_images() {
+        let mut tb = Recorder::default();
+        let mut vbar = VirtualBar::new();
+        let bar = vbar.render(&mut tb, vec![button(Some(10), "a")]);
+        tb.take_calls();
+        // A new image is applied even if its handle equals the consumed one
+        vbar.render(&mut tb, vec![button(Some(10), "a")]);
+        assert_eq!(tb.take_calls(), vec!["button 2 Some(10) None"]);
+        vbar.render(&mut tb, vec![button(Some(11), "a")]);
+        assert_eq!(tb.take_calls(), vec!["button 2 Some(11) None"]);
+        // Removing the image replaces the button
+        vbar.render(&mut tb, vec![button(None, "a")]);
+        assert_eq!(tb.take_calls(), vec!["create button 3", "remove 2"]);
+        assert_eq!(items(&tb, &bar), vec![3]);
+        vbar.render(&mut tb, vec![button(Some(12), "a")]);
+        assert_eq!(tb.take_calls(), vec!["button 3 Some(12) None"]);
+    }
+}
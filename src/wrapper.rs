@@ -66,6 +66,10 @@ macro_rules! objc_subclass {
     }
 }
 
+/// Wrap an Objective-C class in a subclass that tracks allocations
+///
+/// Version with the `objc_wrapper` feature, which subclasses `$superclass`
+/// and counts live objects of the new class.
 #[cfg(feature = "objc_wrapper")]
 #[macro_export]
 macro_rules! objc_subclass {
@@ -79,6 +83,14 @@ macro_rules! objc_subclass {
                 let ptr = this as *mut Object;
                 format!("{}({:x})", stringify!($newclass), ptr as u64)
             }
+            /// Number of objects of this class that are allocated
+            pub fn live_objects() -> usize {
+                $newclass::live_count().load(::std::sync::atomic::Ordering::SeqCst)
+            }
+            fn live_count() -> &'static ::std::sync::atomic::AtomicUsize {
+                static LIVE: ::std::sync::atomic::AtomicUsize = ::std::sync::atomic::AtomicUsize::new(0);
+                &LIVE
+            }
         }
         impl INSObject for $newclass {
             fn class() -> &'static Class {
@@ -86,6 +98,14 @@ macro_rules! objc_subclass {
                     let superclass = Class::get(stringify!($superclass)).unwrap();
                     let mut decl = ClassDecl::new(stringify!($newclass), superclass).unwrap();
                     decl.add_ivar::<u64>("_retain_count");
+                    extern fn objc_alloc(this: &Class, _cmd: Sel) -> *mut Object {
+                        unsafe {
+                            let superclass = Class::get(stringify!($superclass)).unwrap();
+                            let obj: *mut Object = msg_send![super(this, superclass.metaclass()), alloc];
+                            $newclass::live_count().fetch_add(1, ::std::sync::atomic::Ordering::SeqCst);
+                            obj
+                        }
+                    }
                     extern fn objc_retain(this: &mut Object, _cmd: Sel) -> *mut Object {
                         unsafe {
                             //info!("{} retain", $newclass::str_name(this));
@@ -108,12 +128,15 @@ macro_rules! objc_subclass {
                     extern fn objc_dealloc(this: &mut Object, _cmd: Sel) {
                         unsafe {
                             info!("{} dealloc", $newclass::str_name(this));
+                            $newclass::live_count().fetch_sub(1, ::std::sync::atomic::Ordering::SeqCst);
                             let superclass = Class::get(stringify!($superclass)).unwrap();
                             let _: () = msg_send![super(this, superclass), dealloc];
                             //info!("{} dealloc done", $newclass::str_name(this));
                         }
                     }
                     unsafe {
+                        let f: extern fn(&Class, Sel) -> *mut Object = objc_alloc;
+                        decl.add_class_method(sel!(alloc), f);
                         let f: extern fn(&mut Object, Sel) -> *mut Object = objc_retain;
                         decl.add_method(sel!(retain), f);
                         let f: extern fn(&mut Object, Sel) = objc_release;
@@ -134,3 +157,23 @@ objc_subclass!(RRTouchBar, NSTouchBar, RRTOUCHBAR_CLASS);
 objc_subclass!(RRCustomTouchBarItem, NSCustomTouchBarItem, RRCUSTOMITEM_CLASS);
 objc_subclass!(RRPopoverTouchBarItem, NSPopoverTouchBarItem, RRPOPOVERITEM_CLASS);
 objc_subclass!(RRSliderTouchBarItem, NSSliderTouchBarItem, RRSLIDER_CLASS);
+
+/// Number of live objects of each wrapped class, by class name
+///
+/// Only counted with the `objc_wrapper` feature, and empty otherwise.
+#[cfg(feature = "objc_wrapper")]
+pub fn live_objects() -> Vec<(&'static str, usize)> {
+    vec![("RRScrubber", RRScrubber::live_objects()),
+         ("RRTouchBar", RRTouchBar::live_objects()),
+         ("RRCustomTouchBarItem", RRCustomTouchBarItem::live_objects()),
+         ("RRPopoverTouchBarItem", RRPopoverTouchBarItem::live_objects()),
+         ("RRSliderTouchBarItem", RRSliderTouchBarItem::live_objects())]
+}
+
+/// Number of live objects of each wrapped class, by class name
+///
+/// Only counted with the `objc_wrapper` feature, and empty otherwise.
+#[cfg(not(feature = "objc_wrapper"))]
+pub fn live_objects() -> Vec<(&'static str, usize)> {
+    Vec::new()
+}